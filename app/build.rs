@@ -6,7 +6,8 @@ use std::{
     path::Path,
 };
 
-use shaderc::{CompileOptions, Compiler, OptimizationLevel, ShaderKind};
+use ash::vk;
+use shaderc::{CompileOptions, Compiler, OptimizationLevel, ShaderKind, TargetEnv};
 
 const SHADER_PATH: &str = "../shaders/";
 
@@ -24,6 +25,9 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         compile_options.set_optimization_level(optimization_level);
 
+        // Vulkan 1.2, as required by caldeira, so that shaders can use subgroup operations
+        compile_options.set_target_env(TargetEnv::Vulkan, vk::make_version(1, 2, 0));
+
         #[cfg(debug_assertions)]
         compile_options.set_generate_debug_info();
 
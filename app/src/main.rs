@@ -1,9 +1,9 @@
 #![allow(unused_variables)]
 
 use std::num::NonZeroU32;
+use std::path::Path;
 use std::rc::Rc;
 
-use ash::version::DeviceV1_0;
 use ash::vk;
 
 use winit::event::{Event, KeyboardInput, VirtualKeyCode, WindowEvent};
@@ -16,7 +16,7 @@ fn main() {
         .parse_filters("caldeira=trace")
         .init();
 
-    let instance = Rc::new(vulkan::Instance::new());
+    let instance = Rc::new(vulkan::Instance::new_or_panic());
 
     #[allow(unused_variables)]
     #[cfg(feature = "validation-layers")]
@@ -33,7 +33,8 @@ fn main() {
             }
         },
         Rc::clone(&instance),
-    );
+    )
+    .expect("failed to create device");
 
     let mut compute_queue = queues.swap_remove(0).swap_remove(0);
 
@@ -45,13 +46,11 @@ fn main() {
     // let command_buffer = vulkan::SingleTimeCommand::new(&device, &command_pool); // TODO: utiliser des command buffers alloués normalement et stockés
 
     let mut command_buffers = command_pool
-        .allocate_command_buffers(vk::CommandBufferLevel::PRIMARY, 3)
+        .allocate_command_buffers(vk::CommandBufferLevel::PRIMARY, 2)
         .into_iter()
         .map(|command_buffer| command_buffer.begin(vk::CommandBufferUsageFlags::empty()))
         .collect::<Vec<_>>();
 
-    let command_buffer = &mut command_buffers[0];
-
     let descriptor_set_layout = vulkan::DescriptorSetLayoutBuilder::new()
         .with_binding(
             vk::DescriptorType::STORAGE_BUFFER,
@@ -71,48 +70,37 @@ fn main() {
             vk::ShaderStageFlags::COMPUTE,
             None,
         )
-        .build(Rc::clone(&device));
+        .build(Rc::clone(&device))
+        .expect("failed to create the descriptor set layout");
     let descriptor_set_layouts = [descriptor_set_layout];
 
-    let compute_pipeline =
-        vulkan::ComputePipeline::new(&descriptor_set_layouts, Rc::clone(&device));
-
-    let mut buffer = vulkan::Buffer::new(
-        4,
-        vk::BufferUsageFlags::STORAGE_BUFFER
-            | vk::BufferUsageFlags::TRANSFER_SRC
-            | vk::BufferUsageFlags::TRANSFER_DST,
-        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+    let compute_pipeline = vulkan::ComputePipeline::new(
+        Path::new("shaders/compute.comp.spv"),
+        "main",
+        &descriptor_set_layouts,
+        None,
+        None,
+        None,
+        Some("compute"),
         Rc::clone(&device),
-        &instance,
     );
 
-    buffer.copy_data(&0u32, 0);
-
-    let mut output_image = vulkan::Image::new_storage(1_000, 1_000, Rc::clone(&device), &instance);
-
-    let (src_stage_mask, dst_stage_mask, dependency_flags, barrier) =
-        output_image.transition_layout(vk::ImageLayout::GENERAL);
-
-    let image_memory_barriers = [barrier.build()];
-
-    command_buffer.as_generic().pipeline_barrier(
-        src_stage_mask,
-        dst_stage_mask,
-        dependency_flags,
-        &[],
-        &[],
-        &image_memory_barriers,
-    );
+    let mut counter =
+        vulkan::AtomicCounterBuffer::new(Some("counter"), Rc::clone(&device), &instance);
 
-    let command_buffer = command_buffers.swap_remove(0).end();
+    counter
+        .reset(0, &mut command_pool, &mut compute_queue)
+        .expect("device lost while resetting the counter");
 
-    let submits = [vulkan::QueueSubmission::builder()
-        .with_command_buffer(&command_buffer)
-        .build()];
-
-    compute_queue.submit(&submits, None);
-    compute_queue.wait_idle();
+    let mut output_image = vulkan::Image::new_storage_general(
+        1_000,
+        1_000,
+        &mut command_pool,
+        &mut compute_queue,
+        Rc::clone(&device),
+        &instance,
+    )
+    .expect("device lost while creating the output image");
 
     let descriptor_pool = vulkan::DescriptorPoolBuilder::new()
         .with(vk::DescriptorType::STORAGE_BUFFER, 1)
@@ -121,38 +109,13 @@ fn main() {
 
     let descriptor_sets = descriptor_set_layouts[0].allocate_descriptor_sets(1, &descriptor_pool);
 
-    {
-        let buffer_info_1 = vk::DescriptorBufferInfo::builder()
-            .buffer(buffer.handle)
-            .offset(0)
-            .range(4);
-        let buffer_infos = [buffer_info_1.build()];
-
-        let image_info = vk::DescriptorImageInfo::builder()
-            .image_layout(output_image.layout) // Problème de synchronisation : le layout correspond pas encore vu que le command buffer est pas submit
-            .image_view(output_image.view);
-        let image_infos = [image_info.build()];
-
-        let descriptor_write_1 = vk::WriteDescriptorSet::builder()
-            .dst_set(descriptor_sets[0])
-            .dst_binding(0)
-            .dst_array_element(0)
-            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-            .buffer_info(&buffer_infos[0..1]);
-        let descriptor_write_2 = vk::WriteDescriptorSet::builder()
-            .dst_set(descriptor_sets[0])
-            .dst_binding(1)
-            .dst_array_element(0)
-            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
-            .image_info(&image_infos);
-        let descriptor_writes = [descriptor_write_1.build(), descriptor_write_2.build()];
-
-        unsafe {
-            device
-                .device
-                .update_descriptor_sets(&descriptor_writes, &[]);
-        }
-    }
+    descriptor_sets[0]
+        .writer()
+        .write_buffer(0, counter.buffer(), 0, vk::WHOLE_SIZE)
+        .and_then(|writer| writer.write_storage_image(1, &output_image))
+        .expect("failed to write the descriptors of the compute shader")
+        .update();
+    let descriptor_sets = [descriptor_sets[0].handle];
 
     let command_buffer = &mut command_buffers[0];
 
@@ -193,21 +156,20 @@ fn main() {
     //     command_buffer.submit();
     // }
 
-    let command_buffer = command_buffers.swap_remove(0).end();
-
-    let submits = [vulkan::QueueSubmission::builder()
-        .with_command_buffer(&command_buffer)
+    let submits = vec![vulkan::QueueSubmission::builder()
+        .with_owned_command_buffers(vec![command_buffers.swap_remove(0).end()])
         .build()];
 
-    compute_queue.submit(&submits, None);
-    compute_queue.wait_idle();
+    compute_queue
+        .submit_batch(submits)
+        .expect("failed to submit the compute dispatch")
+        .wait()
+        .expect("device lost during the compute dispatch");
 
-    let output = {
-        let mut output = 0;
-        buffer.get_data(&mut output, 0);
-        println!("output: {}", output);
-        output
-    };
+    let output = counter
+        .read(&mut command_pool, &mut compute_queue)
+        .expect("device lost while reading the counter");
+    println!("output: {}", output);
 
     debug_assert!(output == output_image.extent.width * output_image.extent.height);
 
@@ -220,14 +182,16 @@ fn main() {
             size as _,
             vk::BufferUsageFlags::TRANSFER_DST,
             vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+            Some("output staging"),
             Rc::clone(&device),
             &instance,
         );
 
         let command_buffer = &mut command_buffers[0];
 
-        let (src_stage_mask, dst_stage_mask, dependency_flags, barrier) =
-            output_image.transition_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+        let (src_stage_mask, dst_stage_mask, dependency_flags, barrier) = output_image
+            .transition_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .expect("the output image can't be transitioned for the copy");
         let image_memory_barriers = [barrier.build()];
 
         command_buffer.as_generic().pipeline_barrier(
@@ -269,16 +233,19 @@ fn main() {
 
         // output_image.copy_to_buffer(&mut staging_buffer, &command_pool);
 
-        let command_buffer = command_buffers.swap_remove(0).end();
-
-        let submits = [vulkan::QueueSubmission::builder()
-            .with_command_buffer(&command_buffer)
+        let submits = vec![vulkan::QueueSubmission::builder()
+            .with_owned_command_buffers(vec![command_buffers.swap_remove(0).end()])
             .build()];
 
-        compute_queue.submit(&submits, None);
-        compute_queue.wait_idle();
+        compute_queue
+            .submit_batch(submits)
+            .expect("failed to submit the output image copy")
+            .wait()
+            .expect("device lost while reading the output image");
 
-        staging_buffer.get_data(&mut pixels[..], 0);
+        staging_buffer
+            .get_data(&mut pixels[..], 0)
+            .expect("the staging buffer has the size of the pixels");
 
         pixels
     };
@@ -311,7 +278,7 @@ fn main() {
             let descriptors = &descriptor_set_layouts;
             let descriptor_pool = &descriptor_pool;
             let descriptor_sets = &descriptor_sets;
-            let buffer = &buffer;
+            let counter = &counter;
             let output_image = &output_image;
             let compute_pipeline = &compute_pipeline;
 
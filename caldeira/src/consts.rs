@@ -14,9 +14,8 @@ pub const DEBUG: bool = true;
 #[cfg(not(feature = "validation-layers"))]
 pub const DEBUG: bool = false;
 
+/// Instance extensions besides the surface ones, which are chosen at runtime by `vulkan::surface_extensions`
 pub const INSTANCE_EXTENSIONS: &[&str] = &[
-    "VK_KHR_surface",
-    "VK_KHR_win32_surface",
     #[cfg(feature = "validation-layers")]
     "VK_EXT_debug_utils",
 ];
@@ -1,12 +1,17 @@
+use std::collections::HashMap;
 use std::ffi::CString;
 use std::fs::File;
 use std::os::raw::c_char;
 use std::path::Path;
+#[cfg(test)]
+use std::rc::Rc;
 
 use ash::version::{DeviceV1_0, InstanceV1_0};
 use ash::{util, vk};
 
-use crate::vulkan::{Device, Instance, QueueCreateInfo, QueueFamily};
+use crate::vulkan::{Device, Instance, QueueCreateInfo, QueueFamily, SpecializationConstants};
+#[cfg(test)]
+use crate::vulkan::{DeviceBuilder, InstanceBuilder, Queue};
 
 /// Free an iterator of *const c_char allocated by a CString and getted by using CString::into_raw() method
 /// # Safety
@@ -94,6 +99,24 @@ pub fn read_file<P: AsRef<Path>>(path: P) -> Vec<u32> {
     util::read_spv(&mut file).expect("failed to read file!")
 }
 
+/// Compile a GLSL shader into SPIR-V in-process
+/// The error contains the compiler messages
+#[cfg(feature = "runtime-shaderc")]
+pub fn compile_glsl(
+    source: &str,
+    shader_kind: shaderc::ShaderKind,
+    entry: &str,
+) -> Result<Vec<u32>, String> {
+    let mut compiler =
+        shaderc::Compiler::new().ok_or("Error trying to initialize shader compiler")?;
+
+    let compilation_artifact = compiler
+        .compile_into_spirv(source, shader_kind, "runtime.glsl", entry, None)
+        .map_err(|error| error.to_string())?;
+
+    Ok(compilation_artifact.as_binary().to_vec())
+}
+
 pub fn create_shader_module(shader_code: &[u32], device: &Device) -> vk::ShaderModule {
     let create_info = vk::ShaderModuleCreateInfo::builder().code(shader_code);
 
@@ -124,6 +147,147 @@ pub fn find_memory_type(
     panic!("failed to find suitable memory type!")
 }
 
+/// Read the workgroup size of the entry point `entry` in a SPIR-V module, declared by the `LocalSize` or `LocalSizeId`
+/// execution mode, or by a constant decorated with the `WorkgroupSize` built-in which takes precedence,
+/// the specialization constants having the values of `specialization` or their default ones
+/// `None` if there is no such entry point, if the size isn't made of 32 bits constants, such as a size computed
+/// from specialization constants by the shader or specialized with values of another size, or if it's 0
+pub fn spirv_local_size(
+    shader_code: &[u32],
+    entry: &str,
+    specialization: Option<&SpecializationConstants>,
+) -> Option<[u32; 3]> {
+    const HEADER_LEN: usize = 5;
+    const OP_ENTRY_POINT: u32 = 15;
+    const OP_EXECUTION_MODE: u32 = 16;
+    const OP_CONSTANT: u32 = 43;
+    const OP_CONSTANT_COMPOSITE: u32 = 44;
+    const OP_SPEC_CONSTANT: u32 = 50;
+    const OP_SPEC_CONSTANT_COMPOSITE: u32 = 51;
+    const OP_DECORATE: u32 = 71;
+    const OP_EXECUTION_MODE_ID: u32 = 331;
+    const LOCAL_SIZE: u32 = 17;
+    const LOCAL_SIZE_ID: u32 = 38;
+    const SPEC_ID: u32 = 1;
+    const BUILT_IN: u32 = 11;
+    const WORKGROUP_SIZE: u32 = 25;
+
+    let mut entry_id = None;
+    let mut local_size = None;
+    let mut local_size_ids = None;
+    let mut workgroup_size_id = None;
+    let mut spec_ids = HashMap::new();
+    let mut constants = HashMap::new();
+    let mut composites = HashMap::new();
+    let mut words = shader_code.get(HEADER_LEN..)?;
+
+    while let Some(&first) = words.first() {
+        let word_count = (first >> 16) as usize;
+        let opcode = first & 0xffff;
+
+        if word_count == 0 || word_count > words.len() {
+            return None;
+        }
+
+        let operands = &words[1..word_count];
+
+        match opcode {
+            OP_ENTRY_POINT if operands.len() >= 3 => {
+                let name = operands[2..]
+                    .iter()
+                    .flat_map(|word| word.to_le_bytes().to_vec())
+                    .take_while(|&byte| byte != 0)
+                    .collect::<Vec<_>>();
+
+                if name == entry.as_bytes() {
+                    entry_id = Some(operands[1]);
+                }
+            }
+            OP_EXECUTION_MODE | OP_EXECUTION_MODE_ID
+                if operands.len() >= 5 && Some(operands[0]) == entry_id =>
+            {
+                let size = [operands[2], operands[3], operands[4]];
+
+                match (opcode, operands[1]) {
+                    (OP_EXECUTION_MODE, LOCAL_SIZE) => local_size = Some(size),
+                    (OP_EXECUTION_MODE_ID, LOCAL_SIZE_ID) => local_size_ids = Some(size),
+                    _ => (),
+                }
+            }
+            OP_DECORATE if operands.len() >= 3 => match operands[1] {
+                SPEC_ID => {
+                    spec_ids.insert(operands[0], operands[2]);
+                }
+                BUILT_IN if operands[2] == WORKGROUP_SIZE => workgroup_size_id = Some(operands[0]),
+                _ => (),
+            },
+            OP_CONSTANT if operands.len() >= 3 => {
+                constants.insert(operands[1], operands[2]);
+            }
+            OP_SPEC_CONSTANT if operands.len() >= 3 => {
+                let value = match (spec_ids.get(&operands[1]), specialization) {
+                    (Some(&constant_id), Some(specialization))
+                        if specialization.contains(constant_id) =>
+                    {
+                        specialization.u32_value(constant_id)
+                    }
+                    _ => Some(operands[2]),
+                };
+
+                // A value of another size is left unknown rather than replaced by the default one
+                if let Some(value) = value {
+                    constants.insert(operands[1], value);
+                }
+            }
+            OP_CONSTANT_COMPOSITE | OP_SPEC_CONSTANT_COMPOSITE if operands.len() >= 5 => {
+                composites.insert(operands[1], [operands[2], operands[3], operands[4]]);
+            }
+            _ => (),
+        }
+
+        words = &words[word_count..];
+    }
+
+    entry_id?;
+
+    let resolve = |ids: [u32; 3]| {
+        Some([
+            *constants.get(&ids[0])?,
+            *constants.get(&ids[1])?,
+            *constants.get(&ids[2])?,
+        ])
+    };
+
+    let local_size = match (workgroup_size_id, local_size_ids) {
+        (Some(id), _) => resolve(*composites.get(&id)?),
+        (None, Some(ids)) => resolve(ids),
+        (None, None) => local_size,
+    };
+
+    local_size.filter(|size| size.iter().all(|&len| len > 0))
+}
+
+/// Round a range of mapped memory to multiples of `atom_size`, as required to flush or invalidate it,
+/// the end of the range is clamped to `allocation_size` when it is known
+pub fn align_to_non_coherent_atom(
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    atom_size: vk::DeviceSize,
+    allocation_size: Option<vk::DeviceSize>,
+) -> (vk::DeviceSize, vk::DeviceSize) {
+    let start = offset / atom_size * atom_size;
+    let mut end = match (offset + size) % atom_size {
+        0 => offset + size,
+        remainder => offset + size + atom_size - remainder,
+    };
+
+    if let Some(allocation_size) = allocation_size {
+        end = end.min(allocation_size);
+    }
+
+    (start, end - start)
+}
+
 #[allow(dead_code, unused_variables)]
 pub fn image(image: vk::Image, format: vk::Format, device: &Device, instance: &Instance) {
     let format_properties = unsafe {
@@ -139,3 +303,160 @@ pub fn image(image: vk::Image, format: vk::Format, device: &Device, instance: &I
             )
     };
 }
+
+/// Create a device with one compute queue for the tests which need a GPU
+#[cfg(test)]
+pub fn create_test_device() -> (Rc<Instance>, Rc<Device>, Queue) {
+    create_test_device_with_extensions(&[])
+}
+
+/// Same as `create_test_device`, with the device `extensions` enabled
+#[cfg(test)]
+pub fn create_test_device_with_extensions(
+    extensions: &[&str],
+) -> (Rc<Instance>, Rc<Device>, Queue) {
+    let instance = Rc::new(
+        InstanceBuilder::new()
+            .headless()
+            .build()
+            .unwrap_or_else(|error| panic!("{}", error)),
+    );
+
+    // `VK_KHR_swapchain` needs the surface extensions, which a headless instance doesn't have
+    let (device, mut queues) = DeviceBuilder::new()
+        .extensions(extensions)
+        .build(
+            |queue_family, _| {
+                if queue_family.support_compute() {
+                    Some(QueueCreateInfo::new(vec![1.0]))
+                } else {
+                    None
+                }
+            },
+            Rc::clone(&instance),
+        )
+        .expect("failed to create device");
+
+    let queue = queues.swap_remove(0).swap_remove(0);
+
+    (instance, device, queue)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn local_size_of_entry_point() {
+        let shader_code = [
+            0x0723_0203,
+            0x0001_0000,
+            0,
+            8,
+            0,
+            // OpEntryPoint GLCompute %4 "main"
+            (5 << 16) | 15,
+            5,
+            4,
+            u32::from_le_bytes(*b"main"),
+            0,
+            // OpExecutionMode %4 LocalSize 64 2 1
+            (6 << 16) | 16,
+            4,
+            17,
+            64,
+            2,
+            1,
+        ];
+
+        assert_eq!(
+            spirv_local_size(&shader_code, "main", None),
+            Some([64, 2, 1])
+        );
+        assert_eq!(spirv_local_size(&shader_code, "other", None), None);
+    }
+
+    #[test]
+    fn specialized_local_size() {
+        let shader_code = [
+            0x0723_0203,
+            0x0001_0000,
+            0,
+            8,
+            0,
+            // OpEntryPoint GLCompute %4 "main"
+            (5 << 16) | 15,
+            5,
+            4,
+            u32::from_le_bytes(*b"main"),
+            0,
+            // OpExecutionMode %4 LocalSize 1 1 1
+            (6 << 16) | 16,
+            4,
+            17,
+            1,
+            1,
+            1,
+            // OpDecorate %5 SpecId 0
+            (4 << 16) | 71,
+            5,
+            1,
+            0,
+            // OpDecorate %7 BuiltIn WorkgroupSize
+            (4 << 16) | 71,
+            7,
+            11,
+            25,
+            // %5 = OpSpecConstant %uint 32
+            (4 << 16) | 50,
+            2,
+            5,
+            32,
+            // %6 = OpConstant %uint 1
+            (4 << 16) | 43,
+            2,
+            6,
+            1,
+            // %7 = OpSpecConstantComposite %v3uint %5 %6 %6
+            (6 << 16) | 51,
+            3,
+            7,
+            5,
+            6,
+            6,
+        ];
+
+        assert_eq!(
+            spirv_local_size(&shader_code, "main", None),
+            Some([32, 1, 1])
+        );
+
+        let specialization = SpecializationConstants::new().with(0, 128u32);
+        assert_eq!(
+            spirv_local_size(&shader_code, "main", Some(&specialization)),
+            Some([128, 1, 1])
+        );
+
+        for specialization in &[
+            SpecializationConstants::new().with(0, 0u32),
+            SpecializationConstants::new().with(0, 128u64),
+        ] {
+            assert_eq!(
+                spirv_local_size(&shader_code, "main", Some(specialization)),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn non_coherent_atom_rounding() {
+        assert_eq!(align_to_non_coherent_atom(0, 64, 64, None), (0, 64));
+        assert_eq!(align_to_non_coherent_atom(70, 10, 64, None), (64, 64));
+        assert_eq!(align_to_non_coherent_atom(100, 100, 64, None), (64, 192));
+        assert_eq!(
+            align_to_non_coherent_atom(100, 100, 64, Some(220)),
+            (64, 156)
+        );
+        assert_eq!(align_to_non_coherent_atom(3, 5, 1, Some(8)), (3, 5));
+    }
+}
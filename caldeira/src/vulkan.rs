@@ -1,30 +1,79 @@
+mod allocator;
 mod buffer;
 mod byte_copiable;
 mod command_pool;
+mod compute_context;
+mod compute_pass;
 mod compute_pipeline;
 #[cfg(feature = "validation-layers")]
 mod debug;
 mod descriptors;
 mod device;
+mod external_memory;
+mod graphics_pipeline;
 mod image;
 mod instance;
+mod pipeline_cache;
+mod push_constants;
+mod query;
 mod queue;
+mod render_pass;
+mod sampler;
+mod specialization;
+mod surface;
+mod swapchain;
+mod sync;
+mod tracking;
 mod window;
 
-pub use self::buffer::Buffer;
+pub use self::allocator::{Allocation, Allocator, DEFAULT_BLOCK_SIZE};
+pub use self::buffer::{
+    AtomicCounterBuffer, Buffer, BufferBuilder, DynamicUniformRing, MappedBuffer, OutOfBounds,
+    TypedBuffer, UnboundBuffer,
+};
 pub use self::byte_copiable::ByteCopiable;
 pub use self::command_pool::*;
 pub use self::command_pool::{
-    CommandBuffer, CommandBufferRecorder, CommandPool, ExecutableCommandBuffer,
+    CommandBuffer, CommandBufferRecorder, CommandPool, ExecutableCommandBuffer, ResetError,
 };
-pub use self::compute_pipeline::ComputePipeline;
+pub use self::compute_context::{ComputeContext, ContextError};
+pub use self::compute_pass::{reduce, reduction_pipeline, ComputePass, PingPong};
+pub use self::compute_pipeline::{ComputePipeline, ShaderSource};
 #[cfg(feature = "validation-layers")]
-pub use self::debug::Debug;
+pub use self::debug::{Debug, VERBOSITY_VAR};
 pub use self::descriptors::{
-    DescriptorPool, DescriptorPoolBuilder, DescriptorSetLayout, DescriptorSetLayoutBuilder,
+    BindingInfo, DescriptorAllocator, DescriptorPool, DescriptorPoolBuilder, DescriptorSet,
+    DescriptorSetLayout, DescriptorSetLayoutBuilder, DescriptorSetLayoutError, DescriptorSetWriter,
+    DescriptorWriteError,
+};
+pub use self::device::{Device, DeviceBuilder, DeviceError, DeviceLost, PhysicalDeviceInfo};
+pub use self::external_memory::{ExternalMemoryError, EXTERNAL_MEMORY_FD, EXTERNAL_MEMORY_WIN32};
+pub use self::graphics_pipeline::{GraphicsPipeline, GraphicsPipelineBuilder};
+pub use self::image::{
+    ConvertError, Image, ImageBuilder, LayoutTransition, LayoutTransitionError, TextureError,
+    UnboundImage,
+};
+#[cfg(feature = "validation-layers")]
+pub use self::instance::ValidationFeature;
+pub use self::instance::{Instance, InstanceBuilder, InstanceError};
+pub use self::pipeline_cache::PipelineCache;
+pub use self::push_constants::{
+    PushConstant, PushConstantError, PushConstantLayout, PushConstantLayoutBuilder,
+};
+pub use self::query::{PipelineStatistics, QueryPool, QueryResult, Timestamps};
+pub use self::queue::{
+    MultiSubmitHandle, Queue, QueueCapabilities, QueueCreateInfo, QueueFamily, QueueGroup,
+    SubmitError, SubmitHandle, TransitionError,
+};
+pub use self::render_pass::{Framebuffer, RenderPass, RenderPassBuilder};
+pub use self::sampler::{Sampler, SamplerBuilder};
+pub use self::specialization::SpecializationConstants;
+pub use self::surface::{
+    clamp_image_count, surface_extensions, Surface, SurfaceComposition, SurfaceCompositionError,
+};
+pub use self::swapchain::{Swapchain, SwapchainError};
+pub use self::sync::{
+    Event, ExternalSemaphoreError, Fence, FencePool, FrameSync, Semaphore, EXTERNAL_SEMAPHORE_FD,
 };
-pub use self::device::Device;
-pub use self::image::Image;
-pub use self::instance::Instance;
-pub use self::queue::{Queue, QueueCreateInfo, QueueFamily};
+pub use self::tracking::TrackedCommandBuffer;
 pub use self::window::Window;
@@ -0,0 +1,420 @@
+use std::cell::RefCell;
+use std::ops::Range;
+use std::ptr;
+use std::rc::Rc;
+
+use ash::version::{DeviceV1_0, InstanceV1_0};
+use ash::vk;
+
+use super::Device;
+use crate::utils;
+
+/// Size of the blocks allocated by `Allocator::new`
+pub const DEFAULT_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// Sub-allocator of device memory, which allocates large blocks for each memory type and places the resources
+/// in them, to stay below the `max_memory_allocation_count` limit and share the alignment waste
+/// Host visible blocks stay mapped, so that resources of the same block can be mapped at the same time
+pub struct Allocator {
+    inner: Rc<RefCell<AllocatorInner>>,
+}
+
+impl Allocator {
+    pub fn new(device: Rc<Device>) -> Self {
+        Self::with_block_size(DEFAULT_BLOCK_SIZE, device)
+    }
+
+    /// Allocate blocks of `block_size` bytes, the resources larger than that get a block of their own
+    pub fn with_block_size(block_size: vk::DeviceSize, device: Rc<Device>) -> Self {
+        let memory_properties = unsafe {
+            device
+                .instance()
+                .instance
+                .get_physical_device_memory_properties(device.physical_device)
+        };
+
+        Self {
+            inner: Rc::new(RefCell::new(AllocatorInner {
+                blocks: vec![],
+                block_size,
+                memory_properties,
+                device,
+            })),
+        }
+    }
+
+    /// Allocate memory satisfying `requirements` with `properties`, in a block of a new one
+    /// `linear` tells whether the memory is for a buffer or a linear image, which are kept apart from optimal images
+    /// to respect the `buffer_image_granularity` limit
+    pub fn allocate(
+        &mut self,
+        requirements: vk::MemoryRequirements,
+        properties: vk::MemoryPropertyFlags,
+        linear: bool,
+    ) -> Allocation {
+        let mut inner = self.inner.borrow_mut();
+
+        let memory_type_index = utils::find_memory_type(
+            requirements.memory_type_bits,
+            properties,
+            &inner.device,
+            inner.device.instance(),
+        );
+        let property_flags =
+            inner.memory_properties.memory_types[memory_type_index as usize].property_flags;
+
+        // Flushes and invalidations of non coherent memory cover whole atoms, which mustn't be shared
+        let (alignment, size) = if property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+            && !property_flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT)
+        {
+            let atom_size = inner.device.non_coherent_atom_size();
+            (
+                requirements.alignment.max(atom_size),
+                align_up(requirements.size, atom_size),
+            )
+        } else {
+            (requirements.alignment, requirements.size)
+        };
+
+        let found = inner
+            .blocks
+            .iter_mut()
+            .enumerate()
+            .find_map(|(index, block)| {
+                if block.memory_type_index == memory_type_index && block.linear == linear {
+                    block
+                        .free_list
+                        .allocate(size, alignment)
+                        .map(|offset| (index, offset))
+                } else {
+                    None
+                }
+            });
+
+        let (index, offset) = match found {
+            Some(found) => found,
+            None => {
+                let block_size = inner.block_size.max(size);
+                let mut block =
+                    inner.allocate_block(memory_type_index, property_flags, block_size, linear);
+                let offset = block
+                    .free_list
+                    .allocate(size, alignment)
+                    .expect("a new block is large enough for the allocation");
+
+                inner.blocks.push(block);
+
+                (inner.blocks.len() - 1, offset)
+            }
+        };
+
+        let block = &mut inner.blocks[index];
+        block.allocation_count += 1;
+
+        let mapped = if block.mapped.is_null() {
+            ptr::null_mut()
+        } else {
+            unsafe { block.mapped.add(offset as usize) }
+        };
+
+        Allocation {
+            memory: block.memory,
+            offset,
+            size,
+            block_size: block.free_list.size,
+            property_flags,
+            mapped,
+            allocator: Rc::clone(&self.inner),
+        }
+    }
+
+    /// Number of device memory allocations made by the allocator
+    pub fn block_count(&self) -> usize {
+        self.inner.borrow().blocks.len()
+    }
+
+    pub(crate) fn device(&self) -> Rc<Device> {
+        Rc::clone(&self.inner.borrow().device)
+    }
+}
+
+/// A range of a memory block, given back to its `Allocator` when dropped
+/// The resource bound to it must be destroyed before
+pub struct Allocation {
+    pub memory: vk::DeviceMemory,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    block_size: vk::DeviceSize,
+    property_flags: vk::MemoryPropertyFlags,
+    mapped: *mut u8,
+    allocator: Rc<RefCell<AllocatorInner>>,
+}
+
+impl Allocation {
+    /// Properties of the memory type of the block, which may have more than the requested ones
+    pub fn memory_properties(&self) -> vk::MemoryPropertyFlags {
+        self.property_flags
+    }
+
+    /// Pointer to the start of the allocation if the memory is host visible, valid as long as the allocation
+    pub fn mapped_ptr(&self) -> Option<*mut u8> {
+        if self.mapped.is_null() {
+            None
+        } else {
+            Some(self.mapped)
+        }
+    }
+
+    /// Size of the whole block, to which the ranges to flush are clamped
+    pub(crate) fn block_size(&self) -> vk::DeviceSize {
+        self.block_size
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        self.allocator
+            .borrow_mut()
+            .free(self.memory, self.offset, self.size);
+    }
+}
+
+struct AllocatorInner {
+    blocks: Vec<MemoryBlock>,
+    block_size: vk::DeviceSize,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    device: Rc<Device>,
+}
+
+impl AllocatorInner {
+    fn allocate_block(
+        &self,
+        memory_type_index: u32,
+        property_flags: vk::MemoryPropertyFlags,
+        size: vk::DeviceSize,
+        linear: bool,
+    ) -> MemoryBlock {
+        let alloc_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(size)
+            .memory_type_index(memory_type_index);
+
+        let memory = unsafe { self.device.device.allocate_memory(&alloc_info, None) }
+            .expect("failed to allocate memory block!");
+
+        let mapped = if property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            unsafe {
+                self.device.device.map_memory(
+                    memory,
+                    0,
+                    vk::WHOLE_SIZE,
+                    vk::MemoryMapFlags::empty(),
+                )
+            }
+            .expect("failed to map memory block!")
+            .cast()
+        } else {
+            ptr::null_mut()
+        };
+
+        MemoryBlock {
+            memory,
+            memory_type_index,
+            linear,
+            mapped,
+            free_list: FreeList::new(size),
+            allocation_count: 0,
+        }
+    }
+
+    fn free(&mut self, memory: vk::DeviceMemory, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let index = self
+            .blocks
+            .iter()
+            .position(|block| block.memory == memory)
+            .expect("the allocation doesn't come from this allocator");
+
+        let block = &mut self.blocks[index];
+        block.free_list.free(offset, size);
+        block.allocation_count -= 1;
+
+        // Blocks larger than the others were made for a single resource and aren't worth keeping
+        if block.allocation_count == 0 && block.free_list.size > self.block_size {
+            let block = self.blocks.swap_remove(index);
+            self.free_block(block);
+        }
+    }
+
+    fn free_block(&self, block: MemoryBlock) {
+        unsafe {
+            if !block.mapped.is_null() {
+                self.device.device.unmap_memory(block.memory);
+            }
+            self.device.device.free_memory(block.memory, None);
+        }
+    }
+}
+
+impl Drop for AllocatorInner {
+    fn drop(&mut self) {
+        for block in std::mem::take(&mut self.blocks) {
+            self.free_block(block);
+        }
+    }
+}
+
+struct MemoryBlock {
+    memory: vk::DeviceMemory,
+    memory_type_index: u32,
+    linear: bool,
+    /// Start of the mapping of a host visible block, null otherwise
+    mapped: *mut u8,
+    free_list: FreeList,
+    allocation_count: usize,
+}
+
+/// Free ranges of a block of `size` bytes, sorted and never adjacent
+struct FreeList {
+    size: vk::DeviceSize,
+    ranges: Vec<Range<vk::DeviceSize>>,
+}
+
+impl FreeList {
+    fn new(size: vk::DeviceSize) -> Self {
+        Self {
+            size,
+            ranges: vec![Range {
+                start: 0,
+                end: size,
+            }],
+        }
+    }
+
+    /// Offset of `size` bytes aligned to `alignment` in the first free range large enough
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let (index, offset) = self.ranges.iter().enumerate().find_map(|(index, range)| {
+            let offset = align_up(range.start, alignment);
+
+            if offset + size <= range.end {
+                Some((index, offset))
+            } else {
+                None
+            }
+        })?;
+
+        let range = self.ranges.remove(index);
+
+        // The alignment padding and the end of the range stay free
+        let mut remaining = vec![];
+        if range.start < offset {
+            remaining.push(range.start..offset);
+        }
+        if offset + size < range.end {
+            remaining.push(offset + size..range.end);
+        }
+        self.ranges.splice(index..index, remaining);
+
+        Some(offset)
+    }
+
+    /// Give back the range of `size` bytes at `offset`, merging it with the free ranges around
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let index = self
+            .ranges
+            .iter()
+            .position(|range| range.start > offset)
+            .unwrap_or(self.ranges.len());
+
+        let mut freed = offset..offset + size;
+
+        if index < self.ranges.len() && self.ranges[index].start == freed.end {
+            freed.end = self.ranges.remove(index).end;
+        }
+
+        if index > 0 && self.ranges[index - 1].end == freed.start {
+            self.ranges[index - 1].end = freed.end;
+        } else {
+            self.ranges.insert(index, freed);
+        }
+    }
+}
+
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    match value % alignment {
+        0 => value,
+        remainder => value + alignment - remainder,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vulkan::Buffer;
+
+    #[test]
+    fn free_ranges_are_coalesced() {
+        let mut free_list = FreeList::new(1024);
+
+        assert_eq!(free_list.allocate(100, 1), Some(0));
+        // The padding between 100 and 256 stays free
+        assert_eq!(free_list.allocate(256, 256), Some(256));
+        assert_eq!(free_list.allocate(50, 4), Some(100));
+        assert_eq!(free_list.ranges, vec![150..256, 512..1024]);
+        assert_eq!(free_list.allocate(1024, 1), None);
+
+        free_list.free(0, 100);
+        free_list.free(256, 256);
+        assert_eq!(free_list.ranges, vec![0..100, 150..1024]);
+
+        free_list.free(100, 50);
+        assert_eq!(free_list.ranges, vec![0..1024]);
+        assert_eq!(free_list.allocate(1024, 1), Some(0));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn buffers_share_a_block() {
+        let (_instance, device, _queue) = crate::utils::create_test_device();
+        let mut allocator = Allocator::new(device);
+
+        let properties =
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let mut first = Buffer::new_in(
+            16,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            properties,
+            None,
+            &mut allocator,
+        );
+        let mut second = Buffer::new_in(
+            16,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            properties,
+            None,
+            &mut allocator,
+        );
+
+        assert_eq!(allocator.block_count(), 1);
+        assert_eq!(first.memory, second.memory);
+
+        // Both buffers are mapped at once through the mapping of the block
+        let mut first_mapped = first.map();
+        let mut second_mapped = second.map();
+        first_mapped
+            .as_mut_slice::<u32>()
+            .copy_from_slice(&[1, 2, 3, 4]);
+        second_mapped
+            .as_mut_slice::<u32>()
+            .copy_from_slice(&[5, 6, 7, 8]);
+        drop((first_mapped, second_mapped));
+
+        let mut data = [0u32; 4];
+        first.get_data(&mut data[..], 0).unwrap();
+        assert_eq!(data, [1, 2, 3, 4]);
+        second.get_data(&mut data[..], 0).unwrap();
+        assert_eq!(data, [5, 6, 7, 8]);
+    }
+}
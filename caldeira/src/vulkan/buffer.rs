@@ -1,120 +1,1397 @@
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
 use std::mem;
+use std::ops::Range;
 use std::ptr;
 use std::rc::Rc;
+use std::slice;
 
 use ash::version::DeviceV1_0;
 use ash::vk;
 
-use super::{ByteCopiable, Device, Instance};
+use super::external_memory::{self, ExternalMemoryError};
+#[cfg(feature = "validation-layers")]
+use super::Debug;
+use super::{
+    Allocation, Allocator, ByteCopiable, CommandPool, Device, DeviceLost, Instance, Queue,
+};
 use crate::utils;
 
+/// Access to bytes past the end of a buffer, by `Buffer::copy_data` or `Buffer::get_data`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OutOfBounds {
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    pub buffer_size: vk::DeviceSize,
+}
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Access of {} bytes at offset {} out of a buffer of {} bytes!",
+            self.size, self.offset, self.buffer_size
+        )
+    }
+}
+
+impl Error for OutOfBounds {}
+
 pub struct Buffer {
     pub handle: vk::Buffer,
     pub usage: vk::BufferUsageFlags,
     pub memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    memory_offset: vk::DeviceSize,
+    /// Size of the allocation, or of the block for memory from an `Allocator`, known only if the memory is owned
+    allocation_size: Option<vk::DeviceSize>,
+    /// Properties of the memory, known only if the memory is owned
+    memory_properties: Option<vk::MemoryPropertyFlags>,
+    host_coherent: bool,
+    export_handle_types: vk::ExternalMemoryHandleTypeFlags,
+    memory_owned: bool,
+    /// Range of an `Allocator` block the buffer is bound to, given back after the buffer is destroyed
+    allocation: Option<Allocation>,
     device: Rc<Device>,
 }
 
 impl Buffer {
+    /// `name` is given to the buffer for debugging when the `validation-layers` feature is enabled
     pub fn new(
         size: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
         properties: vk::MemoryPropertyFlags,
+        name: Option<&str>,
         device: Rc<Device>,
         instance: &Instance,
     ) -> Self {
-        let (handle, memory) = Self::create_buffer(size, usage, properties, &device, instance);
+        let unbound = Self::create_unbound(size, usage, device);
 
-        Self {
-            handle,
-            usage,
-            memory,
-            device,
+        if let Some(name) = name {
+            unbound.device.set_object_name(unbound.handle, name);
         }
+
+        Self::allocate_and_bind(
+            unbound,
+            properties,
+            vk::ExternalMemoryHandleTypeFlags::empty(),
+            instance,
+        )
     }
 
-    fn create_buffer(
+    /// Create a buffer bound to memory sub-allocated by `allocator`, whose device is used
+    pub fn new_in(
         size: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
         properties: vk::MemoryPropertyFlags,
-        device: &Device,
+        name: Option<&str>,
+        allocator: &mut Allocator,
+    ) -> Self {
+        let unbound = Self::create_unbound(size, usage, allocator.device());
+
+        if let Some(name) = name {
+            unbound.device.set_object_name(unbound.handle, name);
+        }
+
+        let allocation = allocator.allocate(unbound.requirements, properties, true);
+
+        let mut buffer = unbound.bind_memory(allocation.memory, allocation.offset, false);
+        buffer.allocation_size = Some(allocation.block_size());
+        buffer.memory_properties = Some(allocation.memory_properties());
+        buffer.host_coherent = allocation
+            .memory_properties()
+            .contains(vk::MemoryPropertyFlags::HOST_COHERENT);
+        buffer.allocation = Some(allocation);
+
+        buffer
+    }
+
+    /// Create a buffer whose memory can be exported as `handle_type` to be shared with another API
+    /// The device extension of the handle type must be enabled, `EXTERNAL_MEMORY_FD` for `OPAQUE_FD`
+    /// and `EXTERNAL_MEMORY_WIN32` for `OPAQUE_WIN32`
+    pub fn new_exportable(
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        properties: vk::MemoryPropertyFlags,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+        device: Rc<Device>,
         instance: &Instance,
-    ) -> (vk::Buffer, vk::DeviceMemory) {
-        let buffer_info = vk::BufferCreateInfo::builder()
-            .size(size)
-            .usage(usage)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    ) -> Result<Self, ExternalMemoryError> {
+        external_memory::check_handle_type(handle_type, &device)?;
 
-        let buffer = unsafe { device.device.create_buffer(&buffer_info, None) }
-            .expect("failed to allocate command buffers!");
+        let unbound = Self::create_unbound_with_handle_types(size, usage, handle_type, device);
 
-        let mem_requirements = unsafe { device.device.get_buffer_memory_requirements(buffer) };
+        Ok(Self::allocate_and_bind(
+            unbound,
+            properties,
+            handle_type,
+            instance,
+        ))
+    }
 
-        let alloc_info = vk::MemoryAllocateInfo::builder()
-            .allocation_size(mem_requirements.size)
+    fn allocate_and_bind(
+        unbound: UnboundBuffer,
+        properties: vk::MemoryPropertyFlags,
+        export_handle_types: vk::ExternalMemoryHandleTypeFlags,
+        instance: &Instance,
+    ) -> Self {
+        let mut export_info =
+            vk::ExportMemoryAllocateInfo::builder().handle_types(export_handle_types);
+
+        let mut alloc_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(unbound.requirements.size)
             .memory_type_index(utils::find_memory_type(
-                mem_requirements.memory_type_bits,
+                unbound.requirements.memory_type_bits,
                 properties,
-                device,
+                &unbound.device,
                 instance,
             ));
 
-        let memory = unsafe { device.device.allocate_memory(&alloc_info, None) }
+        if !export_handle_types.is_empty() {
+            alloc_info = alloc_info.push_next(&mut export_info);
+        }
+
+        let memory = unsafe { unbound.device.device.allocate_memory(&alloc_info, None) }
             .expect("failed to allocate buffer memory!");
 
+        let mut buffer = unbound.bind_memory(memory, 0, true);
+        buffer.allocation_size = Some(alloc_info.allocation_size);
+        buffer.memory_properties = Some(properties);
+        buffer.host_coherent = properties.contains(vk::MemoryPropertyFlags::HOST_COHERENT);
+        buffer.export_handle_types = export_handle_types;
+
+        buffer
+    }
+
+    /// Create a device local buffer filled with `data` through a staging buffer
+    /// If `transfer` holds a queue of a transfer-only family, the copy is done on it and the ownership
+    /// of the buffer is then given back to the family of `queue`, otherwise the copy is done on `queue`
+    pub fn new_device_local_with_data<T: ?Sized + ByteCopiable>(
+        data: &T,
+        usage: vk::BufferUsageFlags,
+        queue: &mut Queue,
+        command_pool: &mut Rc<CommandPool>,
+        transfer: Option<(&mut Queue, &mut Rc<CommandPool>)>,
+        device: Rc<Device>,
+        instance: &Instance,
+    ) -> Result<Self, DeviceLost> {
+        let size = mem::size_of_val(data) as vk::DeviceSize;
+
+        let mut staging_buffer = Self::new(
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            None,
+            Rc::clone(&device),
+            instance,
+        );
+        staging_buffer
+            .copy_data(data, 0)
+            .expect("the staging buffer has the size of the data");
+
+        let buffer = Self::new(
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            None,
+            device,
+            instance,
+        );
+
+        let regions = [vk::BufferCopy::builder().size(size).build()];
+
+        match transfer {
+            Some((transfer_queue, transfer_command_pool))
+                if transfer_queue.family().support_transfer_only()
+                    && transfer_queue.queue_family_index() != queue.queue_family_index() =>
+            {
+                let ownership_barrier = vk::BufferMemoryBarrier::builder()
+                    .src_queue_family_index(transfer_queue.queue_family_index() as _)
+                    .dst_queue_family_index(queue.queue_family_index() as _)
+                    .buffer(buffer.handle)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)
+                    .build();
+
+                let release_barriers = [vk::BufferMemoryBarrier {
+                    src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                    ..ownership_barrier
+                }];
+
+                transfer_command_pool.single_time(transfer_queue, |recorder| {
+                    recorder
+                        .as_transfer_command_buffer()
+                        .unwrap()
+                        .as_copy()
+                        .copy_buffer(&staging_buffer, &buffer, &regions)
+                        .unwrap();
+
+                    recorder.as_generic().pipeline_barrier(
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &release_barriers,
+                        &[],
+                    );
+                })?;
+
+                let acquire_barriers = [vk::BufferMemoryBarrier {
+                    dst_access_mask: vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+                    ..ownership_barrier
+                }];
+
+                command_pool.single_time(queue, |recorder| {
+                    recorder.as_generic().pipeline_barrier(
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                        vk::PipelineStageFlags::ALL_COMMANDS,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &acquire_barriers,
+                        &[],
+                    );
+                })?;
+            }
+            _ => {
+                let memory_barriers = [vk::MemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE)
+                    .build()];
+
+                command_pool.single_time(queue, |recorder| {
+                    recorder
+                        .as_transfer_command_buffer()
+                        .unwrap()
+                        .as_copy()
+                        .copy_buffer(&staging_buffer, &buffer, &regions)
+                        .unwrap();
+
+                    recorder.as_generic().pipeline_barrier(
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::PipelineStageFlags::ALL_COMMANDS,
+                        vk::DependencyFlags::empty(),
+                        &memory_barriers,
+                        &[],
+                        &[],
+                    );
+                })?;
+            }
+        }
+
+        Ok(buffer)
+    }
+
+    /// Create a buffer without memory, the memory has to be bound with `UnboundBuffer::bind`
+    pub fn create_unbound(
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        device: Rc<Device>,
+    ) -> UnboundBuffer {
+        Self::create_unbound_with_handle_types(
+            size,
+            usage,
+            vk::ExternalMemoryHandleTypeFlags::empty(),
+            device,
+        )
+    }
+
+    fn create_unbound_with_handle_types(
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        handle_types: vk::ExternalMemoryHandleTypeFlags,
+        device: Rc<Device>,
+    ) -> UnboundBuffer {
+        let mut external_info =
+            vk::ExternalMemoryBufferCreateInfo::builder().handle_types(handle_types);
+
+        let mut buffer_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        if !handle_types.is_empty() {
+            buffer_info = buffer_info.push_next(&mut external_info);
+        }
+
+        let handle = unsafe { device.device.create_buffer(&buffer_info, None) }
+            .expect("failed to create buffer!");
+
+        let requirements = unsafe { device.device.get_buffer_memory_requirements(handle) };
+
+        UnboundBuffer {
+            handle,
+            usage,
+            size,
+            requirements,
+            device,
+        }
+    }
+
+    /// Size, alignment and memory type bits the memory bound to this buffer must satisfy
+    pub fn memory_requirements(&self) -> vk::MemoryRequirements {
         unsafe {
-            device.device.bind_buffer_memory(buffer, memory, 0).unwrap();
+            self.device
+                .device
+                .get_buffer_memory_requirements(self.handle)
         }
+    }
 
-        (buffer, memory)
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
     }
 
-    pub fn copy_data<T: ?Sized + ByteCopiable>(&mut self, data: &T, offset: usize) {
+    /// Name the buffer in the validation messages
+    #[cfg(feature = "validation-layers")]
+    pub fn set_name(&self, debug: &Debug, name: &str) {
+        debug.set_object_name(&self.device, self.handle, name);
+    }
+
+    /// Export the memory of a buffer created by `new_exportable` with `OPAQUE_FD` as a file descriptor,
+    /// which is owned by the caller
+    #[cfg(unix)]
+    pub fn export_fd(&self) -> Result<i32, ExternalMemoryError> {
+        external_memory::export_fd(self.memory, self.export_handle_types, &self.device)
+    }
+
+    /// Export the memory of a buffer created by `new_exportable` with `OPAQUE_WIN32` as a handle,
+    /// which is owned by the caller
+    #[cfg(windows)]
+    pub fn export_win32_handle(&self) -> Result<vk::HANDLE, ExternalMemoryError> {
+        external_memory::export_win32_handle(self.memory, self.export_handle_types, &self.device)
+    }
+
+    /// Reallocate the buffer with `new_size` bytes and the same usage and memory properties, then copy
+    /// the old contents at its start
+    /// The buffer must own its memory and have both `TRANSFER_SRC` and `TRANSFER_DST` usages
+    /// The handle changes, so descriptor sets referencing the old one are stale and must be written again
+    pub fn grow(
+        &mut self,
+        new_size: vk::DeviceSize,
+        command_pool: &mut Rc<CommandPool>,
+        queue: &mut Queue,
+        instance: &Instance,
+    ) -> Result<(), DeviceLost> {
+        assert!(new_size >= self.size, "a buffer can't shrink by growing");
+        assert!(
+            self.usage
+                .contains(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST),
+            "a buffer needs TRANSFER_SRC and TRANSFER_DST usages to grow"
+        );
+
+        let properties = self
+            .memory_properties
+            .expect("only buffers owning their memory can grow");
+
+        let mut buffer = Self::new(
+            new_size,
+            self.usage,
+            properties,
+            None,
+            Rc::clone(&self.device),
+            instance,
+        );
+
+        let regions = [vk::BufferCopy::builder().size(self.size).build()];
+
+        let memory_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE)
+            .build()];
+
+        command_pool.single_time(queue, |recorder| {
+            recorder
+                .as_transfer_command_buffer()
+                .unwrap()
+                .as_copy()
+                .copy_buffer(self, &buffer, &regions)
+                .unwrap();
+
+            recorder.as_generic().pipeline_barrier(
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &memory_barriers,
+                &[],
+                &[],
+            );
+        })?;
+
+        // The old buffer is destroyed with `buffer`, the copy being already complete
+        mem::swap(self, &mut buffer);
+
+        Ok(())
+    }
+
+    /// Write `data` at `offset` bytes in the buffer, which fails if it doesn't fit in the buffer
+    pub fn copy_data<T: ?Sized + ByteCopiable>(
+        &mut self,
+        data: &T,
+        offset: usize,
+    ) -> Result<(), OutOfBounds> {
         let size = mem::size_of_val(data);
         let src = data as *const _ as *const u8;
+        let offset = self.memory_offset + self.check_bounds(offset, size)?;
 
         unsafe {
-            let ptr = self
-                .device
-                .device
-                .map_memory(
-                    self.memory,
-                    offset as _,
-                    size as _,
-                    vk::MemoryMapFlags::empty(),
-                )
-                .unwrap();
-            ptr::copy_nonoverlapping(src, ptr.cast(), size);
-            self.device.device.unmap_memory(self.memory);
+            let ptr = self.map_memory(offset, size as _);
+            ptr::copy_nonoverlapping(src, ptr, size);
+
+            if !self.host_coherent {
+                let range = self.mapped_range(offset, size as _);
+                self.device
+                    .device
+                    .flush_mapped_memory_ranges(&[range])
+                    .expect("failed to flush buffer memory!");
+            }
+
+            self.unmap_memory();
         }
+
+        Ok(())
     }
 
-    pub fn get_data<T: ?Sized + ByteCopiable>(&self, data: &mut T, offset: usize) {
+    /// Fill `data` with the bytes at `offset` in the buffer, which fails if `data` is larger than what's left
+    pub fn get_data<T: ?Sized + ByteCopiable>(
+        &self,
+        data: &mut T,
+        offset: usize,
+    ) -> Result<(), OutOfBounds> {
         let dst = data as *mut _ as *mut u8;
         let size = mem::size_of_val(data);
+        let offset = self.memory_offset + self.check_bounds(offset, size)?;
+
+        unsafe {
+            let src = self.map_memory(offset, size as _);
+
+            if !self.host_coherent {
+                let range = self.mapped_range(offset, size as _);
+                self.device
+                    .device
+                    .invalidate_mapped_memory_ranges(&[range])
+                    .expect("failed to invalidate buffer memory!");
+            }
+
+            ptr::copy_nonoverlapping(src, dst, size);
+            self.unmap_memory();
+        }
+
+        Ok(())
+    }
+
+    /// `offset` if the `size` bytes from it are in the buffer
+    fn check_bounds(&self, offset: usize, size: usize) -> Result<vk::DeviceSize, OutOfBounds> {
+        let (offset, size) = (offset as vk::DeviceSize, size as vk::DeviceSize);
+
+        match offset.checked_add(size) {
+            Some(end) if end <= self.size => Ok(offset),
+            _ => Err(OutOfBounds {
+                offset,
+                size,
+                buffer_size: self.size,
+            }),
+        }
+    }
+
+    /// Map the whole buffer until the returned guard is dropped, to access it repeatedly without mapping it each time
+    /// Its memory must be host visible and not mapped elsewhere, unless it comes from an `Allocator`
+    pub fn map(&mut self) -> MappedBuffer<'_> {
+        let ptr = unsafe { self.map_memory(self.memory_offset, self.size) };
+
+        MappedBuffer { buffer: self, ptr }
+    }
+
+    /// Pointer to the `size` bytes at `offset` in the memory, through the persistent mapping of the block
+    /// for memory from an `Allocator`
+    /// Otherwise the range returned by `mapped_range` is mapped, so that it can be flushed and invalidated
+    unsafe fn map_memory(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> *mut u8 {
+        match &self.allocation {
+            Some(allocation) => allocation
+                .mapped_ptr()
+                .expect("the buffer memory isn't host visible")
+                .add((offset - allocation.offset) as usize),
+            None => {
+                let range = self.mapped_range(offset, size);
 
+                self.device
+                    .device
+                    .map_memory(
+                        self.memory,
+                        range.offset,
+                        range.size,
+                        vk::MemoryMapFlags::empty(),
+                    )
+                    .expect("failed to map buffer memory!")
+                    .cast::<u8>()
+                    .add((offset - range.offset) as usize)
+            }
+        }
+    }
+
+    unsafe fn unmap_memory(&self) {
+        if self.allocation.is_none() {
+            self.device.device.unmap_memory(self.memory);
+        }
+    }
+
+    /// Range of memory to flush or invalidate, aligned to the non coherent atom size of the device
+    /// The size of memory bound with `UnboundBuffer::bind` is unknown, so the range runs to its end
+    /// rather than rounding past it
+    fn mapped_range(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> vk::MappedMemoryRange {
+        let (offset, size) = utils::align_to_non_coherent_atom(
+            offset,
+            size,
+            self.device.non_coherent_atom_size(),
+            self.allocation_size,
+        );
+        let size = match self.allocation_size {
+            Some(_) => size,
+            None => vk::WHOLE_SIZE,
+        };
+
+        vk::MappedMemoryRange::builder()
+            .memory(self.memory)
+            .offset(offset)
+            .size(size)
+            .build()
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
         unsafe {
-            let src = self
+            self.device.device.destroy_buffer(self.handle, None);
+            if self.memory_owned {
+                self.device.device.free_memory(self.memory, None);
+            }
+        }
+    }
+}
+
+/// Builder of a `Buffer` of `count` elements of `element_size` bytes, by default a single one
+/// The presets add the usage of a kind of buffer, along with the transfers it's usually used with,
+/// and choose its memory properties
+pub struct BufferBuilder<'a> {
+    element_size: vk::DeviceSize,
+    count: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+    properties: vk::MemoryPropertyFlags,
+    name: Option<&'a str>,
+}
+
+impl<'a> BufferBuilder<'a> {
+    /// The size and the usage must be set before building, the memory is device local by default
+    pub fn new() -> Self {
+        Self {
+            element_size: 0,
+            count: 1,
+            usage: vk::BufferUsageFlags::empty(),
+            properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            name: None,
+        }
+    }
+
+    /// Size of an element in bytes
+    pub fn size(mut self, size: vk::DeviceSize) -> Self {
+        self.element_size = size;
+        self
+    }
+
+    /// Elements of type `T`
+    pub fn size_of<T>(self) -> Self {
+        self.size(mem::size_of::<T>() as _)
+    }
+
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count as _;
+        self
+    }
+
+    /// Add `usage` to the usage of the buffer
+    pub fn usage(mut self, usage: vk::BufferUsageFlags) -> Self {
+        self.usage |= usage;
+        self
+    }
+
+    pub fn memory_properties(mut self, properties: vk::MemoryPropertyFlags) -> Self {
+        self.properties = properties;
+        self
+    }
+
+    /// Buffer written by the host to be copied to the device or the other way around
+    pub fn staging(self) -> Self {
+        self.usage(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST)
+            .memory_properties(
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+    }
+
+    /// Storage buffer in device local memory, filled and read back through staging buffers
+    pub fn storage(self) -> Self {
+        self.usage(
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_SRC
+                | vk::BufferUsageFlags::TRANSFER_DST,
+        )
+        .memory_properties(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+    }
+
+    /// Uniform buffer in host visible memory, to be updated by the host
+    pub fn uniform(self) -> Self {
+        self.usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
+            .memory_properties(
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+    }
+
+    /// Vertex buffer in device local memory, filled through a staging buffer
+    pub fn vertex(self) -> Self {
+        self.usage(vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
+            .memory_properties(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+    }
+
+    /// Index buffer in device local memory, filled through a staging buffer
+    pub fn index(self) -> Self {
+        self.usage(vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
+            .memory_properties(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+    }
+
+    /// Name given to the buffer for debugging when the `validation-layers` feature is enabled
+    pub fn name(mut self, name: &'a str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn build(&self, device: Rc<Device>, instance: &Instance) -> Buffer {
+        Buffer::new(
+            self.buffer_size(),
+            self.checked_usage(),
+            self.properties,
+            self.name,
+            device,
+            instance,
+        )
+    }
+
+    /// Create the buffer in memory sub-allocated by `allocator`
+    pub fn build_in(&self, allocator: &mut Allocator) -> Buffer {
+        Buffer::new_in(
+            self.buffer_size(),
+            self.checked_usage(),
+            self.properties,
+            self.name,
+            allocator,
+        )
+    }
+
+    fn buffer_size(&self) -> vk::DeviceSize {
+        let size = self.element_size * self.count;
+        assert_ne!(size, 0, "a buffer can't be empty");
+
+        size
+    }
+
+    fn checked_usage(&self) -> vk::BufferUsageFlags {
+        assert!(!self.usage.is_empty(), "a buffer needs a usage");
+
+        self.usage
+    }
+}
+
+impl<'a> Default for BufferBuilder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A mapped buffer, unmapped when dropped
+/// Writes to non coherent memory must be flushed before the device reads them, and the memory must be
+/// invalidated before reading what the device wrote
+pub struct MappedBuffer<'a> {
+    buffer: &'a mut Buffer,
+    ptr: *mut u8,
+}
+
+impl<'a> MappedBuffer<'a> {
+    /// The buffer as an array of as many `T` as it can hold
+    pub fn as_slice<T: ByteCopiable>(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr.cast(), self.len::<T>()) }
+    }
+
+    pub fn as_mut_slice<T: ByteCopiable>(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.cast(), self.len::<T>()) }
+    }
+
+    fn len<T>(&self) -> usize {
+        assert_eq!(
+            self.ptr as usize % mem::align_of::<T>(),
+            0,
+            "the mapped memory isn't aligned for this type"
+        );
+
+        self.buffer.size as usize / mem::size_of::<T>()
+    }
+
+    /// Make the host writes to the bytes `range` of the buffer available to the device, nothing is done
+    /// for coherent memory
+    pub fn flush(&self, range: Range<vk::DeviceSize>) {
+        if self.buffer.host_coherent {
+            return;
+        }
+
+        let range = self.mapped_range(range);
+
+        unsafe {
+            self.buffer
                 .device
                 .device
-                .map_memory(
-                    self.memory,
-                    offset as _,
-                    size as _,
-                    vk::MemoryMapFlags::empty(),
-                )
+                .flush_mapped_memory_ranges(&[range])
+        }
+        .expect("failed to flush buffer memory!");
+    }
+
+    /// Make the device writes to the bytes `range` of the buffer visible to the host, nothing is done
+    /// for coherent memory
+    pub fn invalidate(&self, range: Range<vk::DeviceSize>) {
+        if self.buffer.host_coherent {
+            return;
+        }
+
+        let range = self.mapped_range(range);
+
+        unsafe {
+            self.buffer
+                .device
+                .device
+                .invalidate_mapped_memory_ranges(&[range])
+        }
+        .expect("failed to invalidate buffer memory!");
+    }
+
+    fn mapped_range(&self, range: Range<vk::DeviceSize>) -> vk::MappedMemoryRange {
+        assert!(range.end <= self.buffer.size, "range out of the buffer");
+
+        self.buffer.mapped_range(
+            self.buffer.memory_offset + range.start,
+            range.end - range.start,
+        )
+    }
+}
+
+impl<'a> Drop for MappedBuffer<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.buffer.unmap_memory();
+        }
+    }
+}
+
+/// A buffer holding `len` elements of type `T`
+pub struct TypedBuffer<T> {
+    buffer: Buffer,
+    len: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T: ByteCopiable> TypedBuffer<T> {
+    pub fn new(
+        len: usize,
+        usage: vk::BufferUsageFlags,
+        properties: vk::MemoryPropertyFlags,
+        name: Option<&str>,
+        device: Rc<Device>,
+        instance: &Instance,
+    ) -> Self {
+        let size = (len * mem::size_of::<T>()) as vk::DeviceSize;
+
+        Self::from_buffer(Buffer::new(size, usage, properties, name, device, instance))
+    }
+
+    /// View `buffer` as an array of as many `T` as it can hold
+    pub fn from_buffer(buffer: Buffer) -> Self {
+        let len = buffer.size() as usize / mem::size_of::<T>();
+
+        Self {
+            buffer,
+            len,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffer
+    }
+
+    pub fn into_inner(self) -> Buffer {
+        self.buffer
+    }
+
+    /// Copy `data` into the buffer, starting at the element `first`
+    pub fn copy_from_slice(&mut self, data: &[T], first: usize) {
+        assert!(first + data.len() <= self.len, "copy out of the buffer");
+
+        self.buffer
+            .copy_data(data, first * mem::size_of::<T>())
+            .expect("the elements are in the buffer");
+    }
+
+    /// Fill `data` with the buffer elements starting at the element `first`
+    pub fn read_into_slice(&self, data: &mut [T], first: usize) {
+        assert!(first + data.len() <= self.len, "read out of the buffer");
+
+        self.buffer
+            .get_data(data, first * mem::size_of::<T>())
+            .expect("the elements are in the buffer");
+    }
+}
+
+impl<T: ByteCopiable + Copy + Default> TypedBuffer<T> {
+    /// Read every element of a host visible buffer
+    pub fn read_all(&self) -> Vec<T> {
+        if let Some(properties) = self.buffer.memory_properties {
+            assert!(
+                properties.contains(vk::MemoryPropertyFlags::HOST_VISIBLE),
+                "only host visible buffers can be read directly, use read_all_via_staging"
+            );
+        }
+
+        let mut data = vec![T::default(); self.len];
+        self.read_into_slice(&mut data, 0);
+
+        data
+    }
+
+    /// Read every element of a buffer which isn't host visible, through a staging buffer
+    /// The buffer must have the `TRANSFER_SRC` usage, the copy waits for all the commands previously
+    /// submitted to `queue` and is waited for before returning
+    pub fn read_all_via_staging(
+        &self,
+        command_pool: &mut Rc<CommandPool>,
+        queue: &mut Queue,
+    ) -> Result<Vec<T>, DeviceLost> {
+        assert!(
+            self.buffer
+                .usage
+                .contains(vk::BufferUsageFlags::TRANSFER_SRC),
+            "a buffer needs the TRANSFER_SRC usage to be read via staging"
+        );
+
+        if self.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let device = &self.buffer.device;
+
+        // Only the elements are copied, not the padding after the last one
+        let staging_buffer = TypedBuffer::<T>::new(
+            self.len,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            None,
+            Rc::clone(device),
+            device.instance(),
+        );
+
+        let regions = [vk::BufferCopy::builder()
+            .size((self.len * mem::size_of::<T>()) as _)
+            .build()];
+
+        let memory_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .build()];
+
+        command_pool.single_time(queue, |recorder| {
+            recorder.as_generic().pipeline_barrier(
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &memory_barriers,
+                &[],
+                &[],
+            );
+
+            recorder
+                .as_transfer_command_buffer()
+                .unwrap()
+                .as_copy()
+                .copy_buffer(&self.buffer, staging_buffer.buffer(), &regions)
                 .unwrap();
-            ptr::copy_nonoverlapping(src.cast(), dst, size);
-            self.device.device.unmap_memory(self.memory);
+        })?;
+
+        Ok(staging_buffer.read_all())
+    }
+}
+
+/// A device local `u32` counter for compute shaders, declared as `buffer Counter { uint counter; };`
+/// and incremented with `atomicAdd(counter, 1)`
+pub struct AtomicCounterBuffer {
+    buffer: TypedBuffer<u32>,
+}
+
+impl AtomicCounterBuffer {
+    /// The counter is uninitialized until the first `reset`
+    pub fn new(name: Option<&str>, device: Rc<Device>, instance: &Instance) -> Self {
+        Self {
+            buffer: TypedBuffer::new(
+                1,
+                vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_SRC
+                    | vk::BufferUsageFlags::TRANSFER_DST,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                name,
+                device,
+                instance,
+            ),
         }
     }
+
+    pub fn buffer(&self) -> &Buffer {
+        self.buffer.buffer()
+    }
+
+    /// Info to write the counter into a storage buffer descriptor
+    pub fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::builder()
+            .buffer(self.buffer().handle)
+            .offset(0)
+            .range(mem::size_of::<u32>() as _)
+            .build()
+    }
+
+    /// Set the counter to `value`, before any shader submitted afterwards uses it
+    pub fn reset(
+        &mut self,
+        value: u32,
+        command_pool: &mut Rc<CommandPool>,
+        queue: &mut Queue,
+    ) -> Result<(), DeviceLost> {
+        let memory_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .build()];
+
+        let buffer = self.buffer.buffer_mut();
+
+        command_pool.single_time(queue, |recorder| {
+            recorder
+                .as_transfer_command_buffer()
+                .unwrap()
+                .as_clear()
+                .fill_buffer(buffer, 0, mem::size_of::<u32>() as _, value)
+                .unwrap();
+
+            recorder.as_generic().pipeline_barrier(
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &memory_barriers,
+                &[],
+                &[],
+            );
+        })
+    }
+
+    /// Read the counter once the shaders previously submitted to `queue` are done
+    pub fn read(
+        &self,
+        command_pool: &mut Rc<CommandPool>,
+        queue: &mut Queue,
+    ) -> Result<u32, DeviceLost> {
+        Ok(self.buffer.read_all_via_staging(command_pool, queue)?[0])
+    }
 }
 
-impl Drop for Buffer {
+/// Uniform data of type `T` for each frame in flight, in a persistently mapped buffer bound as a
+/// `UNIFORM_BUFFER_DYNAMIC` descriptor, each frame writing its slot and binding it with its dynamic offset
+pub struct DynamicUniformRing<T> {
+    buffer: Buffer,
+    mapped: *mut u8,
+    stride: vk::DeviceSize,
+    frames_in_flight: usize,
+    current: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: ByteCopiable + Default> DynamicUniformRing<T> {
+    /// Every slot is initialized with `T::default()`
+    pub fn new(
+        frames_in_flight: usize,
+        name: Option<&str>,
+        device: Rc<Device>,
+        instance: &Instance,
+    ) -> Self {
+        assert_ne!(frames_in_flight, 0, "at least one frame must be in flight");
+
+        let stride = uniform_stride(
+            mem::size_of::<T>() as _,
+            mem::align_of::<T>() as _,
+            device
+                .properties()
+                .limits
+                .min_uniform_buffer_offset_alignment,
+        );
+
+        let buffer = Buffer::new(
+            stride * frames_in_flight as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            name,
+            device,
+            instance,
+        );
+
+        let mapped = unsafe {
+            buffer.device.device.map_memory(
+                buffer.memory,
+                buffer.memory_offset,
+                buffer.size,
+                vk::MemoryMapFlags::empty(),
+            )
+        }
+        .expect("failed to map uniform ring memory!") as *mut u8;
+
+        for frame in 0..frames_in_flight {
+            unsafe { ptr::write(mapped.add(frame * stride as usize).cast(), T::default()) };
+        }
+
+        Self {
+            buffer,
+            mapped,
+            stride,
+            frames_in_flight,
+            current: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> DynamicUniformRing<T> {
+    /// Dynamic offset of the slot of the current frame and its data, written directly in the buffer
+    pub fn current(&mut self) -> (u32, &mut T) {
+        let offset = self.stride * self.current as vk::DeviceSize;
+
+        // The slot is aligned for `T` since the stride is, and initialized
+        let data = unsafe { &mut *self.mapped.add(offset as usize).cast() };
+
+        (offset as u32, data)
+    }
+
+    /// Move to the slot of the next frame, which must not be read by the device anymore
+    pub fn next_frame(&mut self) {
+        self.current = (self.current + 1) % self.frames_in_flight;
+    }
+
+    /// Distance between the slots, a multiple of `min_uniform_buffer_offset_alignment`
+    pub fn stride(&self) -> vk::DeviceSize {
+        self.stride
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Info to write the ring into a `UNIFORM_BUFFER_DYNAMIC` descriptor, whose range is a single slot
+    pub fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::builder()
+            .buffer(self.buffer.handle)
+            .offset(0)
+            .range(mem::size_of::<T>() as _)
+            .build()
+    }
+}
+
+impl<T> Drop for DynamicUniformRing<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.buffer.device.device.unmap_memory(self.buffer.memory);
+        }
+    }
+}
+
+/// Size of `size` bytes rounded up to `min_alignment` and `align`, which are powers of two
+fn uniform_stride(
+    size: vk::DeviceSize,
+    align: vk::DeviceSize,
+    min_alignment: vk::DeviceSize,
+) -> vk::DeviceSize {
+    let alignment = align.max(min_alignment).max(1);
+
+    match size % alignment {
+        0 => size.max(alignment),
+        remainder => size + alignment - remainder,
+    }
+}
+
+/// A buffer which has no memory bound yet, created by `Buffer::create_unbound`
+pub struct UnboundBuffer {
+    handle: vk::Buffer,
+    usage: vk::BufferUsageFlags,
+    size: vk::DeviceSize,
+    requirements: vk::MemoryRequirements,
+    device: Rc<Device>,
+}
+
+impl UnboundBuffer {
+    pub fn handle(&self) -> vk::Buffer {
+        self.handle
+    }
+
+    /// Size, alignment and memory type bits the memory bound to this buffer must satisfy
+    pub fn memory_requirements(&self) -> vk::MemoryRequirements {
+        self.requirements
+    }
+
+    /// Bind `memory` at `offset` to the buffer
+    /// The memory isn't freed with the buffer, so it must outlive it, and it is treated as non coherent
+    pub fn bind(self, memory: vk::DeviceMemory, offset: vk::DeviceSize) -> Buffer {
+        self.bind_memory(memory, offset, false)
+    }
+
+    fn bind_memory(
+        mut self,
+        memory: vk::DeviceMemory,
+        offset: vk::DeviceSize,
+        memory_owned: bool,
+    ) -> Buffer {
+        unsafe {
+            self.device
+                .device
+                .bind_buffer_memory(self.handle, memory, offset)
+        }
+        .expect("failed to bind buffer memory!");
+
+        // The null handle is ignored when self is dropped
+        let handle = mem::replace(&mut self.handle, vk::Buffer::null());
+
+        Buffer {
+            handle,
+            usage: self.usage,
+            memory,
+            size: self.size,
+            memory_offset: offset,
+            allocation_size: None,
+            memory_properties: None,
+            host_coherent: false,
+            export_handle_types: vk::ExternalMemoryHandleTypeFlags::empty(),
+            memory_owned,
+            allocation: None,
+            device: Rc::clone(&self.device),
+        }
+    }
+}
+
+impl Drop for UnboundBuffer {
     fn drop(&mut self) {
         unsafe {
             self.device.device.destroy_buffer(self.handle, None);
-            self.device.device.free_memory(self.memory, None);
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn read_all_via_staging_returns_elements() {
+        let (instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let mut buffer = TypedBuffer::<u32>::new(
+            3,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            None,
+            device,
+            &instance,
+        );
+        buffer.copy_from_slice(&[1, 2, 3], 0);
+
+        assert_eq!(buffer.read_all(), vec![1, 2, 3]);
+        assert_eq!(
+            buffer
+                .read_all_via_staging(&mut command_pool, &mut queue)
+                .unwrap(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn buffer_builder_presets() {
+        let builder = BufferBuilder::new().size_of::<u32>().count(4).staging();
+        assert_eq!(builder.buffer_size(), 16);
+        assert_eq!(
+            builder.checked_usage(),
+            vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST
+        );
+        assert!(builder
+            .properties
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE));
+
+        let builder = BufferBuilder::new()
+            .size(64)
+            .vertex()
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER);
+        assert_eq!(builder.buffer_size(), 64);
+        assert_eq!(
+            builder.checked_usage(),
+            vk::BufferUsageFlags::VERTEX_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST
+                | vk::BufferUsageFlags::STORAGE_BUFFER
+        );
+        assert_eq!(builder.properties, vk::MemoryPropertyFlags::DEVICE_LOCAL);
+    }
+
+    #[test]
+    fn uniform_stride_alignment() {
+        assert_eq!(uniform_stride(64, 4, 256), 256);
+        assert_eq!(uniform_stride(300, 4, 256), 512);
+        assert_eq!(uniform_stride(256, 16, 256), 256);
+        assert_eq!(uniform_stride(12, 16, 1), 16);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn uniform_ring_offsets_rotate() {
+        let (instance, device, _queue) = utils::create_test_device();
+        let alignment = device
+            .properties()
+            .limits
+            .min_uniform_buffer_offset_alignment;
+
+        let mut ring = DynamicUniformRing::<[f32; 4]>::new(3, None, device, &instance);
+
+        let offsets = (0..6)
+            .map(|frame| {
+                let (offset, data) = ring.current();
+                data[0] = frame as f32;
+                ring.next_frame();
+                offset
+            })
+            .collect::<Vec<_>>();
+
+        let stride = ring.stride() as u32;
+        assert_eq!(offsets, vec![0, stride, 2 * stride, 0, stride, 2 * stride]);
+        for &offset in &offsets {
+            assert_eq!(offset as vk::DeviceSize % alignment, 0);
+        }
+        assert_eq!(ring.current().1[0], 3.0);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn mapped_buffer_round_trip() {
+        let (instance, device, _queue) = utils::create_test_device();
+
+        let mut buffer = Buffer::new(
+            16,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            None,
+            device,
+            &instance,
+        );
+
+        {
+            let mut mapped = buffer.map();
+            mapped.as_mut_slice::<u32>().copy_from_slice(&[1, 2, 3, 4]);
+            mapped.flush(0..16);
+        }
+
+        let mut data = [0u32; 4];
+        buffer.get_data(&mut data[..], 0).unwrap();
+        assert_eq!(data, [1, 2, 3, 4]);
+        assert_eq!(buffer.map().as_slice::<u32>(), &[1, 2, 3, 4]);
+
+        assert_eq!(
+            buffer.get_data(&mut data[..], 4),
+            Err(OutOfBounds {
+                offset: 4,
+                size: 16,
+                buffer_size: 16
+            })
+        );
+        assert!(buffer.copy_data(&0u32, 12).is_ok());
+        assert!(buffer.copy_data(&0u32, 13).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn flush_buffer_bound_at_unaligned_offset() {
+        let (instance, device, _queue) = utils::create_test_device();
+
+        let unbound =
+            Buffer::create_unbound(16, vk::BufferUsageFlags::TRANSFER_SRC, Rc::clone(&device));
+        let requirements = unbound.memory_requirements();
+        // Aligned for the buffer, but usually not to the non coherent atom size
+        let offset = requirements.alignment;
+
+        let alloc_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(offset + requirements.size)
+            .memory_type_index(utils::find_memory_type(
+                requirements.memory_type_bits,
+                vk::MemoryPropertyFlags::HOST_VISIBLE,
+                &device,
+                &instance,
+            ));
+        let memory = unsafe { device.device.allocate_memory(&alloc_info, None) }.unwrap();
+
+        let mut buffer = unbound.bind(memory, offset);
+
+        {
+            let mut mapped = buffer.map();
+            mapped.as_mut_slice::<u32>().copy_from_slice(&[1, 2, 3, 4]);
+            mapped.flush(4..8);
+            mapped.flush(0..16);
+            mapped.invalidate(12..16);
+        }
+
+        let mut data = [0u32; 4];
+        buffer.get_data(&mut data[..], 0).unwrap();
+        assert_eq!(data, [1, 2, 3, 4]);
+
+        drop(buffer);
+        unsafe { device.device.free_memory(memory, None) };
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn grow_preserves_contents() {
+        let (instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let mut buffer = Buffer::new(
+            16,
+            vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            None,
+            device,
+            &instance,
+        );
+        buffer.copy_data(&[1u32, 2, 3, 4], 0).unwrap();
+
+        buffer
+            .grow(32, &mut command_pool, &mut queue, &instance)
+            .unwrap();
+        assert_eq!(buffer.size(), 32);
+
+        let mut data = [0u32; 4];
+        buffer.get_data(&mut data, 0).unwrap();
+        assert_eq!(data, [1, 2, 3, 4]);
+    }
+}
@@ -24,30 +24,38 @@ unsafe impl ByteCopiable for f64 {}
 
 unsafe impl<T: ByteCopiable> ByteCopiable for [T] {}
 
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 0] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 1] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 2] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 3] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 4] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 5] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 6] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 7] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 8] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 9] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 10] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 11] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 12] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 13] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 14] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 15] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 16] {}
-
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 32] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 64] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 128] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 256] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 512] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 1024] {}
-unsafe impl<T: ByteCopiable> ByteCopiable for [T; 2048] {}
-
-unsafe impl<A: ByteCopiable, B: ByteCopiable> ByteCopiable for (A, B) {}
+unsafe impl<T: ByteCopiable, const N: usize> ByteCopiable for [T; N] {}
+
+macro_rules! tuple_impls {
+    ($($name:ident),+) => {
+        unsafe impl<$($name: ByteCopiable),+> ByteCopiable for ($($name,)+) {}
+    };
+}
+
+tuple_impls!(A, B);
+tuple_impls!(A, B, C);
+tuple_impls!(A, B, C, D);
+tuple_impls!(A, B, C, D, E);
+tuple_impls!(A, B, C, D, E, F);
+tuple_impls!(A, B, C, D, E, F, G);
+tuple_impls!(A, B, C, D, E, F, G, H);
+tuple_impls!(A, B, C, D, E, F, G, H, I);
+tuple_impls!(A, B, C, D, E, F, G, H, I, J);
+tuple_impls!(A, B, C, D, E, F, G, H, I, J, K);
+tuple_impls!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_byte_copiable<T: ByteCopiable + ?Sized>() {}
+
+    #[test]
+    fn arrays_and_tuples_are_byte_copiable() {
+        assert_byte_copiable::<[f32; 20]>();
+        assert_byte_copiable::<[[u32; 3]; 4096]>();
+        assert_byte_copiable::<[u8]>();
+        assert_byte_copiable::<(u32, f32, [i16; 2])>();
+        assert_byte_copiable::<(u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8)>();
+    }
+}
@@ -1,27 +1,61 @@
+use std::cell::{Cell, RefCell};
 use std::error::Error;
 use std::fmt;
-use std::marker::PhantomData;
 use std::mem;
-use std::ops::Range;
+use std::ops::{Deref, DerefMut, Range};
 use std::rc::Rc;
 use std::slice;
 
 use ash::version::DeviceV1_0;
 use ash::vk;
 
-use super::{Buffer, ByteCopiable, ComputePipeline, Device, Image, QueueFamily};
-
+#[cfg(feature = "validation-layers")]
+use super::Debug;
+use super::{
+    Buffer, ByteCopiable, ComputePipeline, Device, DeviceLost, Event, GraphicsPipeline, Image,
+    PushConstantError, PushConstantLayout, QueryPool, Queue, QueueFamily, Semaphore, SubmitError,
+    TypedBuffer,
+};
+
+/// A command pool, from which command buffers of one queue family are allocated
+/// Pools and their command buffers share the device through `Rc`, so they can't be moved to another
+/// thread and all the command buffers of an application are recorded on the thread of its device
 pub struct CommandPool {
     command_pool: vk::CommandPool,
     queue_family_index: usize,
+    flags: vk::CommandPoolCreateFlags,
+    /// Fences of the submissions of the command buffers of the pool which aren't observed signaled yet,
+    /// once per pending command buffer and submission
+    pending_fences: RefCell<Vec<vk::Fence>>,
+    /// Incremented by `reset`, which moves the command buffers recorded before back to the initial state
+    generation: Cell<u64>,
     device: Rc<Device>,
 }
 
 impl CommandPool {
     pub fn new(queue_family: &QueueFamily, device: Rc<Device>) -> Self {
+        Self::create(queue_family, vk::CommandPoolCreateFlags::empty(), device)
+    }
+
+    /// Create a pool whose command buffers can be reset one by one, to be recorded again with
+    /// `ExecutableCommandBuffer::reset_and_record`
+    pub fn new_resettable(queue_family: &QueueFamily, device: Rc<Device>) -> Self {
+        Self::create(
+            queue_family,
+            vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            device,
+        )
+    }
+
+    fn create(
+        queue_family: &QueueFamily,
+        flags: vk::CommandPoolCreateFlags,
+        device: Rc<Device>,
+    ) -> Self {
         let command_pool = {
-            let pool_info =
-                vk::CommandPoolCreateInfo::builder().queue_family_index(queue_family.index() as _);
+            let pool_info = vk::CommandPoolCreateInfo::builder()
+                .flags(flags)
+                .queue_family_index(queue_family.index() as _);
 
             unsafe { device.device.create_command_pool(&pool_info, None) }
                 .expect("failed to create command pool")
@@ -30,10 +64,68 @@ impl CommandPool {
         Self {
             command_pool,
             queue_family_index: queue_family.index(),
+            flags,
+            pending_fences: RefCell::new(vec![]),
+            generation: Cell::new(0),
             device,
         }
     }
 
+    /// Whether the command buffers of this pool can be reset individually
+    pub fn is_resettable(&self) -> bool {
+        self.flags
+            .contains(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+    }
+
+    /// Reset all the command buffers of the pool to the initial state at once, it fails with
+    /// `CommandPoolResetError::Pending` if one of them is still pending
+    /// With `release_resources`, the memory they used is given back to the system instead of being kept
+    /// for the next recordings
+    /// The `ExecutableCommandBuffer`s of the pool must then be recorded again before being submitted
+    pub fn reset(&self, release_resources: bool) -> Result<(), CommandPoolResetError> {
+        for &fence in self.pending_fences.borrow().iter() {
+            let signaled = self.device.check_lost(
+                unsafe { self.device.device.get_fence_status(fence) },
+                "failed to get fence status",
+            )?;
+
+            if !signaled {
+                return Err(CommandPoolResetError::Pending);
+            }
+        }
+
+        let flags = if release_resources {
+            vk::CommandPoolResetFlags::RELEASE_RESOURCES
+        } else {
+            vk::CommandPoolResetFlags::empty()
+        };
+
+        unsafe {
+            self.device
+                .device
+                .reset_command_pool(self.command_pool, flags)
+        }
+        .expect("failed to reset command pool");
+
+        // The fences were all signaled, the command buffers reset with the pool don't track them anymore
+        self.pending_fences.borrow_mut().clear();
+        self.generation.set(self.generation.get() + 1);
+
+        Ok(())
+    }
+
+    fn add_pending_fence(&self, fence: vk::Fence) {
+        self.pending_fences.borrow_mut().push(fence);
+    }
+
+    fn remove_pending_fence(&self, fence: vk::Fence) {
+        let mut pending_fences = self.pending_fences.borrow_mut();
+
+        if let Some(index) = pending_fences.iter().position(|&pending| pending == fence) {
+            pending_fences.swap_remove(index);
+        }
+    }
+
     pub fn allocate_command_buffers(
         self: &mut Rc<Self>,
         level: vk::CommandBufferLevel,
@@ -50,14 +142,71 @@ impl CommandPool {
             .map(|command_buffer| CommandBuffer {
                 handle: command_buffer,
                 level,
-                state: CommandBufferState::Initial,
+                state: Cell::new(CommandBufferState::Initial),
                 usage: vk::CommandBufferUsageFlags::empty(),
+                pending_fences: RefCell::new(vec![]),
+                generation: self.generation.get(),
                 command_pool: Rc::clone(&self),
                 device: Rc::clone(&self.device),
             })
             .collect()
     }
 
+    pub fn allocate_command_buffer(
+        self: &mut Rc<Self>,
+        level: vk::CommandBufferLevel,
+    ) -> CommandBuffer {
+        self.allocate_command_buffers(level, 1).remove(0)
+    }
+
+    /// Allocate command buffers to be executed by primary ones with `CommandBufferRecorder::execute_commands`
+    pub fn allocate_secondary_command_buffers(
+        self: &mut Rc<Self>,
+        count: usize,
+    ) -> Vec<SecondaryCommandBuffer> {
+        self.allocate_command_buffers(vk::CommandBufferLevel::SECONDARY, count)
+            .into_iter()
+            .map(SecondaryCommandBuffer)
+            .collect()
+    }
+
+    /// Record a one time command buffer with `record`, submit it to `queue` and wait until it's executed,
+    /// the command buffer is then freed
+    /// Only this submission is waited for, not the whole queue
+    pub fn single_time<'b, F: FnOnce(&mut CommandBufferRecorder<'b>)>(
+        self: &mut Rc<Self>,
+        queue: &mut Queue,
+        record: F,
+    ) -> Result<(), DeviceLost> {
+        let mut recorder = self
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        record(&mut recorder);
+
+        let command_buffer = recorder.end();
+
+        let handle = match queue.submit_batch(vec![QueueSubmission::builder()
+            .with_owned_command_buffers(vec![command_buffer])
+            .build()])
+        {
+            Ok(handle) => handle,
+            Err(SubmitError::DeviceLost) => return Err(DeviceLost),
+            // The command buffer was just recorded, so it can't be pending
+            Err(error) => unreachable!("{}", error),
+        };
+
+        handle.wait()
+    }
+
+    pub fn queue_family_index(&self) -> usize {
+        self.queue_family_index
+    }
+
+    pub fn queue_family(&self) -> &QueueFamily {
+        self.device.get_queue_families(self.queue_family_index)
+    }
+
     pub fn support_graphics(&self) -> bool {
         self.device
             .get_queue_families(self.queue_family_index)
@@ -124,11 +273,87 @@ impl fmt::Display for UnsupportedOperation {
 
 impl Error for UnsupportedOperation {}
 
+/// Failure of `ExecutableCommandBuffer::reset` and `reset_and_record`, which gives the command buffer back
+pub enum ResetError {
+    /// The pool of the command buffer wasn't created by `CommandPool::new_resettable`
+    NotResettable(ExecutableCommandBuffer),
+    /// The fence of a submission of the command buffer isn't signaled yet
+    Pending(ExecutableCommandBuffer),
+    DeviceLost(ExecutableCommandBuffer),
+}
+
+impl ResetError {
+    pub fn into_command_buffer(self) -> ExecutableCommandBuffer {
+        match self {
+            Self::NotResettable(command_buffer)
+            | Self::Pending(command_buffer)
+            | Self::DeviceLost(command_buffer) => command_buffer,
+        }
+    }
+}
+
+impl fmt::Debug for ResetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotResettable(_) => write!(f, "NotResettable"),
+            Self::Pending(_) => write!(f, "Pending"),
+            Self::DeviceLost(_) => write!(f, "DeviceLost"),
+        }
+    }
+}
+
+impl fmt::Display for ResetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotResettable(_) => {
+                write!(
+                    f,
+                    "The command pool doesn't allow resetting command buffers!"
+                )
+            }
+            Self::Pending(_) => write!(f, "The command buffer is still pending!"),
+            Self::DeviceLost(_) => write!(f, "The device was lost!"),
+        }
+    }
+}
+
+impl Error for ResetError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CommandPoolResetError {
+    /// A command buffer of the pool is still pending
+    Pending,
+    DeviceLost,
+}
+
+impl fmt::Display for CommandPoolResetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pending => write!(f, "A command buffer of the pool is still pending!"),
+            Self::DeviceLost => write!(f, "The device was lost!"),
+        }
+    }
+}
+
+impl Error for CommandPoolResetError {}
+
+impl From<DeviceLost> for CommandPoolResetError {
+    fn from(_: DeviceLost) -> Self {
+        Self::DeviceLost
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum DrawError {
+    /// No graphics pipeline is bound, or its vertex buffers or descriptor sets aren't all bound
     Draw,
+    /// No index buffer is bound, or it wasn't created with the `INDEX_BUFFER` usage
     Indexed,
+    /// The indirect buffer wasn't created with the `INDIRECT_BUFFER` usage
     Indirect,
+    /// The indirect offset or stride isn't a multiple of 4, or several draws have a stride smaller than
+    /// their indirect command
+    UnalignedIndirect,
 }
 
 impl fmt::Display for DrawError {
@@ -141,8 +366,19 @@ impl Error for DrawError {}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum DispatchError {
+    /// The layout of the bound pipeline has more descriptor sets than are bound
     Dispatch,
+    /// The indirect buffer wasn't created with the `INDIRECT_BUFFER` usage
     Indirect,
+    /// The indirect offset isn't a multiple of 4
+    UnalignedIndirect,
+    /// No compute pipeline is bound
+    NoPipeline,
+    /// The workgroup size of the bound pipeline isn't known, such as one computed by the shader
+    /// from specialization constants, see `utils::spirv_local_size`
+    UnknownLocalSize,
+    /// The group count is greater than the device limit
+    GroupCountExceeded,
 }
 
 impl fmt::Display for DispatchError {
@@ -153,9 +389,48 @@ impl fmt::Display for DispatchError {
 
 impl Error for DispatchError {}
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum BarrierError {
+    /// The image barrier `index` changes the layout, which can't be done inside a render pass
+    LayoutMismatchInRenderpass { index: usize },
+    /// The image barrier `index` transfers the queue family ownership, which can't be done inside a render pass
+    QueueFamilyMismatchInRenderpass { index: usize },
+}
+
+impl fmt::Display for BarrierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::LayoutMismatchInRenderpass { index } => write!(
+                f,
+                "Old and new layout of image barrier {} must be equal inside a render pass!",
+                index
+            ),
+            Self::QueueFamilyMismatchInRenderpass { index } => write!(
+                f,
+                "Src and dst queue family of image barrier {} must be equal inside a render pass!",
+                index
+            ),
+        }
+    }
+}
+
+impl Error for BarrierError {}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum CopyError {
+    /// A source region overlaps a destination region of a copy within a buffer
     RegionsOverlapped,
+    /// The destination buffer wasn't created with the `TRANSFER_DST` usage
+    MissingTransferDstUsage,
+    /// The copied data doesn't fit in the destination buffer
+    OutOfBounds,
+    /// The destination offset or stride of query results isn't a multiple of the size of their values
+    UnalignedQueryResults,
+    /// An image region isn't aligned to the `min_image_transfer_granularity` of the queue family,
+    /// see `QueueFamily::align_image_region`
+    UnalignedImageRegion,
+    /// The format of the image doesn't support the linear filtering of the blits generating its mipmaps
+    UnsupportedLinearFilter,
 }
 
 impl fmt::Display for CopyError {
@@ -177,17 +452,39 @@ impl fmt::Display for ClearError {
 
 impl Error for ClearError {}
 
-pub type InlineSubpass =
-    dyn FnOnce(&mut InsideOfRenderpassScope<'_, '_>) -> Result<(), Box<dyn Error + Send + Sync>>;
-
-pub enum Subpass {
-    Inline { callback: Box<InlineSubpass> },
+/// Commands of an inline subpass, the resources it captures must outlive the recording `'b`
+/// since the recorded commands reference them
+pub type InlineSubpass<'b> = dyn 'b
+    + FnOnce(&mut InsideOfRenderpassScope<'_, 'b>) -> Result<(), Box<dyn Error + Send + Sync>>;
+
+pub enum Subpass<'b> {
+    Inline {
+        callback: Box<InlineSubpass<'b>>,
+    },
+    /// Subpass whose commands are recorded in secondary command buffers continuing it
+    Secondary {
+        command_buffers: Vec<&'b ExecutableSecondaryCommandBuffer>,
+    },
 }
 
-impl Subpass {
+impl<'b> Subpass<'b> {
     fn contents(&self) -> vk::SubpassContents {
         match self {
             Self::Inline { .. } => vk::SubpassContents::INLINE,
+            Self::Secondary { .. } => vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+        }
+    }
+
+    fn record(
+        self,
+        recorder: &mut CommandBufferRecorder<'b>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self {
+            Self::Inline { callback } => callback(&mut InsideOfRenderpassScope(recorder)),
+            Self::Secondary { command_buffers } => {
+                recorder.execute_commands(&command_buffers);
+                Ok(())
+            }
         }
     }
 }
@@ -195,17 +492,71 @@ impl Subpass {
 pub struct CommandBuffer {
     pub(crate) handle: vk::CommandBuffer,
     level: vk::CommandBufferLevel,
-    state: CommandBufferState,
+    /// Updated through shared references by the submissions borrowing the command buffer
+    state: Cell<CommandBufferState>,
     usage: vk::CommandBufferUsageFlags,
+    /// Fences signaled by the submissions of the command buffer which aren't observed signaled yet, several ones
+    /// with `SIMULTANEOUS_USE`
+    pending_fences: RefCell<Vec<vk::Fence>>,
+    /// Generation of the pool when the recording began, the pool was reset since if it's changed
+    generation: u64,
     command_pool: Rc<CommandPool>,
     device: Rc<Device>,
 }
 
+impl Drop for CommandBuffer {
+    fn drop(&mut self) {
+        for &fence in self.pending_fences.get_mut().iter() {
+            self.command_pool.remove_pending_fence(fence);
+        }
+
+        unsafe {
+            self.device
+                .device
+                .free_command_buffers(self.command_pool.command_pool, &[self.handle])
+        }
+    }
+}
+
 impl CommandBuffer {
-    pub fn begin(mut self, usage: vk::CommandBufferUsageFlags) -> CommandBufferRecorder<'static> {
-        self.state = CommandBufferState::Recording;
+    /// Whether `CommandPool::reset` moved the command buffer back to the initial state since it began recording
+    fn is_reset_by_pool(&self) -> bool {
+        self.generation != self.command_pool.generation.get()
+    }
+
+    /// Name the command buffer in the validation messages
+    #[cfg(feature = "validation-layers")]
+    pub fn set_name(&self, debug: &Debug, name: &str) {
+        debug.set_object_name(&self.device, self.handle, name);
+    }
+
+    /// Start the recording of the command buffer
+    /// With `SIMULTANEOUS_USE`, the command buffer can be submitted again while a previous submission is still pending,
+    /// so several executions may overlap on the GPU: the caller must synchronize the resources they share,
+    /// with semaphores between the submissions or with barriers recorded inside the command buffer
+    pub fn begin(self, usage: vk::CommandBufferUsageFlags) -> CommandBufferRecorder<'static> {
+        assert_eq!(
+            self.level,
+            vk::CommandBufferLevel::PRIMARY,
+            "secondary command buffers are allocated by allocate_secondary_command_buffers"
+        );
+
+        self.begin_with(usage, None)
+    }
 
-        let begin_info = vk::CommandBufferBeginInfo::builder().flags(usage);
+    fn begin_with(
+        mut self,
+        usage: vk::CommandBufferUsageFlags,
+        inheritance: Option<&vk::CommandBufferInheritanceInfo>,
+    ) -> CommandBufferRecorder<'static> {
+        self.state.set(CommandBufferState::Recording);
+        self.usage = usage;
+        self.generation = self.command_pool.generation.get();
+
+        let mut begin_info = vk::CommandBufferBeginInfo::builder().flags(usage);
+        if let Some(inheritance) = inheritance {
+            begin_info = begin_info.inheritance_info(inheritance);
+        }
 
         unsafe {
             self.device
@@ -233,12 +584,45 @@ pub struct GraphicsBindings<'a> {
     index_buffer: bool,
     vertex_buffers: bool,
     descriptors: bool,
+    descriptor_sets: BoundDescriptorSets,
 }
 
 #[derive(Default)]
 pub struct ComputeBindings<'a> {
     compute_pipeline: Option<&'a ComputePipeline>,
     descriptors: bool,
+    descriptor_sets: BoundDescriptorSets,
+}
+
+/// Descriptor sets bound from set 0, to skip binding them again
+#[derive(Default)]
+struct BoundDescriptorSets {
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    dynamic_offsets: Vec<u32>,
+}
+
+impl BoundDescriptorSets {
+    fn is_bound(&self, descriptor_sets: &[vk::DescriptorSet], dynamic_offsets: &[u32]) -> bool {
+        self.descriptor_sets == descriptor_sets && self.dynamic_offsets == dynamic_offsets
+    }
+
+    /// Number of consecutive sets bound from set 0
+    fn count(&self) -> usize {
+        self.descriptor_sets.len()
+    }
+
+    fn bind(&mut self, descriptor_sets: &[vk::DescriptorSet], dynamic_offsets: &[u32]) {
+        self.descriptor_sets = descriptor_sets.to_vec();
+        self.dynamic_offsets = dynamic_offsets.to_vec();
+    }
+
+    /// Forget the sets disturbed by binding a pipeline whose layout is only compatible for the first `count` sets
+    fn keep_compatible(&mut self, count: usize) {
+        if count < self.descriptor_sets.len() {
+            self.descriptor_sets.clear();
+            self.dynamic_offsets.clear();
+        }
+    }
 }
 
 // TODO: ajouter vérification des pipelines (struct intermédiaire avant la renderpass pour graphics)
@@ -288,8 +672,23 @@ impl<'a> CommandBufferRecorder<'a> {
         GenericCommands(self)
     }
 
-    pub fn end(mut self) -> ExecutableCommandBuffer {
-        self.inner.state = CommandBufferState::Executable;
+    pub(crate) fn handle(&self) -> vk::CommandBuffer {
+        self.inner.handle
+    }
+
+    pub(crate) fn device(&self) -> &Device {
+        &self.inner.device
+    }
+
+    /// End the recording, if the pool was reset meanwhile the command buffer is left in the initial state
+    /// and can't be submitted
+    pub fn end(self) -> ExecutableCommandBuffer {
+        if self.inner.is_reset_by_pool() {
+            self.inner.state.set(CommandBufferState::Initial);
+            return ExecutableCommandBuffer(self.inner);
+        }
+
+        self.inner.state.set(CommandBufferState::Executable);
 
         unsafe {
             self.inner
@@ -301,6 +700,118 @@ impl<'a> CommandBufferRecorder<'a> {
 
         ExecutableCommandBuffer(self.inner)
     }
+
+    /// Execute `command_buffers` from this primary command buffer, inside of a render pass they must
+    /// be given to a `Subpass::Secondary` instead
+    pub fn execute_commands(
+        &mut self,
+        command_buffers: &[&'a ExecutableSecondaryCommandBuffer],
+    ) -> &mut Self {
+        assert_eq!(
+            self.inner.level,
+            vk::CommandBufferLevel::PRIMARY,
+            "only primary command buffers can execute secondary ones"
+        );
+
+        assert!(
+            command_buffers
+                .iter()
+                .all(|command_buffer| !command_buffer.0.is_reset_by_pool()),
+            "secondary command buffers must be recorded again once their pool is reset"
+        );
+
+        let handles = command_buffers
+            .iter()
+            .map(|command_buffer| command_buffer.0.handle)
+            .collect::<Vec<_>>();
+
+        unsafe {
+            self.inner
+                .device
+                .device
+                .cmd_execute_commands(self.inner.handle, &handles)
+        }
+
+        // The pipelines, descriptor sets and push constants of this command buffer are undefined afterwards
+        self.graphics_bindings = GraphicsBindings::default();
+        self.compute_bindings = ComputeBindings::default();
+
+        self
+    }
+}
+
+/// A command buffer executed by primary ones, allocated by `CommandPool::allocate_secondary_command_buffers`
+pub struct SecondaryCommandBuffer(CommandBuffer);
+
+impl SecondaryCommandBuffer {
+    /// Start the recording of the command buffer
+    /// With `RENDER_PASS_CONTINUE`, the commands are recorded in the subpass of `inheritance`, through
+    /// `SecondaryCommandBufferRecorder::as_render_pass_continuation`
+    pub fn begin(
+        self,
+        usage: vk::CommandBufferUsageFlags,
+        inheritance: &vk::CommandBufferInheritanceInfo,
+    ) -> SecondaryCommandBufferRecorder<'static> {
+        let render_pass_continue =
+            usage.contains(vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE);
+
+        assert!(
+            !render_pass_continue || inheritance.render_pass != vk::RenderPass::null(),
+            "a command buffer continuing a render pass must inherit it"
+        );
+
+        SecondaryCommandBufferRecorder {
+            recorder: self.0.begin_with(usage, Some(inheritance)),
+            render_pass_continue,
+        }
+    }
+}
+
+/// Recorder of a secondary command buffer, which records the same commands as a primary one
+/// outside of a render pass
+pub struct SecondaryCommandBufferRecorder<'a> {
+    recorder: CommandBufferRecorder<'a>,
+    render_pass_continue: bool,
+}
+
+impl<'a> SecondaryCommandBufferRecorder<'a> {
+    /// Commands recorded in the subpass inherited by a command buffer begun with `RENDER_PASS_CONTINUE`
+    pub fn as_render_pass_continuation(
+        &mut self,
+    ) -> Result<InsideOfRenderpassScope<'_, 'a>, UnsupportedOperation> {
+        if self.render_pass_continue {
+            Ok(InsideOfRenderpassScope(&mut self.recorder))
+        } else {
+            Err(UnsupportedOperation)
+        }
+    }
+
+    pub fn end(self) -> ExecutableSecondaryCommandBuffer {
+        ExecutableSecondaryCommandBuffer(self.recorder.end().0)
+    }
+}
+
+impl<'a> Deref for SecondaryCommandBufferRecorder<'a> {
+    type Target = CommandBufferRecorder<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.recorder
+    }
+}
+
+impl<'a> DerefMut for SecondaryCommandBufferRecorder<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.recorder
+    }
+}
+
+/// A recorded secondary command buffer, which can only be executed by a primary command buffer
+pub struct ExecutableSecondaryCommandBuffer(CommandBuffer);
+
+impl ExecutableSecondaryCommandBuffer {
+    pub fn usage(&self) -> vk::CommandBufferUsageFlags {
+        self.0.usage
+    }
 }
 
 pub struct TransferCommandBuffer<'a, 'b: 'a>(&'a mut CommandBufferRecorder<'b>);
@@ -309,6 +820,10 @@ impl<'a, 'b: 'a> TransferCommandBuffer<'a, 'b> {
     pub fn as_copy(&mut self) -> CopyCommands<'_, 'b> {
         CopyCommands(self.0)
     }
+
+    pub fn as_clear(&mut self) -> ClearCommands<'_, 'b> {
+        ClearCommands(self.0)
+    }
 }
 
 pub struct GraphicsCommandBuffer<'a, 'b: 'a>(&'a mut CommandBufferRecorder<'b>);
@@ -317,7 +832,7 @@ impl<'a, 'b: 'a> GraphicsCommandBuffer<'a, 'b> {
     pub fn renderpass(
         &mut self,
         begin_info: &vk::RenderPassBeginInfo,
-        mut subpasses: Vec<Subpass>,
+        mut subpasses: Vec<Subpass<'b>>,
     ) -> Result<&mut Self, Box<dyn Error + Send + Sync>> {
         if subpasses.is_empty() {
             return Ok(self);
@@ -325,6 +840,12 @@ impl<'a, 'b: 'a> GraphicsCommandBuffer<'a, 'b> {
 
         let command_buffer = &self.0.inner;
 
+        assert_eq!(
+            command_buffer.level,
+            vk::CommandBufferLevel::PRIMARY,
+            "render passes are begun by primary command buffers"
+        );
+
         unsafe {
             command_buffer.device.device.cmd_begin_render_pass(
                 command_buffer.handle,
@@ -333,11 +854,7 @@ impl<'a, 'b: 'a> GraphicsCommandBuffer<'a, 'b> {
             )
         }
 
-        let mut inside = InsideOfRenderpassScope(self.0);
-
-        match subpasses.remove(0) {
-            Subpass::Inline { callback } => callback(&mut inside)?,
-        }
+        subpasses.remove(0).record(self.0)?;
 
         for subpass in subpasses {
             let command_buffer = &self.0.inner;
@@ -349,11 +866,7 @@ impl<'a, 'b: 'a> GraphicsCommandBuffer<'a, 'b> {
                     .cmd_next_subpass(command_buffer.handle, subpass.contents());
             }
 
-            let mut inside = InsideOfRenderpassScope(self.0);
-
-            match subpass {
-                Subpass::Inline { callback } => callback(&mut inside)?,
-            }
+            subpass.record(self.0)?;
         }
 
         let command_buffer = &self.0.inner;
@@ -398,28 +911,10 @@ impl<'a, 'b: 'a> InsideOfRenderpassScope<'a, 'b> {
         dependency_flags: vk::DependencyFlags,
         memory_barriers: &'b [vk::MemoryBarrier],
         image_memory_barriers: &'b [vk::ImageMemoryBarrier],
-    ) -> &mut Self {
-        let command_buffer = &self.0.inner;
-
-        for (index, image_barrier) in image_memory_barriers.iter().enumerate() {
-            //TODO: check for image being an attachment of current subpass as input and (color or depth/stencil)
-
-            if image_barrier.old_layout != image_barrier.new_layout {
-                panic!(format!(
-                    "old and new layout of image barrier {} must be equal",
-                    index
-                ));
-            }
-
-            if image_barrier.src_queue_family_index != image_barrier.dst_queue_family_index {
-                panic!(format!(
-                    "src and dst queue family of image barrier {} must be equal",
-                    index
-                ));
-            }
-        }
+    ) -> Result<&mut Self, BarrierError> {
+        Self::check_image_barriers(image_memory_barriers)?;
 
-        // And add more checks
+        let command_buffer = &self.0.inner;
 
         unsafe {
             command_buffer.device.device.cmd_pipeline_barrier(
@@ -433,7 +928,26 @@ impl<'a, 'b: 'a> InsideOfRenderpassScope<'a, 'b> {
             )
         }
 
-        self
+        Ok(self)
+    }
+
+    /// Check that the image barriers neither change the layout nor the queue family, as required in a subpass
+    fn check_image_barriers(
+        image_memory_barriers: &[vk::ImageMemoryBarrier],
+    ) -> Result<(), BarrierError> {
+        for (index, image_barrier) in image_memory_barriers.iter().enumerate() {
+            //TODO: check for image being an attachment of current subpass as input and (color or depth/stencil)
+
+            if image_barrier.old_layout != image_barrier.new_layout {
+                return Err(BarrierError::LayoutMismatchInRenderpass { index });
+            }
+
+            if image_barrier.src_queue_family_index != image_barrier.dst_queue_family_index {
+                return Err(BarrierError::QueueFamilyMismatchInRenderpass { index });
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -486,45 +1000,361 @@ impl<'a, 'b: 'a> GenericCommands<'a, 'b> {
 
         self
     }
-}
 
-pub struct GraphicsGenericCommands<'a, 'b: 'a>(&'a mut CommandBufferRecorder<'b>);
+    /// Set `event` once the commands recorded before reach `stage_mask`
+    pub fn set_event(&mut self, event: &'b Event, stage_mask: vk::PipelineStageFlags) -> &mut Self {
+        let command_buffer = &self.0.inner;
 
-impl<'a, 'b: 'a> GraphicsGenericCommands<'a, 'b> {
-    fn vertex_buffer_check(vertex_buffer: &Buffer) -> bool {
-        if !vertex_buffer
-            .usage
-            .contains(vk::BufferUsageFlags::VERTEX_BUFFER)
-        {
-            return false;
+        unsafe {
+            command_buffer.device.device.cmd_set_event(
+                command_buffer.handle,
+                event.handle,
+                stage_mask,
+            )
         }
 
-        true
+        self
     }
 
-    fn index_buffer_check(index_buffer: &Buffer) -> bool {
-        if !index_buffer
-            .usage
-            .contains(vk::BufferUsageFlags::INDEX_BUFFER)
-        {
-            return false;
+    /// Unset `event` once the commands recorded before reach `stage_mask`
+    pub fn reset_event(
+        &mut self,
+        event: &'b Event,
+        stage_mask: vk::PipelineStageFlags,
+    ) -> &mut Self {
+        let command_buffer = &self.0.inner;
+
+        unsafe {
+            command_buffer.device.device.cmd_reset_event(
+                command_buffer.handle,
+                event.handle,
+                stage_mask,
+            )
         }
 
-        true
+        self
     }
 
-    pub fn bind_vertex_buffers(
+    /// Wait until `events` are set before executing the `dst_stage_mask` stages of the following commands,
+    /// `src_stage_mask` holds the stages given to the `set_event` of the events, and the barriers apply
+    /// to the commands recorded before these `set_event` only, unlike with a pipeline barrier
+    pub fn wait_events(
         &mut self,
-        first_binding: u32,
-        buffers_and_offsets: &'b [(Buffer, u64)],
-    ) -> Result<&mut Self, DrawError> {
-        for (buffer, _) in buffers_and_offsets {
-            if Self::vertex_buffer_check(buffer) {
-                return Err(DrawError::Draw);
-            }
-        }
-
-        let (buffers, offsets): (Vec<_>, Vec<_>) = buffers_and_offsets
+        events: &[&'b Event],
+        src_stage_mask: vk::PipelineStageFlags,
+        dst_stage_mask: vk::PipelineStageFlags,
+        memory_barriers: &'b [vk::MemoryBarrier],
+        buffer_memory_barriers: &'b [vk::BufferMemoryBarrier],
+        image_memory_barriers: &'b [vk::ImageMemoryBarrier],
+    ) -> &mut Self {
+        let command_buffer = &self.0.inner;
+        let events = events.iter().map(|event| event.handle).collect::<Vec<_>>();
+
+        unsafe {
+            command_buffer.device.device.cmd_wait_events(
+                command_buffer.handle,
+                &events,
+                src_stage_mask,
+                dst_stage_mask,
+                memory_barriers,
+                buffer_memory_barriers,
+                image_memory_barriers,
+            )
+        }
+
+        self
+    }
+
+    /// Mark the current point of the command buffer with `name` in the capture tools and the validation messages,
+    /// `color` is RGBA, it does nothing without the `validation-layers` feature
+    pub fn insert_label(&mut self, name: &str, color: [f32; 4]) -> &mut Self {
+        let command_buffer = &self.0.inner;
+
+        command_buffer
+            .device
+            .cmd_insert_label(command_buffer.handle, name, color);
+
+        self
+    }
+
+    /// Open a region of the command buffer named `name` until `end_label`, regions can be nested,
+    /// it does nothing without the `validation-layers` feature
+    pub fn begin_label(&mut self, name: &str, color: [f32; 4]) -> &mut Self {
+        let command_buffer = &self.0.inner;
+
+        command_buffer
+            .device
+            .cmd_begin_label(command_buffer.handle, name, color);
+
+        self
+    }
+
+    /// Close the last region opened by `begin_label`
+    pub fn end_label(&mut self) -> &mut Self {
+        let command_buffer = &self.0.inner;
+
+        command_buffer.device.cmd_end_label(command_buffer.handle);
+
+        self
+    }
+
+    /// Same as `begin_label`, the region being closed when the returned scope is dropped,
+    /// the commands of the region are recorded through the scope
+    pub fn label_scope(self, name: &str, color: [f32; 4]) -> LabelScope<'a, 'b> {
+        let command_buffer = &self.0.inner;
+
+        command_buffer
+            .device
+            .cmd_begin_label(command_buffer.handle, name, color);
+
+        LabelScope(self.0)
+    }
+
+    /// Reset the queries `queries` of `query_pool`, which must be done before they are used,
+    /// outside of a render pass
+    pub fn reset_query_pool(
+        &mut self,
+        query_pool: &'b QueryPool,
+        queries: Range<u32>,
+    ) -> &mut Self {
+        assert!(
+            queries.end <= query_pool.query_count(),
+            "the queries are out of the pool"
+        );
+
+        let command_buffer = &self.0.inner;
+
+        unsafe {
+            command_buffer.device.device.cmd_reset_query_pool(
+                command_buffer.handle,
+                query_pool.handle,
+                queries.start,
+                queries.end.saturating_sub(queries.start),
+            )
+        }
+
+        self
+    }
+
+    /// Write the timestamp at which the previous commands completed `stage` into the query `query`
+    /// of a `TIMESTAMP` pool, the queue family must support timestamps, see `QueueFamily::timestamp_valid_bits`
+    pub fn write_timestamp(
+        &mut self,
+        stage: vk::PipelineStageFlags,
+        query_pool: &'b QueryPool,
+        query: u32,
+    ) -> &mut Self {
+        assert_eq!(
+            query_pool.query_type(),
+            vk::QueryType::TIMESTAMP,
+            "timestamps are written to a timestamp pool"
+        );
+        assert!(
+            query < query_pool.query_count(),
+            "the query is out of the pool"
+        );
+        assert_ne!(
+            self.0
+                .inner
+                .command_pool
+                .queue_family()
+                .timestamp_valid_bits(),
+            0,
+            "the queue family doesn't support timestamps"
+        );
+
+        let command_buffer = &self.0.inner;
+
+        unsafe {
+            command_buffer.device.device.cmd_write_timestamp(
+                command_buffer.handle,
+                stage,
+                query_pool.handle,
+                query,
+            )
+        }
+
+        self
+    }
+
+    /// Start the query `query` of an `OCCLUSION` or `PIPELINE_STATISTICS` pool, `PRECISE` counts
+    /// the exact number of samples of an occlusion query instead of whether any sample passed
+    pub fn begin_query(
+        &mut self,
+        query_pool: &'b QueryPool,
+        query: u32,
+        flags: vk::QueryControlFlags,
+    ) -> &mut Self {
+        assert_ne!(
+            query_pool.query_type(),
+            vk::QueryType::TIMESTAMP,
+            "timestamps are written with write_timestamp"
+        );
+        assert!(
+            query < query_pool.query_count(),
+            "the query is out of the pool"
+        );
+
+        let command_buffer = &self.0.inner;
+
+        unsafe {
+            command_buffer.device.device.cmd_begin_query(
+                command_buffer.handle,
+                query_pool.handle,
+                query,
+                flags,
+            )
+        }
+
+        self
+    }
+
+    /// End the query `query` started by `begin_query`
+    pub fn end_query(&mut self, query_pool: &'b QueryPool, query: u32) -> &mut Self {
+        let command_buffer = &self.0.inner;
+
+        unsafe {
+            command_buffer.device.device.cmd_end_query(
+                command_buffer.handle,
+                query_pool.handle,
+                query,
+            )
+        }
+
+        self
+    }
+
+    /// Copy the results of the queries `queries` of `query_pool` into `dst_buffer`, the results of a query
+    /// are written at `dst_offset + index * stride` with the size given by `flags`
+    pub fn copy_query_pool_results(
+        &mut self,
+        query_pool: &'b QueryPool,
+        queries: Range<u32>,
+        dst_buffer: &'b mut Buffer,
+        dst_offset: vk::DeviceSize,
+        stride: vk::DeviceSize,
+        flags: vk::QueryResultFlags,
+    ) -> Result<&mut Self, CopyError> {
+        if !dst_buffer
+            .usage
+            .contains(vk::BufferUsageFlags::TRANSFER_DST)
+        {
+            return Err(CopyError::MissingTransferDstUsage);
+        }
+
+        let value_size = if flags.contains(vk::QueryResultFlags::TYPE_64) {
+            8
+        } else {
+            4
+        };
+        let value_count = query_pool.results_per_query() as vk::DeviceSize
+            + flags.contains(vk::QueryResultFlags::WITH_AVAILABILITY) as vk::DeviceSize;
+        let query_count = queries.end.saturating_sub(queries.start) as vk::DeviceSize;
+
+        if !is_aligned(dst_offset, value_size) || !is_aligned(stride, value_size) {
+            return Err(CopyError::UnalignedQueryResults);
+        }
+
+        if queries.end > query_pool.query_count() {
+            return Err(CopyError::OutOfBounds);
+        }
+
+        if query_count > 0
+            && dst_offset + (query_count - 1) * stride + value_count * value_size
+                > dst_buffer.size()
+        {
+            return Err(CopyError::OutOfBounds);
+        }
+
+        let command_buffer = &self.0.inner;
+
+        unsafe {
+            command_buffer.device.device.cmd_copy_query_pool_results(
+                command_buffer.handle,
+                query_pool.handle,
+                queries.start,
+                query_count as _,
+                dst_buffer.handle,
+                dst_offset,
+                stride,
+                flags,
+            )
+        }
+
+        Ok(self)
+    }
+}
+
+/// Region of a command buffer opened by `GenericCommands::label_scope`, closed when it's dropped
+pub struct LabelScope<'a, 'b: 'a>(&'a mut CommandBufferRecorder<'b>);
+
+impl<'a, 'b: 'a> Deref for LabelScope<'a, 'b> {
+    type Target = CommandBufferRecorder<'b>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl<'a, 'b: 'a> DerefMut for LabelScope<'a, 'b> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+    }
+}
+
+impl<'a, 'b: 'a> Drop for LabelScope<'a, 'b> {
+    fn drop(&mut self) {
+        let command_buffer = &self.0.inner;
+
+        command_buffer.device.cmd_end_label(command_buffer.handle);
+    }
+}
+
+pub struct GraphicsGenericCommands<'a, 'b: 'a>(&'a mut CommandBufferRecorder<'b>);
+
+impl<'a, 'b: 'a> GraphicsGenericCommands<'a, 'b> {
+    fn vertex_buffer_check(vertex_buffer: &Buffer) -> bool {
+        if !vertex_buffer
+            .usage
+            .contains(vk::BufferUsageFlags::VERTEX_BUFFER)
+        {
+            return false;
+        }
+
+        true
+    }
+
+    /// Check that all the buffers have the `VERTEX_BUFFER` usage
+    fn vertex_buffers_check(buffers_and_offsets: &[(Buffer, u64)]) -> Result<(), DrawError> {
+        if buffers_and_offsets
+            .iter()
+            .all(|(buffer, _)| Self::vertex_buffer_check(buffer))
+        {
+            Ok(())
+        } else {
+            Err(DrawError::Draw)
+        }
+    }
+
+    fn index_buffer_check(index_buffer: &Buffer) -> bool {
+        if !index_buffer
+            .usage
+            .contains(vk::BufferUsageFlags::INDEX_BUFFER)
+        {
+            return false;
+        }
+
+        true
+    }
+
+    pub fn bind_vertex_buffers(
+        &mut self,
+        first_binding: u32,
+        buffers_and_offsets: &'b [(Buffer, u64)],
+    ) -> Result<&mut Self, DrawError> {
+        Self::vertex_buffers_check(buffers_and_offsets)?;
+
+        let (buffers, offsets): (Vec<_>, Vec<_>) = buffers_and_offsets
             .iter()
             .map(|(buffer, offset)| (buffer.handle, *offset))
             .unzip();
@@ -566,6 +1396,8 @@ impl<'a, 'b: 'a> GraphicsGenericCommands<'a, 'b> {
             );
         }
 
+        self.0.graphics_bindings.index_buffer = true;
+
         Ok(self)
     }
 
@@ -575,24 +1407,37 @@ impl<'a, 'b: 'a> GraphicsGenericCommands<'a, 'b> {
         dynamic_offsets: Option<&'b [u32]>,
     ) -> Result<&mut Self, UnsupportedOperation> {
         let command_buffer = &self.0.inner;
+        let bindings = &mut self.0.graphics_bindings;
+        let dynamic_offsets = dynamic_offsets.unwrap_or(&[]);
+
+        let layout = bindings
+            .graphics_pipeline
+            .as_ref()
+            .ok_or(UnsupportedOperation)?
+            .layout;
+
+        if bindings
+            .descriptor_sets
+            .is_bound(descriptor_sets, dynamic_offsets)
+        {
+            return Ok(self);
+        }
 
         unsafe {
             command_buffer.device.device.cmd_bind_descriptor_sets(
                 command_buffer.handle,
                 vk::PipelineBindPoint::GRAPHICS,
-                self.0
-                    .graphics_bindings
-                    .graphics_pipeline
-                    .as_ref()
-                    .ok_or(UnsupportedOperation)?
-                    .layout,
+                layout,
                 0,
                 descriptor_sets,
-                dynamic_offsets.unwrap_or(&[]),
+                dynamic_offsets,
             )
         }
 
-        self.0.graphics_bindings.descriptors = true;
+        bindings
+            .descriptor_sets
+            .bind(descriptor_sets, dynamic_offsets);
+        bindings.descriptors = true;
 
         Ok(self)
     }
@@ -608,10 +1453,83 @@ impl<'a, 'b: 'a> GraphicsGenericCommands<'a, 'b> {
             )
         }
 
-        self.0.graphics_bindings.graphics_pipeline = Some(pipeline);
+        let bindings = &mut self.0.graphics_bindings;
+
+        // Without the layouts of the sets, only pipelines sharing a pipeline layout are known to be compatible
+        if let Some(previous) = bindings.graphics_pipeline {
+            if previous.layout != pipeline.layout {
+                bindings.descriptor_sets.keep_compatible(0);
+            }
+        }
+
+        bindings.graphics_pipeline = Some(pipeline);
 
         self
     }
+
+    /// Push `data` to the push constant `name` declared in the layout of the bound pipeline
+    pub fn push_constants<T: ByteCopiable>(
+        &mut self,
+        name: &str,
+        data: &T,
+    ) -> Result<&mut Self, PushConstantError> {
+        let pipeline = self
+            .0
+            .graphics_bindings
+            .graphics_pipeline
+            .ok_or(PushConstantError::NoPipeline)?;
+
+        push_constants(
+            &self.0.inner,
+            pipeline.layout,
+            pipeline.push_constant_layout(),
+            name,
+            data,
+        )?;
+
+        Ok(self)
+    }
+}
+
+/// Whether `value` is a multiple of `alignment`, which must be a power of two
+fn is_aligned(value: vk::DeviceSize, alignment: vk::DeviceSize) -> bool {
+    value & (alignment - 1) == 0
+}
+
+/// Record the push of `data` to the push constant `name` of `push_constant_layout`, the one of `layout`
+fn push_constants<T: ByteCopiable>(
+    command_buffer: &CommandBuffer,
+    layout: vk::PipelineLayout,
+    push_constant_layout: &PushConstantLayout,
+    name: &str,
+    data: &T,
+) -> Result<(), PushConstantError> {
+    let push_constant = push_constant_layout
+        .get(name)
+        .ok_or_else(|| PushConstantError::UnknownName(name.to_owned()))?;
+
+    let size = mem::size_of::<T>() as u32;
+
+    if size != push_constant.size() {
+        return Err(PushConstantError::SizeMismatch {
+            expected: push_constant.size(),
+            actual: size,
+        });
+    }
+
+    let constants = unsafe { slice::from_raw_parts(data as *const T as *const u8, size as _) };
+
+    unsafe {
+        command_buffer.device.device.cmd_push_constants(
+            command_buffer.handle,
+            layout,
+            push_constant.push_stage_flags(),
+            push_constant.offset(),
+            constants,
+        )
+    }
+
+    Ok(())
 }
 
 pub struct ComputeGenericCommands<'a, 'b: 'a>(&'a mut CommandBufferRecorder<'b>);
@@ -623,24 +1541,60 @@ impl<'a, 'b: 'a> ComputeGenericCommands<'a, 'b> {
         dynamic_offsets: Option<&'b [u32]>,
     ) -> Result<&mut Self, UnsupportedOperation> {
         let command_buffer = &self.0.inner;
+        let bindings = &mut self.0.compute_bindings;
+        let dynamic_offsets = dynamic_offsets.unwrap_or(&[]);
+
+        let layout = bindings
+            .compute_pipeline
+            .as_ref()
+            .ok_or(UnsupportedOperation)?
+            .layout;
+
+        if bindings
+            .descriptor_sets
+            .is_bound(descriptor_sets, dynamic_offsets)
+        {
+            return Ok(self);
+        }
 
         unsafe {
             command_buffer.device.device.cmd_bind_descriptor_sets(
                 command_buffer.handle,
                 vk::PipelineBindPoint::COMPUTE,
-                self.0
-                    .compute_bindings
-                    .compute_pipeline
-                    .as_ref()
-                    .ok_or(UnsupportedOperation)?
-                    .layout,
+                layout,
                 0,
                 descriptor_sets,
-                dynamic_offsets.unwrap_or(&[]),
+                dynamic_offsets,
             )
         }
 
-        self.0.compute_bindings.descriptors = true;
+        bindings
+            .descriptor_sets
+            .bind(descriptor_sets, dynamic_offsets);
+        bindings.descriptors = true;
+
+        Ok(self)
+    }
+
+    /// Push `data` to the push constant `name` declared in the layout of the bound pipeline
+    pub fn push_constants<T: ByteCopiable>(
+        &mut self,
+        name: &str,
+        data: &T,
+    ) -> Result<&mut Self, PushConstantError> {
+        let pipeline = self
+            .0
+            .compute_bindings
+            .compute_pipeline
+            .ok_or(PushConstantError::NoPipeline)?;
+
+        push_constants(
+            &self.0.inner,
+            pipeline.layout,
+            pipeline.push_constant_layout(),
+            name,
+            data,
+        )?;
 
         Ok(self)
     }
@@ -656,7 +1610,15 @@ impl<'a, 'b: 'a> ComputeGenericCommands<'a, 'b> {
             )
         }
 
-        self.0.compute_bindings.compute_pipeline = Some(&pipeline);
+        let bindings = &mut self.0.compute_bindings;
+
+        if let Some(previous) = bindings.compute_pipeline {
+            bindings
+                .descriptor_sets
+                .keep_compatible(pipeline.compatible_set_count(previous));
+        }
+
+        bindings.compute_pipeline = Some(pipeline);
 
         self
     }
@@ -670,11 +1632,7 @@ impl<'a, 'b: 'a> DrawCommands<'a, 'b> {
         first_binding: u32,
         buffers_and_offsets: &'b [(Buffer, u64)],
     ) -> Result<Self, DrawError> {
-        for (buffer, _) in buffers_and_offsets {
-            if GraphicsGenericCommands::vertex_buffer_check(buffer) {
-                return Err(DrawError::Draw);
-            }
-        }
+        GraphicsGenericCommands::vertex_buffers_check(buffers_and_offsets)?;
 
         let (buffers, offsets): (Vec<_>, Vec<_>) = buffers_and_offsets
             .iter()
@@ -699,27 +1657,48 @@ impl<'a, 'b: 'a> DrawCommands<'a, 'b> {
         Ok(commands)
     }
 
-    /// This function verify that draw preconditions are met
-    /// TODO: complete it
+    /// Whether a graphics pipeline is bound, with the vertex buffers it reads and a descriptor set
+    /// for each set of its layout
     fn can_draw(&self) -> bool {
-        self.0.graphics_bindings.vertex_buffers
+        let bindings = &self.0.graphics_bindings;
+
+        match bindings.graphics_pipeline {
+            Some(pipeline) => {
+                (bindings.vertex_buffers || !pipeline.has_vertex_input())
+                    && bindings.descriptor_sets.count() >= pipeline.set_layout_count()
+            }
+            None => false,
+        }
     }
 
     fn can_draw_indexed(&self) -> bool {
-        self.0.graphics_bindings.index_buffer
+        self.can_draw() && self.0.graphics_bindings.index_buffer
     }
 
-    /// This function verify that indirect draw preconditions are met
-    /// TODO: complete it
-    fn indirect_buffer_check(&self, indirect_buffer: &Buffer) -> bool {
+    /// This function verify that indirect draw preconditions are met for commands of `command_size` bytes
+    fn indirect_buffer_check(
+        &self,
+        indirect_buffer: &Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32,
+        command_size: usize,
+    ) -> Result<(), DrawError> {
         if !indirect_buffer
             .usage
             .contains(vk::BufferUsageFlags::INDIRECT_BUFFER)
         {
-            return false;
+            return Err(DrawError::Indirect);
         }
 
-        true
+        // The stride is only used between several draws
+        if !is_aligned(offset, 4)
+            || (draw_count > 1 && (!is_aligned(stride as _, 4) || (stride as usize) < command_size))
+        {
+            return Err(DrawError::UnalignedIndirect);
+        }
+
+        Ok(())
     }
 
     pub fn as_indexed(&mut self) -> Result<IndexedDrawCommands<'_, 'b>, DrawError> {
@@ -765,9 +1744,13 @@ impl<'a, 'b: 'a> DrawCommands<'a, 'b> {
             return Err(DrawError::Draw);
         }
 
-        if !self.indirect_buffer_check(&indirect_buffer) {
-            return Err(DrawError::Indirect);
-        }
+        self.indirect_buffer_check(
+            &indirect_buffer,
+            offset,
+            draw_count,
+            stride,
+            mem::size_of::<vk::DrawIndirectCommand>(),
+        )?;
 
         let command_buffer = &self.0.inner;
 
@@ -831,6 +1814,8 @@ impl<'a, 'b: 'a> IndexedDrawCommands<'a, 'b> {
             );
         }
 
+        commands.0.graphics_bindings.index_buffer = true;
+
         Ok(commands)
     }
 
@@ -848,6 +1833,10 @@ impl<'a, 'b: 'a> IndexedDrawCommands<'a, 'b> {
             return Err(DrawError::Draw);
         }
 
+        if !self.as_draw().can_draw_indexed() {
+            return Err(DrawError::Indexed);
+        }
+
         let command_buffer = &self.0.inner;
 
         unsafe {
@@ -875,10 +1864,18 @@ impl<'a, 'b: 'a> IndexedDrawCommands<'a, 'b> {
             return Err(DrawError::Draw);
         }
 
-        if !self.as_draw().indirect_buffer_check(&indirect_buffer) {
-            return Err(DrawError::Indirect);
+        if !self.as_draw().can_draw_indexed() {
+            return Err(DrawError::Indexed);
         }
 
+        self.as_draw().indirect_buffer_check(
+            &indirect_buffer,
+            offset,
+            draw_count,
+            stride,
+            mem::size_of::<vk::DrawIndexedIndirectCommand>(),
+        )?;
+
         let command_buffer = &self.0.inner;
 
         unsafe {
@@ -901,16 +1898,29 @@ impl<'a, 'b: 'a> IndexedDrawCommands<'a, 'b> {
     //////////////////////////////////////
 }
 
-/// Add verifications to all functions
 pub struct DispatchCommands<'a, 'b: 'a>(&'a mut CommandBufferRecorder<'b>);
 
 impl<'a, 'b: 'a> DispatchCommands<'a, 'b> {
+    /// Check that a compute pipeline is bound, with a descriptor set for each set of its layout
+    fn check_bindings(&self) -> Result<(), DispatchError> {
+        let bindings = &self.0.compute_bindings;
+        let pipeline = bindings.compute_pipeline.ok_or(DispatchError::NoPipeline)?;
+
+        if bindings.descriptor_sets.count() < pipeline.set_layout_count() {
+            return Err(DispatchError::Dispatch);
+        }
+
+        Ok(())
+    }
+
     pub fn dispatch(
         &mut self,
         group_count_x: u32,
         group_count_y: u32,
         group_count_z: u32,
     ) -> Result<&mut Self, DispatchError> {
+        self.check_bindings()?;
+
         let command_buffer = &self.0.inner;
 
         unsafe {
@@ -930,6 +1940,16 @@ impl<'a, 'b: 'a> DispatchCommands<'a, 'b> {
         buffer: &'b Buffer,
         offset: vk::DeviceSize,
     ) -> Result<&mut Self, DispatchError> {
+        self.check_bindings()?;
+
+        if !buffer.usage.contains(vk::BufferUsageFlags::INDIRECT_BUFFER) {
+            return Err(DispatchError::Indirect);
+        }
+
+        if !is_aligned(offset, 4) {
+            return Err(DispatchError::UnalignedIndirect);
+        }
+
         let command_buffer = &self.0.inner;
 
         unsafe {
@@ -943,6 +1963,43 @@ impl<'a, 'b: 'a> DispatchCommands<'a, 'b> {
         Ok(self)
     }
 
+    /// Dispatch one invocation per element of `buffer` in a 1D grid, with the workgroup size of the bound pipeline
+    /// The group count is rounded up, so the shader must check that its index is in the buffer
+    /// Pipelines whose workgroup size can't be resolved with their specialization constants are rejected
+    pub fn dispatch_over_buffer<T: ByteCopiable>(
+        &mut self,
+        buffer: &TypedBuffer<T>,
+    ) -> Result<&mut Self, DispatchError> {
+        let [local_size_x, _, _] = self
+            .0
+            .compute_bindings
+            .compute_pipeline
+            .ok_or(DispatchError::NoPipeline)?
+            .local_size()
+            .ok_or(DispatchError::UnknownLocalSize)?;
+
+        let len = buffer.len() as u64;
+        let local_size_x = local_size_x as u64;
+        let group_count = match len % local_size_x {
+            0 => len / local_size_x,
+            _ => len / local_size_x + 1,
+        };
+
+        let max_group_count = self
+            .0
+            .inner
+            .device
+            .properties()
+            .limits
+            .max_compute_work_group_count[0];
+
+        if group_count > max_group_count as u64 {
+            return Err(DispatchError::GroupCountExceeded);
+        }
+
+        self.dispatch(group_count as u32, 1, 1)
+    }
+
     //////////////////////////
     // vkCmdDispatchBase    //
     // vkCmdDispatchBaseKHR //
@@ -1050,14 +2107,17 @@ pub struct CopyCommands<'a, 'b: 'a>(&'a mut CommandBufferRecorder<'b>);
 
 impl<'a, 'b: 'a> CopyCommands<'a, 'b> {
     /// dst_buffer should be taken with &mut but src_buffer and dst_buffer can be aliases
-    /// but copy regions shouldn't aliased
-    /// add checks
+    /// When they are, no source region may overlap a destination region
     pub fn copy_buffer(
         &mut self,
         src_buffer: &'b Buffer,
         dst_buffer: &'b Buffer,
         regions: &[vk::BufferCopy],
     ) -> Result<&mut Self, CopyError> {
+        if src_buffer.handle == dst_buffer.handle && Self::regions_overlap(regions) {
+            return Err(CopyError::RegionsOverlapped);
+        }
+
         let command_buffer = &self.0.inner;
 
         unsafe {
@@ -1072,6 +2132,16 @@ impl<'a, 'b: 'a> CopyCommands<'a, 'b> {
         Ok(self)
     }
 
+    /// Whether a source region overlaps a destination region, when both are in the same buffer
+    fn regions_overlap(regions: &[vk::BufferCopy]) -> bool {
+        regions.iter().any(|src| {
+            regions.iter().any(|dst| {
+                src.src_offset < dst.dst_offset + dst.size
+                    && dst.dst_offset < src.src_offset + src.size
+            })
+        })
+    }
+
     /// Should return an error if layout doesn't fit
     /// Same for aliasing as before
     pub fn copy_image(
@@ -1080,6 +2150,21 @@ impl<'a, 'b: 'a> CopyCommands<'a, 'b> {
         dst_image: &'b Image,
         regions: &'b [vk::ImageCopy],
     ) -> Result<&mut Self, CopyError> {
+        for region in regions {
+            self.check_granularity(
+                src_image,
+                region.src_subresource,
+                region.src_offset,
+                region.extent,
+            )?;
+            self.check_granularity(
+                dst_image,
+                region.dst_subresource,
+                region.dst_offset,
+                region.extent,
+            )?;
+        }
+
         let command_buffer = &self.0.inner;
 
         unsafe {
@@ -1102,6 +2187,15 @@ impl<'a, 'b: 'a> CopyCommands<'a, 'b> {
         dst_image: &'b mut Image,
         regions: &'b [vk::BufferImageCopy],
     ) -> Result<&mut Self, CopyError> {
+        for region in regions {
+            self.check_granularity(
+                dst_image,
+                region.image_subresource,
+                region.image_offset,
+                region.image_extent,
+            )?;
+        }
+
         let command_buffer = &self.0.inner;
 
         unsafe {
@@ -1123,6 +2217,15 @@ impl<'a, 'b: 'a> CopyCommands<'a, 'b> {
         dst_buffer: &'b mut Buffer,
         regions: &'b [vk::BufferImageCopy],
     ) -> Result<&mut Self, CopyError> {
+        for region in regions {
+            self.check_granularity(
+                src_image,
+                region.image_subresource,
+                region.image_offset,
+                region.image_extent,
+            )?;
+        }
+
         let command_buffer = &self.0.inner;
 
         unsafe {
@@ -1138,6 +2241,34 @@ impl<'a, 'b: 'a> CopyCommands<'a, 'b> {
         Ok(self)
     }
 
+    /// Check that a region of `image` can be copied by the queue family of the command pool
+    fn check_granularity(
+        &self,
+        image: &Image,
+        subresource: vk::ImageSubresourceLayers,
+        offset: vk::Offset3D,
+        extent: vk::Extent3D,
+    ) -> Result<(), CopyError> {
+        let mip_extent = |len: u32| (len >> subresource.mip_level).max(1);
+        let image_extent = vk::Extent3D {
+            width: mip_extent(image.extent.width),
+            height: mip_extent(image.extent.height),
+            depth: mip_extent(image.extent.depth),
+        };
+
+        if self
+            .0
+            .inner
+            .command_pool
+            .queue_family()
+            .is_image_region_aligned(offset, extent, image_extent)?
+        {
+            Ok(())
+        } else {
+            Err(CopyError::UnalignedImageRegion)
+        }
+    }
+
     pub fn as_graphics_copy(&mut self) -> GraphicsCopyCommands<'_, 'b> {
         if !self.0.inner.command_pool.support_graphics() {
             panic!("Can't use graphics copy command in a command buffer that doesn't supports graphics operation");
@@ -1178,6 +2309,140 @@ impl<'a, 'b: 'a> GraphicsCopyCommands<'a, 'b> {
         Ok(self)
     }
 
+    /// Fill the mip levels of `image` by blitting each one from the previous one with linear filtering,
+    /// the image must have been created with `TRANSFER_SRC` and `TRANSFER_DST` usages and be in
+    /// `TRANSFER_DST_OPTIMAL` layout with its first level written, it's left in `SHADER_READ_ONLY_OPTIMAL` layout
+    /// The format must support linear filtering, see `vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR`
+    pub fn generate_mipmaps(&mut self, image: &'b mut Image) -> Result<&mut Self, CopyError> {
+        assert_eq!(
+            image.layout,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            "the mip levels are blitted in transfer layouts"
+        );
+
+        if !image
+            .format_features()
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+        {
+            return Err(CopyError::UnsupportedLinearFilter);
+        }
+
+        let command_buffer = &self.0.inner;
+        let device = &command_buffer.device.device;
+
+        let mip_offset = |len: u32, level: u32| (len >> level).max(1) as i32;
+        let subresource = |level: u32| {
+            vk::ImageSubresourceLayers::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(level)
+                .base_array_layer(0)
+                .layer_count(image.array_layers())
+                .build()
+        };
+
+        for level in 1..image.mip_levels() {
+            let src_barriers = [image
+                .levels_barrier(
+                    level - 1..level,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                )
+                .build()];
+
+            let regions = [vk::ImageBlit::builder()
+                .src_subresource(subresource(level - 1))
+                .src_offsets([
+                    vk::Offset3D::default(),
+                    vk::Offset3D {
+                        x: mip_offset(image.extent.width, level - 1),
+                        y: mip_offset(image.extent.height, level - 1),
+                        z: mip_offset(image.extent.depth, level - 1),
+                    },
+                ])
+                .dst_subresource(subresource(level))
+                .dst_offsets([
+                    vk::Offset3D::default(),
+                    vk::Offset3D {
+                        x: mip_offset(image.extent.width, level),
+                        y: mip_offset(image.extent.height, level),
+                        z: mip_offset(image.extent.depth, level),
+                    },
+                ])
+                .build()];
+
+            // The source level is done once it's read
+            let sampled_barriers = [image
+                .levels_barrier(
+                    level - 1..level,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::TRANSFER_READ,
+                    vk::AccessFlags::SHADER_READ,
+                )
+                .build()];
+
+            unsafe {
+                device.cmd_pipeline_barrier(
+                    command_buffer.handle,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &src_barriers,
+                );
+                device.cmd_blit_image(
+                    command_buffer.handle,
+                    image.handle,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image.handle,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &regions,
+                    vk::Filter::LINEAR,
+                );
+                device.cmd_pipeline_barrier(
+                    command_buffer.handle,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &sampled_barriers,
+                );
+            }
+        }
+
+        // The last level is only written
+        let last_level = image.mip_levels() - 1;
+        let last_barriers = [image
+            .levels_barrier(
+                last_level..last_level + 1,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            )
+            .build()];
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer.handle,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &last_barriers,
+            );
+        }
+
+        image.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+
+        Ok(self)
+    }
+
     pub fn resolve_image(
         &mut self,
         src_image: &'b Image,
@@ -1201,20 +2466,139 @@ impl<'a, 'b: 'a> GraphicsCopyCommands<'a, 'b> {
     }
 }
 
-/// TODO: créer une vraie graphics pipeline
-pub struct GraphicsPipeline {
-    pub layout: vk::PipelineLayout,
-    pub pipeline: vk::Pipeline,
-}
-
 pub struct ExecutableCommandBuffer(pub(crate) CommandBuffer);
 
 impl ExecutableCommandBuffer {
-    /// # Safety: caller must ensure that this command buffer is in recording state
-    pub unsafe fn to_record(self) -> CommandBufferRecorder<'static> {
+    pub fn usage(&self) -> vk::CommandBufferUsageFlags {
+        self.0.usage
+    }
+
+    /// Whether this command buffer can be submitted again while it's still pending, see `CommandBuffer::begin`
+    pub fn is_simultaneous_use(&self) -> bool {
+        self.0
+            .usage
+            .contains(vk::CommandBufferUsageFlags::SIMULTANEOUS_USE)
+    }
+
+    /// Remember the fence signaled by a submission of this command buffer, for `reset_and_record`
+    /// to check that the command buffer isn't pending anymore, along with the fences of its other submissions
+    /// This is done by `Queue::submit_batch`, whose fences are owned by the handle, but not by `Queue::submit`
+    /// # Safety
+    /// `fence` must be neither destroyed nor reset until the command buffer is observed not pending anymore
+    pub unsafe fn set_pending_fence(&mut self, fence: vk::Fence) {
+        self.mark_pending(fence);
+    }
+
+    /// State of the command buffer, which leaves `Pending` once the fences of all its submissions are observed signaled,
+    /// and is `Initial` once its pool is reset
+    pub fn state(&self) -> Result<CommandBufferState, DeviceLost> {
+        if self.0.is_reset_by_pool() {
+            return Ok(CommandBufferState::Initial);
+        }
+
+        self.is_pending()?;
+
+        Ok(self.0.state.get())
+    }
+
+    /// Whether the fence of one of the submissions of the command buffer isn't signaled yet
+    /// The command buffer is never seen pending after a submission with `Queue::submit`, whose fence isn't tracked
+    pub fn is_pending(&self) -> Result<bool, DeviceLost> {
+        // The pool is only reset once all its submissions are complete
+        if self.0.is_reset_by_pool() {
+            return Ok(false);
+        }
+
+        let fences = self.0.pending_fences.borrow().clone();
+        let mut pending = false;
+
+        for fence in fences {
+            let signaled = self.0.device.check_lost(
+                unsafe { self.0.device.device.get_fence_status(fence) },
+                "failed to get fence status",
+            )?;
+
+            if signaled {
+                self.mark_complete(fence);
+            } else {
+                pending = true;
+            }
+        }
+
+        Ok(pending)
+    }
+
+    /// Track the fence of a new submission, the ones of the previous submissions stay tracked until
+    /// they're observed signaled
+    pub(crate) fn mark_pending(&self, fence: vk::Fence) {
+        self.0.pending_fences.borrow_mut().push(fence);
+        self.0.command_pool.add_pending_fence(fence);
+
+        self.0.state.set(CommandBufferState::Pending);
+    }
+
+    /// Stop tracking the submission signaling `fence`, which is signaled, and leave the `Pending` state
+    /// once no submission is tracked anymore
+    pub(crate) fn mark_complete(&self, fence: vk::Fence) {
+        let mut pending_fences = self.0.pending_fences.borrow_mut();
+
+        if let Some(index) = pending_fences.iter().position(|&pending| pending == fence) {
+            pending_fences.swap_remove(index);
+            self.0.command_pool.remove_pending_fence(fence);
+
+            if pending_fences.is_empty() {
+                self.0.state.set(CommandBufferState::Executable);
+            }
+        }
+    }
+
+    /// Reset the command buffer and record it again with `usage`, see `reset`
+    pub fn reset_and_record(
+        self,
+        usage: vk::CommandBufferUsageFlags,
+    ) -> Result<CommandBufferRecorder<'static>, ResetError> {
+        Ok(self.reset()?.begin(usage))
+    }
+
+    /// Reset the command buffer back to the initial state, the command pool must be created by
+    /// `CommandPool::new_resettable` and the fences given to `set_pending_fence`, if any, must be signaled
+    /// A command buffer already reset with its pool by `CommandPool::reset` is given back as is
+    pub fn reset(self) -> Result<CommandBuffer, ResetError> {
+        if self.0.is_reset_by_pool() {
+            self.0.pending_fences.borrow_mut().clear();
+            self.0.state.set(CommandBufferState::Initial);
+            return Ok(self.0);
+        }
+
+        if !self.0.command_pool.is_resettable() {
+            return Err(ResetError::NotResettable(self));
+        }
+
+        match self.is_pending() {
+            Ok(false) => {}
+            Ok(true) => return Err(ResetError::Pending(self)),
+            Err(DeviceLost) => return Err(ResetError::DeviceLost(self)),
+        }
+
+        self.0.state.set(CommandBufferState::Initial);
+
+        unsafe {
+            self.0
+                .device
+                .device
+                .reset_command_buffer(self.0.handle, vk::CommandBufferResetFlags::empty())
+        }
+        .expect("failed to reset command buffer");
+
+        Ok(self.0)
+    }
+
+    /// Record the command buffer again with the same usage, see `reset_and_record`
+    /// It fails with `ResetError::Pending` rather than recording over a submission still executed by the device
+    pub fn to_record(self) -> Result<CommandBufferRecorder<'static>, ResetError> {
         let usage = self.0.usage;
 
-        self.0.begin(usage)
+        self.reset_and_record(usage)
     }
 
     /// # Safety: caller must ensure that this command buffer can be submitted again
@@ -1229,7 +2613,8 @@ pub struct QueueSubmission<'a> {
     wait_dst_stage_masks: Vec<vk::PipelineStageFlags>,
     command_buffers: Vec<vk::CommandBuffer>,
     signal_semaphores: Vec<vk::Semaphore>,
-    phantom_data: PhantomData<&'a ()>,
+    borrowed_command_buffers: Vec<&'a ExecutableCommandBuffer>,
+    owned_command_buffers: Vec<ExecutableCommandBuffer>,
 }
 
 impl QueueSubmission<'static> {
@@ -1254,6 +2639,46 @@ impl<'a> QueueSubmission<'a> {
     pub(crate) fn signal_semaphores(&self) -> &[vk::Semaphore] {
         &self.signal_semaphores
     }
+
+    fn tracked_command_buffers(&self) -> impl Iterator<Item = &ExecutableCommandBuffer> {
+        self.borrowed_command_buffers
+            .iter()
+            .copied()
+            .chain(&self.owned_command_buffers)
+    }
+
+    pub(crate) fn owns_command_buffers(&self) -> bool {
+        !self.owned_command_buffers.is_empty()
+    }
+
+    /// Fail if a command buffer without `SIMULTANEOUS_USE` is submitted while pending, or if a command buffer
+    /// isn't executable anymore since its pool was reset
+    pub(crate) fn check_not_pending(&self) -> Result<(), SubmitError> {
+        for command_buffer in self.tracked_command_buffers() {
+            if command_buffer.0.is_reset_by_pool() {
+                return Err(SubmitError::NotExecutable);
+            }
+
+            if !command_buffer.is_simultaneous_use() && command_buffer.is_pending()? {
+                return Err(SubmitError::Pending);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn mark_pending(&self, fence: vk::Fence) {
+        for command_buffer in self.tracked_command_buffers() {
+            command_buffer.mark_pending(fence);
+        }
+    }
+
+    /// Called once `fence` is signaled, before it's destroyed or reset
+    pub(crate) fn mark_complete(&self, fence: vk::Fence) {
+        for command_buffer in self.tracked_command_buffers() {
+            command_buffer.mark_complete(fence);
+        }
+    }
 }
 
 #[derive(Default)]
@@ -1274,8 +2699,33 @@ impl<'a> QueueSubmissionBuilder<'a> {
         self
     }
 
+    /// Same as `with_wait_semaphore`, borrowing `semaphore` so that it outlives the submission
+    pub fn with_wait_semaphore_owned(
+        self,
+        semaphore: &'a Semaphore,
+        pipeline_stage: vk::PipelineStageFlags,
+    ) -> Self {
+        self.with_wait_semaphore(semaphore.handle, pipeline_stage)
+    }
+
     pub fn with_command_buffer(mut self, command_buffer: &'a ExecutableCommandBuffer) -> Self {
         self.0.command_buffers.push(command_buffer.0.handle);
+        self.0.borrowed_command_buffers.push(command_buffer);
+        self
+    }
+
+    /// Move the command buffers into the submission, so they are kept alive as long as it
+    /// and freed with it, see `Queue::submit_batch`, `Queue::submit` refuses such submissions
+    pub fn with_owned_command_buffers(
+        mut self,
+        command_buffers: Vec<ExecutableCommandBuffer>,
+    ) -> Self {
+        self.0.command_buffers.extend(
+            command_buffers
+                .iter()
+                .map(|command_buffer| command_buffer.0.handle),
+        );
+        self.0.owned_command_buffers.extend(command_buffers);
         self
     }
 
@@ -1284,6 +2734,12 @@ impl<'a> QueueSubmissionBuilder<'a> {
         self
     }
 
+    /// Same as `with_signal_semaphore`, borrowing `semaphore` so that it outlives the submission
+    pub fn with_signal_semaphore_owned(mut self, semaphore: &'a Semaphore) -> Self {
+        self.0.signal_semaphores.push(semaphore.handle);
+        self
+    }
+
     pub fn with_wait_semaphores<I: IntoIterator<Item = (vk::Semaphore, vk::PipelineStageFlags)>>(
         mut self,
         iter: I,
@@ -1298,10 +2754,9 @@ impl<'a> QueueSubmissionBuilder<'a> {
         mut self,
         iter: I,
     ) -> Self {
-        self.0.command_buffers.extend(
-            iter.into_iter()
-                .map(|command_buffer| command_buffer.0.handle),
-        );
+        for command_buffer in iter {
+            self = self.with_command_buffer(command_buffer);
+        }
         self
     }
 
@@ -1325,6 +2780,609 @@ mod test {
     use ash::vk;
 
     use super::*;
+    use crate::utils;
+    use crate::vulkan::{BufferBuilder, Event, Fence, FencePool};
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn resubmit_simultaneous_use() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), device));
+
+        let command_buffer = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::SIMULTANEOUS_USE)
+            .end();
+
+        assert!(command_buffer.is_simultaneous_use());
+
+        let first = queue
+            .submit_batch(vec![QueueSubmission::builder()
+                .with_command_buffer(&command_buffer)
+                .build()])
+            .unwrap();
+        // The first submission may still be pending here
+        let second = queue
+            .submit_batch(vec![QueueSubmission::builder()
+                .with_command_buffer(&command_buffer)
+                .build()])
+            .unwrap();
+
+        first.wait().unwrap();
+        second.wait().unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn resubmit_pending_command_buffer() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+        let event = Event::new(device);
+
+        // The command buffer stays pending until the host sets the event
+        let mut recorder: CommandBufferRecorder<'_> = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::empty());
+        recorder.as_generic().wait_events(
+            &[&event],
+            vk::PipelineStageFlags::HOST,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            &[],
+            &[],
+            &[],
+        );
+        let command_buffer = recorder.end();
+
+        let submit = || {
+            QueueSubmission::builder()
+                .with_command_buffer(&command_buffer)
+                .build()
+        };
+        let handle = queue.submit_batch(vec![submit()]).unwrap();
+
+        assert_eq!(
+            queue.submit_batch(vec![submit()]).err(),
+            Some(SubmitError::Pending)
+        );
+
+        event.set();
+        handle.wait().unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn owned_command_buffers_need_a_handle() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), device));
+
+        let command_buffer = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .end();
+        let submits = vec![QueueSubmission::builder()
+            .with_owned_command_buffers(vec![command_buffer])
+            .build()];
+
+        assert_eq!(
+            queue.submit(&submits, None),
+            Err(SubmitError::OwnedCommandBuffers)
+        );
+        queue.submit_batch(submits).unwrap().wait().unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn execute_secondary_command_buffers() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), device));
+
+        let inheritance = vk::CommandBufferInheritanceInfo::default();
+        let secondaries = command_pool
+            .allocate_secondary_command_buffers(2)
+            .into_iter()
+            .map(|command_buffer| {
+                let mut recorder =
+                    command_buffer.begin(vk::CommandBufferUsageFlags::empty(), &inheritance);
+                assert!(recorder.as_render_pass_continuation().is_err());
+                recorder.end()
+            })
+            .collect::<Vec<_>>();
+
+        let mut recorder = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        recorder.execute_commands(&secondaries.iter().collect::<Vec<_>>());
+        let command_buffer = recorder.end();
+
+        queue
+            .submit_batch(vec![QueueSubmission::builder()
+                .with_command_buffer(&command_buffer)
+                .build()])
+            .unwrap()
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn bindings_are_forgotten_after_secondary_command_buffers() {
+        use std::num::NonZeroU32;
+        use std::path::Path;
+
+        use crate::vulkan::{DescriptorPoolBuilder, DescriptorSetLayoutBuilder};
+
+        let (_instance, device, queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let layouts = [DescriptorSetLayoutBuilder::new()
+            .with_binding(
+                vk::DescriptorType::STORAGE_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .with_binding(
+                vk::DescriptorType::STORAGE_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .build(Rc::clone(&device))
+            .unwrap()];
+        let pipeline = ComputePipeline::new(
+            Path::new("../shaders/reduce.comp.spv"),
+            "main",
+            &layouts,
+            None,
+            None,
+            None,
+            None,
+            Rc::clone(&device),
+        );
+        let descriptor_pool = DescriptorPoolBuilder::new()
+            .with(vk::DescriptorType::STORAGE_BUFFER, 2)
+            .build(1, Rc::clone(&device));
+        let descriptor_sets = layouts[0]
+            .allocate_descriptor_sets(1, &descriptor_pool)
+            .iter()
+            .map(|descriptor_set| descriptor_set.handle)
+            .collect::<Vec<_>>();
+
+        let secondary = command_pool
+            .allocate_secondary_command_buffers(1)
+            .remove(0)
+            .begin(
+                vk::CommandBufferUsageFlags::empty(),
+                &vk::CommandBufferInheritanceInfo::default(),
+            )
+            .end();
+
+        let mut recorder: CommandBufferRecorder<'_> = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        recorder
+            .as_generic()
+            .as_generic_compute()
+            .unwrap()
+            .bind_pipeline(&pipeline)
+            .bind_descriptor_sets(&descriptor_sets, None)
+            .unwrap();
+
+        recorder.execute_commands(&[&secondary]);
+        assert!(recorder.compute_bindings.compute_pipeline.is_none());
+        assert_eq!(recorder.compute_bindings.descriptor_sets.count(), 0);
+        assert_eq!(
+            recorder
+                .as_compute_command_buffer()
+                .unwrap()
+                .dispatch(1, 1, 1)
+                .err(),
+            Some(DispatchError::NoPipeline)
+        );
+
+        // The same pipeline and descriptor sets are bound again instead of being skipped
+        recorder
+            .as_generic()
+            .as_generic_compute()
+            .unwrap()
+            .bind_pipeline(&pipeline)
+            .bind_descriptor_sets(&descriptor_sets, None)
+            .unwrap();
+        assert!(recorder.compute_bindings.descriptors);
+        assert_eq!(recorder.compute_bindings.descriptor_sets.count(), 1);
+
+        recorder.end();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn semaphore_chain() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let semaphore = Semaphore::new(device);
+
+        let first = QueueSubmission::builder()
+            .with_signal_semaphore_owned(&semaphore)
+            .build();
+        let second = QueueSubmission::builder()
+            .with_wait_semaphore_owned(&semaphore, vk::PipelineStageFlags::ALL_COMMANDS)
+            .build();
+
+        queue
+            .submit_batch(vec![first, second])
+            .unwrap()
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn reset_and_record_after_completion() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new_resettable(
+            queue.family(),
+            Rc::clone(&device),
+        ));
+        let fence_pool = FencePool::new(Rc::clone(&device));
+
+        let mut command_buffer = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .end();
+
+        for _ in 0..3 {
+            let fence = fence_pool.acquire();
+            queue
+                .submit(
+                    &[QueueSubmission::builder()
+                        .with_command_buffer(&command_buffer)
+                        .build()],
+                    Some(fence),
+                )
+                .unwrap();
+            // The fence is released to the pool only once the command buffer is recorded again
+            unsafe { command_buffer.set_pending_fence(fence) };
+
+            unsafe { device.device.wait_for_fences(&[fence], true, u64::MAX) }.unwrap();
+
+            command_buffer = command_buffer
+                .reset_and_record(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+                .unwrap()
+                .end();
+            fence_pool.release(fence).unwrap();
+        }
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn pending_command_buffer_is_not_recorded() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new_resettable(
+            queue.family(),
+            Rc::clone(&device),
+        ));
+        let fence = Fence::new(false, Rc::clone(&device));
+
+        let mut command_buffer = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::empty())
+            .end();
+
+        // The fence isn't signaled yet, as if the submission was still pending
+        unsafe { command_buffer.set_pending_fence(fence.handle) };
+        let mut command_buffer = match command_buffer.to_record() {
+            Err(ResetError::Pending(command_buffer)) => command_buffer,
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        };
+        assert_eq!(command_buffer.state(), Ok(CommandBufferState::Pending));
+
+        // A later submission doesn't hide the pending one
+        let signaled = Fence::new(true, Rc::clone(&device));
+        unsafe { command_buffer.set_pending_fence(signaled.handle) };
+        assert_eq!(command_buffer.state(), Ok(CommandBufferState::Pending));
+
+        queue.submit_fenced(&[], &fence).unwrap();
+        fence.wait(None).unwrap();
+        assert_eq!(command_buffer.state(), Ok(CommandBufferState::Executable));
+        let command_buffer = command_buffer.to_record().unwrap().end();
+
+        let handle = queue
+            .submit_batch(vec![QueueSubmission::builder()
+                .with_command_buffer(&command_buffer)
+                .build()])
+            .unwrap();
+        assert_eq!(command_buffer.0.state.get(), CommandBufferState::Pending);
+
+        // The handle observes the completion before destroying its fence
+        handle.wait().unwrap();
+        assert!(command_buffer.0.pending_fences.borrow().is_empty());
+        assert_eq!(command_buffer.0.state.get(), CommandBufferState::Executable);
+
+        drop(command_buffer);
+        command_pool.reset(true).unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn pool_with_pending_command_buffers_is_not_reset() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+        let event = Event::new(device);
+
+        let mut recorder: CommandBufferRecorder<'_> = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::empty());
+        recorder.as_generic().wait_events(
+            &[&event],
+            vk::PipelineStageFlags::HOST,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            &[],
+            &[],
+            &[],
+        );
+        let command_buffer = recorder.end();
+
+        let handle = queue
+            .submit_batch(vec![QueueSubmission::builder()
+                .with_command_buffer(&command_buffer)
+                .build()])
+            .unwrap();
+        assert_eq!(
+            command_pool.reset(false),
+            Err(CommandPoolResetError::Pending)
+        );
+
+        event.set();
+        handle.wait().unwrap();
+        assert_eq!(command_pool.reset(false), Ok(()));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn simultaneous_submissions_are_all_tracked() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+        let event = Event::new(Rc::clone(&device));
+
+        let mut recorder = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::SIMULTANEOUS_USE);
+        recorder.as_generic().wait_events(
+            &[&event],
+            vk::PipelineStageFlags::HOST,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            &[],
+            &[],
+            &[],
+        );
+        let command_buffer = recorder.end();
+
+        let first = queue
+            .submit_batch(vec![QueueSubmission::builder()
+                .with_command_buffer(&command_buffer)
+                .build()])
+            .unwrap();
+        let second = queue
+            .submit_batch(vec![QueueSubmission::builder()
+                .with_command_buffer(&command_buffer)
+                .build()])
+            .unwrap();
+        assert_eq!(command_buffer.0.pending_fences.borrow().len(), 2);
+
+        event.set();
+        second.wait().unwrap();
+        first.wait().unwrap();
+        assert_eq!(command_buffer.state(), Ok(CommandBufferState::Executable));
+        assert_eq!(command_pool.reset(false), Ok(()));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn command_buffers_are_not_submitted_after_pool_reset() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let command_buffer = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::empty())
+            .end();
+        let recorder = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::empty());
+
+        queue
+            .submit_batch(vec![QueueSubmission::builder()
+                .with_command_buffer(&command_buffer)
+                .build()])
+            .unwrap()
+            .wait()
+            .unwrap();
+
+        command_pool.reset(false).unwrap();
+        assert_eq!(command_buffer.state(), Ok(CommandBufferState::Initial));
+        assert_eq!(
+            queue
+                .submit_batch(vec![QueueSubmission::builder()
+                    .with_command_buffer(&command_buffer)
+                    .build()])
+                .err(),
+            Some(SubmitError::NotExecutable)
+        );
+
+        // The recording was interrupted by the reset, so it isn't ended
+        let interrupted = recorder.end();
+        assert_eq!(interrupted.state(), Ok(CommandBufferState::Initial));
+
+        // Recorded again, the command buffer can be submitted
+        let command_buffer = command_buffer.to_record().unwrap().end();
+        queue
+            .submit_batch(vec![QueueSubmission::builder()
+                .with_command_buffer(&command_buffer)
+                .build()])
+            .unwrap()
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn overlapping_buffer_copies() {
+        let region = |src_offset, dst_offset, size| vk::BufferCopy {
+            src_offset,
+            dst_offset,
+            size,
+        };
+
+        assert!(!CopyCommands::regions_overlap(&[region(0, 16, 16)]));
+        assert!(CopyCommands::regions_overlap(&[region(0, 8, 16)]));
+        // The source of a region overlaps the destination of another one
+        assert!(CopyCommands::regions_overlap(&[
+            region(0, 64, 16),
+            region(32, 8, 16)
+        ]));
+        assert!(!CopyCommands::regions_overlap(&[
+            region(0, 64, 16),
+            region(16, 80, 16)
+        ]));
+    }
+
+    #[test]
+    fn render_pass_image_barriers() {
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .old_layout(vk::ImageLayout::GENERAL)
+            .new_layout(vk::ImageLayout::GENERAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .build();
+        let transition = vk::ImageMemoryBarrier {
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ..barrier
+        };
+        let ownership_transfer = vk::ImageMemoryBarrier {
+            dst_queue_family_index: 1,
+            ..barrier
+        };
+
+        assert_eq!(
+            InsideOfRenderpassScope::check_image_barriers(&[barrier, barrier]),
+            Ok(())
+        );
+        assert_eq!(
+            InsideOfRenderpassScope::check_image_barriers(&[barrier, transition]),
+            Err(BarrierError::LayoutMismatchInRenderpass { index: 1 })
+        );
+        assert_eq!(
+            InsideOfRenderpassScope::check_image_barriers(&[ownership_transfer]),
+            Err(BarrierError::QueueFamilyMismatchInRenderpass { index: 0 })
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn unaligned_query_results_are_not_copied() {
+        let (instance, device, queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let query_pool = QueryPool::new(
+            vk::QueryType::OCCLUSION,
+            2,
+            vk::QueryPipelineStatisticFlags::empty(),
+            Rc::clone(&device),
+        );
+        let copies = [
+            (2, 4, vk::QueryResultFlags::empty()),
+            (0, 6, vk::QueryResultFlags::empty()),
+            (4, 8, vk::QueryResultFlags::TYPE_64),
+            (8, 12, vk::QueryResultFlags::TYPE_64),
+            (8, 16, vk::QueryResultFlags::TYPE_64),
+        ];
+        // The destination buffers are borrowed for the whole recording
+        let mut buffers = copies
+            .iter()
+            .map(|_| {
+                BufferBuilder::new()
+                    .size(64)
+                    .usage(vk::BufferUsageFlags::TRANSFER_DST)
+                    .build(Rc::clone(&device), &instance)
+            })
+            .collect::<Vec<_>>();
+
+        let mut recorder: CommandBufferRecorder<'_> = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        let mut generic = recorder.as_generic();
+
+        let results = copies
+            .iter()
+            .zip(&mut buffers)
+            .map(|(&(dst_offset, stride, flags), buffer)| {
+                generic
+                    .copy_query_pool_results(&query_pool, 0..2, buffer, dst_offset, stride, flags)
+                    .err()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            results,
+            vec![
+                Some(CopyError::UnalignedQueryResults),
+                Some(CopyError::UnalignedQueryResults),
+                Some(CopyError::UnalignedQueryResults),
+                Some(CopyError::UnalignedQueryResults),
+                None,
+            ]
+        );
+
+        recorder.end();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn vertex_buffers_need_the_vertex_usage() {
+        let (instance, device, _queue) = utils::create_test_device();
+
+        let vertex_buffer = BufferBuilder::new()
+            .size(16)
+            .vertex()
+            .build(Rc::clone(&device), &instance);
+        let storage_buffer = BufferBuilder::new()
+            .size(16)
+            .storage()
+            .build(device, &instance);
+
+        let buffers_and_offsets = [(vertex_buffer, 0)];
+        assert_eq!(
+            GraphicsGenericCommands::vertex_buffers_check(&buffers_and_offsets),
+            Ok(())
+        );
+
+        let [(vertex_buffer, _)] = buffers_and_offsets;
+        let buffers_and_offsets = [(vertex_buffer, 0), (storage_buffer, 0)];
+        assert_eq!(
+            GraphicsGenericCommands::vertex_buffers_check(&buffers_and_offsets),
+            Err(DrawError::Draw)
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn dispatch_requires_a_pipeline() {
+        let (_instance, device, queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), device));
+
+        let mut recorder = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        assert_eq!(
+            recorder
+                .as_compute_command_buffer()
+                .unwrap()
+                .dispatch(1, 1, 1)
+                .err(),
+            Some(DispatchError::NoPipeline)
+        );
+
+        recorder.end();
+    }
 
     #[test]
     #[should_panic]
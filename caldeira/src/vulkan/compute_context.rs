@@ -0,0 +1,259 @@
+use std::error::Error;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::rc::Rc;
+use std::slice;
+
+use ash::vk;
+
+use super::{
+    Buffer, CommandPool, ComputePipeline, DescriptorPool, DescriptorPoolBuilder, DescriptorSet,
+    DescriptorSetLayout, DescriptorSetLayoutBuilder, DescriptorWriteError, Device, DeviceBuilder,
+    DeviceError, DeviceLost, Image, Instance, InstanceBuilder, InstanceError, Queue,
+    QueueCreateInfo,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ContextError {
+    Instance(InstanceError),
+    Device(DeviceError),
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Instance(error) => error.fmt(f),
+            Self::Device(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for ContextError {}
+
+impl From<InstanceError> for ContextError {
+    fn from(error: InstanceError) -> Self {
+        Self::Instance(error)
+    }
+}
+
+impl From<DeviceError> for ContextError {
+    fn from(error: DeviceError) -> Self {
+        Self::Device(error)
+    }
+}
+
+/// Everything needed to run a single compute shader, built in one call: an instance, a device with
+/// one compute queue, a command pool, the descriptor set of the shader and its pipeline
+/// The lower layers stay reachable through the getters
+pub struct ComputeContext {
+    // Fields are dropped in declaration order, so every object is destroyed before the device
+    pipeline: ComputePipeline,
+    descriptor_set: DescriptorSet,
+    descriptor_pool: DescriptorPool,
+    descriptor_set_layout: DescriptorSetLayout,
+    command_pool: Rc<CommandPool>,
+    queue: Queue,
+    device: Rc<Device>,
+    instance: Rc<Instance>,
+}
+
+impl ComputeContext {
+    /// `shader_spirv` is the compute shader, whose entry point is `main`, and `descriptor_bindings` the
+    /// types of the bindings 0, 1... of its set 0, each holding a single descriptor
+    pub fn new(
+        shader_spirv: &[u32],
+        descriptor_bindings: &[vk::DescriptorType],
+    ) -> Result<Self, ContextError> {
+        let instance = Rc::new(InstanceBuilder::new().headless().build()?);
+
+        // No swapchain is created, so the device doesn't need the extension either
+        let (device, mut queues) = DeviceBuilder::new().extensions(&[]).build(
+            |queue_family, _| {
+                if queue_family.support_compute() {
+                    Some(QueueCreateInfo::new(vec![1.0]))
+                } else {
+                    None
+                }
+            },
+            Rc::clone(&instance),
+        )?;
+
+        let queue = queues.swap_remove(0).swap_remove(0);
+        let command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let descriptor_set_layout = descriptor_bindings
+            .iter()
+            .fold(
+                DescriptorSetLayoutBuilder::new(),
+                |builder, &descriptor_type| {
+                    builder.with_binding(
+                        descriptor_type,
+                        NonZeroU32::new(1).unwrap(),
+                        vk::ShaderStageFlags::COMPUTE,
+                        None,
+                    )
+                },
+            )
+            .build(Rc::clone(&device))
+            .expect("a layout without binding flags requires no feature");
+
+        let descriptor_pool =
+            DescriptorPoolBuilder::from_layout(&descriptor_set_layout).build(1, Rc::clone(&device));
+
+        let descriptor_set = descriptor_set_layout
+            .allocate_descriptor_sets(1, &descriptor_pool)
+            .remove(0);
+
+        let pipeline = ComputePipeline::from_spirv(
+            shader_spirv,
+            "main",
+            slice::from_ref(&descriptor_set_layout),
+            None,
+            None,
+            None,
+            Some("compute context"),
+            Rc::clone(&device),
+        );
+
+        Ok(Self {
+            pipeline,
+            descriptor_set,
+            descriptor_pool,
+            descriptor_set_layout,
+            command_pool,
+            queue,
+            device,
+            instance,
+        })
+    }
+
+    /// Write the whole `buffer` into `binding`, which must be a uniform or storage buffer binding
+    pub fn write_buffer(&self, binding: u32, buffer: &Buffer) -> Result<(), DescriptorWriteError> {
+        self.descriptor_set
+            .writer()
+            .write_buffer(binding, buffer, 0, vk::WHOLE_SIZE)?
+            .update();
+
+        Ok(())
+    }
+
+    /// Write `image` into the storage image `binding`, see `DescriptorSetWriter::write_storage_image`
+    pub fn write_storage_image(
+        &self,
+        binding: u32,
+        image: &Image,
+    ) -> Result<(), DescriptorWriteError> {
+        self.descriptor_set
+            .writer()
+            .write_storage_image(binding, image)?
+            .update();
+
+        Ok(())
+    }
+
+    /// Dispatch `x * y * z` workgroups and wait until they are executed
+    pub fn dispatch(&mut self, x: u32, y: u32, z: u32) -> Result<(), DeviceLost> {
+        let pipeline = &self.pipeline;
+        let descriptor_sets = [self.descriptor_set.handle];
+
+        self.command_pool.single_time(&mut self.queue, |recorder| {
+            recorder
+                .as_generic()
+                .as_generic_compute()
+                .unwrap()
+                .bind_pipeline(pipeline)
+                .bind_descriptor_sets(&descriptor_sets, None)
+                .unwrap();
+
+            recorder
+                .as_compute_command_buffer()
+                .unwrap()
+                .dispatch(x, y, z)
+                .unwrap();
+        })
+    }
+
+    pub fn instance(&self) -> &Rc<Instance> {
+        &self.instance
+    }
+
+    pub fn device(&self) -> &Rc<Device> {
+        &self.device
+    }
+
+    pub fn queue(&mut self) -> &mut Queue {
+        &mut self.queue
+    }
+
+    pub fn command_pool(&mut self) -> &mut Rc<CommandPool> {
+        &mut self.command_pool
+    }
+
+    /// The command pool and the queue, both needed by the operations waiting for their commands
+    pub fn command_pool_and_queue(&mut self) -> (&mut Rc<CommandPool>, &mut Queue) {
+        (&mut self.command_pool, &mut self.queue)
+    }
+
+    pub fn pipeline(&self) -> &ComputePipeline {
+        &self.pipeline
+    }
+
+    pub fn descriptor_set_layout(&self) -> &DescriptorSetLayout {
+        &self.descriptor_set_layout
+    }
+
+    pub fn descriptor_set(&self) -> &DescriptorSet {
+        &self.descriptor_set
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils;
+    use crate::vulkan::TypedBuffer;
+
+    #[test]
+    #[ignore = "requires a Vulkan device and the compiled shaders"]
+    fn dispatch_reduction() {
+        let shader_spirv = utils::read_file("../shaders/reduce.comp.spv");
+        let mut context = ComputeContext::new(
+            &shader_spirv,
+            &[
+                vk::DescriptorType::STORAGE_BUFFER,
+                vk::DescriptorType::STORAGE_BUFFER,
+            ],
+        )
+        .unwrap();
+
+        let host_visible =
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let mut input = TypedBuffer::<u32>::new(
+            256,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            host_visible,
+            None,
+            Rc::clone(context.device()),
+            context.instance(),
+        );
+        input.copy_from_slice(&[1; 256], 0);
+        let output = TypedBuffer::<u32>::new(
+            1,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            None,
+            Rc::clone(context.device()),
+            context.instance(),
+        );
+
+        context.write_buffer(0, input.buffer()).unwrap();
+        context.write_buffer(1, output.buffer()).unwrap();
+        context.dispatch(1, 1, 1).unwrap();
+
+        let (command_pool, queue) = context.command_pool_and_queue();
+        assert_eq!(
+            output.read_all_via_staging(command_pool, queue).unwrap(),
+            vec![256]
+        );
+    }
+}
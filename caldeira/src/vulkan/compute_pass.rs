@@ -0,0 +1,406 @@
+use std::error::Error;
+use std::mem;
+use std::rc::Rc;
+use std::slice;
+
+use ash::vk;
+
+use super::{
+    ByteCopiable, CommandBufferRecorder, CommandPool, ComputePipeline, DescriptorPoolBuilder,
+    DescriptorSetLayout, Device, DeviceLost, Instance, Queue, TypedBuffer,
+};
+use crate::utils;
+
+/// Two resources used alternatively as input and output of successive passes
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PingPong<T> {
+    resources: [T; 2],
+    current: usize,
+}
+
+impl<T> PingPong<T> {
+    pub fn new(current: T, next: T) -> Self {
+        Self {
+            resources: [current, next],
+            current: 0,
+        }
+    }
+
+    /// The resource read by the next pass
+    pub fn current(&self) -> &T {
+        &self.resources[self.current]
+    }
+
+    /// The resource written by the next pass
+    pub fn next(&self) -> &T {
+        &self.resources[1 - self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut T {
+        &mut self.resources[self.current]
+    }
+
+    pub fn next_mut(&mut self) -> &mut T {
+        &mut self.resources[1 - self.current]
+    }
+
+    pub fn swap(&mut self) {
+        self.current = 1 - self.current;
+    }
+
+    /// Swap once per pass, to follow the resources after `iterations` passes
+    pub fn advance(&mut self, iterations: u32) {
+        if iterations % 2 == 1 {
+            self.swap();
+        }
+    }
+
+    pub fn into_inner(self) -> [T; 2] {
+        self.resources
+    }
+}
+
+/// Dispatch a compute pipeline several times, with a barrier between each pass so that
+/// a pass reads what the previous one wrote
+pub struct ComputePass<'a> {
+    pipeline: &'a ComputePipeline,
+    descriptor_sets: &'a PingPong<Vec<vk::DescriptorSet>>,
+    group_count: [u32; 3],
+    iterations: u32,
+    memory_barriers: [vk::MemoryBarrier; 1],
+}
+
+impl<'a> ComputePass<'a> {
+    /// `descriptor_sets.current()` is bound on even passes and `descriptor_sets.next()` on odd ones
+    pub fn new(
+        pipeline: &'a ComputePipeline,
+        descriptor_sets: &'a PingPong<Vec<vk::DescriptorSet>>,
+        group_count: [u32; 3],
+        iterations: u32,
+    ) -> Self {
+        let memory_barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .build();
+
+        Self {
+            pipeline,
+            descriptor_sets,
+            group_count,
+            iterations,
+            memory_barriers: [memory_barrier],
+        }
+    }
+
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    /// Record all the passes, resources bound as storage images must be in the `GENERAL` layout
+    pub fn record(
+        &'a self,
+        command_buffer: &mut CommandBufferRecorder<'a>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let [x, y, z] = self.group_count;
+
+        for iteration in 0..self.iterations {
+            if iteration > 0 {
+                command_buffer.as_generic().pipeline_barrier(
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &self.memory_barriers,
+                    &[],
+                    &[],
+                );
+            }
+
+            let descriptor_sets = if iteration % 2 == 0 {
+                self.descriptor_sets.current()
+            } else {
+                self.descriptor_sets.next()
+            };
+
+            command_buffer
+                .as_generic()
+                .as_generic_compute()?
+                .bind_pipeline(self.pipeline)
+                .bind_descriptor_sets(descriptor_sets, None)?;
+
+            command_buffer
+                .as_compute_command_buffer()?
+                .dispatch(x, y, z)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reduce `input` to a single value by running `pipeline` until one element is left, then read it back
+/// Each workgroup of the shader must reduce up to `local_size_x` elements of the storage buffer at binding 0,
+/// whose length is the number of elements left, into the element `gl_WorkGroupID.x` of the storage buffer
+/// at binding 1, as `shaders/reduce.comp` does, and `layout` must be the layout of these bindings
+/// `input` must have the `STORAGE_BUFFER` and `TRANSFER_SRC` usages
+pub fn reduce<T: ByteCopiable + Copy + Default>(
+    input: &TypedBuffer<T>,
+    pipeline: &ComputePipeline,
+    layout: &DescriptorSetLayout,
+    command_pool: &mut Rc<CommandPool>,
+    queue: &mut Queue,
+    device: Rc<Device>,
+    instance: &Instance,
+) -> Result<T, DeviceLost> {
+    if input.is_empty() {
+        return Ok(T::default());
+    }
+
+    // A single element is already reduced, without any pass to run
+    if input.len() == 1 {
+        return Ok(input.read_all_via_staging(command_pool, queue)?[0]);
+    }
+
+    let [local_size_x, _, _] = pipeline
+        .local_size()
+        .expect("the workgroup size of a reduction pipeline must be known");
+    let local_size_x = local_size_x as usize;
+
+    // Number of elements read by each pass, the last pass writes a single element
+    let mut lens = vec![input.len()];
+    while *lens.last().unwrap() > 1 {
+        let len = *lens.last().unwrap();
+        lens.push(group_count(len, local_size_x));
+    }
+    lens.pop();
+
+    let scratch_len = lens.get(1).copied().unwrap_or(1);
+    let new_scratch = || {
+        TypedBuffer::<T>::new(
+            scratch_len,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            None,
+            Rc::clone(&device),
+            instance,
+        )
+    };
+    let mut scratch = PingPong::new(new_scratch(), new_scratch());
+
+    let readback = TypedBuffer::<T>::new(
+        1,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        None,
+        Rc::clone(&device),
+        instance,
+    );
+
+    let pass_count = lens.len() as u32;
+    let descriptor_pool = DescriptorPoolBuilder::new()
+        .with(vk::DescriptorType::STORAGE_BUFFER, 2 * pass_count)
+        .build(pass_count, Rc::clone(&device));
+    let descriptor_sets = layout.allocate_descriptor_sets(pass_count, &descriptor_pool);
+
+    // The first pass reads `input`, the next ones read what the previous pass wrote
+    for (pass, (descriptor_set, &len)) in descriptor_sets.iter().zip(&lens).enumerate() {
+        let (source, destination) = match pass {
+            0 => (input, scratch.next()),
+            pass if pass % 2 == 0 => (scratch.current(), scratch.next()),
+            _ => (scratch.next(), scratch.current()),
+        };
+
+        descriptor_set
+            .writer()
+            .write_buffer(0, source.buffer(), 0, (len * mem::size_of::<T>()) as _)
+            .and_then(|writer| writer.write_buffer(1, destination.buffer(), 0, vk::WHOLE_SIZE))
+            .expect("the bindings of a reduction must be storage buffers")
+            .update();
+    }
+
+    // After one swap per pass, the last written buffer is the current one
+    scratch.advance(pass_count);
+    let result = scratch.current().buffer();
+
+    let pass_barriers = [vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)
+        .build()];
+    let readback_barriers = [vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .build()];
+    let regions = [vk::BufferCopy::builder()
+        .size(mem::size_of::<T>() as _)
+        .build()];
+    let descriptor_sets = descriptor_sets
+        .iter()
+        .map(|descriptor_set| [descriptor_set.handle])
+        .collect::<Vec<_>>();
+
+    command_pool.single_time(queue, |recorder| {
+        for (pass, (descriptor_set, &len)) in descriptor_sets.iter().zip(&lens).enumerate() {
+            if pass > 0 {
+                recorder.as_generic().pipeline_barrier(
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &pass_barriers,
+                    &[],
+                    &[],
+                );
+            }
+
+            recorder
+                .as_generic()
+                .as_generic_compute()
+                .unwrap()
+                .bind_pipeline(pipeline)
+                .bind_descriptor_sets(descriptor_set, None)
+                .unwrap();
+
+            recorder
+                .as_compute_command_buffer()
+                .unwrap()
+                .dispatch(group_count(len, local_size_x) as _, 1, 1)
+                .unwrap();
+        }
+
+        recorder.as_generic().pipeline_barrier(
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &readback_barriers,
+            &[],
+            &[],
+        );
+
+        recorder
+            .as_transfer_command_buffer()
+            .unwrap()
+            .as_copy()
+            .copy_buffer(result, readback.buffer(), &regions)
+            .unwrap();
+    })?;
+
+    let mut value = [T::default()];
+    readback.read_into_slice(&mut value, 0);
+
+    Ok(value[0])
+}
+
+/// Pipeline for `reduce` with the bindings of `layout`, running `shaders/reduce_subgroup.comp` which adds with
+/// subgroup operations if the device supports them in compute shaders, and `shaders/reduce.comp` otherwise
+pub fn reduction_pipeline(layout: &DescriptorSetLayout, device: Rc<Device>) -> ComputePipeline {
+    let path = if device.supports_subgroup_arithmetic() {
+        "shaders/reduce_subgroup.comp.spv"
+    } else {
+        "shaders/reduce.comp.spv"
+    };
+
+    ComputePipeline::from_spirv(
+        &utils::read_file(path),
+        "main",
+        slice::from_ref(layout),
+        None,
+        None,
+        None,
+        Some("reduction"),
+        device,
+    )
+}
+
+/// Number of workgroups of `local_size` invocations needed to cover `len` elements
+fn group_count(len: usize, local_size: usize) -> usize {
+    match len % local_size {
+        0 => len / local_size,
+        _ => len / local_size + 1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ping_pong_swap() {
+        let mut ping_pong = PingPong::new(1, 2);
+
+        assert_eq!((*ping_pong.current(), *ping_pong.next()), (1, 2));
+
+        ping_pong.swap();
+        assert_eq!((*ping_pong.current(), *ping_pong.next()), (2, 1));
+
+        *ping_pong.next_mut() = 3;
+        ping_pong.advance(3);
+        assert_eq!((*ping_pong.current(), *ping_pong.next()), (3, 2));
+
+        ping_pong.advance(2);
+        assert_eq!(ping_pong.into_inner(), [3, 2]);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn single_element_is_reduced_without_pass() {
+        use std::num::NonZeroU32;
+        use std::path::Path;
+
+        use crate::vulkan::DescriptorSetLayoutBuilder;
+
+        let (instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let layout = DescriptorSetLayoutBuilder::new()
+            .with_binding(
+                vk::DescriptorType::STORAGE_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .with_binding(
+                vk::DescriptorType::STORAGE_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .build(Rc::clone(&device))
+            .unwrap();
+        let pipeline = ComputePipeline::new(
+            Path::new("../shaders/reduce.comp.spv"),
+            "main",
+            slice::from_ref(&layout),
+            None,
+            None,
+            None,
+            None,
+            Rc::clone(&device),
+        );
+
+        let mut input = TypedBuffer::<u32>::new(
+            1,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            None,
+            Rc::clone(&device),
+            &instance,
+        );
+        input.copy_from_slice(&[42], 0);
+
+        let sum = reduce(
+            &input,
+            &pipeline,
+            &layout,
+            &mut command_pool,
+            &mut queue,
+            device,
+            &instance,
+        )
+        .unwrap();
+        assert_eq!(sum, 42);
+    }
+
+    #[test]
+    fn group_count_rounds_up() {
+        assert_eq!(group_count(1, 256), 1);
+        assert_eq!(group_count(256, 256), 1);
+        assert_eq!(group_count(257, 256), 2);
+    }
+}
@@ -1,31 +1,210 @@
 use std::ffi::CString;
+use std::path::Path;
 use std::rc::Rc;
 
 use ash::version::DeviceV1_0;
 use ash::vk;
 
-use super::{DescriptorSetLayout, Device};
+use super::descriptors::LayoutDescription;
+use super::{
+    DescriptorSetLayout, Device, PipelineCache, PushConstantLayout, SpecializationConstants,
+};
 use crate::utils;
 
+/// SPIR-V code of a shader, or the path of a file containing it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderSource<'a> {
+    Path(&'a Path),
+    Spirv(&'a [u32]),
+}
+
+impl<'a> From<&'a Path> for ShaderSource<'a> {
+    fn from(path: &'a Path) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl<'a> From<&'a [u32]> for ShaderSource<'a> {
+    fn from(shader_code: &'a [u32]) -> Self {
+        Self::Spirv(shader_code)
+    }
+}
+
 pub struct ComputePipeline {
     pub pipeline: vk::Pipeline,
     pub layout: vk::PipelineLayout,
+    local_size: Option<[u32; 3]>,
+    set_layouts: Vec<LayoutDescription>,
+    push_constant_layout: PushConstantLayout,
     _device: Rc<Device>,
 }
 
 impl ComputePipeline {
-    pub fn new(descriptor_set_layouts: &[DescriptorSetLayout], device: Rc<Device>) -> Self {
-        let (pipeline, layout) = Self::create_compute_pipeline(descriptor_set_layouts, &device);
+    /// Build a pipeline running the entry point `entry` of the compute shader `source`
+    /// `name` is given to the pipeline for debugging when the `validation-layers` feature is enabled
+    /// and the specialization constants of the shader take the values of `specialization`, or their default ones
+    /// The compiled pipeline is looked for and stored in `pipeline_cache` if there is one
+    pub fn new<'a, S: Into<ShaderSource<'a>>>(
+        source: S,
+        entry: &str,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+        push_constant_layout: Option<PushConstantLayout>,
+        specialization: Option<&SpecializationConstants>,
+        pipeline_cache: Option<&PipelineCache>,
+        name: Option<&str>,
+        device: Rc<Device>,
+    ) -> Self {
+        let read_code;
+        let shader_code = match source.into() {
+            ShaderSource::Path(path) => {
+                read_code = utils::read_file(path);
+                &read_code
+            }
+            ShaderSource::Spirv(shader_code) => shader_code,
+        };
+
+        Self::from_spirv(
+            shader_code,
+            entry,
+            descriptor_set_layouts,
+            push_constant_layout,
+            specialization,
+            pipeline_cache,
+            name,
+            device,
+        )
+    }
+
+    /// Compile the GLSL compute shader `source` at runtime and build a pipeline from it
+    /// The error contains the compiler messages if the compilation failed
+    #[cfg(feature = "runtime-shaderc")]
+    pub fn from_glsl(
+        source: &str,
+        entry: &str,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+        push_constant_layout: Option<PushConstantLayout>,
+        specialization: Option<&SpecializationConstants>,
+        pipeline_cache: Option<&PipelineCache>,
+        name: Option<&str>,
+        device: Rc<Device>,
+    ) -> Result<Self, String> {
+        let shader_code = utils::compile_glsl(source, shaderc::ShaderKind::Compute, entry)?;
+
+        Ok(Self::from_spirv(
+            &shader_code,
+            entry,
+            descriptor_set_layouts,
+            push_constant_layout,
+            specialization,
+            pipeline_cache,
+            name,
+            device,
+        ))
+    }
+
+    pub(crate) fn from_spirv(
+        shader_code: &[u32],
+        entry: &str,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+        push_constant_layout: Option<PushConstantLayout>,
+        specialization: Option<&SpecializationConstants>,
+        pipeline_cache: Option<&PipelineCache>,
+        name: Option<&str>,
+        device: Rc<Device>,
+    ) -> Self {
+        let push_constant_layout = push_constant_layout.unwrap_or_default();
+
+        assert!(
+            push_constant_layout.size() <= device.properties().limits.max_push_constants_size,
+            "the push constants are larger than the device limit"
+        );
+
+        let (pipeline, layout) = Self::create_compute_pipeline(
+            shader_code,
+            entry,
+            descriptor_set_layouts,
+            &push_constant_layout,
+            specialization,
+            pipeline_cache,
+            &device,
+        );
+
+        if let Some(name) = name {
+            device.set_object_name(pipeline, name);
+        }
+
+        // Probably a layout copied from another pipeline, the validation layers don't complain about it
+        if cfg!(debug_assertions) {
+            Self::warn_unreachable_bindings(descriptor_set_layouts, name);
+        }
 
         Self {
             pipeline,
             layout,
+            local_size: utils::spirv_local_size(shader_code, entry, specialization),
+            set_layouts: descriptor_set_layouts
+                .iter()
+                .map(|layout| layout.description().clone())
+                .collect(),
+            push_constant_layout,
             _device: device,
         }
     }
 
+    fn warn_unreachable_bindings(
+        descriptor_set_layouts: &[DescriptorSetLayout],
+        name: Option<&str>,
+    ) {
+        for (set, layout) in descriptor_set_layouts.iter().enumerate() {
+            for (binding, stage_flags) in layout
+                .description()
+                .unreachable_bindings(vk::ShaderStageFlags::COMPUTE)
+            {
+                log::warn!(
+                    "binding {} of set {} of compute pipeline {} is only declared for {:?}",
+                    binding,
+                    set,
+                    name.unwrap_or("<unnamed>"),
+                    stage_flags
+                );
+            }
+        }
+    }
+
+    /// Workgroup size declared in the shader, with the values of the specialization constants it was created with
+    pub fn local_size(&self) -> Option<[u32; 3]> {
+        self.local_size
+    }
+
+    pub fn push_constant_layout(&self) -> &PushConstantLayout {
+        &self.push_constant_layout
+    }
+
+    /// Number of descriptor sets of the layout, which must all be bound to dispatch
+    pub(crate) fn set_layout_count(&self) -> usize {
+        self.set_layouts.len()
+    }
+
+    /// Number of leading sets which stay valid when switching from `other` to this pipeline
+    pub(crate) fn compatible_set_count(&self, other: &ComputePipeline) -> usize {
+        // Pipeline layouts with different push constant ranges aren't compatible for any set
+        if !self
+            .push_constant_layout
+            .is_compatible_with(&other.push_constant_layout)
+        {
+            return 0;
+        }
+
+        self.set_layouts
+            .iter()
+            .zip(&other.set_layouts)
+            .take_while(|(layout, other_layout)| layout == other_layout)
+            .count()
+    }
+
     fn create_pipeline_layout(
         descriptor_set_layouts: &[DescriptorSetLayout],
+        push_constant_layout: &PushConstantLayout,
         device: &Device,
     ) -> vk::PipelineLayout {
         let set_layouts = descriptor_set_layouts
@@ -33,30 +212,40 @@ impl ComputePipeline {
             .map(|descriptor| descriptor.descriptor_set_layout)
             .collect::<Vec<_>>();
 
-        let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts);
-        // .push_constant_ranges(push_constant_ranges);
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(push_constant_layout.ranges());
 
         unsafe { device.device.create_pipeline_layout(&layout_info, None) }
             .expect("failed to create pipeline layout!")
     }
 
     fn create_compute_pipeline(
+        shader_code: &[u32],
+        entry: &str,
         descriptor_set_layouts: &[DescriptorSetLayout],
+        push_constant_layout: &PushConstantLayout,
+        specialization: Option<&SpecializationConstants>,
+        pipeline_cache: Option<&PipelineCache>,
         device: &Device,
     ) -> (vk::Pipeline, vk::PipelineLayout) {
-        let shader_code = utils::read_file("shaders/compute.comp.spv");
-        let module = utils::create_shader_module(&shader_code, device);
+        let module = utils::create_shader_module(shader_code, device);
 
-        let name = CString::new("main").unwrap();
+        let name = CString::new(entry).expect("entry point name must not contain a nul byte");
 
-        let stage = vk::PipelineShaderStageCreateInfo::builder()
+        let specialization_info = specialization.map(|specialization| specialization.info());
+
+        let mut stage = vk::PipelineShaderStageCreateInfo::builder()
             .stage(vk::ShaderStageFlags::COMPUTE)
             .module(module)
-            .name(&name)
-            // .specialization_info(specialization_info)
-            .build();
+            .name(&name);
+        if let Some(specialization_info) = &specialization_info {
+            stage = stage.specialization_info(specialization_info);
+        }
+        let stage = stage.build();
 
-        let pipeline_layout = Self::create_pipeline_layout(descriptor_set_layouts, device);
+        let pipeline_layout =
+            Self::create_pipeline_layout(descriptor_set_layouts, push_constant_layout, device);
 
         let pipeline_info = vk::ComputePipelineCreateInfo::builder()
             .stage(stage)
@@ -65,7 +254,7 @@ impl ComputePipeline {
 
         let pipeline = unsafe {
             device.device.create_compute_pipelines(
-                vk::PipelineCache::null(),
+                pipeline_cache.map_or(vk::PipelineCache::null(), |cache| cache.handle),
                 &[pipeline_info],
                 None,
             )
@@ -90,3 +279,49 @@ impl Drop for ComputePipeline {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroU32;
+
+    use super::*;
+    use crate::vulkan::DescriptorSetLayoutBuilder;
+
+    #[test]
+    #[ignore = "requires a Vulkan device and the compiled shaders"]
+    fn specialized_local_size() {
+        let (_instance, device, _queue) = utils::create_test_device();
+
+        let layouts = [DescriptorSetLayoutBuilder::new()
+            .with_binding(
+                vk::DescriptorType::STORAGE_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .build(Rc::clone(&device))
+            .unwrap()];
+
+        let pipelines = [64u32, 128]
+            .iter()
+            .map(|&local_size_x| {
+                let specialization = SpecializationConstants::new().with(0, local_size_x);
+
+                ComputePipeline::new(
+                    Path::new("../shaders/local_size.comp.spv"),
+                    "main",
+                    &layouts,
+                    None,
+                    Some(&specialization),
+                    None,
+                    None,
+                    Rc::clone(&device),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(pipelines[0].local_size(), Some([64, 1, 1]));
+        assert_eq!(pipelines[1].local_size(), Some([128, 1, 1]));
+        assert_ne!(pipelines[0].pipeline, pipelines[1].pipeline);
+    }
+}
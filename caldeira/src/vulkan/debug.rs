@@ -1,17 +1,27 @@
-use std::ffi::{c_void, CStr};
+use std::cell::Cell;
+use std::env;
+use std::ffi::{c_void, CStr, CString};
 use std::rc::Rc;
 
 use ash::extensions::ext::DebugUtils;
 use ash::vk;
 
-use super::Instance;
+use super::{Device, Instance};
 
+/// `user_data` points to the `abort_on_error` flag of the `Debug`, returning `vk::TRUE` aborts the call
+/// which triggered the message
 unsafe extern "system" fn debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _user_data: *mut c_void,
+    user_data: *mut c_void,
 ) -> u32 {
+    let abort = if (*(user_data as *const Cell<bool>)).get() {
+        vk::TRUE
+    } else {
+        vk::FALSE
+    };
+
     // if message_severity < vk::DebugUtilsMessageSeverityFlagsEXT::WARNING {
     //     return vk::FALSE;
     // }
@@ -34,29 +44,68 @@ unsafe extern "system" fn debug_callback(
             vk::FALSE
         }
         vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => {
-            log::warn!("[INFO][{}] Validation layer: {}", message_type, message);
+            log::warn!("[WARNING][{}] Validation layer: {}", message_type, message);
             vk::FALSE
         }
         vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => {
-            log::error!("[INFO][{}] Validation layer: {}", message_type, message);
-            vk::TRUE
+            log::error!("[ERROR][{}] Validation layer: {}", message_type, message);
+            abort
         }
         _ => {
             log::error!("[UNKNOWN][{}] Validation layer: {}", message_type, message);
-            vk::TRUE
+            abort
         }
     }
 }
 
+/// Environment variable read by `Debug::new` to choose the lowest severity reported, among
+/// `error`, `warning`, `info` and `verbose`
+pub const VERBOSITY_VAR: &str = "CALDEIRA_VK_VERBOSITY";
+
 pub struct Debug {
     pub debug_utils: DebugUtils,
     pub debug_utils_messenger: vk::DebugUtilsMessengerEXT,
+    /// Boxed so that the callback can read it while the `Debug` moves
+    abort_on_error: Box<Cell<bool>>,
     _instance: Rc<Instance>,
 }
 
 impl Debug {
+    /// Report the errors and the warnings of every message type, or the severities down to the one
+    /// given by the `CALDEIRA_VK_VERBOSITY` environment variable
     pub fn new(instance: Rc<Instance>) -> Self {
-        let create_info = Self::populate_debug_messenger_create_info();
+        let severity = env::var(VERBOSITY_VAR)
+            .ok()
+            .and_then(|verbosity| {
+                let severity = severity_from_verbosity(&verbosity);
+                if severity.is_none() {
+                    log::warn!("unknown {} value {:?}", VERBOSITY_VAR, verbosity);
+                }
+                severity
+            })
+            .unwrap_or(
+                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING,
+            );
+
+        Self::with_config(
+            instance,
+            severity,
+            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+        )
+    }
+
+    /// Report only the messages of `severity` and `types`
+    pub fn with_config(
+        instance: Rc<Instance>,
+        severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+        types: vk::DebugUtilsMessageTypeFlagsEXT,
+    ) -> Self {
+        let abort_on_error = Box::new(Cell::new(false));
+        let create_info = Self::populate_debug_messenger_create_info(severity, types)
+            .user_data(&*abort_on_error as *const Cell<bool> as *mut c_void);
 
         let debug_utils = DebugUtils::new(&instance.entry, &instance.instance);
 
@@ -67,28 +116,66 @@ impl Debug {
         Self {
             debug_utils,
             debug_utils_messenger,
+            abort_on_error,
             _instance: instance,
         }
     }
 
-    fn populate_debug_messenger_create_info<'a>() -> vk::DebugUtilsMessengerCreateInfoEXTBuilder<'a>
-    {
+    /// Make the calls which trigger an error message fail with `ERROR_VALIDATION_FAILED_EXT`,
+    /// by default they go on so that the effects of the error can be observed
+    pub fn set_abort_on_error(&self, abort_on_error: bool) {
+        self.abort_on_error.set(abort_on_error);
+    }
+
+    /// Give `handle`, an object of `device`, a name shown in the validation messages and the capture tools
+    pub fn set_object_name<H: vk::Handle>(&self, device: &Device, handle: H, name: &str) {
+        set_object_name(&self.debug_utils, device.device.handle(), handle, name);
+    }
+
+    fn populate_debug_messenger_create_info<'a>(
+        severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+        types: vk::DebugUtilsMessageTypeFlagsEXT,
+    ) -> vk::DebugUtilsMessengerCreateInfoEXTBuilder<'a> {
         vk::DebugUtilsMessengerCreateInfoEXT::builder()
-            .message_severity(
-                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                    // | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
-                    // | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
-            )
-            .message_type(
-                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
-                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
-            )
+            .message_severity(severity)
+            .message_type(types)
             .pfn_user_callback(Some(debug_callback))
     }
 }
 
+/// Severities from `verbosity`, one of the values of `CALDEIRA_VK_VERBOSITY` whatever the case, to `ERROR`
+fn severity_from_verbosity(verbosity: &str) -> Option<vk::DebugUtilsMessageSeverityFlagsEXT> {
+    let error = vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+    let warning = error | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING;
+    let info = warning | vk::DebugUtilsMessageSeverityFlagsEXT::INFO;
+    let verbose = info | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+
+    match verbosity.to_lowercase().as_str() {
+        "error" => Some(error),
+        "warning" => Some(warning),
+        "info" => Some(info),
+        "verbose" => Some(verbose),
+        _ => None,
+    }
+}
+
+pub(crate) fn set_object_name<H: vk::Handle>(
+    debug_utils: &DebugUtils,
+    device: vk::Device,
+    handle: H,
+    name: &str,
+) {
+    let name = CString::new(name).expect("object name must not contain a nul byte");
+
+    let name_info = vk::DebugUtilsObjectNameInfoEXT::builder()
+        .object_type(H::TYPE)
+        .object_handle(handle.as_raw())
+        .object_name(&name);
+
+    unsafe { debug_utils.debug_utils_set_object_name(device, &name_info) }
+        .expect("failed to set object name!");
+}
+
 impl Drop for Debug {
     fn drop(&mut self) {
         unsafe {
@@ -97,3 +184,24 @@ impl Drop for Debug {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn verbosity_levels() {
+        assert_eq!(
+            severity_from_verbosity("Warning"),
+            Some(
+                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+            )
+        );
+        assert_eq!(
+            severity_from_verbosity("verbose"),
+            Some(vk::DebugUtilsMessageSeverityFlagsEXT::all())
+        );
+        assert_eq!(severity_from_verbosity("loud"), None);
+    }
+}
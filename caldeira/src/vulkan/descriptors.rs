@@ -1,18 +1,179 @@
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
 use std::num::NonZeroU32;
 use std::rc::Rc;
+use std::slice;
 
+use ash::prelude::VkResult;
 use ash::version::DeviceV1_0;
 use ash::vk;
 
-use super::Device;
+use super::{Buffer, Device, Image, Sampler};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DescriptorWriteError {
+    /// The image wasn't created with the `STORAGE` usage
+    MissingStorageUsage,
+    /// Storage images must be in `GENERAL` layout, the image is in the given layout
+    NotInGeneralLayout(vk::ImageLayout),
+    /// The image wasn't created with the `SAMPLED` usage
+    MissingSampledUsage,
+    /// The buffer wasn't created with the given usage, required by the type of the binding
+    MissingBufferUsage(vk::BufferUsageFlags),
+    /// The layout of the set has no such binding
+    UnknownBinding(u32),
+    /// The binding was declared with another descriptor type than the written one
+    WrongDescriptorType {
+        binding: u32,
+        declared: vk::DescriptorType,
+    },
+}
+
+impl fmt::Display for DescriptorWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingStorageUsage => {
+                write!(f, "The image wasn't created with the STORAGE usage!")
+            }
+            Self::NotInGeneralLayout(layout) => write!(
+                f,
+                "A storage image must be in GENERAL layout, not {:?}!",
+                layout
+            ),
+            Self::MissingSampledUsage => {
+                write!(f, "The image wasn't created with the SAMPLED usage!")
+            }
+            Self::MissingBufferUsage(usage) => {
+                write!(f, "The buffer wasn't created with the {:?} usage!", usage)
+            }
+            Self::UnknownBinding(binding) => {
+                write!(f, "The layout of the set has no binding {}!", binding)
+            }
+            Self::WrongDescriptorType { binding, declared } => write!(
+                f,
+                "Binding {} was declared as {:?}, which doesn't match the write!",
+                binding, declared
+            ),
+        }
+    }
+}
+
+impl Error for DescriptorWriteError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DescriptorSetLayoutError {
+    /// The flags of the binding require the given descriptor indexing feature, which isn't enabled on the device
+    FeatureNotEnabled { binding: u32, feature: &'static str },
+    /// Descriptors of this type can't be updated after bind
+    UpdateAfterBindNotSupported {
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+    },
+}
+
+impl fmt::Display for DescriptorSetLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FeatureNotEnabled { binding, feature } => write!(
+                f,
+                "The flags of binding {} require the {} feature, which isn't enabled!",
+                binding, feature
+            ),
+            Self::UpdateAfterBindNotSupported {
+                binding,
+                descriptor_type,
+            } => write!(
+                f,
+                "Binding {} is UPDATE_AFTER_BIND, which {:?} descriptors can't be!",
+                binding, descriptor_type
+            ),
+        }
+    }
+}
+
+impl Error for DescriptorSetLayoutError {}
+
+/// Definition of a binding, retained to check the compatibility of layouts
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BindingDescription {
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    descriptor_count: u32,
+    stage_flags: vk::ShaderStageFlags,
+    immutable_samplers: Vec<vk::Sampler>,
+    flags: vk::DescriptorBindingFlags,
+}
+
+/// Type, count and stages of a binding of a `DescriptorSetLayout`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BindingInfo {
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub descriptor_count: u32,
+    pub stage_flags: vk::ShaderStageFlags,
+}
+
+impl From<&BindingDescription> for BindingInfo {
+    fn from(description: &BindingDescription) -> Self {
+        Self {
+            binding: description.binding,
+            descriptor_type: description.descriptor_type,
+            descriptor_count: description.descriptor_count,
+            stage_flags: description.stage_flags,
+        }
+    }
+}
+
+/// Everything that defines a descriptor set layout, two layouts with the same description are identically defined
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LayoutDescription {
+    bindings: Vec<BindingDescription>,
+    update_after_bind: bool,
+}
+
+impl LayoutDescription {
+    /// Bindings declared for none of `stages`, which a pipeline made of these stages can't access
+    pub(crate) fn unreachable_bindings(
+        &self,
+        stages: vk::ShaderStageFlags,
+    ) -> impl Iterator<Item = (u32, vk::ShaderStageFlags)> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |binding| !binding.stage_flags.intersects(stages))
+            .map(|binding| (binding.binding, binding.stage_flags))
+    }
+
+    /// Maximum descriptor count of the last binding if it has a variable descriptor count
+    fn variable_descriptor_count(&self) -> Option<u32> {
+        self.bindings
+            .last()
+            .filter(|binding| {
+                binding
+                    .flags
+                    .contains(vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT)
+            })
+            .map(|binding| binding.descriptor_count)
+    }
+
+    fn binding(&self, binding: u32) -> Result<&BindingDescription, DescriptorWriteError> {
+        self.bindings
+            .iter()
+            .find(|description| description.binding == binding)
+            .ok_or(DescriptorWriteError::UnknownBinding(binding))
+    }
+}
+
 pub struct DescriptorSetLayoutBuilder<'a> {
     layout_bindings: Vec<vk::DescriptorSetLayoutBindingBuilder<'a>>,
+    binding_flags: Vec<vk::DescriptorBindingFlags>,
 }
 
 impl<'a> DescriptorSetLayoutBuilder<'a> {
     pub fn new() -> Self {
         Self {
             layout_bindings: vec![],
+            binding_flags: vec![],
         }
     }
 
@@ -32,17 +193,102 @@ impl<'a> DescriptorSetLayoutBuilder<'a> {
             layout_binding = layout_binding.immutable_samplers(immutable_samplers);
         }
         self.layout_bindings.push(layout_binding);
+        self.binding_flags.push(vk::DescriptorBindingFlags::empty());
         self
     }
 
-    pub fn build(self, device: Rc<Device>) -> DescriptorSetLayout {
+    /// Same as `with_binding` with descriptor indexing flags, such as `UPDATE_AFTER_BIND` or `PARTIALLY_BOUND`
+    /// If a binding is update after bind, the layout is created with `UPDATE_AFTER_BIND_POOL` and its sets
+    /// must be allocated from a pool built with `DescriptorPoolBuilder::update_after_bind`
+    /// Only the last binding can be `VARIABLE_DESCRIPTOR_COUNT`, its `descriptor_count` is then the upper bound of
+    /// the counts given to `DescriptorSetLayout::allocate_variable_descriptor_sets`
+    /// The matching descriptor indexing features must be enabled with `DeviceBuilder::descriptor_indexing_features`,
+    /// otherwise `build` fails
+    pub fn with_binding_flags(
+        mut self,
+        descriptor_type: vk::DescriptorType,
+        descriptor_count: NonZeroU32,
+        stage_flags: vk::ShaderStageFlags,
+        immutable_samplers: Option<&'a [vk::Sampler]>,
+        binding_flags: vk::DescriptorBindingFlags,
+    ) -> Self {
+        self = self.with_binding(
+            descriptor_type,
+            descriptor_count,
+            stage_flags,
+            immutable_samplers,
+        );
+        *self.binding_flags.last_mut().unwrap() = binding_flags;
+        self
+    }
+
+    pub fn build(
+        self,
+        device: Rc<Device>,
+    ) -> Result<DescriptorSetLayout, DescriptorSetLayoutError> {
+        for (binding, &flags) in self.layout_bindings.iter().zip(&self.binding_flags) {
+            check_binding_flags(binding.binding, binding.descriptor_type, flags, &device)?;
+        }
+
+        if let Some((_, previous)) = self.binding_flags.split_last() {
+            assert!(
+                !previous
+                    .iter()
+                    .any(|flags| flags
+                        .contains(vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT)),
+                "only the last binding can have a variable descriptor count"
+            );
+        }
+
+        let binding_descriptions = self
+            .layout_bindings
+            .iter()
+            .zip(&self.binding_flags)
+            .map(|(binding, &flags)| BindingDescription {
+                binding: binding.binding,
+                descriptor_type: binding.descriptor_type,
+                descriptor_count: binding.descriptor_count,
+                stage_flags: binding.stage_flags,
+                immutable_samplers: if binding.p_immutable_samplers.is_null() {
+                    vec![]
+                } else {
+                    // The builder borrows the samplers for 'a, and there is one per descriptor
+                    unsafe {
+                        slice::from_raw_parts(
+                            binding.p_immutable_samplers,
+                            binding.descriptor_count as _,
+                        )
+                    }
+                    .to_vec()
+                },
+                flags,
+            })
+            .collect();
+
         let bindings = self
             .layout_bindings
             .into_iter()
             .map(|binding| binding.build())
             .collect::<Vec<_>>();
 
-        let layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let update_after_bind = self
+            .binding_flags
+            .iter()
+            .any(|flags| flags.contains(vk::DescriptorBindingFlags::UPDATE_AFTER_BIND));
+
+        let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+            .binding_flags(&self.binding_flags);
+
+        let mut layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+
+        if self.binding_flags.iter().any(|flags| !flags.is_empty()) {
+            layout_info = layout_info.push_next(&mut binding_flags_info);
+        }
+
+        if update_after_bind {
+            layout_info =
+                layout_info.flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL);
+        }
 
         let descriptor_set_layout = unsafe {
             device
@@ -51,10 +297,91 @@ impl<'a> DescriptorSetLayoutBuilder<'a> {
         }
         .expect("failed to create descriptor set layout!");
 
-        DescriptorSetLayout {
+        Ok(DescriptorSetLayout {
             descriptor_set_layout,
+            description: Rc::new(LayoutDescription {
+                bindings: binding_descriptions,
+                update_after_bind,
+            }),
             device,
-        }
+        })
+    }
+}
+
+/// Check that the descriptor indexing features required by the `flags` of `binding` are enabled
+fn check_binding_flags(
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    flags: vk::DescriptorBindingFlags,
+    device: &Device,
+) -> Result<(), DescriptorSetLayoutError> {
+    type Feature = fn(&vk::PhysicalDeviceDescriptorIndexingFeatures) -> vk::Bool32;
+
+    let mut required: Vec<(&'static str, Feature)> = vec![];
+
+    if flags.contains(vk::DescriptorBindingFlags::UPDATE_AFTER_BIND) {
+        required.push(match descriptor_type {
+            vk::DescriptorType::UNIFORM_BUFFER => (
+                "descriptorBindingUniformBufferUpdateAfterBind",
+                |features| features.descriptor_binding_uniform_buffer_update_after_bind,
+            ),
+            vk::DescriptorType::SAMPLER
+            | vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+            | vk::DescriptorType::SAMPLED_IMAGE => {
+                ("descriptorBindingSampledImageUpdateAfterBind", |features| {
+                    features.descriptor_binding_sampled_image_update_after_bind
+                })
+            }
+            vk::DescriptorType::STORAGE_IMAGE => {
+                ("descriptorBindingStorageImageUpdateAfterBind", |features| {
+                    features.descriptor_binding_storage_image_update_after_bind
+                })
+            }
+            vk::DescriptorType::STORAGE_BUFFER => (
+                "descriptorBindingStorageBufferUpdateAfterBind",
+                |features| features.descriptor_binding_storage_buffer_update_after_bind,
+            ),
+            vk::DescriptorType::UNIFORM_TEXEL_BUFFER => (
+                "descriptorBindingUniformTexelBufferUpdateAfterBind",
+                |features| features.descriptor_binding_uniform_texel_buffer_update_after_bind,
+            ),
+            vk::DescriptorType::STORAGE_TEXEL_BUFFER => (
+                "descriptorBindingStorageTexelBufferUpdateAfterBind",
+                |features| features.descriptor_binding_storage_texel_buffer_update_after_bind,
+            ),
+            _ => {
+                return Err(DescriptorSetLayoutError::UpdateAfterBindNotSupported {
+                    binding,
+                    descriptor_type,
+                })
+            }
+        });
+    }
+
+    if flags.contains(vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING) {
+        required.push(("descriptorBindingUpdateUnusedWhilePending", |features| {
+            features.descriptor_binding_update_unused_while_pending
+        }));
+    }
+
+    if flags.contains(vk::DescriptorBindingFlags::PARTIALLY_BOUND) {
+        required.push(("descriptorBindingPartiallyBound", |features| {
+            features.descriptor_binding_partially_bound
+        }));
+    }
+
+    if flags.contains(vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT) {
+        required.push(("descriptorBindingVariableDescriptorCount", |features| {
+            features.descriptor_binding_variable_descriptor_count
+        }));
+    }
+
+    match required
+        .into_iter()
+        .find(|(_, feature)| !device.is_descriptor_indexing_feature_enabled(feature))
+    {
+        Some((feature, _)) => Err(DescriptorSetLayoutError::FeatureNotEnabled { binding, feature }),
+        None => Ok(()),
     }
 }
 
@@ -66,23 +393,187 @@ impl<'a> Default for DescriptorSetLayoutBuilder<'a> {
 
 pub struct DescriptorSetLayout {
     pub descriptor_set_layout: vk::DescriptorSetLayout,
+    description: Rc<LayoutDescription>,
     device: Rc<Device>,
 }
 
 impl DescriptorSetLayout {
+    /// Whether `binding` was declared with immutable samplers
+    pub fn has_immutable_samplers(&self, binding: u32) -> bool {
+        !self.description.bindings[binding as usize]
+            .immutable_samplers
+            .is_empty()
+    }
+
+    /// Whether both layouts are identically defined, so that a set bound with a pipeline using
+    /// one of them stays valid for a pipeline using the other
+    pub fn is_compatible_with(&self, other: &DescriptorSetLayout) -> bool {
+        self.description == other.description
+    }
+
+    /// How `binding` was declared, `None` if the layout has no such binding
+    pub fn binding_info(&self, binding: u32) -> Option<BindingInfo> {
+        self.description
+            .binding(binding)
+            .ok()
+            .map(BindingInfo::from)
+    }
+
+    /// How each binding was declared, in binding order
+    pub fn bindings(&self) -> impl Iterator<Item = BindingInfo> + '_ {
+        self.description.bindings.iter().map(BindingInfo::from)
+    }
+
+    pub(crate) fn description(&self) -> &LayoutDescription {
+        &self.description
+    }
+
+    /// Write `image` in its current layout into the combined image sampler `binding` of `descriptor_set`
+    /// `sampler` is ignored if the binding was declared with immutable samplers, and required otherwise
+    pub fn write_combined_image_sampler(
+        &self,
+        descriptor_set: vk::DescriptorSet,
+        binding: u32,
+        image: &Image,
+        sampler: Option<&Sampler>,
+    ) {
+        let sampler = if self.has_immutable_samplers(binding) {
+            vk::Sampler::null()
+        } else {
+            sampler
+                .expect("a sampler is required for a binding without immutable samplers")
+                .handle
+        };
+
+        let image_infos = [vk::DescriptorImageInfo::builder()
+            .sampler(sampler)
+            .image_view(image.view)
+            .image_layout(image.layout)
+            .build()];
+
+        let descriptor_writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(binding)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_infos)
+            .build()];
+
+        unsafe {
+            self.device
+                .device
+                .update_descriptor_sets(&descriptor_writes, &[])
+        }
+    }
+
+    /// Write `image` into the storage image `binding` of `descriptor_set`
+    /// The image must have the `STORAGE` usage and be in `GENERAL` layout
+    pub fn write_storage_image(
+        &self,
+        descriptor_set: vk::DescriptorSet,
+        binding: u32,
+        image: &Image,
+    ) -> Result<(), DescriptorWriteError> {
+        check_storage_image(image)?;
+
+        let image_infos = [vk::DescriptorImageInfo::builder()
+            .image_view(image.view)
+            .image_layout(image.layout)
+            .build()];
+
+        let descriptor_writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(binding)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(&image_infos)
+            .build()];
+
+        unsafe {
+            self.device
+                .device
+                .update_descriptor_sets(&descriptor_writes, &[])
+        }
+
+        Ok(())
+    }
+
     pub fn allocate_descriptor_sets(
         &self,
         descriptor_set_count: u32,
         descriptor_pool: &DescriptorPool,
-    ) -> Vec<vk::DescriptorSet> {
+    ) -> Vec<DescriptorSet> {
+        self.try_allocate_descriptor_sets(descriptor_set_count, None, descriptor_pool)
+            .expect("failed to allocate descriptor sets!")
+    }
+
+    /// Allocate a set per element of `descriptor_counts`, with this number of descriptors in the last binding,
+    /// which must have been declared with `VARIABLE_DESCRIPTOR_COUNT`
+    pub fn allocate_variable_descriptor_sets(
+        &self,
+        descriptor_counts: &[u32],
+        descriptor_pool: &DescriptorPool,
+    ) -> Vec<DescriptorSet> {
+        self.try_allocate_descriptor_sets(
+            descriptor_counts.len() as _,
+            Some(descriptor_counts),
+            descriptor_pool,
+        )
+        .expect("failed to allocate descriptor sets!")
+    }
+
+    fn try_allocate_descriptor_sets(
+        &self,
+        descriptor_set_count: u32,
+        variable_descriptor_counts: Option<&[u32]>,
+        descriptor_pool: &DescriptorPool,
+    ) -> VkResult<Vec<DescriptorSet>> {
+        assert!(
+            !self.description.update_after_bind
+                || descriptor_pool
+                    .flags
+                    .contains(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND),
+            "sets of an update after bind layout must be allocated from an update after bind pool"
+        );
+
         let layouts = vec![self.descriptor_set_layout; descriptor_set_count as _];
 
-        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+        let mut variable_count_info =
+            vk::DescriptorSetVariableDescriptorCountAllocateInfo::builder();
+
+        let mut alloc_info = vk::DescriptorSetAllocateInfo::builder()
             .descriptor_pool(descriptor_pool.descriptor_pool)
             .set_layouts(&layouts);
 
-        unsafe { self.device.device.allocate_descriptor_sets(&alloc_info) }
-            .expect("failed to allocate descriptor sets!")
+        if let Some(descriptor_counts) = variable_descriptor_counts {
+            let max_count = self
+                .description
+                .variable_descriptor_count()
+                .expect("the last binding of the layout doesn't have a variable descriptor count");
+
+            assert!(
+                descriptor_counts.iter().all(|&count| count <= max_count),
+                "a variable descriptor count exceeds the {} descriptors of the binding",
+                max_count
+            );
+
+            variable_count_info = variable_count_info.descriptor_counts(descriptor_counts);
+            alloc_info = alloc_info.push_next(&mut variable_count_info);
+        }
+
+        let handles = unsafe { self.device.device.allocate_descriptor_sets(&alloc_info) }?;
+
+        Ok(handles
+            .into_iter()
+            .map(|handle| DescriptorSet {
+                handle,
+                pool: descriptor_pool.descriptor_pool,
+                pool_generation: Rc::clone(&descriptor_pool.generation),
+                generation: descriptor_pool.generation.get(),
+                description: Rc::clone(&self.description),
+                device: Rc::clone(&self.device),
+            })
+            .collect())
     }
 }
 
@@ -96,13 +587,276 @@ impl Drop for DescriptorSetLayout {
     }
 }
 
+fn check_storage_image(image: &Image) -> Result<(), DescriptorWriteError> {
+    if !image.usage.contains(vk::ImageUsageFlags::STORAGE) {
+        return Err(DescriptorWriteError::MissingStorageUsage);
+    }
+
+    if image.layout != vk::ImageLayout::GENERAL {
+        return Err(DescriptorWriteError::NotInGeneralLayout(image.layout));
+    }
+
+    Ok(())
+}
+
+/// A set allocated from a `DescriptorPool`, which keeps the bindings of its layout to check the writes
+/// It is freed with its pool, by `DescriptorPool::free` or when the pool is reset or dropped
+pub struct DescriptorSet {
+    pub handle: vk::DescriptorSet,
+    pool: vk::DescriptorPool,
+    /// Generation of the pool, which changes when its sets are freed all at once
+    pool_generation: Rc<Cell<u64>>,
+    generation: u64,
+    description: Rc<LayoutDescription>,
+    device: Rc<Device>,
+}
+
+impl DescriptorSet {
+    /// Whether the set is still allocated, i.e. its pool wasn't reset or dropped since its allocation
+    pub fn is_valid(&self) -> bool {
+        self.pool_generation.get() == self.generation
+    }
+
+    /// Start a batch of writes, applied by `DescriptorSetWriter::update`
+    pub fn writer(&self) -> DescriptorSetWriter<'_> {
+        DescriptorSetWriter {
+            descriptor_set: self,
+            writes: vec![],
+        }
+    }
+
+    /// Whether the layout of the set is identically defined to `layout`, so that the set can be bound
+    /// for a pipeline using `layout`
+    pub fn matches_layout(&self, layout: &DescriptorSetLayout) -> bool {
+        *self.description == *layout.description
+    }
+}
+
+enum DescriptorInfo {
+    Buffer(vk::DescriptorBufferInfo),
+    Image(vk::DescriptorImageInfo),
+}
+
+struct PendingWrite {
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    info: DescriptorInfo,
+}
+
+/// Writes to the first descriptor of bindings of a set, checked against the layout of the set
+pub struct DescriptorSetWriter<'a> {
+    descriptor_set: &'a DescriptorSet,
+    writes: Vec<PendingWrite>,
+}
+
+impl<'a> DescriptorSetWriter<'a> {
+    /// Write `range` bytes of `buffer` from `offset` into the uniform or storage buffer `binding`
+    pub fn write_buffer(
+        mut self,
+        binding: u32,
+        buffer: &Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> Result<Self, DescriptorWriteError> {
+        let descriptor_type = self
+            .descriptor_set
+            .description
+            .binding(binding)?
+            .descriptor_type;
+
+        let usage = match descriptor_type {
+            vk::DescriptorType::UNIFORM_BUFFER | vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC => {
+                vk::BufferUsageFlags::UNIFORM_BUFFER
+            }
+            vk::DescriptorType::STORAGE_BUFFER | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => {
+                vk::BufferUsageFlags::STORAGE_BUFFER
+            }
+            declared => {
+                return Err(DescriptorWriteError::WrongDescriptorType { binding, declared })
+            }
+        };
+
+        if !buffer.usage.contains(usage) {
+            return Err(DescriptorWriteError::MissingBufferUsage(usage));
+        }
+
+        let buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(buffer.handle)
+            .offset(offset)
+            .range(range)
+            .build();
+
+        self.writes.push(PendingWrite {
+            binding,
+            descriptor_type,
+            info: DescriptorInfo::Buffer(buffer_info),
+        });
+
+        Ok(self)
+    }
+
+    /// Write `image` into the storage image `binding`
+    /// The image must have the `STORAGE` usage and be in `GENERAL` layout
+    pub fn write_storage_image(
+        mut self,
+        binding: u32,
+        image: &Image,
+    ) -> Result<Self, DescriptorWriteError> {
+        self.check_type(binding, vk::DescriptorType::STORAGE_IMAGE)?;
+        check_storage_image(image)?;
+
+        let image_info = vk::DescriptorImageInfo::builder()
+            .image_view(image.view)
+            .image_layout(image.layout)
+            .build();
+
+        self.writes.push(PendingWrite {
+            binding,
+            descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+            info: DescriptorInfo::Image(image_info),
+        });
+
+        Ok(self)
+    }
+
+    /// Write `image` in its current layout into the combined image sampler `binding`
+    /// `sampler` is ignored if the binding was declared with immutable samplers, and required otherwise
+    pub fn write_combined_image_sampler(
+        mut self,
+        binding: u32,
+        image: &Image,
+        sampler: Option<&Sampler>,
+    ) -> Result<Self, DescriptorWriteError> {
+        let description = self.check_type(binding, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)?;
+
+        if !image.usage.contains(vk::ImageUsageFlags::SAMPLED) {
+            return Err(DescriptorWriteError::MissingSampledUsage);
+        }
+
+        let sampler = if description.immutable_samplers.is_empty() {
+            sampler
+                .expect("a sampler is required for a binding without immutable samplers")
+                .handle
+        } else {
+            vk::Sampler::null()
+        };
+
+        let image_info = vk::DescriptorImageInfo::builder()
+            .sampler(sampler)
+            .image_view(image.view)
+            .image_layout(image.layout)
+            .build();
+
+        self.writes.push(PendingWrite {
+            binding,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            info: DescriptorInfo::Image(image_info),
+        });
+
+        Ok(self)
+    }
+
+    /// Apply all the writes with a single `vkUpdateDescriptorSets`
+    pub fn update(self) {
+        assert!(
+            self.descriptor_set.is_valid(),
+            "the descriptor set was freed with its pool"
+        );
+
+        let descriptor_writes = self
+            .writes
+            .iter()
+            .map(|write| {
+                let descriptor_write = vk::WriteDescriptorSet::builder()
+                    .dst_set(self.descriptor_set.handle)
+                    .dst_binding(write.binding)
+                    .dst_array_element(0)
+                    .descriptor_type(write.descriptor_type);
+
+                match &write.info {
+                    DescriptorInfo::Buffer(info) => {
+                        descriptor_write.buffer_info(slice::from_ref(info))
+                    }
+                    DescriptorInfo::Image(info) => {
+                        descriptor_write.image_info(slice::from_ref(info))
+                    }
+                }
+                .build()
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            self.descriptor_set
+                .device
+                .device
+                .update_descriptor_sets(&descriptor_writes, &[])
+        }
+    }
+
+    fn check_type(
+        &self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+    ) -> Result<&'a BindingDescription, DescriptorWriteError> {
+        let description = self.descriptor_set.description.binding(binding)?;
+
+        if description.descriptor_type != descriptor_type {
+            return Err(DescriptorWriteError::WrongDescriptorType {
+                binding,
+                declared: description.descriptor_type,
+            });
+        }
+
+        Ok(description)
+    }
+}
+
 pub struct DescriptorPoolBuilder {
     pool_sizes: Vec<vk::DescriptorPoolSize>,
+    flags: vk::DescriptorPoolCreateFlags,
 }
 
 impl DescriptorPoolBuilder {
     pub fn new() -> Self {
-        Self { pool_sizes: vec![] }
+        Self {
+            pool_sizes: vec![],
+            flags: vk::DescriptorPoolCreateFlags::empty(),
+        }
+    }
+
+    /// Pool sizes holding the descriptors of one set of `layout`, to build a pool for a single set
+    /// The pool is update after bind if the layout is
+    pub fn from_layout(layout: &DescriptorSetLayout) -> Self {
+        let mut builder = Self::new();
+
+        for binding in &layout.description.bindings {
+            match builder
+                .pool_sizes
+                .iter_mut()
+                .find(|pool_size| pool_size.ty == binding.descriptor_type)
+            {
+                Some(pool_size) => pool_size.descriptor_count += binding.descriptor_count,
+                None => builder = builder.with(binding.descriptor_type, binding.descriptor_count),
+            }
+        }
+
+        if layout.description.update_after_bind {
+            builder = builder.update_after_bind();
+        }
+
+        builder
+    }
+
+    /// Allow the sets to be freed one by one with `DescriptorPool::free`
+    pub fn free_descriptor_set(mut self) -> Self {
+        self.flags |= vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET;
+        self
+    }
+
+    /// Allow the pool to allocate sets of layouts with update after bind bindings
+    pub fn update_after_bind(mut self) -> Self {
+        self.flags |= vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND;
+        self
     }
 
     pub fn with(mut self, descriptor_type: vk::DescriptorType, descriptor_count: u32) -> Self {
@@ -117,6 +871,7 @@ impl DescriptorPoolBuilder {
 
     pub fn build(self, max_sets: u32, device: Rc<Device>) -> DescriptorPool {
         let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(self.flags)
             .pool_sizes(&self.pool_sizes)
             .max_sets(max_sets);
 
@@ -125,6 +880,8 @@ impl DescriptorPoolBuilder {
 
         DescriptorPool {
             descriptor_pool,
+            flags: self.flags,
+            generation: Rc::new(Cell::new(0)),
             device,
         }
     }
@@ -138,9 +895,59 @@ impl Default for DescriptorPoolBuilder {
 
 pub struct DescriptorPool {
     pub descriptor_pool: vk::DescriptorPool,
+    flags: vk::DescriptorPoolCreateFlags,
+    /// Incremented each time all the sets are freed, which invalidates the `DescriptorSet`s allocated before
+    generation: Rc<Cell<u64>>,
     device: Rc<Device>,
 }
 
+impl DescriptorPool {
+    /// Free all the sets allocated from the pool, which become invalid
+    pub fn reset(&mut self) {
+        unsafe {
+            self.device
+                .device
+                .reset_descriptor_pool(self.descriptor_pool, vk::DescriptorPoolResetFlags::empty())
+        }
+        .expect("failed to reset descriptor pool!");
+
+        self.generation.set(self.generation.get() + 1);
+    }
+
+    /// Give `descriptor_sets` back to the pool, which must have been built with
+    /// `DescriptorPoolBuilder::free_descriptor_set`
+    /// The sets must not be in use by pending command buffers
+    pub fn free(&mut self, descriptor_sets: Vec<DescriptorSet>) {
+        assert!(
+            self.flags
+                .contains(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET),
+            "descriptor sets can only be freed from a pool built with free_descriptor_set"
+        );
+
+        let handles = descriptor_sets
+            .iter()
+            .map(|descriptor_set| {
+                assert!(
+                    descriptor_set.pool == self.descriptor_pool,
+                    "the descriptor set wasn't allocated from this pool"
+                );
+                assert!(
+                    descriptor_set.is_valid(),
+                    "the descriptor set was already freed by a reset"
+                );
+
+                descriptor_set.handle
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            self.device
+                .device
+                .free_descriptor_sets(self.descriptor_pool, &handles)
+        }
+    }
+}
+
 impl Drop for DescriptorPool {
     fn drop(&mut self) {
         unsafe {
@@ -148,5 +955,520 @@ impl Drop for DescriptorPool {
                 .device
                 .destroy_descriptor_pool(self.descriptor_pool, None)
         };
+
+        self.generation.set(self.generation.get() + 1);
+    }
+}
+
+/// Allocate descriptor sets from a list of pools, a new pool twice as large as the last one
+/// is created when all of them are full
+pub struct DescriptorAllocator {
+    pool_sizes: Vec<vk::DescriptorPoolSize>,
+    flags: vk::DescriptorPoolCreateFlags,
+    max_sets: u32,
+    pools: Vec<DescriptorPool>,
+    current: usize,
+    device: Rc<Device>,
+}
+
+impl DescriptorAllocator {
+    /// The first pool is built from `builder` with `max_sets` sets
+    pub fn new(builder: DescriptorPoolBuilder, max_sets: u32, device: Rc<Device>) -> Self {
+        assert!(max_sets > 0, "a descriptor pool must hold at least one set");
+
+        Self {
+            pool_sizes: builder.pool_sizes,
+            flags: builder.flags,
+            max_sets,
+            pools: vec![],
+            current: 0,
+            device,
+        }
+    }
+
+    pub fn allocate_descriptor_sets(
+        &mut self,
+        descriptor_set_count: u32,
+        layout: &DescriptorSetLayout,
+    ) -> Vec<DescriptorSet> {
+        self.allocate(descriptor_set_count, None, layout)
+    }
+
+    /// See `DescriptorSetLayout::allocate_variable_descriptor_sets`
+    pub fn allocate_variable_descriptor_sets(
+        &mut self,
+        descriptor_counts: &[u32],
+        layout: &DescriptorSetLayout,
+    ) -> Vec<DescriptorSet> {
+        self.allocate(
+            descriptor_counts.len() as _,
+            Some(descriptor_counts),
+            layout,
+        )
+    }
+
+    fn allocate(
+        &mut self,
+        descriptor_set_count: u32,
+        variable_descriptor_counts: Option<&[u32]>,
+        layout: &DescriptorSetLayout,
+    ) -> Vec<DescriptorSet> {
+        loop {
+            // A new pool failing means the sets would never fit, e.g. for a missing descriptor type
+            let new_pool = self.current == self.pools.len();
+
+            if new_pool {
+                self.grow(descriptor_set_count);
+            }
+
+            let pool = &self.pools[self.current];
+
+            match layout.try_allocate_descriptor_sets(
+                descriptor_set_count,
+                variable_descriptor_counts,
+                pool,
+            ) {
+                Ok(descriptor_sets) => return descriptor_sets,
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY)
+                | Err(vk::Result::ERROR_FRAGMENTED_POOL)
+                    if !new_pool =>
+                {
+                    self.current += 1
+                }
+                Err(err) => panic!("failed to allocate descriptor sets: {}", err),
+            }
+        }
+    }
+
+    /// Reset all the pools, the sets allocated so far must not be used anymore
+    pub fn reset_all(&mut self) {
+        for pool in &mut self.pools {
+            pool.reset();
+        }
+
+        self.current = 0;
+    }
+
+    pub fn pool_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// Create a pool at least twice as large as the previous one, and able to hold `descriptor_set_count` sets
+    fn grow(&mut self, descriptor_set_count: u32) {
+        let mut factor = if self.pools.is_empty() { 1 } else { 2 };
+
+        while self.max_sets * factor < descriptor_set_count {
+            factor *= 2;
+        }
+
+        self.max_sets *= factor;
+
+        for pool_size in &mut self.pool_sizes {
+            pool_size.descriptor_count *= factor;
+        }
+
+        let builder = DescriptorPoolBuilder {
+            pool_sizes: self.pool_sizes.clone(),
+            flags: self.flags,
+        };
+
+        self.pools
+            .push(builder.build(self.max_sets, Rc::clone(&self.device)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroU32;
+    use std::rc::Rc;
+
+    use ash::vk;
+
+    use super::*;
+    use crate::utils;
+    use crate::vulkan::{
+        BufferBuilder, CommandPool, DeviceBuilder, QueueCreateInfo, SamplerBuilder,
+    };
+
+    #[test]
+    fn unreachable_bindings() {
+        let binding = |binding, stage_flags| BindingDescription {
+            binding,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+            stage_flags,
+            immutable_samplers: vec![],
+            flags: vk::DescriptorBindingFlags::empty(),
+        };
+        let description = LayoutDescription {
+            bindings: vec![
+                binding(0, vk::ShaderStageFlags::COMPUTE),
+                binding(1, vk::ShaderStageFlags::FRAGMENT),
+                binding(2, vk::ShaderStageFlags::ALL),
+            ],
+            update_after_bind: false,
+        };
+
+        assert_eq!(
+            description
+                .unreachable_bindings(vk::ShaderStageFlags::COMPUTE)
+                .collect::<Vec<_>>(),
+            vec![(1, vk::ShaderStageFlags::FRAGMENT)]
+        );
+    }
+
+    #[test]
+    fn variable_descriptor_count() {
+        let binding = |binding, flags| BindingDescription {
+            binding,
+            descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+            descriptor_count: 1024,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            immutable_samplers: vec![],
+            flags,
+        };
+        let mut description = LayoutDescription {
+            bindings: vec![
+                binding(0, vk::DescriptorBindingFlags::empty()),
+                binding(1, vk::DescriptorBindingFlags::empty()),
+            ],
+            update_after_bind: false,
+        };
+        assert_eq!(description.variable_descriptor_count(), None);
+
+        description.bindings[1].flags = vk::DescriptorBindingFlags::PARTIALLY_BOUND
+            | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT;
+        assert_eq!(description.variable_descriptor_count(), Some(1024));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn immutable_sampler_binding() {
+        let (instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let sampler = SamplerBuilder::new().build(Rc::clone(&device));
+        let samplers = [sampler.handle];
+
+        let descriptor_set_layout = DescriptorSetLayoutBuilder::new()
+            .with_binding(
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                Some(&samplers),
+            )
+            .build(Rc::clone(&device))
+            .unwrap();
+
+        assert!(descriptor_set_layout.has_immutable_samplers(0));
+
+        let descriptor_pool = DescriptorPoolBuilder::new()
+            .with(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1)
+            .build(1, Rc::clone(&device));
+        let descriptor_sets = descriptor_set_layout.allocate_descriptor_sets(1, &descriptor_pool);
+
+        let texture = Image::new_texture(
+            image::RgbaImage::new(1, 1),
+            Rc::clone(&device),
+            &instance,
+            &mut command_pool,
+            &mut queue,
+            None,
+        )
+        .unwrap();
+
+        descriptor_set_layout.write_combined_image_sampler(
+            descriptor_sets[0].handle,
+            0,
+            &texture,
+            None,
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn binding_flags_need_descriptor_indexing_features() {
+        let (instance, device, _) = utils::create_test_device();
+
+        let bindless_layout = || {
+            DescriptorSetLayoutBuilder::new()
+                .with_binding_flags(
+                    vk::DescriptorType::STORAGE_BUFFER,
+                    NonZeroU32::new(1).unwrap(),
+                    vk::ShaderStageFlags::COMPUTE,
+                    None,
+                    vk::DescriptorBindingFlags::UPDATE_AFTER_BIND,
+                )
+                .with_binding_flags(
+                    vk::DescriptorType::STORAGE_BUFFER,
+                    NonZeroU32::new(8).unwrap(),
+                    vk::ShaderStageFlags::COMPUTE,
+                    None,
+                    vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                        | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT,
+                )
+        };
+
+        assert_eq!(
+            bindless_layout().build(device).err(),
+            Some(DescriptorSetLayoutError::FeatureNotEnabled {
+                binding: 0,
+                feature: "descriptorBindingStorageBufferUpdateAfterBind"
+            })
+        );
+
+        let (device, _) = DeviceBuilder::new()
+            .extensions(&[])
+            .descriptor_indexing_features(
+                vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+                    .descriptor_binding_storage_buffer_update_after_bind(true)
+                    .descriptor_binding_partially_bound(true)
+                    .descriptor_binding_variable_descriptor_count(true)
+                    .build(),
+            )
+            .build(
+                |queue_family, _| {
+                    if queue_family.support_compute() {
+                        Some(QueueCreateInfo::new(vec![1.0]))
+                    } else {
+                        None
+                    }
+                },
+                Rc::clone(&instance),
+            )
+            .unwrap();
+        assert!(device.is_descriptor_indexing_feature_enabled(
+            |features| features.descriptor_binding_partially_bound
+        ));
+
+        assert_eq!(
+            DescriptorSetLayoutBuilder::new()
+                .with_binding_flags(
+                    vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+                    NonZeroU32::new(1).unwrap(),
+                    vk::ShaderStageFlags::COMPUTE,
+                    None,
+                    vk::DescriptorBindingFlags::UPDATE_AFTER_BIND,
+                )
+                .build(Rc::clone(&device))
+                .err(),
+            Some(DescriptorSetLayoutError::UpdateAfterBindNotSupported {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+            })
+        );
+
+        let descriptor_set_layout = bindless_layout().build(Rc::clone(&device)).unwrap();
+        let descriptor_pool = DescriptorPoolBuilder::from_layout(&descriptor_set_layout)
+            .update_after_bind()
+            .build(1, Rc::clone(&device));
+        let descriptor_set = descriptor_set_layout
+            .allocate_variable_descriptor_sets(&[4], &descriptor_pool)
+            .remove(0);
+
+        // Only the first of the 4 variable descriptors is written, which the partial binding allows
+        let storage = BufferBuilder::new()
+            .size(16)
+            .storage()
+            .build(Rc::clone(&device), &instance);
+        descriptor_set
+            .writer()
+            .write_buffer(0, &storage, 0, 16)
+            .unwrap()
+            .write_buffer(1, &storage, 0, 16)
+            .unwrap()
+            .update();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn writer_checks_descriptor_types() {
+        let (instance, device, _) = utils::create_test_device();
+
+        let descriptor_set_layout = DescriptorSetLayoutBuilder::new()
+            .with_binding(
+                vk::DescriptorType::STORAGE_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .with_binding(
+                vk::DescriptorType::UNIFORM_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .build(Rc::clone(&device))
+            .unwrap();
+
+        let pool_builder = DescriptorPoolBuilder::from_layout(&descriptor_set_layout);
+        assert_eq!(pool_builder.pool_sizes.len(), 2);
+        let descriptor_pool = pool_builder.build(1, Rc::clone(&device));
+        let descriptor_sets = descriptor_set_layout.allocate_descriptor_sets(1, &descriptor_pool);
+
+        let storage = BufferBuilder::new()
+            .size(16)
+            .storage()
+            .build(Rc::clone(&device), &instance);
+        let uniform = BufferBuilder::new()
+            .size(16)
+            .uniform()
+            .build(Rc::clone(&device), &instance);
+
+        let writer = descriptor_sets[0].writer();
+        assert_eq!(
+            writer.write_buffer(1, &storage, 0, vk::WHOLE_SIZE).err(),
+            Some(DescriptorWriteError::MissingBufferUsage(
+                vk::BufferUsageFlags::UNIFORM_BUFFER
+            ))
+        );
+
+        let writer = descriptor_sets[0].writer();
+        assert_eq!(
+            writer.write_buffer(2, &storage, 0, vk::WHOLE_SIZE).err(),
+            Some(DescriptorWriteError::UnknownBinding(2))
+        );
+
+        descriptor_sets[0]
+            .writer()
+            .write_buffer(0, &storage, 0, vk::WHOLE_SIZE)
+            .unwrap()
+            .write_buffer(1, &uniform, 0, vk::WHOLE_SIZE)
+            .unwrap()
+            .update();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn layout_compatibility() {
+        let (_instance, device, _) = utils::create_test_device();
+
+        let storage_buffer_layout = || {
+            DescriptorSetLayoutBuilder::new().with_binding(
+                vk::DescriptorType::STORAGE_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+        };
+
+        let layout = storage_buffer_layout().build(Rc::clone(&device)).unwrap();
+        let same_layout = storage_buffer_layout().build(Rc::clone(&device)).unwrap();
+        let larger_layout = storage_buffer_layout()
+            .with_binding(
+                vk::DescriptorType::UNIFORM_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .build(device)
+            .unwrap();
+
+        assert_eq!(
+            larger_layout.binding_info(1),
+            Some(BindingInfo {
+                binding: 1,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+            })
+        );
+        assert_eq!(layout.binding_info(1), None);
+        assert_eq!(larger_layout.bindings().count(), 2);
+
+        let descriptor_pool = DescriptorPoolBuilder::new()
+            .with(vk::DescriptorType::STORAGE_BUFFER, 1)
+            .build(1, Rc::clone(&layout.device));
+        let descriptor_sets = layout.allocate_descriptor_sets(1, &descriptor_pool);
+        assert!(descriptor_sets[0].matches_layout(&same_layout));
+        assert!(!descriptor_sets[0].matches_layout(&larger_layout));
+
+        assert!(layout.is_compatible_with(&same_layout));
+        assert!(!layout.is_compatible_with(&larger_layout));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn pool_reset_invalidates_sets() {
+        let (_instance, device, _) = utils::create_test_device();
+
+        let descriptor_set_layout = DescriptorSetLayoutBuilder::new()
+            .with_binding(
+                vk::DescriptorType::STORAGE_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .build(Rc::clone(&device))
+            .unwrap();
+
+        let mut descriptor_pool = DescriptorPoolBuilder::new()
+            .with(vk::DescriptorType::STORAGE_BUFFER, 2)
+            .free_descriptor_set()
+            .build(2, device);
+
+        let mut descriptor_sets =
+            descriptor_set_layout.allocate_descriptor_sets(2, &descriptor_pool);
+        let last = descriptor_sets.pop().unwrap();
+        descriptor_pool.free(vec![last]);
+
+        // The freed set can be allocated again
+        let descriptor_set = descriptor_set_layout
+            .allocate_descriptor_sets(1, &descriptor_pool)
+            .remove(0);
+        assert!(descriptor_set.is_valid());
+
+        descriptor_pool.reset();
+        assert!(!descriptor_set.is_valid());
+        assert!(!descriptor_sets[0].is_valid());
+        assert!(descriptor_set_layout
+            .allocate_descriptor_sets(2, &descriptor_pool)
+            .iter()
+            .all(DescriptorSet::is_valid));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn allocator_grows_when_full() {
+        let (_instance, device, _) = utils::create_test_device();
+
+        let descriptor_set_layout = DescriptorSetLayoutBuilder::new()
+            .with_binding(
+                vk::DescriptorType::STORAGE_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .build(Rc::clone(&device))
+            .unwrap();
+
+        let mut allocator = DescriptorAllocator::new(
+            DescriptorPoolBuilder::new().with(vk::DescriptorType::STORAGE_BUFFER, 2),
+            2,
+            device,
+        );
+
+        // The first pool holds 2 sets
+        let descriptor_sets = allocator.allocate_descriptor_sets(2, &descriptor_set_layout);
+        assert_eq!(descriptor_sets.len(), 2);
+        assert_eq!(allocator.pool_count(), 1);
+
+        // The second one 4 sets, so it still has room for 3 of them
+        allocator.allocate_descriptor_sets(1, &descriptor_set_layout);
+        assert_eq!(allocator.pool_count(), 2);
+        assert_eq!(allocator.max_sets, 4);
+        allocator.allocate_descriptor_sets(3, &descriptor_set_layout);
+        assert_eq!(allocator.pool_count(), 2);
+
+        // The third one grows from the size of the second one
+        allocator.allocate_descriptor_sets(1, &descriptor_set_layout);
+        assert_eq!(allocator.pool_count(), 3);
+        assert_eq!(allocator.max_sets, 8);
+
+        // After a reset, the sets fit in the second pool
+        allocator.reset_all();
+        allocator.allocate_descriptor_sets(4, &descriptor_set_layout);
+        assert_eq!(allocator.pool_count(), 3);
+        assert_eq!(allocator.current, 1);
     }
 }
@@ -1,36 +1,244 @@
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::ffi::{c_void, CStr, CString};
+use std::fmt;
+use std::mem;
+use std::ptr;
 use std::rc::Rc;
-use std::slice::SliceIndex;
+use std::slice::{self, SliceIndex};
 
-use ash::version::{DeviceV1_0, InstanceV1_0};
+#[cfg(feature = "validation-layers")]
+use ash::extensions::ext::DebugUtils;
+use ash::prelude::VkResult;
+use ash::version::{DeviceV1_0, InstanceV1_0, InstanceV1_1};
 use ash::vk;
 
-use super::{Instance, Queue, QueueCreateInfo, QueueFamily};
+#[cfg(feature = "validation-layers")]
+use super::debug;
+use super::{
+    Instance, MultiSubmitHandle, Queue, QueueCreateInfo, QueueFamily, QueueSubmission, SubmitError,
+    SubmitHandle,
+};
+use crate::consts::DEVICE_EXTENSIONS;
 use crate::utils;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DeviceError {
+    /// The queue finder didn't return a `QueueCreateInfo` for any queue family
+    NoMatchingQueueFamily,
+    /// No physical device supports the required extensions and features
+    NoSuitablePhysicalDevice,
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoMatchingQueueFamily => write!(
+                f,
+                "No queue family matched the queue finder, the device would have no queue!"
+            ),
+            Self::NoSuitablePhysicalDevice => write!(
+                f,
+                "No physical device supports the required extensions and features!"
+            ),
+        }
+    }
+}
+
+impl Error for DeviceError {}
+
+/// The device was lost, for instance because a shader hung or faulted
+/// It can't be used anymore and has to be destroyed and created again
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DeviceLost;
+
+impl fmt::Display for DeviceLost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The device was lost!")
+    }
+}
+
+impl Error for DeviceLost {}
+
+/// Index of each queue family the device was created with queues of, and the queues created
+type QueueDatas = Vec<(usize, QueueCreateInfo)>;
+
+/// Callback registered by `SubmitHandle::on_complete` with the submission it waits for
+type CompletionCallback = (SubmitHandle<'static>, Box<dyn FnOnce()>);
+
+/// Score given by `DeviceBuilder::score` to a physical device, 0 if it mustn't be picked
+type DeviceScorer = Box<dyn Fn(&PhysicalDeviceInfo) -> u32>;
+
+/// A physical device and its properties and supported features, as enumerated by `Device::enumerate_physical_devices`
+#[derive(Clone, Copy)]
+pub struct PhysicalDeviceInfo {
+    pub handle: vk::PhysicalDevice,
+    pub properties: vk::PhysicalDeviceProperties,
+    pub features: vk::PhysicalDeviceFeatures,
+}
+
+impl PhysicalDeviceInfo {
+    fn new(instance: &Instance, handle: vk::PhysicalDevice) -> Self {
+        let (properties, features) = unsafe {
+            (
+                instance.instance.get_physical_device_properties(handle),
+                instance.instance.get_physical_device_features(handle),
+            )
+        };
+
+        Self {
+            handle,
+            properties,
+            features,
+        }
+    }
+
+    /// Name of the device given by the driver
+    pub fn name(&self) -> String {
+        unsafe { CStr::from_ptr(self.properties.device_name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn device_type(&self) -> vk::PhysicalDeviceType {
+        self.properties.device_type
+    }
+}
+
+/// Features and extensions a device was created with
+struct EnabledCapabilities {
+    features: vk::PhysicalDeviceFeatures,
+    descriptor_indexing_features: vk::PhysicalDeviceDescriptorIndexingFeatures,
+    extensions: Vec<CString>,
+}
+
+/// Device extensions and features a device is created with, by default the extensions of `DEVICE_EXTENSIONS`
+/// and no feature
+/// The physical devices which don't support all of them are not picked, the one with the highest score among
+/// the others is
+pub struct DeviceBuilder {
+    extensions: Vec<CString>,
+    features: vk::PhysicalDeviceFeatures,
+    descriptor_indexing_features: vk::PhysicalDeviceDescriptorIndexingFeatures,
+    scorer: Option<DeviceScorer>,
+}
+
+impl DeviceBuilder {
+    pub fn new() -> Self {
+        let builder = Self {
+            extensions: vec![],
+            features: vk::PhysicalDeviceFeatures::default(),
+            descriptor_indexing_features: vk::PhysicalDeviceDescriptorIndexingFeatures::default(),
+            scorer: None,
+        };
+
+        builder.extensions(DEVICE_EXTENSIONS)
+    }
+
+    /// Replace the required device extensions
+    pub fn extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = extensions
+            .iter()
+            .map(|&extension| {
+                CString::new(extension).expect("the extension name contains a nul byte")
+            })
+            .collect();
+        self
+    }
+
+    /// Features to enable, such as `vk::PhysicalDeviceFeatures::builder().shader_int64(true).build()`
+    pub fn features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Descriptor indexing features to enable, core in Vulkan 1.2, such as
+    /// `vk::PhysicalDeviceDescriptorIndexingFeatures::builder().descriptor_binding_partially_bound(true).build()`
+    /// They are required by the binding flags of `DescriptorSetLayoutBuilder::with_binding_flags`
+    pub fn descriptor_indexing_features(
+        mut self,
+        features: vk::PhysicalDeviceDescriptorIndexingFeatures,
+    ) -> Self {
+        self.descriptor_indexing_features = features;
+        // The structure is chained by the builder itself
+        self.descriptor_indexing_features.p_next = ptr::null_mut();
+        self
+    }
+
+    /// Replace the default scoring, which favors discrete GPUs and large images, with `scorer`,
+    /// for instance to pick a device by name or by type
+    /// Devices scored 0 are never picked
+    pub fn score<F: Fn(&PhysicalDeviceInfo) -> u32 + 'static>(mut self, scorer: F) -> Self {
+        self.scorer = Some(Box::new(scorer));
+        self
+    }
+
+    pub fn build<F: FnMut(QueueFamily, &[(usize, QueueCreateInfo)]) -> Option<QueueCreateInfo>>(
+        &self,
+        queue_finder: F,
+        instance: Rc<Instance>,
+    ) -> Result<(Rc<Device>, Vec<Vec<Queue>>), DeviceError> {
+        Device::create(self, queue_finder, instance)
+    }
+}
+
+impl Default for DeviceBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct Device {
     pub physical_device: vk::PhysicalDevice,
     pub device: ash::Device,
     queue_families: Vec<QueueFamily>,
+    properties: vk::PhysicalDeviceProperties,
+    subgroup_properties: vk::PhysicalDeviceSubgroupProperties,
+    enabled_features: vk::PhysicalDeviceFeatures,
+    enabled_descriptor_indexing_features: vk::PhysicalDeviceDescriptorIndexingFeatures,
+    enabled_extensions: Vec<String>,
+    lost: Cell<bool>,
+    device_lost_callback: RefCell<Option<Box<dyn FnOnce()>>>,
+    completion_callbacks: RefCell<Vec<CompletionCallback>>,
+    #[cfg(feature = "validation-layers")]
+    debug_utils: DebugUtils,
     instance: Rc<Instance>,
 }
 
 impl Device {
+    /// Create a device with the defaults of `DeviceBuilder`
     pub fn new<F: FnMut(QueueFamily, &[(usize, QueueCreateInfo)]) -> Option<QueueCreateInfo>>(
         queue_finder: F,
         instance: Rc<Instance>,
-    ) -> (Rc<Device>, Vec<Vec<Queue>>) {
-        let devices = unsafe {
-            instance
-                .instance
-                .enumerate_physical_devices()
-                .expect("failed to enumerate physical devices")
-        };
+    ) -> Result<(Rc<Device>, Vec<Vec<Queue>>), DeviceError> {
+        DeviceBuilder::new().build(queue_finder, instance)
+    }
 
-        let physical_device = Self::pick_physical_device(&instance, &devices);
+    /// Physical devices available to `instance`, to choose one in `DeviceBuilder::score`
+    pub fn enumerate_physical_devices(instance: &Instance) -> Vec<PhysicalDeviceInfo> {
+        unsafe { instance.instance.enumerate_physical_devices() }
+            .expect("failed to enumerate physical devices")
+            .into_iter()
+            .map(|handle| PhysicalDeviceInfo::new(instance, handle))
+            .collect()
+    }
+
+    fn create<F: FnMut(QueueFamily, &[(usize, QueueCreateInfo)]) -> Option<QueueCreateInfo>>(
+        builder: &DeviceBuilder,
+        queue_finder: F,
+        instance: Rc<Instance>,
+    ) -> Result<(Rc<Device>, Vec<Vec<Queue>>), DeviceError> {
+        let devices = Self::enumerate_physical_devices(&instance);
+
+        let physical_device = Self::pick_physical_device(builder, &instance, &devices)
+            .ok_or(DeviceError::NoSuitablePhysicalDevice)?;
 
-        let (device, queue_datas) =
-            Self::create_device_and_query_queue_datas(queue_finder, &instance, physical_device);
+        let (device, queue_datas, enabled) = Self::create_device_and_query_queue_datas(
+            builder,
+            queue_finder,
+            &instance,
+            physical_device,
+        )?;
 
         let queue_families = unsafe {
             instance
@@ -46,10 +254,35 @@ impl Device {
         })
         .collect();
 
+        let properties = unsafe {
+            instance
+                .instance
+                .get_physical_device_properties(physical_device)
+        };
+
+        let subgroup_properties = Self::query_subgroup_properties(&instance, physical_device);
+
+        #[cfg(feature = "validation-layers")]
+        let debug_utils = DebugUtils::new(&instance.entry, &instance.instance);
+
         let device = Rc::new(Self {
             physical_device,
             device,
             queue_families,
+            properties,
+            subgroup_properties,
+            enabled_features: enabled.features,
+            enabled_descriptor_indexing_features: enabled.descriptor_indexing_features,
+            enabled_extensions: enabled
+                .extensions
+                .into_iter()
+                .map(|extension| extension.to_string_lossy().into_owned())
+                .collect(),
+            lost: Cell::new(false),
+            device_lost_callback: RefCell::new(None),
+            completion_callbacks: RefCell::new(vec![]),
+            #[cfg(feature = "validation-layers")]
+            debug_utils,
             instance,
         });
 
@@ -79,65 +312,133 @@ impl Device {
             queue_groups.push(queues);
         }
 
-        (device, queue_groups)
+        Ok((device, queue_groups))
     }
 
-    fn pick_physical_device(
+    fn query_subgroup_properties(
         instance: &Instance,
-        physical_devices: &[vk::PhysicalDevice],
-    ) -> vk::PhysicalDevice {
-        let mut candidates = HashMap::new();
-
-        for device in physical_devices {
-            let score = Self::rate_device_suitability(instance, *device);
-            if score > 0 {
-                candidates.insert(score, device);
-            }
+        physical_device: vk::PhysicalDevice,
+    ) -> vk::PhysicalDeviceSubgroupProperties {
+        let mut subgroup_properties = vk::PhysicalDeviceSubgroupProperties::default();
+
+        {
+            let mut properties = vk::PhysicalDeviceProperties2::builder()
+                .push_next(&mut subgroup_properties)
+                .build();
+
+            unsafe {
+                instance
+                    .instance
+                    .get_physical_device_properties2(physical_device, &mut properties)
+            };
         }
 
-        let (_, device) = candidates
-            .into_iter()
-            .max_by_key(|(score, _)| *score)
-            .expect("failed to find a suitable GPU!");
-        *device
+        // The chain pointed to a temporary structure
+        subgroup_properties.p_next = ptr::null_mut();
+
+        subgroup_properties
     }
 
-    fn rate_device_suitability(instance: &Instance, physical_device: vk::PhysicalDevice) -> u32 {
-        let indices = utils::find_queue_families(instance, physical_device);
+    fn query_descriptor_indexing_features(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> vk::PhysicalDeviceDescriptorIndexingFeatures {
+        let mut descriptor_indexing_features =
+            vk::PhysicalDeviceDescriptorIndexingFeatures::default();
 
-        if !indices.is_complete() {
-            return 0;
+        {
+            // ash doesn't allow chaining it to `PhysicalDeviceFeatures2` in this version
+            let mut features = vk::PhysicalDeviceFeatures2 {
+                p_next: &mut descriptor_indexing_features
+                    as *mut vk::PhysicalDeviceDescriptorIndexingFeatures
+                    as *mut c_void,
+                ..Default::default()
+            };
+
+            unsafe {
+                instance
+                    .instance
+                    .get_physical_device_features2(physical_device, &mut features)
+            };
         }
 
-        let properties = unsafe {
-            instance
-                .instance
-                .get_physical_device_properties(physical_device)
-        };
+        // The chain pointed to a temporary structure
+        descriptor_indexing_features.p_next = ptr::null_mut();
 
-        let features = unsafe {
-            instance
-                .instance
-                .get_physical_device_features(physical_device)
-        };
+        descriptor_indexing_features
+    }
+
+    fn pick_physical_device(
+        builder: &DeviceBuilder,
+        instance: &Instance,
+        physical_devices: &[PhysicalDeviceInfo],
+    ) -> Option<vk::PhysicalDevice> {
+        physical_devices
+            .iter()
+            .map(|device| {
+                (
+                    Self::rate_device_suitability(builder, instance, device),
+                    device,
+                )
+            })
+            .filter(|(score, _)| *score > 0)
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, device)| device.handle)
+    }
 
-        let mut score = 0;
+    fn rate_device_suitability(
+        builder: &DeviceBuilder,
+        instance: &Instance,
+        device: &PhysicalDeviceInfo,
+    ) -> u32 {
+        let indices = utils::find_queue_families(instance, device.handle);
 
-        if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
-            score += 1000;
+        if !indices.is_complete() {
+            return 0;
         }
 
-        score += properties.limits.max_image_dimension2_d;
+        if !Self::supports_device_extensions(&builder.extensions, instance, device.handle) {
+            return 0;
+        }
 
-        if features.geometry_shader == 0 {
+        if !supports_features(&device.features, &builder.features) {
             return 0;
         }
 
-        if features.shader_storage_image_write_without_format == 0 {
+        let descriptor_indexing_features =
+            Self::query_descriptor_indexing_features(instance, device.handle);
+        if !supports_descriptor_indexing_features(
+            &descriptor_indexing_features,
+            &builder.descriptor_indexing_features,
+        ) {
             return 0;
         }
 
-        score
+        match &builder.scorer {
+            Some(scorer) => scorer(device),
+            None => default_score(device),
+        }
+    }
+
+    /// Whether the physical device supports all of `extensions`
+    fn supports_device_extensions(
+        extensions: &[CString],
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> bool {
+        let properties = unsafe {
+            instance
+                .instance
+                .enumerate_device_extension_properties(physical_device)
+        }
+        .expect("failed to enumerate device extensions");
+
+        extensions.iter().all(|extension| {
+            properties.iter().any(|property| {
+                let name = unsafe { CStr::from_ptr(property.extension_name.as_ptr()) };
+                name == extension.as_c_str()
+            })
+        })
     }
 
     fn create_logical_device_and_queues(
@@ -171,6 +472,290 @@ impl Device {
         (device, compute_queue)
     }
 
+    /// Number of nanoseconds required for a timestamp query to be incremented by 1
+    pub fn timestamp_period(&self) -> f32 {
+        self.properties.limits.timestamp_period
+    }
+
+    /// Give `handle` a name shown in the validation messages and the capture tools,
+    /// it does nothing without the `validation-layers` feature
+    pub fn set_object_name<T: vk::Handle>(&self, handle: T, name: &str) {
+        #[cfg(feature = "validation-layers")]
+        debug::set_object_name(&self.debug_utils, self.device.handle(), handle, name);
+
+        #[cfg(not(feature = "validation-layers"))]
+        let _ = (handle, name);
+    }
+
+    /// Open a region of `command_buffer` named `name` until `cmd_end_label`,
+    /// it does nothing without the `validation-layers` feature
+    pub(crate) fn cmd_begin_label(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+        color: [f32; 4],
+    ) {
+        #[cfg(feature = "validation-layers")]
+        {
+            let name = CString::new(name).expect("label must not contain a nul byte");
+            let label = vk::DebugUtilsLabelEXT::builder()
+                .label_name(&name)
+                .color(color);
+
+            unsafe {
+                self.debug_utils
+                    .cmd_begin_debug_utils_label(command_buffer, &label)
+            }
+        }
+
+        #[cfg(not(feature = "validation-layers"))]
+        let _ = (command_buffer, name, color);
+    }
+
+    /// Close the last region opened by `cmd_begin_label` in `command_buffer`
+    pub(crate) fn cmd_end_label(&self, command_buffer: vk::CommandBuffer) {
+        #[cfg(feature = "validation-layers")]
+        unsafe {
+            self.debug_utils.cmd_end_debug_utils_label(command_buffer)
+        }
+
+        #[cfg(not(feature = "validation-layers"))]
+        let _ = command_buffer;
+    }
+
+    /// Mark the current point of `command_buffer` with `name`
+    pub(crate) fn cmd_insert_label(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+        color: [f32; 4],
+    ) {
+        #[cfg(feature = "validation-layers")]
+        {
+            let name = CString::new(name).expect("label must not contain a nul byte");
+            let label = vk::DebugUtilsLabelEXT::builder()
+                .label_name(&name)
+                .color(color);
+
+            unsafe {
+                self.debug_utils
+                    .cmd_insert_debug_utils_label(command_buffer, &label)
+            }
+        }
+
+        #[cfg(not(feature = "validation-layers"))]
+        let _ = (command_buffer, name, color);
+    }
+
+    /// Features enabled at creation, the other ones must not be used even if the physical device supports them
+    pub fn enabled_features(&self) -> &vk::PhysicalDeviceFeatures {
+        &self.enabled_features
+    }
+
+    /// Whether the feature selected by `feature` was enabled at creation,
+    /// for instance `device.is_feature_enabled(|features| features.shader_int64)`
+    pub fn is_feature_enabled<F: FnOnce(&vk::PhysicalDeviceFeatures) -> vk::Bool32>(
+        &self,
+        feature: F,
+    ) -> bool {
+        feature(&self.enabled_features) == vk::TRUE
+    }
+
+    /// Descriptor indexing features enabled at creation with `DeviceBuilder::descriptor_indexing_features`
+    pub fn enabled_descriptor_indexing_features(
+        &self,
+    ) -> &vk::PhysicalDeviceDescriptorIndexingFeatures {
+        &self.enabled_descriptor_indexing_features
+    }
+
+    /// Whether the descriptor indexing feature selected by `feature` was enabled at creation,
+    /// for instance `device.is_descriptor_indexing_feature_enabled(|features| features.runtime_descriptor_array)`
+    pub fn is_descriptor_indexing_feature_enabled<
+        F: FnOnce(&vk::PhysicalDeviceDescriptorIndexingFeatures) -> vk::Bool32,
+    >(
+        &self,
+        feature: F,
+    ) -> bool {
+        feature(&self.enabled_descriptor_indexing_features) == vk::TRUE
+    }
+
+    /// Whether the device extension `name` was enabled at creation
+    pub fn is_extension_enabled(&self, name: &str) -> bool {
+        self.enabled_extensions
+            .iter()
+            .any(|extension| extension == name)
+    }
+
+    pub(crate) fn instance(&self) -> &Instance {
+        &self.instance
+    }
+
+    /// Call `callback` the first time a submission or a wait reports that the device was lost,
+    /// so that the application can tear down and recreate it, replacing the previous callback
+    pub fn on_device_lost<F: FnOnce() + 'static>(&self, callback: F) {
+        // Dropped once the cell is released, in case dropping the previous callback uses the device
+        let _previous = self.device_lost_callback.replace(Some(Box::new(callback)));
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.lost.get()
+    }
+
+    /// Run the callbacks registered with `SubmitHandle::on_complete` whose submissions are complete,
+    /// on the thread calling `poll`, and return how many were run
+    pub fn poll(&self) -> Result<usize, DeviceLost> {
+        // Taken out so that the callbacks can register new ones
+        let mut pending = mem::take(&mut *self.completion_callbacks.borrow_mut()).into_iter();
+        let mut run = 0;
+
+        while let Some((handle, callback)) = pending.next() {
+            match handle.is_complete() {
+                Ok(true) => {
+                    // The resources of the submission are freed before the callback runs
+                    drop(handle);
+                    callback();
+                    run += 1;
+                }
+                Ok(false) => self
+                    .completion_callbacks
+                    .borrow_mut()
+                    .push((handle, callback)),
+                Err(DeviceLost) => {
+                    let mut callbacks = self.completion_callbacks.borrow_mut();
+                    callbacks.push((handle, callback));
+                    callbacks.extend(pending);
+
+                    return Err(DeviceLost);
+                }
+            }
+        }
+
+        Ok(run)
+    }
+
+    /// Wait for the submissions registered with `SubmitHandle::on_complete` and run their callbacks,
+    /// the callbacks of a lost device are dropped without being run
+    /// Each registered handle keeps the device alive, as do the callbacks capturing an `Rc<Device>`, so this must
+    /// be called before dropping the last `Rc<Device>` while some are registered, otherwise they and the device leak
+    pub fn shutdown(&self) -> Result<(), DeviceLost> {
+        let mut result = Ok(());
+
+        // The callbacks may register new ones
+        loop {
+            let pending = mem::take(&mut *self.completion_callbacks.borrow_mut());
+
+            if pending.is_empty() {
+                return result;
+            }
+
+            for (handle, callback) in pending {
+                match handle.wait() {
+                    Ok(()) => callback(),
+                    Err(DeviceLost) => result = Err(DeviceLost),
+                }
+            }
+        }
+    }
+
+    pub(crate) fn register_completion_callback(
+        &self,
+        handle: SubmitHandle<'static>,
+        callback: Box<dyn FnOnce()>,
+    ) {
+        self.completion_callbacks
+            .borrow_mut()
+            .push((handle, callback));
+    }
+
+    /// Turn `ERROR_DEVICE_LOST` into `DeviceLost`, calling the device lost callback the first time,
+    /// any other error is unrecoverable and panics with `message`
+    pub(crate) fn check_lost<T>(
+        &self,
+        result: VkResult<T>,
+        message: &str,
+    ) -> Result<T, DeviceLost> {
+        match result {
+            Ok(value) => Ok(value),
+            Err(vk::Result::ERROR_DEVICE_LOST) => {
+                if !self.lost.replace(true) {
+                    log::error!("device lost!");
+
+                    // Taken out so that the callback can use the device, which may report the loss again
+                    let callback = self.device_lost_callback.borrow_mut().take();
+                    if let Some(callback) = callback {
+                        callback();
+                    }
+                }
+
+                Err(DeviceLost)
+            }
+            Err(result) => panic!("{}: {}", message, result),
+        }
+    }
+
+    /// Submit each batch of submissions to its queue with its own fence, the returned handle
+    /// waits for all of them at once and keeps the submissions alive until then
+    pub fn submit_multi<'a>(
+        self: &Rc<Self>,
+        submits: Vec<(&mut Queue, Vec<QueueSubmission<'a>>)>,
+    ) -> Result<MultiSubmitHandle<'a>, SubmitError> {
+        let mut handle = MultiSubmitHandle::new(Rc::clone(self));
+
+        for (queue, submits) in submits {
+            let fence_info = vk::FenceCreateInfo::builder();
+
+            let fence = unsafe { self.device.create_fence(&fence_info, None) }
+                .expect("failed to create fence");
+
+            // Only submitted fences go in the handle, which waits for them when dropped, so the batches
+            // submitted before a failure are still waited for
+            if let Err(error) = queue.submit_tracked(&submits, fence) {
+                unsafe { self.device.destroy_fence(fence, None) };
+
+                return Err(error);
+            }
+
+            handle.push(fence, submits);
+        }
+
+        Ok(handle)
+    }
+
+    /// Properties of the physical device, queried once at creation
+    pub fn properties(&self) -> &vk::PhysicalDeviceProperties {
+        &self.properties
+    }
+
+    /// Subgroup size, and operations and stages supporting subgroup operations
+    pub fn subgroup_properties(&self) -> &vk::PhysicalDeviceSubgroupProperties {
+        &self.subgroup_properties
+    }
+
+    /// Whether compute shaders can use the `GL_KHR_shader_subgroup_arithmetic` operations, such as `subgroupAdd`
+    pub fn supports_subgroup_arithmetic(&self) -> bool {
+        self.subgroup_properties
+            .supported_stages
+            .contains(vk::ShaderStageFlags::COMPUTE)
+            && self
+                .subgroup_properties
+                .supported_operations
+                .contains(vk::SubgroupFeatureFlags::BASIC | vk::SubgroupFeatureFlags::ARITHMETIC)
+    }
+
+    /// Alignment of the ranges of non coherent memory which are flushed or invalidated
+    pub fn non_coherent_atom_size(&self) -> vk::DeviceSize {
+        self.properties.limits.non_coherent_atom_size
+    }
+
+    /// Find among the queues returned by `Device::new` one whose family supports transfer only,
+    /// such families are usually backed by a dedicated copy engine which runs concurrently with compute
+    pub fn find_transfer_only_queue(queue_groups: &mut [Vec<Queue>]) -> Option<&mut Queue> {
+        queue_groups
+            .iter_mut()
+            .flatten()
+            .find(|queue| queue.family().support_transfer_only())
+    }
+
     pub fn get_queue_families<'a, I>(
         self: &'a Rc<Self>,
         index: I,
@@ -184,13 +769,18 @@ impl Device {
     fn create_device_and_query_queue_datas<
         F: FnMut(QueueFamily, &[(usize, QueueCreateInfo)]) -> Option<QueueCreateInfo>,
     >(
+        builder: &DeviceBuilder,
         queue_finder: F,
         instance: &Instance,
         physical_device: vk::PhysicalDevice,
-    ) -> (ash::Device, Vec<(usize, QueueCreateInfo)>) {
+    ) -> Result<(ash::Device, QueueDatas, EnabledCapabilities), DeviceError> {
         let queue_create_infos =
             utils::find_queue_families2(queue_finder, instance, physical_device);
 
+        if queue_create_infos.is_empty() {
+            return Err(DeviceError::NoMatchingQueueFamily);
+        }
+
         let vk_create_infos_builder = queue_create_infos.iter().map(|(idx, queue_create_info)| {
             vk::DeviceQueueCreateInfo::builder()
                 .queue_family_index(*idx as _)
@@ -201,11 +791,23 @@ impl Device {
             .map(|builder| builder.build())
             .collect();
 
-        let device_features = vk::PhysicalDeviceFeatures::builder();
+        // Returned as they are given to the device, so that the device knows what it can rely on
+        let device_features = builder.features;
+        let mut descriptor_indexing_features = builder.descriptor_indexing_features;
+        let extensions = builder.extensions.clone();
+        let extension_names = extensions
+            .iter()
+            .map(|extension| extension.as_ptr())
+            .collect::<Vec<_>>();
 
-        let create_info = vk::DeviceCreateInfo::builder()
+        let mut create_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(&vk_create_infos)
-            .enabled_features(&device_features);
+            .enabled_features(&device_features)
+            .enabled_extension_names(&extension_names);
+
+        if descriptor_indexing_bools(&descriptor_indexing_features).contains(&vk::TRUE) {
+            create_info = create_info.push_next(&mut descriptor_indexing_features);
+        }
 
         let device = unsafe {
             instance
@@ -214,15 +816,251 @@ impl Device {
         }
         .expect("failed to create logical device!");
 
-        (device, queue_create_infos)
+        // The chain pointed to the create info
+        descriptor_indexing_features.p_next = ptr::null_mut();
+
+        let enabled = EnabledCapabilities {
+            features: device_features,
+            descriptor_indexing_features,
+            extensions,
+        };
+
+        Ok((device, queue_create_infos, enabled))
+    }
+}
+
+/// Score of `device` when `DeviceBuilder::score` isn't given, higher for discrete GPUs
+/// No feature is required besides the ones given to `DeviceBuilder::features`, so that compute only devices
+/// such as integrated and mobile GPUs without geometry shaders can be picked
+fn default_score(device: &PhysicalDeviceInfo) -> u32 {
+    // 0 would reject the device
+    let mut score = 1;
+
+    if device.device_type() == vk::PhysicalDeviceType::DISCRETE_GPU {
+        score += 1000;
+    }
+
+    score += device.properties.limits.max_image_dimension2_d;
+
+    score
+}
+
+/// Whether all the features enabled in `required` are enabled in `supported`
+fn supports_features(
+    supported: &vk::PhysicalDeviceFeatures,
+    required: &vk::PhysicalDeviceFeatures,
+) -> bool {
+    let as_slice = |features: &vk::PhysicalDeviceFeatures| {
+        // The structure only contains `Bool32`s
+        unsafe {
+            slice::from_raw_parts(
+                features as *const vk::PhysicalDeviceFeatures as *const vk::Bool32,
+                mem::size_of::<vk::PhysicalDeviceFeatures>() / mem::size_of::<vk::Bool32>(),
+            )
+        }
+    };
+
+    as_slice(supported)
+        .iter()
+        .zip(as_slice(required))
+        .all(|(&supported, &required)| required == vk::FALSE || supported == vk::TRUE)
+}
+
+/// Whether all the features enabled in `required` are enabled in `supported`
+fn supports_descriptor_indexing_features(
+    supported: &vk::PhysicalDeviceDescriptorIndexingFeatures,
+    required: &vk::PhysicalDeviceDescriptorIndexingFeatures,
+) -> bool {
+    descriptor_indexing_bools(supported)
+        .iter()
+        .zip(descriptor_indexing_bools(required))
+        .all(|(&supported, &required)| required == vk::FALSE || supported == vk::TRUE)
+}
+
+/// The features of the structure, without its `s_type` and `p_next` header
+fn descriptor_indexing_bools(
+    features: &vk::PhysicalDeviceDescriptorIndexingFeatures,
+) -> &[vk::Bool32] {
+    let first = &features.shader_input_attachment_array_dynamic_indexing as *const vk::Bool32;
+    let header = first as usize - features as *const _ as usize;
+
+    // The structure only contains `Bool32`s after its header
+    unsafe {
+        slice::from_raw_parts(
+            first,
+            (mem::size_of::<vk::PhysicalDeviceDescriptorIndexingFeatures>() - header)
+                / mem::size_of::<vk::Bool32>(),
+        )
     }
 }
 
 impl Drop for Device {
     fn drop(&mut self) {
+        // The registered handles hold the device, so they must have been released by `poll` or `shutdown`
+        debug_assert!(
+            self.completion_callbacks.get_mut().is_empty(),
+            "the device is dropped with completion callbacks left, call `Device::shutdown` first"
+        );
+
         unsafe {
-            self.device.device_wait_idle().unwrap();
+            // A lost device can still be destroyed
+            let _ = self.check_lost(self.device.device_wait_idle(), "failed to wait device idle");
             self.device.destroy_device(None);
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn no_matching_queue_family() {
+        let instance = Rc::new(Instance::new_or_panic());
+
+        let result = Device::new(|_, _| None, instance);
+
+        assert_eq!(result.err(), Some(DeviceError::NoMatchingQueueFamily));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn pick_device_by_name() {
+        let instance = Rc::new(Instance::new_or_panic());
+        let physical_devices = Device::enumerate_physical_devices(&instance);
+        let name = physical_devices[physical_devices.len() - 1].name();
+
+        let expected = name.clone();
+        let (device, _) = DeviceBuilder::new()
+            .extensions(&[])
+            .score(move |device| (device.name() == expected) as u32)
+            .build(
+                |queue_family, _| {
+                    if queue_family.support_compute() {
+                        Some(QueueCreateInfo::new(vec![1.0]))
+                    } else {
+                        None
+                    }
+                },
+                Rc::clone(&instance),
+            )
+            .unwrap();
+
+        let picked = unsafe { CStr::from_ptr(device.properties().device_name.as_ptr()) };
+        assert_eq!(picked.to_str(), Ok(name.as_str()));
+
+        assert_eq!(
+            DeviceBuilder::new()
+                .score(|_| 0)
+                .build(|_, _| None, instance)
+                .err(),
+            Some(DeviceError::NoSuitablePhysicalDevice)
+        );
+    }
+
+    #[test]
+    fn devices_without_geometry_shaders_are_suitable() {
+        let mut device = PhysicalDeviceInfo {
+            handle: vk::PhysicalDevice::null(),
+            properties: vk::PhysicalDeviceProperties::default(),
+            features: vk::PhysicalDeviceFeatures::default(),
+        };
+        device.properties.device_type = vk::PhysicalDeviceType::INTEGRATED_GPU;
+        let integrated = default_score(&device);
+        assert!(integrated > 0);
+
+        device.properties.device_type = vk::PhysicalDeviceType::DISCRETE_GPU;
+        assert!(default_score(&device) > integrated);
+    }
+
+    #[test]
+    fn required_features_support() {
+        let supported = vk::PhysicalDeviceFeatures::builder()
+            .geometry_shader(true)
+            .shader_int64(true)
+            .build();
+
+        assert!(supports_features(
+            &supported,
+            &vk::PhysicalDeviceFeatures::default()
+        ));
+        assert!(supports_features(
+            &supported,
+            &vk::PhysicalDeviceFeatures::builder()
+                .shader_int64(true)
+                .build()
+        ));
+        assert!(!supports_features(
+            &supported,
+            &vk::PhysicalDeviceFeatures::builder()
+                .shader_float64(true)
+                .build()
+        ));
+    }
+
+    #[test]
+    fn required_descriptor_indexing_features_support() {
+        let supported = vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+            .descriptor_binding_partially_bound(true)
+            .runtime_descriptor_array(true)
+            .build();
+
+        assert_eq!(descriptor_indexing_bools(&supported).len(), 20);
+        assert!(supports_descriptor_indexing_features(
+            &supported,
+            &vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+                .runtime_descriptor_array(true)
+                .build()
+        ));
+        assert!(!supports_descriptor_indexing_features(
+            &supported,
+            &vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+                .descriptor_binding_variable_descriptor_count(true)
+                .build()
+        ));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn features_not_enabled_are_reported() {
+        let (_instance, device, _queue) = utils::create_test_device();
+
+        assert!(!device.is_feature_enabled(|features| features.geometry_shader));
+        assert!(!device.is_extension_enabled("VK_KHR_ray_query"));
+        // The test device is headless, so it has no swapchain
+        assert!(!device.is_extension_enabled("VK_KHR_swapchain"));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn device_lost_callback_can_use_the_device() {
+        use std::cell::Cell;
+
+        let (_instance, device, _queue) = utils::create_test_device();
+        let called = Rc::new(Cell::new(0));
+
+        {
+            let called = Rc::clone(&called);
+            let callback_device = Rc::clone(&device);
+            device.on_device_lost(move || {
+                called.set(called.get() + 1);
+                // Neither reporting the loss again nor registering a callback borrows it twice
+                assert_eq!(
+                    callback_device.check_lost::<()>(Err(vk::Result::ERROR_DEVICE_LOST), ""),
+                    Err(DeviceLost)
+                );
+                callback_device.on_device_lost(|| ());
+            });
+        }
+
+        assert_eq!(
+            device.check_lost::<()>(Err(vk::Result::ERROR_DEVICE_LOST), ""),
+            Err(DeviceLost)
+        );
+        assert_eq!(called.get(), 1);
+        assert!(device.is_lost());
+    }
+}
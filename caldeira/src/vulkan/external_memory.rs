@@ -0,0 +1,211 @@
+use std::error::Error;
+use std::fmt;
+
+use ash::vk;
+
+use super::Device;
+
+pub const EXTERNAL_MEMORY_FD: &str = "VK_KHR_external_memory_fd";
+pub const EXTERNAL_MEMORY_WIN32: &str = "VK_KHR_external_memory_win32";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExternalMemoryError {
+    /// The device extension required by the handle type isn't enabled
+    ExtensionNotEnabled(&'static str),
+    /// Only `OPAQUE_FD` and `OPAQUE_WIN32` handles are supported
+    UnsupportedHandleType(vk::ExternalMemoryHandleTypeFlags),
+    /// The memory wasn't allocated to be exported to this handle type
+    NotExportable(vk::ExternalMemoryHandleTypeFlags),
+    Vulkan(vk::Result),
+}
+
+impl fmt::Display for ExternalMemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ExtensionNotEnabled(extension) => {
+                write!(f, "The device extension {} isn't enabled!", extension)
+            }
+            Self::UnsupportedHandleType(handle_type) => {
+                write!(
+                    f,
+                    "Unsupported external memory handle type {:?}!",
+                    handle_type
+                )
+            }
+            Self::NotExportable(handle_type) => write!(
+                f,
+                "The memory wasn't allocated to be exported as {:?}!",
+                handle_type
+            ),
+            Self::Vulkan(result) => write!(f, "Failed to export memory: {}!", result),
+        }
+    }
+}
+
+impl Error for ExternalMemoryError {}
+
+/// Check that memory can be exported to `handle_type` with the extensions enabled on `device`
+pub(crate) fn check_handle_type(
+    handle_type: vk::ExternalMemoryHandleTypeFlags,
+    device: &Device,
+) -> Result<(), ExternalMemoryError> {
+    let extension = match handle_type {
+        vk::ExternalMemoryHandleTypeFlags::EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_FD => {
+            EXTERNAL_MEMORY_FD
+        }
+        vk::ExternalMemoryHandleTypeFlags::EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_WIN32 => {
+            EXTERNAL_MEMORY_WIN32
+        }
+        _ => return Err(ExternalMemoryError::UnsupportedHandleType(handle_type)),
+    };
+
+    if device.is_extension_enabled(extension) {
+        Ok(())
+    } else {
+        Err(ExternalMemoryError::ExtensionNotEnabled(extension))
+    }
+}
+
+/// Export `memory`, allocated to be exported to `export_handle_types`, as an opaque file descriptor
+/// The file descriptor is owned by the caller
+#[cfg(unix)]
+pub(crate) fn export_fd(
+    memory: vk::DeviceMemory,
+    export_handle_types: vk::ExternalMemoryHandleTypeFlags,
+    device: &Device,
+) -> Result<i32, ExternalMemoryError> {
+    use ash::extensions::khr::ExternalMemoryFd;
+
+    let handle_type = vk::ExternalMemoryHandleTypeFlags::EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_FD;
+
+    check_handle_type(handle_type, device)?;
+
+    if !export_handle_types.contains(handle_type) {
+        return Err(ExternalMemoryError::NotExportable(handle_type));
+    }
+
+    let external_memory_fd = ExternalMemoryFd::new(&device.instance().instance, &device.device);
+
+    let get_fd_info = vk::MemoryGetFdInfoKHR::builder()
+        .memory(memory)
+        .handle_type(handle_type);
+
+    unsafe { external_memory_fd.get_memory_fd(&get_fd_info) }.map_err(ExternalMemoryError::Vulkan)
+}
+
+/// Export `memory`, allocated to be exported to `export_handle_types`, as an opaque win32 handle
+/// The handle is owned by the caller
+#[cfg(windows)]
+pub(crate) fn export_win32_handle(
+    memory: vk::DeviceMemory,
+    export_handle_types: vk::ExternalMemoryHandleTypeFlags,
+    device: &Device,
+) -> Result<vk::HANDLE, ExternalMemoryError> {
+    use std::{mem, ptr};
+
+    use ash::version::{DeviceV1_0, InstanceV1_0};
+
+    let handle_type = vk::ExternalMemoryHandleTypeFlags::EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_WIN32;
+
+    check_handle_type(handle_type, device)?;
+
+    if !export_handle_types.contains(handle_type) {
+        return Err(ExternalMemoryError::NotExportable(handle_type));
+    }
+
+    let external_memory_win32 = vk::KhrExternalMemoryWin32Fn::load(|name| unsafe {
+        mem::transmute(
+            device
+                .instance()
+                .instance
+                .get_device_proc_addr(device.device.handle(), name.as_ptr()),
+        )
+    });
+
+    let get_handle_info = vk::MemoryGetWin32HandleInfoKHR::builder()
+        .memory(memory)
+        .handle_type(handle_type);
+
+    let mut handle = ptr::null_mut();
+
+    match unsafe {
+        external_memory_win32.get_memory_win32_handle_khr(
+            device.device.handle(),
+            &*get_handle_info,
+            &mut handle,
+        )
+    } {
+        vk::Result::SUCCESS => Ok(handle),
+        result => Err(ExternalMemoryError::Vulkan(result)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::rc::Rc;
+
+    use crate::utils;
+    use crate::vulkan::Buffer;
+
+    const OPAQUE_FD: vk::ExternalMemoryHandleTypeFlags =
+        vk::ExternalMemoryHandleTypeFlags::EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_FD;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn exportable_memory_needs_extension() {
+        let (instance, device, _queue) = utils::create_test_device();
+
+        let result = Buffer::new_exportable(
+            256,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            OPAQUE_FD,
+            device,
+            &instance,
+        );
+
+        assert_eq!(
+            result.err(),
+            Some(ExternalMemoryError::ExtensionNotEnabled(EXTERNAL_MEMORY_FD))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn buffer_memory_exported_as_fd() {
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+
+        let (instance, device, _queue) =
+            utils::create_test_device_with_extensions(&[EXTERNAL_MEMORY_FD]);
+
+        let buffer = Buffer::new_exportable(
+            256,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            OPAQUE_FD,
+            Rc::clone(&device),
+            &instance,
+        )
+        .unwrap();
+        let fd = buffer.export_fd().unwrap();
+        assert!(fd >= 0);
+        // The caller owns the file descriptor, which is closed with the file
+        drop(unsafe { File::from_raw_fd(fd) });
+
+        let not_exportable = Buffer::new(
+            256,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            None,
+            device,
+            &instance,
+        );
+        assert_eq!(
+            not_exportable.export_fd(),
+            Err(ExternalMemoryError::NotExportable(OPAQUE_FD))
+        );
+    }
+}
@@ -0,0 +1,475 @@
+use std::ffi::CString;
+use std::rc::Rc;
+
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+use super::{DescriptorSetLayout, Device, PipelineCache, PushConstantLayout};
+use crate::utils;
+
+/// A graphics pipeline with a vertex and an optional fragment shader, built by `GraphicsPipelineBuilder`
+pub struct GraphicsPipeline {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    push_constant_layout: PushConstantLayout,
+    set_layout_count: usize,
+    vertex_binding_count: usize,
+    device: Rc<Device>,
+}
+
+impl GraphicsPipeline {
+    pub fn push_constant_layout(&self) -> &PushConstantLayout {
+        &self.push_constant_layout
+    }
+
+    /// Number of descriptor sets of the layout, which must all be bound to draw
+    pub(crate) fn set_layout_count(&self) -> usize {
+        self.set_layout_count
+    }
+
+    /// Whether the pipeline reads vertex buffers, which must then be bound to draw
+    pub(crate) fn has_vertex_input(&self) -> bool {
+        self.vertex_binding_count > 0
+    }
+}
+
+impl Drop for GraphicsPipeline {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device.destroy_pipeline(self.pipeline, None);
+            self.device
+                .device
+                .destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
+
+/// Builder of a `GraphicsPipeline` drawing triangle lists with a static viewport and scissor
+/// By default, polygons are filled without culling and a single color attachment is written without blending
+pub struct GraphicsPipelineBuilder<'a> {
+    vertex_shader: Option<&'a [u32]>,
+    fragment_shader: Option<&'a [u32]>,
+    vertex_bindings: Vec<vk::VertexInputBindingDescription>,
+    vertex_attributes: Vec<vk::VertexInputAttributeDescription>,
+    topology: vk::PrimitiveTopology,
+    render_pass: vk::RenderPass,
+    subpass: u32,
+    viewport: vk::Viewport,
+    scissor: vk::Rect2D,
+    rasterization: vk::PipelineRasterizationStateCreateInfo,
+    color_blend_attachments: Vec<vk::PipelineColorBlendAttachmentState>,
+    descriptor_set_layouts: &'a [DescriptorSetLayout],
+    push_constant_layout: Option<PushConstantLayout>,
+    pipeline_cache: Option<&'a PipelineCache>,
+    name: Option<&'a str>,
+}
+
+impl<'a> GraphicsPipelineBuilder<'a> {
+    pub fn new() -> Self {
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+            .polygon_mode(vk::PolygonMode::FILL)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .line_width(1.0)
+            .build();
+
+        let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(vk::ColorComponentFlags::all())
+            .build();
+
+        Self {
+            vertex_shader: None,
+            fragment_shader: None,
+            vertex_bindings: vec![],
+            vertex_attributes: vec![],
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            render_pass: vk::RenderPass::null(),
+            subpass: 0,
+            viewport: vk::Viewport::default(),
+            scissor: vk::Rect2D::default(),
+            rasterization,
+            color_blend_attachments: vec![color_blend_attachment],
+            descriptor_set_layouts: &[],
+            push_constant_layout: None,
+            pipeline_cache: None,
+            name: None,
+        }
+    }
+
+    /// SPIR-V code of the vertex shader, whose entry point is `main`
+    pub fn vertex_shader(mut self, shader_code: &'a [u32]) -> Self {
+        self.vertex_shader = Some(shader_code);
+        self
+    }
+
+    /// SPIR-V code of the fragment shader, whose entry point is `main`
+    pub fn fragment_shader(mut self, shader_code: &'a [u32]) -> Self {
+        self.fragment_shader = Some(shader_code);
+        self
+    }
+
+    pub fn vertex_input(
+        mut self,
+        bindings: &[vk::VertexInputBindingDescription],
+        attributes: &[vk::VertexInputAttributeDescription],
+    ) -> Self {
+        self.vertex_bindings = bindings.to_vec();
+        self.vertex_attributes = attributes.to_vec();
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Subpass `subpass` of `render_pass` in which the pipeline is used, this is required
+    pub fn render_pass(mut self, render_pass: vk::RenderPass, subpass: u32) -> Self {
+        self.render_pass = render_pass;
+        self.subpass = subpass;
+        self
+    }
+
+    /// Viewport and scissor of the pipeline, this or `extent` is required
+    pub fn viewport(mut self, viewport: vk::Viewport, scissor: vk::Rect2D) -> Self {
+        self.viewport = viewport;
+        self.scissor = scissor;
+        self
+    }
+
+    /// Viewport and scissor covering the whole `extent`
+    pub fn extent(self, extent: vk::Extent2D) -> Self {
+        let viewport = vk::Viewport::builder()
+            .width(extent.width as _)
+            .height(extent.height as _)
+            .max_depth(1.0)
+            .build();
+        let scissor = vk::Rect2D::builder().extent(extent).build();
+
+        self.viewport(viewport, scissor)
+    }
+
+    pub fn rasterization(
+        mut self,
+        rasterization: vk::PipelineRasterizationStateCreateInfo,
+    ) -> Self {
+        self.rasterization = rasterization;
+        self
+    }
+
+    /// Blending of each color attachment of the subpass
+    pub fn color_blend_attachments(
+        mut self,
+        attachments: &[vk::PipelineColorBlendAttachmentState],
+    ) -> Self {
+        self.color_blend_attachments = attachments.to_vec();
+        self
+    }
+
+    pub fn descriptor_set_layouts(mut self, layouts: &'a [DescriptorSetLayout]) -> Self {
+        self.descriptor_set_layouts = layouts;
+        self
+    }
+
+    pub fn push_constant_layout(mut self, push_constant_layout: PushConstantLayout) -> Self {
+        self.push_constant_layout = Some(push_constant_layout);
+        self
+    }
+
+    /// Cache the compiled pipeline is looked for and stored in
+    pub fn pipeline_cache(mut self, pipeline_cache: &'a PipelineCache) -> Self {
+        self.pipeline_cache = Some(pipeline_cache);
+        self
+    }
+
+    /// Name given to the pipeline for debugging when the `validation-layers` feature is enabled
+    pub fn name(mut self, name: &'a str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn build(self, device: Rc<Device>) -> GraphicsPipeline {
+        let vertex_shader = self
+            .vertex_shader
+            .expect("a graphics pipeline needs a vertex shader");
+        assert_ne!(
+            self.render_pass,
+            vk::RenderPass::null(),
+            "a graphics pipeline needs a render pass"
+        );
+        assert!(
+            self.viewport.width != 0.0
+                && self.viewport.height != 0.0
+                && self.scissor.extent.width != 0
+                && self.scissor.extent.height != 0,
+            "a graphics pipeline needs a viewport and a scissor, given by viewport or extent"
+        );
+
+        let push_constant_layout = self.push_constant_layout.unwrap_or_default();
+
+        assert!(
+            push_constant_layout.size() <= device.properties().limits.max_push_constants_size,
+            "the push constants are larger than the device limit"
+        );
+
+        let entry = CString::new("main").unwrap();
+
+        let mut modules = vec![(
+            vk::ShaderStageFlags::VERTEX,
+            utils::create_shader_module(vertex_shader, &device),
+        )];
+        if let Some(fragment_shader) = self.fragment_shader {
+            modules.push((
+                vk::ShaderStageFlags::FRAGMENT,
+                utils::create_shader_module(fragment_shader, &device),
+            ));
+        }
+
+        let stages = modules
+            .iter()
+            .map(|&(stage, module)| {
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(stage)
+                    .module(module)
+                    .name(&entry)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(&self.vertex_bindings)
+            .vertex_attribute_descriptions(&self.vertex_attributes);
+
+        let input_assembly =
+            vk::PipelineInputAssemblyStateCreateInfo::builder().topology(self.topology);
+
+        let viewports = [self.viewport];
+        let scissors = [self.scissor];
+        let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+            .viewports(&viewports)
+            .scissors(&scissors);
+
+        let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        let color_blend = vk::PipelineColorBlendStateCreateInfo::builder()
+            .attachments(&self.color_blend_attachments);
+
+        let set_layouts = self
+            .descriptor_set_layouts
+            .iter()
+            .map(|layout| layout.descriptor_set_layout)
+            .collect::<Vec<_>>();
+
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(push_constant_layout.ranges());
+
+        let layout = unsafe { device.device.create_pipeline_layout(&layout_info, None) }
+            .expect("failed to create pipeline layout!");
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&self.rasterization)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .layout(layout)
+            .render_pass(self.render_pass)
+            .subpass(self.subpass)
+            .build();
+
+        let pipeline = unsafe {
+            device.device.create_graphics_pipelines(
+                self.pipeline_cache
+                    .map_or(vk::PipelineCache::null(), |cache| cache.handle),
+                &[pipeline_info],
+                None,
+            )
+        }
+        .expect("failed to create graphics pipeline")[0];
+
+        for (_, module) in modules {
+            unsafe {
+                device.device.destroy_shader_module(module, None);
+            }
+        }
+
+        if let Some(name) = self.name {
+            device.set_object_name(pipeline, name);
+        }
+
+        GraphicsPipeline {
+            layout,
+            pipeline,
+            push_constant_layout,
+            set_layout_count: set_layouts.len(),
+            vertex_binding_count: self.vertex_bindings.len(),
+            device,
+        }
+    }
+}
+
+impl<'a> Default for GraphicsPipelineBuilder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vulkan::{
+        BufferBuilder, CommandPool, DeviceBuilder, Framebuffer, Image, InstanceBuilder,
+        PushConstantLayoutBuilder, QueueCreateInfo, RenderPassBuilder, Subpass,
+    };
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn triangle_in_color_attachment() {
+        let instance = Rc::new(InstanceBuilder::new().headless().build().unwrap());
+        let (device, mut queues) = DeviceBuilder::new()
+            .extensions(&[])
+            .build(
+                |queue_family, _| {
+                    if queue_family.support_graphics() {
+                        Some(QueueCreateInfo::new(vec![1.0]))
+                    } else {
+                        None
+                    }
+                },
+                Rc::clone(&instance),
+            )
+            .unwrap();
+        let mut queue = queues.swap_remove(0).swap_remove(0);
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let format = vk::Format::R8G8B8A8_UNORM;
+        let extent = vk::Extent2D {
+            width: 16,
+            height: 16,
+        };
+
+        // The copy after the render pass reads what the subpass wrote
+        let render_pass = RenderPassBuilder::new()
+            .color_attachment(format, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .subpass(
+                &[],
+                &[vk::AttachmentReference::builder()
+                    .attachment(0)
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .build()],
+                None,
+            )
+            .dependency(
+                vk::SubpassDependency::builder()
+                    .src_subpass(0)
+                    .dst_subpass(vk::SUBPASS_EXTERNAL)
+                    .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                    .dst_stage_mask(vk::PipelineStageFlags::TRANSFER)
+                    .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                    .build(),
+            )
+            .build(Rc::clone(&device));
+
+        let mut image = Image::new_color_attachment(
+            extent.width,
+            extent.height,
+            format,
+            vk::ImageUsageFlags::TRANSFER_SRC,
+            Rc::clone(&device),
+            &instance,
+        )
+        .unwrap();
+        let framebuffer = Framebuffer::new(&render_pass, &[&image], extent, Rc::clone(&device));
+
+        let vertex_shader = utils::read_file("../shaders/triangle.vert.spv");
+        let fragment_shader = utils::read_file("../shaders/triangle.frag.spv");
+        let pipeline = GraphicsPipelineBuilder::new()
+            .vertex_shader(&vertex_shader)
+            .fragment_shader(&fragment_shader)
+            .render_pass(render_pass.handle, 0)
+            .extent(extent)
+            .push_constant_layout(
+                PushConstantLayoutBuilder::new()
+                    .with::<[f32; 4]>("color", vk::ShaderStageFlags::FRAGMENT)
+                    .build(),
+            )
+            .build(Rc::clone(&device));
+
+        let mut readback = BufferBuilder::new()
+            .size(extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4)
+            .staging()
+            .build(Rc::clone(&device), &instance);
+
+        let clear_values = [vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 1.0],
+            },
+        }];
+        let begin_info = render_pass
+            .begin_info(&framebuffer, framebuffer.render_area(), &clear_values)
+            .build();
+        let regions = [vk::BufferImageCopy::builder()
+            .image_subresource(
+                vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1)
+                    .build(),
+            )
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .build()];
+
+        // The render pass leaves the image in the layout the copy reads it in
+        image.layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+
+        command_pool
+            .single_time(&mut queue, |recorder| {
+                recorder
+                    .as_graphics_command_buffer()
+                    .unwrap()
+                    .renderpass(
+                        &begin_info,
+                        vec![Subpass::Inline {
+                            callback: Box::new(|scope| {
+                                scope
+                                    .as_graphics_generic()
+                                    .bind_pipeline(&pipeline)
+                                    .push_constants("color", &[0.0f32, 1.0, 0.0, 1.0])?;
+                                scope.as_draw().draw(0..3, 0..1)?;
+                                Ok(())
+                            }),
+                        }],
+                    )
+                    .unwrap();
+
+                recorder
+                    .as_transfer_command_buffer()
+                    .unwrap()
+                    .as_copy()
+                    .copy_image_to_buffer(&image, &mut readback, &regions)
+                    .unwrap();
+            })
+            .unwrap();
+
+        let mut pixels = vec![0u8; readback.size() as usize];
+        readback.get_data(&mut pixels[..], 0).unwrap();
+
+        let pixel = |x: u32, y: u32| {
+            let offset = ((y * extent.width + x) * 4) as usize;
+            &pixels[offset..offset + 4]
+        };
+        // The middle is covered by the triangle, the corners only cleared
+        assert_eq!(pixel(8, 8), [0, 255, 0, 255]);
+        assert_eq!(pixel(0, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(15, 15), [0, 0, 0, 255]);
+    }
+}
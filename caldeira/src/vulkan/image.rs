@@ -1,23 +1,467 @@
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+use std::ptr;
 use std::rc::Rc;
 
-use ash::version::DeviceV1_0;
+use ash::version::{DeviceV1_0, InstanceV1_0};
 use ash::vk;
 
 use image::RgbaImage;
 
-use super::{Buffer, Device, Instance};
+use super::external_memory::{self, ExternalMemoryError};
+#[cfg(feature = "validation-layers")]
+use super::Debug;
+use super::{
+    Allocation, Allocator, Buffer, CommandPool, Device, DeviceLost, Instance, Queue,
+    TransitionError,
+};
 use crate::utils;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConvertError {
+    /// Blits can only be recorded in command buffers of graphics queues
+    UnsupportedQueue,
+    /// The source image needs the `TRANSFER_SRC` usage and the destination one `TRANSFER_DST`
+    MissingTransferUsage,
+    /// The format of the source image doesn't support `BLIT_SRC` with its tiling
+    UnsupportedSrcFormat(vk::Format),
+    /// The format of the destination image doesn't support `BLIT_DST` with its tiling
+    UnsupportedDstFormat(vk::Format),
+    /// Unsigned and signed integer formats can only be blitted to formats of the same kind
+    IncompatibleFormats(vk::Format, vk::Format),
+    /// One of the images is in a layout which can't be transitioned to or from the transfer layouts
+    UnsupportedLayout(LayoutTransitionError),
+    DeviceLost,
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedQueue => write!(f, "Blits are only supported by graphics queues!"),
+            Self::MissingTransferUsage => write!(
+                f,
+                "The images must have the TRANSFER_SRC and TRANSFER_DST usages!"
+            ),
+            Self::UnsupportedSrcFormat(format) => {
+                write!(f, "The format {:?} can't be blitted from!", format)
+            }
+            Self::UnsupportedDstFormat(format) => {
+                write!(f, "The format {:?} can't be blitted to!", format)
+            }
+            Self::IncompatibleFormats(src_format, dst_format) => write!(
+                f,
+                "The format {:?} can't be blitted to {:?}!",
+                src_format, dst_format
+            ),
+            Self::UnsupportedLayout(error) => error.fmt(f),
+            Self::DeviceLost => write!(f, "The device was lost during the conversion!"),
+        }
+    }
+}
+
+impl Error for ConvertError {}
+
+impl From<DeviceLost> for ConvertError {
+    fn from(_: DeviceLost) -> Self {
+        Self::DeviceLost
+    }
+}
+
+impl From<LayoutTransitionError> for ConvertError {
+    fn from(error: LayoutTransitionError) -> Self {
+        Self::UnsupportedLayout(error)
+    }
+}
+
+/// The layout transition isn't supported by `Image::transition_layout`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LayoutTransitionError {
+    pub from: vk::ImageLayout,
+    pub to: vk::ImageLayout,
+}
+
+impl fmt::Display for LayoutTransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Unsupported layout transition from {:?} to {:?}!",
+            self.from, self.to
+        )
+    }
+}
+
+impl Error for LayoutTransitionError {}
+
+/// Source and destination stages, dependency flags and image memory barrier of a layout transition
+pub type LayoutTransition<'a> = (
+    vk::PipelineStageFlags,
+    vk::PipelineStageFlags,
+    vk::DependencyFlags,
+    vk::ImageMemoryBarrierBuilder<'a>,
+);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureError {
+    /// The pixels are 8 bits RGBA, so the format must be one of the `R8G8B8A8` formats
+    IncompatibleFormat(vk::Format),
+    /// The format doesn't support these usages with optimal tiling
+    UnsupportedUsage(vk::Format, vk::ImageUsageFlags),
+    /// The format isn't one of the BC or ASTC block-compressed formats
+    NotCompressed(vk::Format),
+    /// The data doesn't have the size of the compressed blocks covering the image
+    DataSizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// The format doesn't support linear filtering, which is needed to blit the mipmaps
+    UnsupportedLinearFilter(vk::Format),
+    /// The image wasn't created with the `TRANSFER_DST` usage, needed to upload its pixels
+    MissingTransferDstUsage,
+    /// The size of the texels of the format isn't known, such as for depth or multi-planar formats,
+    /// so its pixels can't be uploaded
+    UnknownTexelSize(vk::Format),
+    DeviceLost,
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IncompatibleFormat(format) => write!(
+                f,
+                "The format {:?} can't hold RGBA pixels of 8 bits!",
+                format
+            ),
+            Self::UnsupportedUsage(format, usage) => write!(
+                f,
+                "The format {:?} doesn't support the usages {:?}!",
+                format, usage
+            ),
+            Self::NotCompressed(format) => {
+                write!(f, "The format {:?} isn't block-compressed!", format)
+            }
+            Self::DataSizeMismatch { expected, actual } => write!(
+                f,
+                "The texture is {} bytes long but {} bytes were given!",
+                expected, actual
+            ),
+            Self::UnsupportedLinearFilter(format) => write!(
+                f,
+                "The format {:?} doesn't support linear filtering!",
+                format
+            ),
+            Self::MissingTransferDstUsage => {
+                write!(f, "The image wasn't created with the TRANSFER_DST usage!")
+            }
+            Self::UnknownTexelSize(format) => write!(
+                f,
+                "The size of the texels of the format {:?} is unknown!",
+                format
+            ),
+            Self::DeviceLost => write!(f, "The device was lost during the upload!"),
+        }
+    }
+}
+
+impl Error for TextureError {}
+
+impl From<DeviceLost> for TextureError {
+    fn from(_: DeviceLost) -> Self {
+        Self::DeviceLost
+    }
+}
+
+/// Width and height in texels and size in bytes of the blocks of a BC or ASTC compressed format
+fn block_layout(format: vk::Format) -> Option<(u32, u32, usize)> {
+    let layout = match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::BC4_SNORM_BLOCK => (4, 4, 8),
+        vk::Format::BC2_UNORM_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::BC5_SNORM_BLOCK
+        | vk::Format::BC6H_UFLOAT_BLOCK
+        | vk::Format::BC6H_SFLOAT_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK => (4, 4, 16),
+        // Every ASTC block is 16 bytes long, whatever its footprint
+        vk::Format::ASTC_4X4_UNORM_BLOCK | vk::Format::ASTC_4X4_SRGB_BLOCK => (4, 4, 16),
+        vk::Format::ASTC_5X4_UNORM_BLOCK | vk::Format::ASTC_5X4_SRGB_BLOCK => (5, 4, 16),
+        vk::Format::ASTC_5X5_UNORM_BLOCK | vk::Format::ASTC_5X5_SRGB_BLOCK => (5, 5, 16),
+        vk::Format::ASTC_6X5_UNORM_BLOCK | vk::Format::ASTC_6X5_SRGB_BLOCK => (6, 5, 16),
+        vk::Format::ASTC_6X6_UNORM_BLOCK | vk::Format::ASTC_6X6_SRGB_BLOCK => (6, 6, 16),
+        vk::Format::ASTC_8X5_UNORM_BLOCK | vk::Format::ASTC_8X5_SRGB_BLOCK => (8, 5, 16),
+        vk::Format::ASTC_8X6_UNORM_BLOCK | vk::Format::ASTC_8X6_SRGB_BLOCK => (8, 6, 16),
+        vk::Format::ASTC_8X8_UNORM_BLOCK | vk::Format::ASTC_8X8_SRGB_BLOCK => (8, 8, 16),
+        vk::Format::ASTC_10X5_UNORM_BLOCK | vk::Format::ASTC_10X5_SRGB_BLOCK => (10, 5, 16),
+        vk::Format::ASTC_10X6_UNORM_BLOCK | vk::Format::ASTC_10X6_SRGB_BLOCK => (10, 6, 16),
+        vk::Format::ASTC_10X8_UNORM_BLOCK | vk::Format::ASTC_10X8_SRGB_BLOCK => (10, 8, 16),
+        vk::Format::ASTC_10X10_UNORM_BLOCK | vk::Format::ASTC_10X10_SRGB_BLOCK => (10, 10, 16),
+        vk::Format::ASTC_12X10_UNORM_BLOCK | vk::Format::ASTC_12X10_SRGB_BLOCK => (12, 10, 16),
+        vk::Format::ASTC_12X12_UNORM_BLOCK | vk::Format::ASTC_12X12_SRGB_BLOCK => (12, 12, 16),
+        _ => return None,
+    };
+
+    Some(layout)
+}
+
+/// Aspects of the texels of `format`, depth and/or stencil for depth/stencil formats and color otherwise
+fn format_aspect(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+/// Size in bytes of an image of a compressed `format`, whose edges are covered by partial blocks
+fn compressed_size(format: vk::Format, width: u32, height: u32) -> Option<usize> {
+    let blocks = |len: u32, block_len: u32| match len % block_len {
+        0 => len / block_len,
+        _ => len / block_len + 1,
+    } as usize;
+
+    block_layout(format).map(|(block_width, block_height, block_size)| {
+        blocks(width, block_width) * blocks(height, block_height) * block_size
+    })
+}
+
+/// Size in bytes of a texel of an uncompressed color `format`, `None` for the other formats
+fn texel_size(format: vk::Format) -> Option<usize> {
+    let size = match format {
+        vk::Format::R4G4_UNORM_PACK8
+        | vk::Format::R8_UNORM
+        | vk::Format::R8_SNORM
+        | vk::Format::R8_USCALED
+        | vk::Format::R8_SSCALED
+        | vk::Format::R8_UINT
+        | vk::Format::R8_SINT
+        | vk::Format::R8_SRGB => 1,
+        vk::Format::R4G4B4A4_UNORM_PACK16
+        | vk::Format::B4G4R4A4_UNORM_PACK16
+        | vk::Format::R5G6B5_UNORM_PACK16
+        | vk::Format::B5G6R5_UNORM_PACK16
+        | vk::Format::R5G5B5A1_UNORM_PACK16
+        | vk::Format::B5G5R5A1_UNORM_PACK16
+        | vk::Format::A1R5G5B5_UNORM_PACK16
+        | vk::Format::R8G8_UNORM
+        | vk::Format::R8G8_SNORM
+        | vk::Format::R8G8_USCALED
+        | vk::Format::R8G8_SSCALED
+        | vk::Format::R8G8_UINT
+        | vk::Format::R8G8_SINT
+        | vk::Format::R8G8_SRGB
+        | vk::Format::R16_UNORM
+        | vk::Format::R16_SNORM
+        | vk::Format::R16_USCALED
+        | vk::Format::R16_SSCALED
+        | vk::Format::R16_UINT
+        | vk::Format::R16_SINT
+        | vk::Format::R16_SFLOAT => 2,
+        vk::Format::R8G8B8_UNORM
+        | vk::Format::R8G8B8_SNORM
+        | vk::Format::R8G8B8_USCALED
+        | vk::Format::R8G8B8_SSCALED
+        | vk::Format::R8G8B8_UINT
+        | vk::Format::R8G8B8_SINT
+        | vk::Format::R8G8B8_SRGB
+        | vk::Format::B8G8R8_UNORM
+        | vk::Format::B8G8R8_SNORM
+        | vk::Format::B8G8R8_USCALED
+        | vk::Format::B8G8R8_SSCALED
+        | vk::Format::B8G8R8_UINT
+        | vk::Format::B8G8R8_SINT
+        | vk::Format::B8G8R8_SRGB => 3,
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SNORM
+        | vk::Format::R8G8B8A8_USCALED
+        | vk::Format::R8G8B8A8_SSCALED
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::R8G8B8A8_SINT
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SNORM
+        | vk::Format::B8G8R8A8_USCALED
+        | vk::Format::B8G8R8A8_SSCALED
+        | vk::Format::B8G8R8A8_UINT
+        | vk::Format::B8G8R8A8_SINT
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A8B8G8R8_UNORM_PACK32
+        | vk::Format::A8B8G8R8_SNORM_PACK32
+        | vk::Format::A8B8G8R8_USCALED_PACK32
+        | vk::Format::A8B8G8R8_SSCALED_PACK32
+        | vk::Format::A8B8G8R8_UINT_PACK32
+        | vk::Format::A8B8G8R8_SINT_PACK32
+        | vk::Format::A8B8G8R8_SRGB_PACK32
+        | vk::Format::A2R10G10B10_UNORM_PACK32
+        | vk::Format::A2R10G10B10_SNORM_PACK32
+        | vk::Format::A2R10G10B10_USCALED_PACK32
+        | vk::Format::A2R10G10B10_SSCALED_PACK32
+        | vk::Format::A2R10G10B10_UINT_PACK32
+        | vk::Format::A2R10G10B10_SINT_PACK32
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::A2B10G10R10_SNORM_PACK32
+        | vk::Format::A2B10G10R10_USCALED_PACK32
+        | vk::Format::A2B10G10R10_SSCALED_PACK32
+        | vk::Format::A2B10G10R10_UINT_PACK32
+        | vk::Format::A2B10G10R10_SINT_PACK32
+        | vk::Format::R16G16_UNORM
+        | vk::Format::R16G16_SNORM
+        | vk::Format::R16G16_USCALED
+        | vk::Format::R16G16_SSCALED
+        | vk::Format::R16G16_UINT
+        | vk::Format::R16G16_SINT
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_UINT
+        | vk::Format::R32_SINT
+        | vk::Format::R32_SFLOAT
+        | vk::Format::B10G11R11_UFLOAT_PACK32
+        | vk::Format::E5B9G9R9_UFLOAT_PACK32 => 4,
+        vk::Format::R16G16B16_UNORM
+        | vk::Format::R16G16B16_SNORM
+        | vk::Format::R16G16B16_USCALED
+        | vk::Format::R16G16B16_SSCALED
+        | vk::Format::R16G16B16_UINT
+        | vk::Format::R16G16B16_SINT
+        | vk::Format::R16G16B16_SFLOAT => 6,
+        vk::Format::R16G16B16A16_UNORM
+        | vk::Format::R16G16B16A16_SNORM
+        | vk::Format::R16G16B16A16_USCALED
+        | vk::Format::R16G16B16A16_SSCALED
+        | vk::Format::R16G16B16A16_UINT
+        | vk::Format::R16G16B16A16_SINT
+        | vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R32G32_UINT
+        | vk::Format::R32G32_SINT
+        | vk::Format::R32G32_SFLOAT
+        | vk::Format::R64_UINT
+        | vk::Format::R64_SINT
+        | vk::Format::R64_SFLOAT => 8,
+        vk::Format::R32G32B32_UINT | vk::Format::R32G32B32_SINT | vk::Format::R32G32B32_SFLOAT => {
+            12
+        }
+        vk::Format::R32G32B32A32_UINT
+        | vk::Format::R32G32B32A32_SINT
+        | vk::Format::R32G32B32A32_SFLOAT
+        | vk::Format::R64G64_UINT
+        | vk::Format::R64G64_SINT
+        | vk::Format::R64G64_SFLOAT => 16,
+        vk::Format::R64G64B64_UINT | vk::Format::R64G64B64_SINT | vk::Format::R64G64B64_SFLOAT => {
+            24
+        }
+        vk::Format::R64G64B64A64_UINT
+        | vk::Format::R64G64B64A64_SINT
+        | vk::Format::R64G64B64A64_SFLOAT => 32,
+        _ => return None,
+    };
+
+    Some(size)
+}
+
+/// Size in bytes of the tightly packed texels or blocks of the first level of an image of `format`,
+/// for the formats whose texel size is known
+fn upload_size(format: vk::Format, width: u32, height: u32) -> Option<usize> {
+    match texel_size(format) {
+        Some(texel_size) => Some(width as usize * height as usize * texel_size),
+        None => compressed_size(format, width, height),
+    }
+}
+
+/// Kind of the values of a format, which must be the same for the source and destination of a blit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NumericKind {
+    Uint,
+    Sint,
+    Float,
+}
+
+impl NumericKind {
+    fn of(format: vk::Format) -> Self {
+        match format {
+            vk::Format::R8_UINT
+            | vk::Format::R8G8_UINT
+            | vk::Format::R8G8B8_UINT
+            | vk::Format::B8G8R8_UINT
+            | vk::Format::R8G8B8A8_UINT
+            | vk::Format::B8G8R8A8_UINT
+            | vk::Format::A8B8G8R8_UINT_PACK32
+            | vk::Format::A2R10G10B10_UINT_PACK32
+            | vk::Format::A2B10G10R10_UINT_PACK32
+            | vk::Format::R16_UINT
+            | vk::Format::R16G16_UINT
+            | vk::Format::R16G16B16_UINT
+            | vk::Format::R16G16B16A16_UINT
+            | vk::Format::R32_UINT
+            | vk::Format::R32G32_UINT
+            | vk::Format::R32G32B32_UINT
+            | vk::Format::R32G32B32A32_UINT
+            | vk::Format::R64_UINT
+            | vk::Format::R64G64_UINT
+            | vk::Format::R64G64B64_UINT
+            | vk::Format::R64G64B64A64_UINT
+            | vk::Format::S8_UINT => Self::Uint,
+            vk::Format::R8_SINT
+            | vk::Format::R8G8_SINT
+            | vk::Format::R8G8B8_SINT
+            | vk::Format::B8G8R8_SINT
+            | vk::Format::R8G8B8A8_SINT
+            | vk::Format::B8G8R8A8_SINT
+            | vk::Format::A8B8G8R8_SINT_PACK32
+            | vk::Format::A2R10G10B10_SINT_PACK32
+            | vk::Format::A2B10G10R10_SINT_PACK32
+            | vk::Format::R16_SINT
+            | vk::Format::R16G16_SINT
+            | vk::Format::R16G16B16_SINT
+            | vk::Format::R16G16B16A16_SINT
+            | vk::Format::R32_SINT
+            | vk::Format::R32G32_SINT
+            | vk::Format::R32G32B32_SINT
+            | vk::Format::R32G32B32A32_SINT
+            | vk::Format::R64_SINT
+            | vk::Format::R64G64_SINT
+            | vk::Format::R64G64B64_SINT
+            | vk::Format::R64G64B64A64_SINT => Self::Sint,
+            _ => Self::Float,
+        }
+    }
+}
+
 pub struct Image {
     pub handle: vk::Image,
     pub memory: vk::DeviceMemory,
     pub extent: vk::Extent3D,
     pub layout: vk::ImageLayout,
     pub view: vk::ImageView,
+    pub usage: vk::ImageUsageFlags,
+    pub format: vk::Format,
+    tiling: vk::ImageTiling,
+    mip_levels: u32,
+    array_layers: u32,
+    aspect_flags: vk::ImageAspectFlags,
+    memory_offset: vk::DeviceSize,
+    export_handle_types: vk::ExternalMemoryHandleTypeFlags,
+    memory_owned: bool,
+    /// Range of an `Allocator` block the image is bound to, given back after the image is destroyed
+    allocation: Option<Allocation>,
     device: Rc<Device>,
 }
 
 impl Image {
+    /// `name` is given to the image for debugging when the `validation-layers` feature is enabled
     pub fn new(
         width: u32,
         height: u32,
@@ -26,66 +470,396 @@ impl Image {
         usage: vk::ImageUsageFlags,
         aspect_flags: vk::ImageAspectFlags,
         properties: vk::MemoryPropertyFlags,
+        name: Option<&str>,
         device: Rc<Device>,
         instance: &Instance,
     ) -> Self {
-        let (handle, memory, extent) = Self::create_image(
-            width, height, format, tiling, usage, properties, &device, instance,
-        );
-        let view = Self::create_image_view(handle, format, aspect_flags, &device);
-        let layout = vk::ImageLayout::UNDEFINED;
+        let mut builder = ImageBuilder::new(width, height, format, usage)
+            .tiling(tiling)
+            .aspect_flags(aspect_flags)
+            .memory_properties(properties);
 
-        Self {
-            handle,
-            memory,
-            extent,
-            layout,
-            view,
+        if let Some(name) = name {
+            builder = builder.name(name);
+        }
+
+        builder.build(device, instance)
+    }
+
+    /// Create an image whose memory can be exported as `handle_type` to be shared with another API
+    /// The device extension of the handle type must be enabled, `EXTERNAL_MEMORY_FD` for `OPAQUE_FD`
+    /// and `EXTERNAL_MEMORY_WIN32` for `OPAQUE_WIN32`
+    pub fn new_exportable(
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        tiling: vk::ImageTiling,
+        usage: vk::ImageUsageFlags,
+        aspect_flags: vk::ImageAspectFlags,
+        properties: vk::MemoryPropertyFlags,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+        device: Rc<Device>,
+        instance: &Instance,
+    ) -> Result<Self, ExternalMemoryError> {
+        external_memory::check_handle_type(handle_type, &device)?;
+
+        Ok(ImageBuilder::new(width, height, format, usage)
+            .tiling(tiling)
+            .aspect_flags(aspect_flags)
+            .memory_properties(properties)
+            .export_handle_types(handle_type)
+            .build(device, instance))
+    }
+
+    /// Create a `R8G8B8A8_SRGB` texture from `image` and upload it on `queue`, leaving it in
+    /// `SHADER_READ_ONLY_OPTIMAL` layout
+    /// The copy is done on `transfer` if it holds a queue of a transfer-only family, see `upload_from_pixels`
+    pub fn new_texture(
+        image: RgbaImage,
+        device: Rc<Device>,
+        instance: &Instance,
+        command_pool: &mut Rc<CommandPool>,
+        queue: &mut Queue,
+        transfer: Option<(&mut Queue, &mut Rc<CommandPool>)>,
+    ) -> Result<Self, DeviceLost> {
+        match Self::new_texture_with(
+            image,
+            vk::ImageUsageFlags::empty(),
+            None,
             device,
+            instance,
+            command_pool,
+            queue,
+            transfer,
+        ) {
+            Ok(texture) => Ok(texture),
+            Err(TextureError::DeviceLost) => Err(DeviceLost),
+            Err(error) => panic!(
+                "sampled R8G8B8A8_SRGB images are always supported: {}",
+                error
+            ),
         }
     }
 
-    pub fn new_texture(image: RgbaImage, device: Rc<Device>, instance: &Instance) -> Self {
+    /// Same as `new_texture`, with `extra_usage` besides `TRANSFER_DST | SAMPLED`, for instance `STORAGE`
+    /// or `TRANSFER_SRC` to generate mipmaps, and another `R8G8B8A8` format than `R8G8B8A8_SRGB`
+    pub fn new_texture_with(
+        image: RgbaImage,
+        extra_usage: vk::ImageUsageFlags,
+        format: Option<vk::Format>,
+        device: Rc<Device>,
+        instance: &Instance,
+        command_pool: &mut Rc<CommandPool>,
+        queue: &mut Queue,
+        transfer: Option<(&mut Queue, &mut Rc<CommandPool>)>,
+    ) -> Result<Self, TextureError> {
+        let format = format.unwrap_or(vk::Format::R8G8B8A8_SRGB);
+        let usage = vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED | extra_usage;
+
+        match format {
+            vk::Format::R8G8B8A8_UNORM
+            | vk::Format::R8G8B8A8_SNORM
+            | vk::Format::R8G8B8A8_USCALED
+            | vk::Format::R8G8B8A8_SSCALED
+            | vk::Format::R8G8B8A8_UINT
+            | vk::Format::R8G8B8A8_SINT
+            | vk::Format::R8G8B8A8_SRGB => {}
+            _ => return Err(TextureError::IncompatibleFormat(format)),
+        }
+
+        Self::check_usage_support(format, usage, &device)?;
+
         let (width, height) = image.dimensions();
-        let size = width * height * 4;
         let pixels = image.into_raw();
 
-        let texture_image = Self::new(
+        let mut texture_image = Self::new(
             width,
             height,
-            vk::Format::R8G8B8A8_SRGB,
+            format,
             vk::ImageTiling::OPTIMAL,
-            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+            usage,
             vk::ImageAspectFlags::COLOR,
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            Rc::clone(&device),
+            None,
+            device,
+            instance,
+        );
+
+        texture_image.upload_from_pixels(&pixels, command_pool, queue, transfer)?;
+
+        Ok(texture_image)
+    }
+
+    /// Create a texture of a BC or ASTC block-compressed `format` from `data`, its blocks row after row,
+    /// and upload it on `queue`, leaving it in `SHADER_READ_ONLY_OPTIMAL` layout
+    /// The copy is done on `transfer` if it holds a queue of a transfer-only family, see `upload_from_pixels`
+    pub fn new_compressed_texture(
+        data: &[u8],
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        device: Rc<Device>,
+        instance: &Instance,
+        command_pool: &mut Rc<CommandPool>,
+        queue: &mut Queue,
+        transfer: Option<(&mut Queue, &mut Rc<CommandPool>)>,
+    ) -> Result<Self, TextureError> {
+        if block_layout(format).is_none() {
+            return Err(TextureError::NotCompressed(format));
+        }
+
+        let usage = vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED;
+
+        Self::check_usage_support(format, usage, &device)?;
+
+        let mut texture_image = Self::new(
+            width,
+            height,
+            format,
+            vk::ImageTiling::OPTIMAL,
+            usage,
+            vk::ImageAspectFlags::COLOR,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            None,
+            device,
             instance,
         );
 
+        texture_image.upload_from_pixels(data, command_pool, queue, transfer)?;
+
+        Ok(texture_image)
+    }
+
+    /// Create a texture with all its mip levels, see `mip_level_count`, and upload it on `queue`,
+    /// leaving it in `SHADER_READ_ONLY_OPTIMAL` layout
+    /// The levels are blitted from each other by `GraphicsCopyCommands::generate_mipmaps`, so `queue` must support
+    /// graphics operations and `format`, `R8G8B8A8_SRGB` by default, must support linear filtering
+    pub fn new_texture_mipmapped(
+        image: RgbaImage,
+        format: Option<vk::Format>,
+        device: Rc<Device>,
+        instance: &Instance,
+        command_pool: &mut Rc<CommandPool>,
+        queue: &mut Queue,
+    ) -> Result<Self, TextureError> {
+        let format = format.unwrap_or(vk::Format::R8G8B8A8_SRGB);
+        let usage = vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST
+            | vk::ImageUsageFlags::SAMPLED;
+
+        match format {
+            vk::Format::R8G8B8A8_UNORM
+            | vk::Format::R8G8B8A8_SNORM
+            | vk::Format::R8G8B8A8_USCALED
+            | vk::Format::R8G8B8A8_SSCALED
+            | vk::Format::R8G8B8A8_UINT
+            | vk::Format::R8G8B8A8_SINT
+            | vk::Format::R8G8B8A8_SRGB => {}
+            _ => return Err(TextureError::IncompatibleFormat(format)),
+        }
+
+        Self::check_usage_support(format, usage, &device)?;
+
+        if !Self::tiling_features(format, vk::ImageTiling::OPTIMAL, &device)
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+        {
+            return Err(TextureError::UnsupportedLinearFilter(format));
+        }
+
+        let (width, height) = image.dimensions();
+        let pixels = image.into_raw();
+
+        let mut texture_image = ImageBuilder::new(width, height, format, usage)
+            .mip_levels(Self::mip_level_count(width, height))
+            .build(Rc::clone(&device), instance);
+
         let mut staging_buffer = Buffer::new(
-            size as _,
+            pixels.len() as _,
             vk::BufferUsageFlags::TRANSFER_SRC,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-            Rc::clone(&device),
+            None,
+            device,
             instance,
         );
-        staging_buffer.copy_data(&pixels[..], 0);
+        staging_buffer
+            .copy_data(&pixels[..], 0)
+            .expect("the staging buffer has the size of the pixels");
+
+        // All the levels are transitioned, the first one is uploaded and the others are blitted
+        let (src_stage_mask, dst_stage_mask, dependency_flags, barrier) = texture_image
+            .transition_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .expect("an undefined image can always be transitioned");
+        let upload_barriers = [barrier.build()];
+
+        let regions = [vk::BufferImageCopy::builder()
+            .image_subresource(
+                vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .build(),
+            )
+            .image_extent(texture_image.extent)
+            .build()];
 
-        texture_image
+        command_pool.single_time(queue, |recorder| {
+            recorder.as_generic().pipeline_barrier(
+                src_stage_mask,
+                dst_stage_mask,
+                dependency_flags,
+                &[],
+                &[],
+                &upload_barriers,
+            );
+
+            recorder
+                .as_transfer_command_buffer()
+                .unwrap()
+                .as_copy()
+                .copy_buffer_to_image(&staging_buffer, &mut texture_image, &regions)
+                .unwrap();
+        })?;
+
+        // The recorded commands borrow the image, so the mipmaps are generated by another submission
+        command_pool.single_time(queue, |recorder| {
+            recorder
+                .as_transfer_command_buffer()
+                .unwrap()
+                .as_copy()
+                .as_graphics_copy()
+                .generate_mipmaps(&mut texture_image)
+                .expect("the format was checked to support linear filtering");
+        })?;
+
+        Ok(texture_image)
+    }
+
+    /// Number of mip levels of a complete mipmap chain of an image of `width` by `height`,
+    /// down to a single texel
+    pub fn mip_level_count(width: u32, height: u32) -> u32 {
+        32 - width.max(height).max(1).leading_zeros()
     }
 
     pub fn new_storage(width: u32, height: u32, device: Rc<Device>, instance: &Instance) -> Self {
-        Self::new(
+        ImageBuilder::new(
             width,
             height,
             vk::Format::R8G8B8A8_UINT,
-            vk::ImageTiling::OPTIMAL,
             vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC,
+        )
+        .build(device, instance)
+    }
+
+    /// Create an image to render to, `extra_usage` being added to `COLOR_ATTACHMENT`, commonly `SAMPLED`
+    /// to read it in a next pass or `TRANSFER_SRC` to read it back
+    pub fn new_color_attachment(
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        extra_usage: vk::ImageUsageFlags,
+        device: Rc<Device>,
+        instance: &Instance,
+    ) -> Result<Self, TextureError> {
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | extra_usage;
+
+        Self::check_usage_support(format, usage, &device)?;
+
+        Ok(Self::new(
+            width,
+            height,
+            format,
+            vk::ImageTiling::OPTIMAL,
+            usage,
             vk::ImageAspectFlags::COLOR,
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            None,
             device,
             instance,
-        )
+        ))
+    }
+
+    /// Create a depth attachment, `extra_usage` being added to `DEPTH_STENCIL_ATTACHMENT`, commonly `SAMPLED`
+    /// for shadow maps, the stencil aspect is included when `format` has one
+    pub fn new_depth_attachment(
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        extra_usage: vk::ImageUsageFlags,
+        device: Rc<Device>,
+        instance: &Instance,
+    ) -> Result<Self, TextureError> {
+        let usage = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | extra_usage;
+
+        Self::check_usage_support(format, usage, &device)?;
+
+        Ok(ImageBuilder::new(width, height, format, usage).build(device, instance))
+    }
+
+    /// Check that optimal images of `format` support `usage`
+    fn check_usage_support(
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        device: &Device,
+    ) -> Result<(), TextureError> {
+        let features = Self::tiling_features(format, vk::ImageTiling::OPTIMAL, device);
+        let unsupported_usage = [
+            (
+                vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::FormatFeatureFlags::TRANSFER_SRC,
+            ),
+            (
+                vk::ImageUsageFlags::TRANSFER_DST,
+                vk::FormatFeatureFlags::TRANSFER_DST,
+            ),
+            (
+                vk::ImageUsageFlags::SAMPLED,
+                vk::FormatFeatureFlags::SAMPLED_IMAGE,
+            ),
+            (
+                vk::ImageUsageFlags::STORAGE,
+                vk::FormatFeatureFlags::STORAGE_IMAGE,
+            ),
+            (
+                vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                vk::FormatFeatureFlags::COLOR_ATTACHMENT,
+            ),
+            (
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+            ),
+        ]
+        .iter()
+        .filter(|(usage_flag, feature)| usage.contains(*usage_flag) && !features.contains(*feature))
+        .fold(
+            vk::ImageUsageFlags::empty(),
+            |unsupported, (usage_flag, _)| unsupported | *usage_flag,
+        );
+
+        if unsupported_usage.is_empty() {
+            Ok(())
+        } else {
+            Err(TextureError::UnsupportedUsage(format, unsupported_usage))
+        }
+    }
+
+    /// Same as `new_storage`, but the image is transitioned to `GENERAL` layout on `queue` before being returned
+    pub fn new_storage_general(
+        width: u32,
+        height: u32,
+        command_pool: &mut Rc<CommandPool>,
+        queue: &mut Queue,
+        device: Rc<Device>,
+        instance: &Instance,
+    ) -> Result<Self, DeviceLost> {
+        let mut image = Self::new_storage(width, height, device, instance);
+
+        match queue.transition_image(&mut image, vk::ImageLayout::GENERAL, command_pool) {
+            Ok(()) => Ok(image),
+            Err(TransitionError::DeviceLost) => Err(DeviceLost),
+            // The transition only waits for and blocks stages supported by every queue
+            Err(error) => unreachable!("an undefined image can always be transitioned: {}", error),
+        }
     }
 
     pub fn new_staging(
@@ -95,114 +869,422 @@ impl Image {
         device: Rc<Device>,
         instance: &Instance,
     ) -> Self {
-        let (handle, memory, extent) = Self::create_image(
+        // Preinitialized so that the contents written by the host are kept by the first transition
+        ImageBuilder::new(
             width,
             height,
             format,
-            vk::ImageTiling::LINEAR,
             vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+        )
+        .tiling(vk::ImageTiling::LINEAR)
+        .memory_properties(
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-            &device,
-            instance,
-        );
-        let layout = vk::ImageLayout::UNDEFINED;
-        let view = vk::ImageView::null();
-
-        Self {
-            handle,
-            memory,
-            extent,
-            layout,
-            view,
-            device,
-        }
+        )
+        .initial_layout(vk::ImageLayout::PREINITIALIZED)
+        .build(device, instance)
     }
 
-    fn create_image(
+    /// Create an image without memory, the memory has to be bound with `UnboundImage::bind`
+    pub fn create_unbound(
         width: u32,
         height: u32,
         format: vk::Format,
         tiling: vk::ImageTiling,
         usage: vk::ImageUsageFlags,
-        properties: vk::MemoryPropertyFlags,
-        device: &Device,
-        instance: &Instance,
-    ) -> (vk::Image, vk::DeviceMemory, vk::Extent3D) {
-        let extent = vk::Extent3D::builder()
-            .width(width)
-            .height(height)
-            .depth(1)
-            .build();
-
-        let image_info = vk::ImageCreateInfo::builder()
-            .image_type(vk::ImageType::TYPE_2D)
-            .extent(extent)
-            .mip_levels(1)
-            .array_layers(1)
-            .format(format)
+        device: Rc<Device>,
+    ) -> UnboundImage {
+        ImageBuilder::new(width, height, format, usage)
             .tiling(tiling)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .usage(usage)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE);
-
-        let image = unsafe { device.device.create_image(&image_info, None) }
-            .expect("failed to create image!");
+            .build_unbound(device)
+    }
 
-        let mem_requirements = unsafe { device.device.get_image_memory_requirements(image) };
+    /// Name the image in the validation messages
+    #[cfg(feature = "validation-layers")]
+    pub fn set_name(&self, debug: &Debug, name: &str) {
+        debug.set_object_name(&self.device, self.handle, name);
+    }
 
-        let alloc_info = vk::MemoryAllocateInfo::builder()
-            .allocation_size(mem_requirements.size)
-            .memory_type_index(utils::find_memory_type(
-                mem_requirements.memory_type_bits,
-                properties,
-                device,
-                instance,
-            ));
+    /// Export the memory of an image created by `new_exportable` with `OPAQUE_FD` as a file descriptor,
+    /// which is owned by the caller
+    #[cfg(unix)]
+    pub fn export_fd(&self) -> Result<i32, ExternalMemoryError> {
+        external_memory::export_fd(self.memory, self.export_handle_types, &self.device)
+    }
 
-        let memory = unsafe { device.device.allocate_memory(&alloc_info, None) }
-            .expect("failed to allocate image memory!");
+    /// Export the memory of an image created by `new_exportable` with `OPAQUE_WIN32` as a handle,
+    /// which is owned by the caller
+    #[cfg(windows)]
+    pub fn export_win32_handle(&self) -> Result<vk::HANDLE, ExternalMemoryError> {
+        external_memory::export_win32_handle(self.memory, self.export_handle_types, &self.device)
+    }
 
+    /// Size, alignment and memory type bits the memory bound to this image must satisfy
+    pub fn memory_requirements(&self) -> vk::MemoryRequirements {
         unsafe {
-            device.device.bind_image_memory(image, memory, 0).unwrap();
+            self.device
+                .device
+                .get_image_memory_requirements(self.handle)
         }
+    }
 
-        (image, memory, extent)
+    /// Layout of `subresource` in the memory of the image, which has padding between rows for linear images
+    pub fn subresource_layout(&self, subresource: vk::ImageSubresource) -> vk::SubresourceLayout {
+        unsafe {
+            self.device
+                .device
+                .get_image_subresource_layout(self.handle, subresource)
+        }
     }
 
-    /// Return all src_stage_mask, dst_stage_mask, depency_flags and the image memory barrier
-    /// This functions set the new layout, and therefore the transition is considered done
-    pub fn transition_layout(
+    /// Upload `pixels`, tightly packed rows of texels or of compressed blocks, to the first mip level and
+    /// array layer of the color aspect through a staging buffer, and leave the image in `SHADER_READ_ONLY_OPTIMAL`
+    /// layout once the upload on `queue` is done
+    /// The image needs the `TRANSFER_DST` usage, and its previous content is discarded
+    /// Only the shader stages `queue` supports wait for the upload, see `QueueFamily::shader_stages`
+    /// If `transfer` holds a queue of a transfer-only family, the copy is done on it and the ownership
+    /// of the image is then given back to the family of `queue`, otherwise the copy is done on `queue`
+    pub fn upload_from_pixels(
         &mut self,
-        new_layout: vk::ImageLayout,
-    ) -> (
-        vk::PipelineStageFlags,
-        vk::PipelineStageFlags,
-        vk::DependencyFlags,
-        vk::ImageMemoryBarrierBuilder<'_>,
-    ) {
-        if self.layout == new_layout {
-            return (
-                vk::PipelineStageFlags::empty(),
-                vk::PipelineStageFlags::empty(),
-                vk::DependencyFlags::empty(),
-                vk::ImageMemoryBarrier::builder(),
-            );
+        pixels: &[u8],
+        command_pool: &mut Rc<CommandPool>,
+        queue: &mut Queue,
+        transfer: Option<(&mut Queue, &mut Rc<CommandPool>)>,
+    ) -> Result<(), TextureError> {
+        if !self.usage.contains(vk::ImageUsageFlags::TRANSFER_DST) {
+            return Err(TextureError::MissingTransferDstUsage);
         }
 
-        let subresource_range = vk::ImageSubresourceRange::builder()
-            .aspect_mask(vk::ImageAspectFlags::COLOR)
-            .base_mip_level(0)
-            .level_count(1)
-            .base_array_layer(0)
-            .layer_count(1)
-            .build();
+        let expected = upload_size(self.format, self.extent.width, self.extent.height)
+            .ok_or(TextureError::UnknownTexelSize(self.format))?;
+        if pixels.len() != expected {
+            return Err(TextureError::DataSizeMismatch {
+                expected,
+                actual: pixels.len(),
+            });
+        }
 
-        let (src_access_mask, src_stage_mask) = match self.layout {
-            vk::ImageLayout::UNDEFINED => (
-                vk::AccessFlags::empty(),
-                vk::PipelineStageFlags::TOP_OF_PIPE,
-            ),
+        let mut staging_buffer = Buffer::new(
+            pixels.len() as _,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            None,
+            Rc::clone(&self.device),
+            self.device.instance(),
+        );
+        staging_buffer
+            .copy_data(pixels, 0)
+            .expect("the staging buffer has the size of the pixels");
+
+        // The content is overwritten, so it doesn't need to be preserved by the transition
+        self.layout = vk::ImageLayout::UNDEFINED;
+        let (src_stage_mask, dst_stage_mask, dependency_flags, barrier) = self
+            .transition_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .expect("an undefined image can always be transitioned");
+        let upload_barriers = [barrier.build()];
+
+        let regions = [vk::BufferImageCopy::builder()
+            .image_subresource(
+                vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .build(),
+            )
+            .image_extent(self.extent)
+            .build()];
+
+        let (sampled_src_stage_mask, _, _, mut barrier) = self
+            .transition_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .expect("a transfer destination can always be transitioned to be sampled");
+
+        // Only the shaders the queue can run can be waited for, a transfer only queue has none,
+        // the reads are then ordered by the synchronization with the queue which does them
+        let sampled_dst_stage_mask = match queue.family().shader_stages() {
+            stages if stages.is_empty() => {
+                barrier = barrier.dst_access_mask(vk::AccessFlags::empty());
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE
+            }
+            stages => stages,
+        };
+        let sampled_barrier = barrier.build();
+
+        // The copy is recorded while the image is in the layout it has after the first barrier
+        self.layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+
+        match transfer {
+            Some((transfer_queue, transfer_command_pool))
+                if transfer_queue.family().support_transfer_only()
+                    && transfer_queue.queue_family_index() != queue.queue_family_index() =>
+            {
+                let ownership_barrier = vk::ImageMemoryBarrier {
+                    src_queue_family_index: transfer_queue.queue_family_index() as _,
+                    dst_queue_family_index: queue.queue_family_index() as _,
+                    ..sampled_barrier
+                };
+
+                let release_barriers = [vk::ImageMemoryBarrier {
+                    dst_access_mask: vk::AccessFlags::empty(),
+                    ..ownership_barrier
+                }];
+
+                transfer_command_pool.single_time(transfer_queue, |recorder| {
+                    recorder.as_generic().pipeline_barrier(
+                        src_stage_mask,
+                        dst_stage_mask,
+                        dependency_flags,
+                        &[],
+                        &[],
+                        &upload_barriers,
+                    );
+
+                    recorder
+                        .as_transfer_command_buffer()
+                        .unwrap()
+                        .as_copy()
+                        .copy_buffer_to_image(&staging_buffer, self, &regions)
+                        .unwrap();
+
+                    recorder.as_generic().pipeline_barrier(
+                        sampled_src_stage_mask,
+                        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[],
+                        &release_barriers,
+                    );
+                })?;
+
+                let acquire_barriers = [vk::ImageMemoryBarrier {
+                    src_access_mask: vk::AccessFlags::empty(),
+                    ..ownership_barrier
+                }];
+
+                command_pool.single_time(queue, |recorder| {
+                    recorder.as_generic().pipeline_barrier(
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                        sampled_dst_stage_mask,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[],
+                        &acquire_barriers,
+                    );
+                })?;
+            }
+            _ => {
+                let sampled_barriers = [sampled_barrier];
+
+                command_pool.single_time(queue, |recorder| {
+                    recorder.as_generic().pipeline_barrier(
+                        src_stage_mask,
+                        dst_stage_mask,
+                        dependency_flags,
+                        &[],
+                        &[],
+                        &upload_barriers,
+                    );
+
+                    recorder
+                        .as_transfer_command_buffer()
+                        .unwrap()
+                        .as_copy()
+                        .copy_buffer_to_image(&staging_buffer, self, &regions)
+                        .unwrap();
+
+                    recorder.as_generic().pipeline_barrier(
+                        sampled_src_stage_mask,
+                        sampled_dst_stage_mask,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[],
+                        &sampled_barriers,
+                    );
+                })?;
+            }
+        }
+
+        self.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+
+        Ok(())
+    }
+
+    /// Write `pixels`, tightly packed rows of texels, to the color aspect of a linear image created by
+    /// `new_staging`, following the row pitch of the image
+    /// The image must be in the `PREINITIALIZED` or `GENERAL` layout and not used by the device
+    pub fn write_pixels(&mut self, pixels: &[u8]) {
+        let (layout, row_size) = self.host_access_layout(pixels.len());
+
+        unsafe {
+            let ptr = self.map_subresource(&layout);
+
+            for (row, src) in pixels.chunks_exact(row_size).enumerate() {
+                let dst = ptr.add(row * layout.row_pitch as usize);
+                ptr::copy_nonoverlapping(src.as_ptr(), dst, row_size);
+            }
+
+            self.unmap_memory();
+        }
+    }
+
+    /// Read the color aspect of a linear image created by `new_staging` into `pixels`, as tightly packed
+    /// rows of texels, following the row pitch of the image
+    /// The image must be in the `GENERAL` layout and its writes by the device made visible to the host
+    pub fn read_pixels(&self, pixels: &mut [u8]) {
+        let (layout, row_size) = self.host_access_layout(pixels.len());
+
+        unsafe {
+            let ptr = self.map_subresource(&layout);
+
+            for (row, dst) in pixels.chunks_exact_mut(row_size).enumerate() {
+                let src = ptr.add(row * layout.row_pitch as usize);
+                ptr::copy_nonoverlapping(src, dst.as_mut_ptr(), row_size);
+            }
+
+            self.unmap_memory();
+        }
+    }
+
+    /// Layout of the color aspect and size of a row of the pixels accessed by the host
+    fn host_access_layout(&self, len: usize) -> (vk::SubresourceLayout, usize) {
+        assert_eq!(
+            self.tiling,
+            vk::ImageTiling::LINEAR,
+            "only linear images can be accessed by the host"
+        );
+        assert!(
+            matches!(
+                self.layout,
+                vk::ImageLayout::PREINITIALIZED | vk::ImageLayout::GENERAL
+            ),
+            "images can only be accessed by the host in the PREINITIALIZED or GENERAL layout"
+        );
+        assert_eq!(
+            len % self.extent.height as usize,
+            0,
+            "the pixels must fill every row of the image"
+        );
+
+        let subresource = vk::ImageSubresource::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .array_layer(0)
+            .build();
+        let layout = self.subresource_layout(subresource);
+        let row_size = len / self.extent.height as usize;
+
+        assert!(
+            row_size as vk::DeviceSize <= layout.row_pitch,
+            "the rows of pixels are larger than the ones of the image"
+        );
+
+        (layout, row_size)
+    }
+
+    /// Map the memory of the subresource, which must be host visible and coherent
+    /// Memory from an `Allocator` is already mapped with its block
+    unsafe fn map_subresource(&self, layout: &vk::SubresourceLayout) -> *mut u8 {
+        match &self.allocation {
+            Some(allocation) => allocation
+                .mapped_ptr()
+                .expect("the image memory isn't host visible")
+                .add(layout.offset as usize),
+            None => self
+                .device
+                .device
+                .map_memory(
+                    self.memory,
+                    self.memory_offset + layout.offset,
+                    layout.size,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("failed to map image memory!")
+                .cast(),
+        }
+    }
+
+    unsafe fn unmap_memory(&self) {
+        if self.allocation.is_none() {
+            self.device.device.unmap_memory(self.memory);
+        }
+    }
+
+    /// Whether `transition_layout` supports transitions from `from` to `to`
+    pub fn can_transition(from: vk::ImageLayout, to: vk::ImageLayout) -> bool {
+        from == to || (Self::src_access(from).is_some() && Self::dst_access(to).is_some())
+    }
+
+    /// Return all src_stage_mask, dst_stage_mask, depency_flags and the image memory barrier
+    /// This functions set the new layout, and therefore the transition is considered done
+    /// It fails without changing the layout if the transition isn't supported, see `can_transition`
+    pub fn transition_layout<'a>(
+        &mut self,
+        new_layout: vk::ImageLayout,
+    ) -> Result<LayoutTransition<'a>, LayoutTransitionError> {
+        let transition = self.layout_transition(new_layout)?;
+        self.layout = new_layout;
+
+        Ok(transition)
+    }
+
+    /// `transition_layout` without setting the new layout, for a barrier that may not be executed,
+    /// the layout must be set once it's submitted
+    pub fn layout_transition<'a>(
+        &self,
+        new_layout: vk::ImageLayout,
+    ) -> Result<LayoutTransition<'a>, LayoutTransitionError> {
+        if self.layout == new_layout {
+            return Ok((
+                vk::PipelineStageFlags::empty(),
+                vk::PipelineStageFlags::empty(),
+                vk::DependencyFlags::empty(),
+                vk::ImageMemoryBarrier::builder(),
+            ));
+        }
+
+        let error = LayoutTransitionError {
+            from: self.layout,
+            to: new_layout,
+        };
+        let (src_access_mask, src_stage_mask) = Self::src_access(self.layout).ok_or(error)?;
+        let (dst_access_mask, dst_stage_mask) = Self::dst_access(new_layout).ok_or(error)?;
+
+        let barrier = self.layout_barrier(new_layout, src_access_mask, dst_access_mask);
+
+        // unsafe {
+        //     let memory_barriers = [];
+        //     let buffer_memory_barriers = [];
+        //     let image_memory_barriers = [barrier.build()];
+
+        //     self.device.device.cmd_pipeline_barrier(
+        //         command_buffer.command_buffer,
+        //         src_stage_mask,
+        //         dst_stage_mask,
+        //         vk::DependencyFlags::empty(),
+        //         &memory_barriers,
+        //         &buffer_memory_barriers,
+        //         &image_memory_barriers,
+        //     );
+        // }
+
+        Ok((
+            src_stage_mask,
+            dst_stage_mask,
+            vk::DependencyFlags::empty(),
+            barrier,
+        ))
+    }
+
+    /// Accesses to make available and stages to wait for when leaving `layout`
+    fn src_access(layout: vk::ImageLayout) -> Option<(vk::AccessFlags, vk::PipelineStageFlags)> {
+        let access = match layout {
+            vk::ImageLayout::UNDEFINED => (
+                vk::AccessFlags::empty(),
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+            ),
+            // Host writes are made visible by the submission, but ordering them is harmless
+            vk::ImageLayout::PREINITIALIZED => {
+                (vk::AccessFlags::HOST_WRITE, vk::PipelineStageFlags::HOST)
+            }
             vk::ImageLayout::TRANSFER_DST_OPTIMAL => (
                 vk::AccessFlags::TRANSFER_WRITE,
                 vk::PipelineStageFlags::TRANSFER,
@@ -210,11 +1292,41 @@ impl Image {
             vk::ImageLayout::GENERAL => {
                 (vk::AccessFlags::all(), vk::PipelineStageFlags::ALL_COMMANDS)
             }
+            // Reads only need an execution dependency before the next accesses
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL => {
+                (vk::AccessFlags::empty(), vk::PipelineStageFlags::TRANSFER)
+            }
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (
+                vk::AccessFlags::empty(),
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            ),
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => (
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            ),
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL => (
+                vk::AccessFlags::empty(),
+                vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            ),
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            ),
+            // The image is acquired by a semaphore, which may be waited at any stage
+            vk::ImageLayout::PRESENT_SRC_KHR => (
+                vk::AccessFlags::empty(),
+                vk::PipelineStageFlags::ALL_COMMANDS,
+            ),
 
-            _ => panic!("Unsupported layout transition"),
+            _ => return None,
         };
 
-        let (dst_access_mask, dst_stage_mask) = match new_layout {
+        Some(access)
+    }
+
+    /// Accesses to make visible and stages to block when entering `layout`
+    fn dst_access(layout: vk::ImageLayout) -> Option<(vk::AccessFlags, vk::PipelineStageFlags)> {
+        let access = match layout {
             vk::ImageLayout::TRANSFER_DST_OPTIMAL => (
                 vk::AccessFlags::TRANSFER_WRITE,
                 vk::PipelineStageFlags::TRANSFER,
@@ -230,77 +1342,1129 @@ impl Image {
                 vk::AccessFlags::TRANSFER_READ,
                 vk::PipelineStageFlags::TRANSFER,
             ),
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => (
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            ),
+            // Sampled by a shader or only tested against
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL => (
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            ),
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            ),
+            // The presentation is ordered by the semaphore it waits for
+            vk::ImageLayout::PRESENT_SRC_KHR => (
+                vk::AccessFlags::empty(),
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            ),
+
+            _ => return None,
+        };
+
+        Some(access)
+    }
+
+    /// Copy the whole image into `dst`, converting its format with a blit when the formats differ
+    /// or scaling it when the extents differ, otherwise with a copy
+    /// `queue` must support graphics operations, and the layout of the image is restored after the copy
+    /// while `dst` is left in `TRANSFER_DST_OPTIMAL` layout
+    pub fn convert_to(
+        &mut self,
+        dst: &mut Image,
+        command_pool: &mut Rc<CommandPool>,
+        queue: &mut Queue,
+    ) -> Result<(), ConvertError> {
+        if !self.usage.contains(vk::ImageUsageFlags::TRANSFER_SRC)
+            || !dst.usage.contains(vk::ImageUsageFlags::TRANSFER_DST)
+        {
+            return Err(ConvertError::MissingTransferUsage);
+        }
+
+        let copy = self.format == dst.format && self.extent == dst.extent;
+
+        if !copy {
+            if !command_pool.support_graphics() {
+                return Err(ConvertError::UnsupportedQueue);
+            }
+
+            if !self
+                .format_features()
+                .contains(vk::FormatFeatureFlags::BLIT_SRC)
+            {
+                return Err(ConvertError::UnsupportedSrcFormat(self.format));
+            }
+
+            if !dst
+                .format_features()
+                .contains(vk::FormatFeatureFlags::BLIT_DST)
+            {
+                return Err(ConvertError::UnsupportedDstFormat(dst.format));
+            }
+
+            if NumericKind::of(self.format) != NumericKind::of(dst.format) {
+                return Err(ConvertError::IncompatibleFormats(self.format, dst.format));
+            }
+        }
+
+        let layout = self.layout;
+        let dst_layout = dst.layout;
+
+        // The layout is restored only if the contents were defined, and an image can't go back to preinitialized
+        let restored_layout = match layout {
+            vk::ImageLayout::UNDEFINED | vk::ImageLayout::PREINITIALIZED => None,
+            layout => Some(layout),
+        };
+
+        // Check every transition first, so that no layout is changed when one of them is unsupported
+        let transitions = [
+            (layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            (dst.layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+            (
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                restored_layout.unwrap_or(vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            ),
+        ];
+        if let Some(&(from, to)) = transitions
+            .iter()
+            .find(|&&(from, to)| !Self::can_transition(from, to))
+        {
+            return Err(LayoutTransitionError { from, to }.into());
+        }
+
+        let (src_stage_mask, dst_stage_mask, dependency_flags, src_barrier) =
+            self.transition_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)?;
+        let src_barriers = [src_barrier.build()];
+        let (dst_src_stage_mask, dst_dst_stage_mask, _, dst_barrier) =
+            dst.transition_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)?;
+        let dst_barriers = [dst_barrier.build()];
+
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build();
 
-            _ => panic!("Unsupported layout transition"),
+        let copy_regions = [vk::ImageCopy::builder()
+            .src_subresource(subresource)
+            .dst_subresource(subresource)
+            .extent(self.extent)
+            .build()];
+
+        let corner = |extent: vk::Extent3D| vk::Offset3D {
+            x: extent.width as _,
+            y: extent.height as _,
+            z: extent.depth as _,
+        };
+        let blit_regions = [vk::ImageBlit::builder()
+            .src_subresource(subresource)
+            .src_offsets([vk::Offset3D::default(), corner(self.extent)])
+            .dst_subresource(subresource)
+            .dst_offsets([vk::Offset3D::default(), corner(dst.extent)])
+            .build()];
+
+        let restore = match restored_layout {
+            None => None,
+            Some(layout) => {
+                let (src_stage_mask, dst_stage_mask, dependency_flags, barrier) =
+                    self.transition_layout(layout)?;
+                Some((
+                    src_stage_mask,
+                    dst_stage_mask,
+                    dependency_flags,
+                    [barrier.build()],
+                ))
+            }
         };
 
-        let barrier = vk::ImageMemoryBarrier::builder()
-            .old_layout(self.layout)
+        // The copy reads the image in the transfer layout, the restored one is tracked once it's submitted
+        self.layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+        let src: &Image = self;
+
+        let result = command_pool.single_time(queue, |recorder| {
+            // Without a barrier for images already in the right layout
+            if !src_stage_mask.is_empty() {
+                recorder.as_generic().pipeline_barrier(
+                    src_stage_mask,
+                    dst_stage_mask,
+                    dependency_flags,
+                    &[],
+                    &[],
+                    &src_barriers,
+                );
+            }
+
+            if !dst_src_stage_mask.is_empty() {
+                recorder.as_generic().pipeline_barrier(
+                    dst_src_stage_mask,
+                    dst_dst_stage_mask,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &dst_barriers,
+                );
+            }
+
+            let mut copy_commands = recorder.as_transfer_command_buffer().unwrap();
+            let mut copy_commands = copy_commands.as_copy();
+
+            if copy {
+                copy_commands.copy_image(src, dst, &copy_regions).unwrap();
+            } else {
+                copy_commands
+                    .as_graphics_copy()
+                    .blit_image(src, dst, &blit_regions, vk::Filter::NEAREST)
+                    .unwrap();
+            }
+
+            if let Some((src_stage_mask, dst_stage_mask, dependency_flags, barriers)) = &restore {
+                recorder.as_generic().pipeline_barrier(
+                    *src_stage_mask,
+                    *dst_stage_mask,
+                    *dependency_flags,
+                    &[],
+                    &[],
+                    barriers,
+                );
+            }
+        });
+
+        if let Err(error) = result {
+            self.layout = layout;
+            dst.layout = dst_layout;
+            return Err(error.into());
+        }
+
+        if let Some(layout) = restored_layout {
+            self.layout = layout;
+        }
+
+        Ok(())
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
+    /// Aspects of the view of the image, the barriers cover all the aspects of its format
+    pub fn aspect_flags(&self) -> vk::ImageAspectFlags {
+        self.aspect_flags
+    }
+
+    /// Features supported by the format of the image with its tiling
+    pub fn format_features(&self) -> vk::FormatFeatureFlags {
+        Self::tiling_features(self.format, self.tiling, &self.device)
+    }
+
+    fn tiling_features(
+        format: vk::Format,
+        tiling: vk::ImageTiling,
+        device: &Device,
+    ) -> vk::FormatFeatureFlags {
+        let properties = unsafe {
+            device
+                .instance()
+                .instance
+                .get_physical_device_format_properties(device.physical_device, format)
+        };
+
+        match tiling {
+            vk::ImageTiling::LINEAR => properties.linear_tiling_features,
+            _ => properties.optimal_tiling_features,
+        }
+    }
+
+    /// Same as `transition_layout`, for an image written by a compute shader in `GENERAL` layout
+    /// which will then be sampled by a fragment shader
+    pub fn compute_to_sampled_barrier(
+        &mut self,
+    ) -> (
+        vk::PipelineStageFlags,
+        vk::PipelineStageFlags,
+        vk::DependencyFlags,
+        vk::ImageMemoryBarrierBuilder<'_>,
+    ) {
+        let barrier = self.layout_barrier(
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        );
+
+        self.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+
+        (
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            barrier,
+        )
+    }
+
+    /// Same as `transition_layout`, for an image sampled by a fragment shader
+    /// which will then be written by a compute shader in `GENERAL` layout
+    pub fn sampled_to_compute_barrier(
+        &mut self,
+    ) -> (
+        vk::PipelineStageFlags,
+        vk::PipelineStageFlags,
+        vk::DependencyFlags,
+        vk::ImageMemoryBarrierBuilder<'_>,
+    ) {
+        let barrier = self.layout_barrier(
+            vk::ImageLayout::GENERAL,
+            vk::AccessFlags::SHADER_READ,
+            vk::AccessFlags::SHADER_WRITE,
+        );
+
+        self.layout = vk::ImageLayout::GENERAL;
+
+        (
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            barrier,
+        )
+    }
+
+    /// Barrier of all the mip levels from the current layout to `new_layout`,
+    /// it doesn't change the layout of the image
+    pub(crate) fn layout_barrier<'a>(
+        &self,
+        new_layout: vk::ImageLayout,
+        src_access_mask: vk::AccessFlags,
+        dst_access_mask: vk::AccessFlags,
+    ) -> vk::ImageMemoryBarrierBuilder<'a> {
+        self.levels_barrier(
+            0..self.mip_levels,
+            self.layout,
+            new_layout,
+            src_access_mask,
+            dst_access_mask,
+        )
+    }
+
+    /// Barrier of the mip levels `levels` from `old_layout` to `new_layout`, for the commands handling
+    /// the levels separately, it doesn't change the layout of the image
+    pub(crate) fn levels_barrier<'a>(
+        &self,
+        levels: Range<u32>,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        src_access_mask: vk::AccessFlags,
+        dst_access_mask: vk::AccessFlags,
+    ) -> vk::ImageMemoryBarrierBuilder<'a> {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(format_aspect(self.format))
+            .base_mip_level(levels.start)
+            .level_count(levels.end - levels.start)
+            .base_array_layer(0)
+            .layer_count(self.array_layers)
+            .build();
+
+        vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
             .new_layout(new_layout)
             .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .image(self.handle)
             .subresource_range(subresource_range)
             .src_access_mask(src_access_mask)
-            .dst_access_mask(dst_access_mask);
+            .dst_access_mask(dst_access_mask)
+    }
+}
 
-        // unsafe {
-        //     let memory_barriers = [];
-        //     let buffer_memory_barriers = [];
-        //     let image_memory_barriers = [barrier.build()];
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device.destroy_image_view(self.view, None);
+            self.device.device.destroy_image(self.handle, None);
+            if self.memory_owned {
+                self.device.device.free_memory(self.memory, None);
+            }
+        }
+    }
+}
 
-        //     self.device.device.cmd_pipeline_barrier(
-        //         command_buffer.command_buffer,
-        //         src_stage_mask,
-        //         dst_stage_mask,
-        //         vk::DependencyFlags::empty(),
-        //         &memory_barriers,
-        //         &buffer_memory_barriers,
-        //         &image_memory_barriers,
-        //     );
-        // }
+/// Builder of an `Image`, by default a 2D color image with a single layer, mip level and sample,
+/// with optimal tiling in device local memory
+/// A view of the whole image is created when its usage allows one, for instance `SAMPLED` or `STORAGE`
+pub struct ImageBuilder<'a> {
+    image_type: vk::ImageType,
+    extent: vk::Extent3D,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    tiling: vk::ImageTiling,
+    mip_levels: u32,
+    array_layers: u32,
+    samples: vk::SampleCountFlags,
+    aspect_flags: vk::ImageAspectFlags,
+    properties: vk::MemoryPropertyFlags,
+    initial_layout: vk::ImageLayout,
+    export_handle_types: vk::ExternalMemoryHandleTypeFlags,
+    name: Option<&'a str>,
+}
 
-        self.layout = new_layout;
+impl<'a> ImageBuilder<'a> {
+    pub fn new(width: u32, height: u32, format: vk::Format, usage: vk::ImageUsageFlags) -> Self {
+        Self {
+            image_type: vk::ImageType::TYPE_2D,
+            extent: vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+            format,
+            usage,
+            tiling: vk::ImageTiling::OPTIMAL,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            aspect_flags: format_aspect(format),
+            properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            export_handle_types: vk::ExternalMemoryHandleTypeFlags::empty(),
+            name: None,
+        }
+    }
 
-        (
-            src_stage_mask,
-            dst_stage_mask,
-            vk::DependencyFlags::empty(),
-            barrier,
-        )
+    /// The height of a 1D image must be 1, and only 3D images have a depth
+    pub fn image_type(mut self, image_type: vk::ImageType) -> Self {
+        self.image_type = image_type;
+        self
     }
 
-    fn create_image_view(
-        image: vk::Image,
-        format: vk::Format,
+    /// Depth of a 3D image
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.extent.depth = depth;
+        self
+    }
+
+    pub fn tiling(mut self, tiling: vk::ImageTiling) -> Self {
+        self.tiling = tiling;
+        self
+    }
+
+    pub fn mip_levels(mut self, mip_levels: u32) -> Self {
+        self.mip_levels = mip_levels;
+        self
+    }
+
+    /// Number of layers of a 1D or 2D image, which is viewed as an array when it has several ones
+    pub fn array_layers(mut self, array_layers: u32) -> Self {
+        self.array_layers = array_layers;
+        self
+    }
+
+    /// Samples per texel of a multisampled attachment
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Aspects of the view, all the aspects of the format by default, only one of the depth and stencil aspects
+    /// must be given to sample a depth/stencil image
+    pub fn aspect_flags(mut self, aspect_flags: vk::ImageAspectFlags) -> Self {
+        self.aspect_flags = aspect_flags;
+        self
+    }
+
+    pub fn memory_properties(mut self, properties: vk::MemoryPropertyFlags) -> Self {
+        self.properties = properties;
+        self
+    }
+
+    /// `UNDEFINED` or `PREINITIALIZED`, to keep the contents written by the host in a linear image
+    pub fn initial_layout(mut self, initial_layout: vk::ImageLayout) -> Self {
+        self.initial_layout = initial_layout;
+        self
+    }
+
+    /// Name given to the image for debugging when the `validation-layers` feature is enabled
+    pub fn name(mut self, name: &'a str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// The handle types must be checked by `external_memory::check_handle_type`
+    fn export_handle_types(mut self, handle_types: vk::ExternalMemoryHandleTypeFlags) -> Self {
+        self.export_handle_types = handle_types;
+        self
+    }
+
+    pub fn build(&self, device: Rc<Device>, instance: &Instance) -> Image {
+        let unbound = self.build_unbound(device);
+
+        let mut export_info =
+            vk::ExportMemoryAllocateInfo::builder().handle_types(self.export_handle_types);
+
+        let mut alloc_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(unbound.requirements.size)
+            .memory_type_index(utils::find_memory_type(
+                unbound.requirements.memory_type_bits,
+                self.properties,
+                &unbound.device,
+                instance,
+            ));
+
+        if !self.export_handle_types.is_empty() {
+            alloc_info = alloc_info.push_next(&mut export_info);
+        }
+
+        let memory = unsafe { unbound.device.device.allocate_memory(&alloc_info, None) }
+            .expect("failed to allocate image memory!");
+
+        let mut image = unbound.bind_memory(memory, 0, self.aspect_flags, true);
+        image.export_handle_types = self.export_handle_types;
+
+        image
+    }
+
+    /// Create the image in memory sub-allocated by `allocator`, whose device is used
+    pub fn build_in(&self, allocator: &mut Allocator) -> Image {
+        assert!(
+            self.export_handle_types.is_empty(),
+            "exportable images need a dedicated allocation"
+        );
+
+        let unbound = self.build_unbound(allocator.device());
+        let allocation = allocator.allocate(
+            unbound.requirements,
+            self.properties,
+            self.tiling == vk::ImageTiling::LINEAR,
+        );
+
+        let mut image = unbound.bind_memory(
+            allocation.memory,
+            allocation.offset,
+            self.aspect_flags,
+            false,
+        );
+        image.allocation = Some(allocation);
+
+        image
+    }
+
+    /// Create the image without memory, the memory has to be bound with `UnboundImage::bind`
+    pub fn build_unbound(&self, device: Rc<Device>) -> UnboundImage {
+        assert!(
+            self.image_type == vk::ImageType::TYPE_3D || self.extent.depth == 1,
+            "only 3D images have a depth"
+        );
+        assert!(
+            self.image_type != vk::ImageType::TYPE_3D || self.array_layers == 1,
+            "3D images can't have several layers"
+        );
+
+        let mut external_info =
+            vk::ExternalMemoryImageCreateInfo::builder().handle_types(self.export_handle_types);
+
+        let mut image_info = vk::ImageCreateInfo::builder()
+            .image_type(self.image_type)
+            .extent(self.extent)
+            .mip_levels(self.mip_levels)
+            .array_layers(self.array_layers)
+            .format(self.format)
+            .tiling(self.tiling)
+            .initial_layout(self.initial_layout)
+            .usage(self.usage)
+            .samples(self.samples)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        if !self.export_handle_types.is_empty() {
+            image_info = image_info.push_next(&mut external_info);
+        }
+
+        let handle = unsafe { device.device.create_image(&image_info, None) }
+            .expect("failed to create image!");
+
+        if let Some(name) = self.name {
+            device.set_object_name(handle, name);
+        }
+
+        let requirements = unsafe { device.device.get_image_memory_requirements(handle) };
+
+        UnboundImage {
+            handle,
+            extent: self.extent,
+            format: self.format,
+            tiling: self.tiling,
+            usage: self.usage,
+            mip_levels: self.mip_levels,
+            array_layers: self.array_layers,
+            view_type: self.view_type(),
+            initial_layout: self.initial_layout,
+            requirements,
+            device,
+        }
+    }
+
+    /// Type of the view of the whole image
+    fn view_type(&self) -> vk::ImageViewType {
+        match (self.image_type, self.array_layers) {
+            (vk::ImageType::TYPE_1D, 1) => vk::ImageViewType::TYPE_1D,
+            (vk::ImageType::TYPE_1D, _) => vk::ImageViewType::TYPE_1D_ARRAY,
+            (vk::ImageType::TYPE_3D, _) => vk::ImageViewType::TYPE_3D,
+            (_, 1) => vk::ImageViewType::TYPE_2D,
+            _ => vk::ImageViewType::TYPE_2D_ARRAY,
+        }
+    }
+}
+
+/// An image which has no memory bound yet, created by `Image::create_unbound` or `ImageBuilder::build_unbound`
+pub struct UnboundImage {
+    handle: vk::Image,
+    extent: vk::Extent3D,
+    format: vk::Format,
+    tiling: vk::ImageTiling,
+    usage: vk::ImageUsageFlags,
+    mip_levels: u32,
+    array_layers: u32,
+    view_type: vk::ImageViewType,
+    initial_layout: vk::ImageLayout,
+    requirements: vk::MemoryRequirements,
+    device: Rc<Device>,
+}
+
+impl UnboundImage {
+    pub fn handle(&self) -> vk::Image {
+        self.handle
+    }
+
+    /// Size, alignment and memory type bits the memory bound to this image must satisfy
+    pub fn memory_requirements(&self) -> vk::MemoryRequirements {
+        self.requirements
+    }
+
+    /// Bind `memory` at `offset` to the image and create its view if its usage allows one
+    /// The memory isn't freed with the image, so it must outlive it
+    pub fn bind(
+        self,
+        memory: vk::DeviceMemory,
+        offset: vk::DeviceSize,
         aspect_flags: vk::ImageAspectFlags,
-        device: &Device,
-    ) -> vk::ImageView {
+    ) -> Image {
+        self.bind_memory(memory, offset, aspect_flags, false)
+    }
+
+    fn bind_memory(
+        mut self,
+        memory: vk::DeviceMemory,
+        offset: vk::DeviceSize,
+        aspect_flags: vk::ImageAspectFlags,
+        memory_owned: bool,
+    ) -> Image {
+        unsafe {
+            self.device
+                .device
+                .bind_image_memory(self.handle, memory, offset)
+        }
+        .expect("failed to bind image memory!");
+
+        // The null handle is ignored when self is dropped
+        let handle = mem::replace(&mut self.handle, vk::Image::null());
+
+        let view_usage = vk::ImageUsageFlags::SAMPLED
+            | vk::ImageUsageFlags::STORAGE
+            | vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+            | vk::ImageUsageFlags::INPUT_ATTACHMENT;
+
+        let view = if self.usage.intersects(view_usage) {
+            self.create_view(handle, aspect_flags)
+        } else {
+            vk::ImageView::null()
+        };
+
+        Image {
+            handle,
+            memory,
+            extent: self.extent,
+            layout: self.initial_layout,
+            view,
+            usage: self.usage,
+            format: self.format,
+            tiling: self.tiling,
+            mip_levels: self.mip_levels,
+            array_layers: self.array_layers,
+            aspect_flags,
+            memory_offset: offset,
+            export_handle_types: vk::ExternalMemoryHandleTypeFlags::empty(),
+            memory_owned,
+            allocation: None,
+            device: Rc::clone(&self.device),
+        }
+    }
+
+    /// View of all the levels and layers of the image
+    fn create_view(&self, image: vk::Image, aspect_flags: vk::ImageAspectFlags) -> vk::ImageView {
         let subresource_range = vk::ImageSubresourceRange::builder()
             .aspect_mask(aspect_flags)
             .base_mip_level(0)
-            .level_count(1)
+            .level_count(self.mip_levels)
             .base_array_layer(0)
-            .layer_count(1)
+            .layer_count(self.array_layers)
             .build();
 
         let view_info = vk::ImageViewCreateInfo::builder()
             .image(image)
-            .view_type(vk::ImageViewType::TYPE_2D)
-            .format(format)
+            .view_type(self.view_type)
+            .format(self.format)
             .subresource_range(subresource_range);
 
-        unsafe { device.device.create_image_view(&view_info, None) }
+        unsafe { self.device.device.create_image_view(&view_info, None) }
             .expect("failed to create texture image view!")
     }
 }
 
-impl Drop for Image {
+impl Drop for UnboundImage {
     fn drop(&mut self) {
         unsafe {
-            self.device.device.destroy_image_view(self.view, None);
             self.device.device.destroy_image(self.handle, None);
-            self.device.device.free_memory(self.memory, None);
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compressed_sizes() {
+        assert_eq!(
+            compressed_size(vk::Format::BC1_RGBA_UNORM_BLOCK, 8, 8),
+            Some(32)
+        );
+        // Partial blocks on the edges take a whole block
+        assert_eq!(compressed_size(vk::Format::BC7_SRGB_BLOCK, 5, 5), Some(64));
+        assert_eq!(
+            compressed_size(vk::Format::ASTC_6X5_UNORM_BLOCK, 13, 10),
+            Some(96)
+        );
+        assert_eq!(compressed_size(vk::Format::R8G8B8A8_SRGB, 4, 4), None);
+    }
+
+    #[test]
+    fn upload_sizes() {
+        assert_eq!(upload_size(vk::Format::R8G8B8A8_SRGB, 3, 5), Some(60));
+        assert_eq!(
+            upload_size(vk::Format::BC1_RGBA_UNORM_BLOCK, 8, 8),
+            Some(32)
+        );
+        assert_eq!(upload_size(vk::Format::R32_SFLOAT, 4, 4), Some(64));
+        assert_eq!(upload_size(vk::Format::R16G16B16_SFLOAT, 2, 1), Some(12));
+        assert_eq!(upload_size(vk::Format::D24_UNORM_S8_UINT, 4, 4), None);
+    }
+
+    #[test]
+    fn mip_level_counts() {
+        assert_eq!(Image::mip_level_count(1, 1), 1);
+        assert_eq!(Image::mip_level_count(256, 256), 9);
+        // The largest dimension is halved down to one texel, rounding down
+        assert_eq!(Image::mip_level_count(300, 20), 9);
+        assert_eq!(Image::mip_level_count(7, 512), 10);
+    }
+
+    #[test]
+    fn supported_layout_transitions() {
+        assert!(Image::can_transition(
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL
+        ));
+        assert!(Image::can_transition(
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        ));
+        // An image can't go back to the undefined or preinitialized layouts
+        assert!(!Image::can_transition(
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::PREINITIALIZED
+        ));
+        assert!(Image::can_transition(
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        ));
+        assert!(Image::can_transition(
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR
+        ));
+        assert!(!Image::can_transition(
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::ImageLayout::UNDEFINED
+        ));
+    }
+
+    #[test]
+    fn format_aspects() {
+        assert_eq!(
+            format_aspect(vk::Format::R8G8B8A8_SRGB),
+            vk::ImageAspectFlags::COLOR
+        );
+        assert_eq!(
+            format_aspect(vk::Format::D32_SFLOAT),
+            vk::ImageAspectFlags::DEPTH
+        );
+        assert_eq!(
+            format_aspect(vk::Format::D24_UNORM_S8_UINT),
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        );
+    }
+
+    #[test]
+    fn image_view_types() {
+        let builder =
+            || ImageBuilder::new(16, 16, vk::Format::R8_UNORM, vk::ImageUsageFlags::SAMPLED);
+
+        assert_eq!(builder().view_type(), vk::ImageViewType::TYPE_2D);
+        assert_eq!(
+            builder().array_layers(6).view_type(),
+            vk::ImageViewType::TYPE_2D_ARRAY
+        );
+        assert_eq!(
+            builder()
+                .image_type(vk::ImageType::TYPE_3D)
+                .depth(16)
+                .view_type(),
+            vk::ImageViewType::TYPE_3D
+        );
+        assert_eq!(
+            builder()
+                .image_type(vk::ImageType::TYPE_1D)
+                .array_layers(2)
+                .view_type(),
+            vk::ImageViewType::TYPE_1D_ARRAY
+        );
+    }
+
+    #[test]
+    fn blit_numeric_kinds() {
+        assert_eq!(
+            NumericKind::of(vk::Format::R8G8B8A8_UINT),
+            NumericKind::Uint
+        );
+        assert_eq!(NumericKind::of(vk::Format::R32_SINT), NumericKind::Sint);
+        assert_eq!(
+            NumericKind::of(vk::Format::R8G8B8A8_SRGB),
+            NumericKind::of(vk::Format::R16G16B16A16_SFLOAT)
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn color_attachment_format_support() {
+        let (instance, device, _queue) = utils::create_test_device();
+
+        // Both are mandatory for this format
+        assert!(Image::new_color_attachment(
+            16,
+            16,
+            vk::Format::R8G8B8A8_UNORM,
+            vk::ImageUsageFlags::SAMPLED,
+            Rc::clone(&device),
+            &instance,
+        )
+        .is_ok());
+
+        // Compressed formats can't be rendered to
+        assert_eq!(
+            Image::new_color_attachment(
+                16,
+                16,
+                vk::Format::BC1_RGBA_UNORM_BLOCK,
+                vk::ImageUsageFlags::empty(),
+                device,
+                &instance,
+            )
+            .err(),
+            Some(TextureError::UnsupportedUsage(
+                vk::Format::BC1_RGBA_UNORM_BLOCK,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+            ))
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn depth_attachment_transition() {
+        let (instance, device, _queue) = utils::create_test_device();
+
+        // The only depth format which must support being an attachment
+        let mut depth = Image::new_depth_attachment(
+            16,
+            16,
+            vk::Format::D16_UNORM,
+            vk::ImageUsageFlags::empty(),
+            device,
+            &instance,
+        )
+        .unwrap();
+
+        assert_ne!(depth.view, vk::ImageView::null());
+
+        let (_, dst_stage_mask, _, barrier) = depth
+            .transition_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .unwrap();
+
+        assert_eq!(dst_stage_mask, vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS);
+        assert_eq!(
+            barrier.subresource_range.aspect_mask,
+            vk::ImageAspectFlags::DEPTH
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn linear_image_round_trip() {
+        let (instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        // An odd width makes padding between the rows of linear images likely
+        let (width, height) = (13, 7);
+        let format = vk::Format::R8G8B8A8_UNORM;
+        let pixels = (0..width * height * 4).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut source = Image::new_staging(width, height, format, Rc::clone(&device), &instance);
+        source.write_pixels(&pixels);
+
+        let mut optimal = Image::new(
+            width,
+            height,
+            format,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+            vk::ImageAspectFlags::COLOR,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            None,
+            Rc::clone(&device),
+            &instance,
+        );
+        source
+            .convert_to(&mut optimal, &mut command_pool, &mut queue)
+            .unwrap();
+
+        let mut readback = Image::new_staging(width, height, format, Rc::clone(&device), &instance);
+        optimal
+            .convert_to(&mut readback, &mut command_pool, &mut queue)
+            .unwrap();
+
+        let (_, _, _, barrier) = readback
+            .transition_layout(vk::ImageLayout::GENERAL)
+            .unwrap();
+        let barriers = [barrier.dst_access_mask(vk::AccessFlags::HOST_READ).build()];
+        command_pool
+            .single_time(&mut queue, |recorder| {
+                recorder.as_generic().pipeline_barrier(
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::HOST,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &barriers,
+                );
+            })
+            .unwrap();
+
+        let mut read = vec![0; pixels.len()];
+        readback.read_pixels(&mut read);
+
+        assert_eq!(read, pixels);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn texture_round_trip() {
+        let (instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let (width, height) = (5, 3);
+        let format = vk::Format::R8G8B8A8_UNORM;
+        let pixels = (0..width * height * 4).map(|i| i as u8).collect::<Vec<_>>();
+        let image = RgbaImage::from_raw(width, height, pixels.clone()).unwrap();
+
+        let mut texture = Image::new_texture_with(
+            image,
+            vk::ImageUsageFlags::TRANSFER_SRC,
+            Some(format),
+            Rc::clone(&device),
+            &instance,
+            &mut command_pool,
+            &mut queue,
+            None,
+        )
+        .unwrap();
+        assert_eq!(texture.layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+        let mut readback = Image::new_staging(width, height, format, Rc::clone(&device), &instance);
+        texture
+            .convert_to(&mut readback, &mut command_pool, &mut queue)
+            .unwrap();
+
+        let (_, _, _, barrier) = readback
+            .transition_layout(vk::ImageLayout::GENERAL)
+            .unwrap();
+        let barriers = [barrier.dst_access_mask(vk::AccessFlags::HOST_READ).build()];
+        command_pool
+            .single_time(&mut queue, |recorder| {
+                recorder.as_generic().pipeline_barrier(
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::HOST,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &barriers,
+                );
+            })
+            .unwrap();
+
+        let mut read = vec![0; pixels.len()];
+        readback.read_pixels(&mut read);
+
+        assert_eq!(read, pixels);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn convert_from_general_layout() {
+        let (instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let (width, height) = (4, 2);
+        let format = vk::Format::R8G8B8A8_UNORM;
+        let pixels = (0..width * height * 4).map(|i| i as u8).collect::<Vec<_>>();
+        let image = RgbaImage::from_raw(width, height, pixels.clone()).unwrap();
+
+        let mut texture = Image::new_texture_with(
+            image,
+            vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::STORAGE,
+            Some(format),
+            Rc::clone(&device),
+            &instance,
+            &mut command_pool,
+            &mut queue,
+            None,
+        )
+        .unwrap();
+        queue
+            .transition_image(&mut texture, vk::ImageLayout::GENERAL, &mut command_pool)
+            .unwrap();
+
+        // The copy must read the image in the transfer layout, then give it back its own
+        let mut readback = Image::new_staging(width, height, format, Rc::clone(&device), &instance);
+        texture
+            .convert_to(&mut readback, &mut command_pool, &mut queue)
+            .unwrap();
+        assert_eq!(texture.layout, vk::ImageLayout::GENERAL);
+        assert_eq!(readback.layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+
+        let (_, _, _, barrier) = readback
+            .transition_layout(vk::ImageLayout::GENERAL)
+            .unwrap();
+        let barriers = [barrier.dst_access_mask(vk::AccessFlags::HOST_READ).build()];
+        command_pool
+            .single_time(&mut queue, |recorder| {
+                recorder.as_generic().pipeline_barrier(
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::HOST,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &barriers,
+                );
+            })
+            .unwrap();
+
+        let mut read = vec![0; pixels.len()];
+        readback.read_pixels(&mut read);
+
+        assert_eq!(read, pixels);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn texture_uploaded_on_transfer_queue() {
+        use crate::vulkan::{DeviceBuilder, InstanceBuilder, QueueCreateInfo};
+
+        let instance = Rc::new(InstanceBuilder::new().headless().build().unwrap());
+        let (device, queue_groups) = DeviceBuilder::new()
+            .extensions(&[])
+            .build(
+                |queue_family, _| {
+                    if queue_family.support_compute() || queue_family.support_transfer_only() {
+                        Some(QueueCreateInfo::new(vec![1.0]))
+                    } else {
+                        None
+                    }
+                },
+                Rc::clone(&instance),
+            )
+            .unwrap();
+        let mut queues = queue_groups.into_iter().flatten().collect::<Vec<_>>();
+        // Without transfer-only family the upload falls back to the compute queue
+        let mut transfer_queue = queues
+            .iter()
+            .position(|queue| queue.family().support_transfer_only())
+            .map(|index| queues.swap_remove(index));
+        let mut queue = queues
+            .into_iter()
+            .find(|queue| queue.family().support_compute())
+            .unwrap();
+
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+        let mut transfer_command_pool = transfer_queue
+            .as_ref()
+            .map(|queue| Rc::new(CommandPool::new(queue.family(), Rc::clone(&device))));
+
+        let (width, height) = (4, 4);
+        let format = vk::Format::R8G8B8A8_UNORM;
+        let pixels = (0..width * height * 4).map(|i| i as u8).collect::<Vec<_>>();
+        let image = RgbaImage::from_raw(width, height, pixels.clone()).unwrap();
+
+        let mut texture = Image::new_texture_with(
+            image,
+            vk::ImageUsageFlags::TRANSFER_SRC,
+            Some(format),
+            Rc::clone(&device),
+            &instance,
+            &mut command_pool,
+            &mut queue,
+            transfer_queue.as_mut().zip(transfer_command_pool.as_mut()),
+        )
+        .unwrap();
+        assert_eq!(texture.layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+        let mut readback = Image::new_staging(width, height, format, Rc::clone(&device), &instance);
+        texture
+            .convert_to(&mut readback, &mut command_pool, &mut queue)
+            .unwrap();
+
+        let (_, _, _, barrier) = readback
+            .transition_layout(vk::ImageLayout::GENERAL)
+            .unwrap();
+        let barriers = [barrier.dst_access_mask(vk::AccessFlags::HOST_READ).build()];
+        command_pool
+            .single_time(&mut queue, |recorder| {
+                recorder.as_generic().pipeline_barrier(
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::HOST,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &barriers,
+                );
+            })
+            .unwrap();
+
+        let mut read = vec![0; pixels.len()];
+        readback.read_pixels(&mut read);
+
+        assert_eq!(read, pixels);
+    }
+}
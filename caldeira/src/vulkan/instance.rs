@@ -1,71 +1,266 @@
 use std::collections::HashSet;
+use std::error::Error;
 use std::ffi::{CStr, CString};
-use std::os::raw::c_char;
+use std::fmt;
 
 use ash::version::{EntryV1_0, InstanceV1_0};
 use ash::vk;
 
-use crate::consts::{
-    INSTANCE_EXTENSIONS, REQUIRED_MAJOR, REQUIRED_MINOR, REQUIRED_PATCH, REQUIRED_VERSION,
-    VALIDATION_LAYERS,
-};
+use super::surface::platform_surface_extensions;
+use crate::consts::{INSTANCE_EXTENSIONS, REQUIRED_VERSION, VALIDATION_LAYERS};
+#[cfg(feature = "validation-layers")]
 use crate::utils;
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum InstanceError {
+    /// The Vulkan library couldn't be loaded, with the message of the loader
+    Loading(String),
+    /// The Vulkan implementation is older than the required version, versions are made with `vk::make_version`
+    UnsupportedVersion { found: u32, required: u32 },
+    /// Required instance extensions aren't available
+    MissingExtensions(Vec<String>),
+    /// Required layers aren't available
+    MissingLayers(Vec<String>),
+    /// The instance couldn't be created
+    Creation(vk::Result),
+    /// Surfaces can't be created on the target platform, so only headless instances are supported
+    UnsupportedPlatform,
+}
+
+impl fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let version = |version: u32| {
+            format!(
+                "{}.{}.{}",
+                vk::version_major(version),
+                vk::version_minor(version),
+                vk::version_patch(version)
+            )
+        };
+
+        match self {
+            Self::Loading(message) => write!(f, "Failed to load Vulkan: {}!", message),
+            Self::UnsupportedVersion { found, required } => write!(
+                f,
+                "The minimum required version is {} and the instance version is {}!",
+                version(*required),
+                version(*found)
+            ),
+            Self::MissingExtensions(extensions) => write!(
+                f,
+                "The instance extensions {} are not available!",
+                extensions.join(", ")
+            ),
+            Self::MissingLayers(layers) => {
+                write!(f, "The layers {} are not available!", layers.join(", "))
+            }
+            Self::Creation(result) => write!(f, "Failed to create the instance: {}!", result),
+            Self::UnsupportedPlatform => write!(
+                f,
+                "Windowed instances are not supported on this platform, only headless ones!"
+            ),
+        }
+    }
+}
+
+impl Error for InstanceError {}
+
+/// Checks of the validation layers which can be enabled besides the default ones
+#[cfg(feature = "validation-layers")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ValidationFeature {
+    /// Instrument shaders to validate descriptor accesses
+    GpuAssisted,
+    /// Reserve the last descriptor set binding slot for `GpuAssisted`
+    GpuAssistedReserveBindingSlot,
+    /// Warn about valid but inefficient usages of the API
+    BestPractices,
+    /// Print the `debugPrintfEXT` calls of the shaders
+    DebugPrintf,
+}
+
+#[cfg(feature = "validation-layers")]
+impl ValidationFeature {
+    fn to_vk(self) -> vk::ValidationFeatureEnableEXT {
+        match self {
+            Self::GpuAssisted => vk::ValidationFeatureEnableEXT::GPU_ASSISTED,
+            Self::GpuAssistedReserveBindingSlot => {
+                vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT
+            }
+            Self::BestPractices => vk::ValidationFeatureEnableEXT::BEST_PRACTICES,
+            Self::DebugPrintf => vk::ValidationFeatureEnableEXT::DEBUG_PRINTF,
+        }
+    }
+}
+
 pub struct Instance {
     pub entry: ash::Entry,
     pub instance: ash::Instance,
+    headless: bool,
+    /// Extensions enabled at creation
+    extensions: Vec<String>,
 }
 
-impl Instance {
+/// Name and version of the application and engine reported to the driver and the tools, and the API version
+/// of an instance, by default the application `Test` and engine `Caldeira` both in version 0.1.0
+pub struct InstanceBuilder {
+    application_name: CString,
+    application_version: u32,
+    engine_name: CString,
+    engine_version: u32,
+    api_version: u32,
+    headless: bool,
+    #[cfg(feature = "validation-layers")]
+    validation_features: Vec<ValidationFeature>,
+}
+
+impl InstanceBuilder {
     pub fn new() -> Self {
-        let entry = ash::Entry::new().expect("failed to load vulkan");
+        Self {
+            application_name: CString::new("Test").unwrap(),
+            application_version: vk::make_version(0, 1, 0),
+            engine_name: CString::new("Caldeira").unwrap(),
+            engine_version: vk::make_version(0, 1, 0),
+            api_version: REQUIRED_VERSION,
+            headless: false,
+            #[cfg(feature = "validation-layers")]
+            validation_features: vec![ValidationFeature::BestPractices],
+        }
+    }
+
+    /// `version` is made with `vk::make_version`
+    pub fn application(mut self, name: &str, version: u32) -> Self {
+        self.application_name =
+            CString::new(name).expect("the application name contains a nul byte");
+        self.application_version = version;
+        self
+    }
+
+    /// `version` is made with `vk::make_version`
+    pub fn engine(mut self, name: &str, version: u32) -> Self {
+        self.engine_name = CString::new(name).expect("the engine name contains a nul byte");
+        self.engine_version = version;
+        self
+    }
+
+    /// Highest API version the application uses, which can't be lower than `REQUIRED_VERSION`
+    pub fn api_version(mut self, api_version: u32) -> Self {
+        assert!(
+            api_version >= REQUIRED_VERSION,
+            "the API version can't be lower than the required one"
+        );
+
+        self.api_version = api_version;
+        self
+    }
+
+    /// Don't enable the surface extensions, for compute only applications without a window or
+    /// machines without a display server, a headless instance can't create a `Surface`
+    /// These are the only instances on platforms without surface support, see `InstanceError::UnsupportedPlatform`
+    pub fn headless(mut self) -> Self {
+        self.headless = true;
+        self
+    }
+
+    /// Checks of the validation layers besides the default ones, `BestPractices` by default
+    #[cfg(feature = "validation-layers")]
+    pub fn validation_features(mut self, validation_features: &[ValidationFeature]) -> Self {
+        self.validation_features = validation_features.to_vec();
+        self
+    }
+
+    pub fn build(&self) -> Result<Instance, InstanceError> {
+        Instance::create(self)
+    }
+}
+
+impl Default for InstanceBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Instance {
+    /// Create an instance with the defaults of `InstanceBuilder`
+    pub fn new() -> Result<Self, InstanceError> {
+        InstanceBuilder::new().build()
+    }
+
+    /// Like `new`, but panic if Vulkan isn't available
+    pub fn new_or_panic() -> Self {
+        Self::new().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Create an instance with the validation layers checking `validation_features` besides the default checks
+    #[cfg(feature = "validation-layers")]
+    pub fn with_validation_features(
+        validation_features: &[ValidationFeature],
+    ) -> Result<Self, InstanceError> {
+        InstanceBuilder::new()
+            .validation_features(validation_features)
+            .build()
+    }
+
+    fn create(builder: &InstanceBuilder) -> Result<Self, InstanceError> {
+        let entry = ash::Entry::new().map_err(|error| InstanceError::Loading(error.to_string()))?;
         let version = entry
             .try_enumerate_instance_version()
-            .unwrap()
+            .map_err(InstanceError::Creation)?
             .unwrap_or_else(|| vk::make_version(1, 0, 0)); // If vulkan 1.1 is not supported, this function is not present
 
-        let major = vk::version_major(version);
-        let minor = vk::version_minor(version);
-        let patch = vk::version_patch(version);
-
-        println!("Vulkan version: {}.{}.{}", major, minor, patch);
+        println!(
+            "Vulkan version: {}.{}.{}",
+            vk::version_major(version),
+            vk::version_minor(version),
+            vk::version_patch(version)
+        );
 
-        // We allow this lint since it's not a extreme comparison but a default parameter
-        #[allow(clippy::absurd_extreme_comparisons)]
-        if major < REQUIRED_MAJOR || minor < REQUIRED_MINOR || patch < REQUIRED_PATCH {
-            panic!(
-                "The minimum required version is {}.{}.{} and device version is {}.{}.{}",
-                REQUIRED_MAJOR, REQUIRED_MINOR, REQUIRED_PATCH, major, minor, patch
-            );
+        if version < REQUIRED_VERSION {
+            return Err(InstanceError::UnsupportedVersion {
+                found: version,
+                required: REQUIRED_VERSION,
+            });
         }
 
-        let application_name = CString::new("Test").unwrap();
-        let engine_name = CString::new("Caldeira").unwrap();
         let app_info = vk::ApplicationInfo::builder()
-            .application_name(&application_name)
-            .application_version(vk::make_version(0, 1, 0))
-            .engine_name(&engine_name)
-            .engine_version(vk::make_version(0, 1, 0))
-            .api_version(REQUIRED_VERSION);
+            .application_name(&builder.application_name)
+            .application_version(builder.application_version)
+            .engine_name(&builder.engine_name)
+            .engine_version(builder.engine_version)
+            .api_version(builder.api_version);
 
-        let extension_names = Self::check_instance_extensions(&entry)
-            .expect("instance extensions requested, but not available!");
+        let platform_extensions = if builder.headless {
+            &[]
+        } else {
+            platform_surface_extensions().ok_or(InstanceError::UnsupportedPlatform)?
+        };
+        let extensions = Self::check_instance_extensions(
+            &entry,
+            &required_extensions(builder.headless),
+            platform_extensions,
+        )?;
+        let extension_cstrings = extensions
+            .iter()
+            .map(|&name| CString::new(name).unwrap())
+            .collect::<Vec<_>>();
+        let extension_names = extension_cstrings
+            .iter()
+            .map(|name| name.as_ptr())
+            .collect::<Vec<_>>();
 
         let create_info = vk::InstanceCreateInfo::builder()
             .application_info(&app_info)
             .enabled_extension_names(&extension_names);
 
         #[cfg(feature = "validation-layers")]
-        let validation_layers = Self::check_validation_layers(&entry)
-            .expect("validation layers requested, but not available!");
+        let validation_layers = Self::check_validation_layers(&entry)?;
 
         #[cfg(feature = "validation-layers")]
-        let enabled = [
-            // vk::ValidationFeatureEnableEXT::GPU_ASSISTED,
-            // vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT,
-            vk::ValidationFeatureEnableEXT::BEST_PRACTICES,
-            // vk::ValidationFeatureEnableEXT::DEBUG_PRINTF,
-        ];
+        let enabled = builder
+            .validation_features
+            .iter()
+            .map(|feature| feature.to_vk())
+            .collect::<Vec<_>>();
         #[cfg(feature = "validation-layers")]
         let mut validation_features =
             vk::ValidationFeaturesEXT::builder().enabled_validation_features(&enabled);
@@ -75,46 +270,60 @@ impl Instance {
             .enabled_layer_names(&validation_layers)
             .push_next(&mut validation_features);
 
-        let instance = unsafe { entry.create_instance(&create_info, None) }
-            .expect("failed to create instance!");
+        let instance = unsafe { entry.create_instance(&create_info, None) };
 
+        #[cfg(feature = "validation-layers")]
         unsafe {
-            utils::free_cstring(extension_names);
-
-            #[cfg(feature = "validation-layers")]
             utils::free_cstring(validation_layers);
         }
 
-        Self { entry, instance }
+        let instance = instance.map_err(|error| match error {
+            ash::InstanceError::LoadError(functions) => {
+                InstanceError::Loading(format!("missing the functions {}", functions.join(", ")))
+            }
+            ash::InstanceError::VkError(result) => InstanceError::Creation(result),
+        })?;
+
+        Ok(Self {
+            entry,
+            instance,
+            headless: builder.headless,
+            extensions: extensions.into_iter().map(str::to_owned).collect(),
+        })
+    }
+
+    /// Whether the instance was created without the surface extensions by `InstanceBuilder::headless`
+    pub fn is_headless(&self) -> bool {
+        self.headless
     }
 
-    fn check_instance_extensions(entry: &ash::Entry) -> Option<Vec<*const c_char>> {
-        let extension_names = entry
+    /// Whether the instance extension `name` was enabled at creation, such as one of the platform surface extensions
+    pub fn is_extension_enabled(&self, name: &str) -> bool {
+        self.extensions.iter().any(|extension| extension == name)
+    }
+
+    /// `required` and the extensions of `platform` the driver lists, at least one of them being needed
+    fn check_instance_extensions(
+        entry: &ash::Entry,
+        required: &[&'static str],
+        platform: &[&'static str],
+    ) -> Result<Vec<&'static str>, InstanceError> {
+        let available = entry
             .enumerate_instance_extension_properties()
-            .expect("failed to enumerate extensions")
+            .map_err(InstanceError::Creation)?
             .into_iter()
             .map(|property| property.extension_name)
             .map(|name| unsafe { CStr::from_ptr(name.as_ptr()).to_owned() })
             .inspect(|name| println!("instance extension: {:?}", name))
-            .filter(|name| INSTANCE_EXTENSIONS.contains(&name.to_str().unwrap()))
             .collect::<HashSet<_>>();
 
-        if extension_names.len() == INSTANCE_EXTENSIONS.len() {
-            let extensions = extension_names
-                .into_iter()
-                .map(|name| name.into_raw() as *const _)
-                .collect::<Vec<_>>();
-
-            Some(extensions)
-        } else {
-            None
-        }
+        select_extensions(required, platform, &available)
     }
 
-    fn check_validation_layers(entry: &ash::Entry) -> Option<Vec<*const i8>> {
+    fn check_validation_layers(entry: &ash::Entry) -> Result<Vec<*const i8>, InstanceError> {
         let validation_layer_names = entry
             .enumerate_instance_layer_properties()
-            .expect("failed to enumerate validation layers")
+            .map_err(InstanceError::Creation)?
             .into_iter()
             .map(|property| property.layer_name)
             .map(|name| unsafe { CStr::from_ptr(name.as_ptr()).to_owned() })
@@ -127,19 +336,73 @@ impl Instance {
                 .into_iter()
                 .map(|name| name.into_raw() as *const _)
                 .collect::<Vec<_>>();
-            Some(validation_layers)
+            Ok(validation_layers)
         } else {
-            None
+            Err(InstanceError::MissingLayers(missing(
+                VALIDATION_LAYERS,
+                &validation_layer_names,
+            )))
         }
     }
 }
 
 impl Default for Instance {
     fn default() -> Self {
-        Self::new()
+        Self::new_or_panic()
+    }
+}
+
+/// Instance extensions to enable, `VK_KHR_surface` only if the instance isn't `headless`,
+/// the platform surface extensions being chosen among the available ones
+fn required_extensions(headless: bool) -> Vec<&'static str> {
+    let mut extensions = INSTANCE_EXTENSIONS.to_vec();
+
+    if !headless {
+        extensions.push("VK_KHR_surface");
+    }
+
+    extensions
+}
+
+/// `required` and the extensions of `platform` in `available`, failing if one of `required` is missing
+/// or if none of `platform` is available
+fn select_extensions<'a>(
+    required: &[&'a str],
+    platform: &[&'a str],
+    available: &HashSet<CString>,
+) -> Result<Vec<&'a str>, InstanceError> {
+    let mut missing_extensions = missing(required, available);
+
+    let available_platform = platform
+        .iter()
+        .copied()
+        .filter(|&name| missing(&[name], available).is_empty())
+        .collect::<Vec<_>>();
+
+    if !platform.is_empty() && available_platform.is_empty() {
+        missing_extensions.extend(platform.iter().map(|&name| name.to_owned()));
+    }
+
+    if missing_extensions.is_empty() {
+        Ok(required.iter().copied().chain(available_platform).collect())
+    } else {
+        Err(InstanceError::MissingExtensions(missing_extensions))
     }
 }
 
+/// Names of `required` which aren't in `available`
+fn missing(required: &[&str], available: &HashSet<CString>) -> Vec<String> {
+    required
+        .iter()
+        .filter(|&&name| {
+            !available
+                .iter()
+                .any(|available| available.to_str() == Ok(name))
+        })
+        .map(|&name| name.to_owned())
+        .collect()
+}
+
 impl Drop for Instance {
     fn drop(&mut self) {
         unsafe {
@@ -147,3 +410,68 @@ impl Drop for Instance {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn missing_extensions_are_reported() {
+        let available = ["VK_KHR_surface"]
+            .iter()
+            .map(|&name| CString::new(name).unwrap())
+            .collect::<HashSet<_>>();
+
+        let error = InstanceError::MissingExtensions(missing(
+            &["VK_KHR_surface", "VK_KHR_xlib_surface"],
+            &available,
+        ));
+
+        assert_eq!(
+            error,
+            InstanceError::MissingExtensions(vec!["VK_KHR_xlib_surface".to_owned()])
+        );
+        assert_eq!(
+            error.to_string(),
+            "The instance extensions VK_KHR_xlib_surface are not available!"
+        );
+    }
+
+    #[test]
+    fn headless_instances_have_no_surface_extension() {
+        let headless = required_extensions(true);
+        assert!(!headless.contains(&"VK_KHR_surface"));
+        assert_eq!(headless, INSTANCE_EXTENSIONS);
+
+        let windowed = required_extensions(false);
+        assert!(windowed.contains(&"VK_KHR_surface"));
+        // The platform extensions are chosen among the available ones
+        assert_eq!(windowed.len(), INSTANCE_EXTENSIONS.len() + 1);
+    }
+
+    #[test]
+    fn available_platform_extensions_are_enabled() {
+        let platform = ["VK_KHR_wayland_surface", "VK_KHR_xlib_surface"];
+        let available = ["VK_KHR_surface", "VK_KHR_xlib_surface"]
+            .iter()
+            .map(|&name| CString::new(name).unwrap())
+            .collect::<HashSet<_>>();
+
+        assert_eq!(
+            select_extensions(&["VK_KHR_surface"], &platform, &available),
+            Ok(vec!["VK_KHR_surface", "VK_KHR_xlib_surface"])
+        );
+        assert_eq!(
+            select_extensions(&["VK_KHR_surface"], &platform[..1], &available),
+            Err(InstanceError::MissingExtensions(vec![
+                "VK_KHR_wayland_surface".to_owned()
+            ]))
+        );
+        assert_eq!(
+            select_extensions(&["VK_EXT_debug_utils"], &[], &available),
+            Err(InstanceError::MissingExtensions(vec![
+                "VK_EXT_debug_utils".to_owned()
+            ]))
+        );
+    }
+}
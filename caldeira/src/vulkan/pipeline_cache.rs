@@ -0,0 +1,96 @@
+use std::rc::Rc;
+
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+use super::Device;
+
+/// Cache of compiled pipelines, which can be saved with `get_data` and reloaded with `with_data`
+/// to speed up the creation of the pipelines in the next runs
+pub struct PipelineCache {
+    pub handle: vk::PipelineCache,
+    device: Rc<Device>,
+}
+
+impl PipelineCache {
+    pub fn new(device: Rc<Device>) -> Self {
+        Self::with_data(&[], device)
+    }
+
+    /// Create a cache filled with `data`, previously returned by `get_data`
+    /// Data from another device or driver version is ignored by the driver and the cache starts empty
+    pub fn with_data(data: &[u8], device: Rc<Device>) -> Self {
+        let create_info = vk::PipelineCacheCreateInfo::builder().initial_data(data);
+
+        let handle = unsafe { device.device.create_pipeline_cache(&create_info, None) }
+            .expect("failed to create pipeline cache!");
+
+        Self { handle, device }
+    }
+
+    /// Content of the cache, to be given to `with_data`
+    pub fn get_data(&self) -> Vec<u8> {
+        // Queries the size of the data, then fills a vector of this size
+        unsafe { self.device.device.get_pipeline_cache_data(self.handle) }
+            .expect("failed to get pipeline cache data!")
+    }
+}
+
+impl Drop for PipelineCache {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device.destroy_pipeline_cache(self.handle, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroU32;
+    use std::path::Path;
+
+    use super::*;
+    use crate::utils;
+    use crate::vulkan::{ComputePipeline, DescriptorSetLayoutBuilder};
+
+    #[test]
+    #[ignore = "requires a Vulkan device and the compiled shaders"]
+    fn data_round_trip() {
+        let (_instance, device, _queue) = utils::create_test_device();
+
+        let layouts = [DescriptorSetLayoutBuilder::new()
+            .with_binding(
+                vk::DescriptorType::STORAGE_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .with_binding(
+                vk::DescriptorType::STORAGE_BUFFER,
+                NonZeroU32::new(1).unwrap(),
+                vk::ShaderStageFlags::COMPUTE,
+                None,
+            )
+            .build(Rc::clone(&device))
+            .unwrap()];
+
+        let cache = PipelineCache::new(Rc::clone(&device));
+        let _pipeline = ComputePipeline::new(
+            Path::new("../shaders/reduce.comp.spv"),
+            "main",
+            &layouts,
+            None,
+            None,
+            Some(&cache),
+            None,
+            Rc::clone(&device),
+        );
+
+        // The data starts with a header identifying the device, even if the driver doesn't cache anything
+        let data = cache.get_data();
+        assert!(data.len() >= 32);
+
+        let reloaded = PipelineCache::with_data(&data, device).get_data();
+        assert_eq!(reloaded[..32], data[..32]);
+    }
+}
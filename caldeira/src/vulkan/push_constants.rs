@@ -0,0 +1,237 @@
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use ash::vk;
+
+use super::ByteCopiable;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PushConstantError {
+    /// No pipeline is bound
+    NoPipeline,
+    /// The layout of the bound pipeline doesn't declare a push constant with this name
+    UnknownName(String),
+    /// The pushed value doesn't have the size of the declared one
+    SizeMismatch { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for PushConstantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoPipeline => write!(f, "No pipeline is bound to push constants to!"),
+            Self::UnknownName(name) => write!(f, "No push constant is named {}!", name),
+            Self::SizeMismatch { expected, actual } => write!(
+                f,
+                "The push constant is {} bytes long but {} bytes were pushed!",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl Error for PushConstantError {}
+
+/// A named push constant, laid out after the previous ones
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PushConstant {
+    name: String,
+    stage_flags: vk::ShaderStageFlags,
+    offset: u32,
+    size: u32,
+    /// Stages of every range overlapping this push constant, which must all be given to `cmd_push_constants`
+    push_stage_flags: vk::ShaderStageFlags,
+}
+
+impl PushConstant {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn stage_flags(&self) -> vk::ShaderStageFlags {
+        self.stage_flags
+    }
+
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub(crate) fn push_stage_flags(&self) -> vk::ShaderStageFlags {
+        self.push_stage_flags
+    }
+}
+
+pub struct PushConstantLayoutBuilder {
+    push_constants: Vec<PushConstant>,
+    size: u32,
+}
+
+impl PushConstantLayoutBuilder {
+    pub fn new() -> Self {
+        Self {
+            push_constants: vec![],
+            size: 0,
+        }
+    }
+
+    /// Declare a push constant of type `T` named `name` used by `stage_flags`, right after the previous one
+    pub fn with<T: ByteCopiable>(mut self, name: &str, stage_flags: vk::ShaderStageFlags) -> Self {
+        let size = mem::size_of::<T>() as u32;
+
+        assert_ne!(size, 0, "a push constant can't be empty");
+        assert_eq!(
+            size % 4,
+            0,
+            "the size of a push constant must be a multiple of 4"
+        );
+        assert!(
+            self.push_constants
+                .iter()
+                .all(|constant| constant.name != name),
+            "push constant {} is declared twice",
+            name
+        );
+
+        self.push_constants.push(PushConstant {
+            name: name.to_owned(),
+            stage_flags,
+            offset: self.size,
+            size,
+            push_stage_flags: stage_flags,
+        });
+        self.size += size;
+        self
+    }
+
+    /// Merge the push constants of each stage into a single range, since a stage can be in only one range
+    pub fn build(mut self) -> PushConstantLayout {
+        let stage_flags = self
+            .push_constants
+            .iter()
+            .fold(vk::ShaderStageFlags::empty(), |stage_flags, constant| {
+                stage_flags | constant.stage_flags
+            });
+
+        let ranges = (0..32)
+            .map(|bit| vk::ShaderStageFlags::from_raw(1 << bit))
+            .filter(|&stage| stage_flags.contains(stage))
+            .map(|stage| {
+                let mut constants = self
+                    .push_constants
+                    .iter()
+                    .filter(|constant| constant.stage_flags.contains(stage));
+                let first = constants.next().unwrap();
+                let end = constants
+                    .next_back()
+                    .map_or(first.offset + first.size, |last| last.offset + last.size);
+
+                vk::PushConstantRange::builder()
+                    .stage_flags(stage)
+                    .offset(first.offset)
+                    .size(end - first.offset)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        for constant in &mut self.push_constants {
+            let end = constant.offset + constant.size;
+
+            constant.push_stage_flags = ranges
+                .iter()
+                .filter(|range| range.offset < end && constant.offset < range.offset + range.size)
+                .fold(vk::ShaderStageFlags::empty(), |stage_flags, range| {
+                    stage_flags | range.stage_flags
+                });
+        }
+
+        PushConstantLayout {
+            push_constants: self.push_constants,
+            ranges,
+            size: self.size,
+        }
+    }
+}
+
+impl Default for PushConstantLayoutBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Named push constants of a pipeline layout, built by `PushConstantLayoutBuilder`
+#[derive(Clone, Debug, Default)]
+pub struct PushConstantLayout {
+    push_constants: Vec<PushConstant>,
+    ranges: Vec<vk::PushConstantRange>,
+    size: u32,
+}
+
+impl PushConstantLayout {
+    /// Ranges to create the pipeline layout with, one per stage
+    pub fn ranges(&self) -> &[vk::PushConstantRange] {
+        &self.ranges
+    }
+
+    /// Size of all the push constants
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PushConstant> {
+        self.push_constants
+            .iter()
+            .find(|constant| constant.name == name)
+    }
+
+    /// Whether pipeline layouts created with both layouts have identically defined push constant ranges
+    pub(crate) fn is_compatible_with(&self, other: &PushConstantLayout) -> bool {
+        self.ranges.len() == other.ranges.len()
+            && self.ranges.iter().zip(&other.ranges).all(|(range, other)| {
+                (range.stage_flags, range.offset, range.size)
+                    == (other.stage_flags, other.offset, other.size)
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ranges_per_stage() {
+        let layout = PushConstantLayoutBuilder::new()
+            .with::<u32>("time", vk::ShaderStageFlags::COMPUTE)
+            .with::<[f32; 2]>("scale", vk::ShaderStageFlags::VERTEX)
+            .with::<u32>("frame", vk::ShaderStageFlags::COMPUTE)
+            .build();
+
+        assert_eq!(layout.size(), 16);
+        assert_eq!(
+            layout
+                .ranges()
+                .iter()
+                .map(|range| (range.stage_flags, range.offset, range.size))
+                .collect::<Vec<_>>(),
+            vec![
+                (vk::ShaderStageFlags::VERTEX, 4, 8),
+                (vk::ShaderStageFlags::COMPUTE, 0, 16),
+            ]
+        );
+
+        // The compute range covers the vertex one, so both stages must be given when pushing it
+        let scale = layout.get("scale").unwrap();
+        assert_eq!(scale.offset(), 4);
+        assert_eq!(
+            scale.push_stage_flags(),
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::COMPUTE
+        );
+        assert_eq!(
+            layout.get("frame").unwrap().push_stage_flags(),
+            vk::ShaderStageFlags::COMPUTE
+        );
+    }
+}
@@ -0,0 +1,472 @@
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::Duration;
+
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+use super::{Device, DeviceLost, QueueFamily};
+
+/// Integer type the results of queries are read as, `u32` or `u64`
+pub trait QueryResult: Copy + Default {
+    /// `TYPE_64` for 64 bits results
+    const FLAGS: vk::QueryResultFlags;
+}
+
+impl QueryResult for u32 {
+    const FLAGS: vk::QueryResultFlags = vk::QueryResultFlags::empty();
+}
+
+impl QueryResult for u64 {
+    const FLAGS: vk::QueryResultFlags = vk::QueryResultFlags::TYPE_64;
+}
+
+/// A pool of `query_count` queries of the same type
+pub struct QueryPool {
+    pub handle: vk::QueryPool,
+    query_type: vk::QueryType,
+    query_count: u32,
+    pipeline_statistics: vk::QueryPipelineStatisticFlags,
+    device: Rc<Device>,
+}
+
+impl QueryPool {
+    /// `pipeline_statistics` is ignored unless `query_type` is `PIPELINE_STATISTICS`
+    pub fn new(
+        query_type: vk::QueryType,
+        query_count: u32,
+        pipeline_statistics: vk::QueryPipelineStatisticFlags,
+        device: Rc<Device>,
+    ) -> Self {
+        let pipeline_statistics = if query_type == vk::QueryType::PIPELINE_STATISTICS {
+            pipeline_statistics
+        } else {
+            vk::QueryPipelineStatisticFlags::empty()
+        };
+
+        let create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(query_type)
+            .query_count(query_count)
+            .pipeline_statistics(pipeline_statistics);
+
+        let handle = unsafe { device.device.create_query_pool(&create_info, None) }
+            .expect("failed to create query pool!");
+
+        Self {
+            handle,
+            query_type,
+            query_count,
+            pipeline_statistics,
+            device,
+        }
+    }
+
+    pub fn query_type(&self) -> vk::QueryType {
+        self.query_type
+    }
+
+    pub fn query_count(&self) -> u32 {
+        self.query_count
+    }
+
+    pub fn pipeline_statistics(&self) -> vk::QueryPipelineStatisticFlags {
+        self.pipeline_statistics
+    }
+
+    /// Number of values written for each query, without the availability value
+    pub fn results_per_query(&self) -> usize {
+        if self.query_type == vk::QueryType::PIPELINE_STATISTICS {
+            PipelineStatistics::result_count(self.pipeline_statistics)
+        } else {
+            1
+        }
+    }
+
+    /// Read the results of the queries `queries`, the `results_per_query` values of each query followed by
+    /// its availability with `WITH_AVAILABILITY`, the size of the values is given by `T` rather than `flags`
+    /// Return `None` if a query isn't available yet, unless `flags` contains `WAIT`, `WITH_AVAILABILITY` or `PARTIAL`
+    pub fn get_results<T: QueryResult>(
+        &self,
+        queries: Range<u32>,
+        flags: vk::QueryResultFlags,
+    ) -> Result<Option<Vec<T>>, DeviceLost> {
+        assert!(
+            queries.end <= self.query_count,
+            "the queries are out of the pool"
+        );
+
+        let flags = (flags & !vk::QueryResultFlags::TYPE_64) | T::FLAGS;
+        let values_per_query = self.results_per_query()
+            + flags.contains(vk::QueryResultFlags::WITH_AVAILABILITY) as usize;
+        let query_count = queries.end.saturating_sub(queries.start);
+
+        let mut data = vec![T::default(); query_count as usize * values_per_query];
+
+        // ash assumes a single value per query, which isn't true for pipeline statistics
+        let result = unsafe {
+            self.device.device.fp_v1_0().get_query_pool_results(
+                self.device.device.handle(),
+                self.handle,
+                queries.start,
+                query_count,
+                data.len() * mem::size_of::<T>(),
+                data.as_mut_ptr().cast(),
+                (values_per_query * mem::size_of::<T>()) as _,
+                flags,
+            )
+        };
+
+        match result {
+            vk::Result::SUCCESS => Ok(Some(data)),
+            vk::Result::NOT_READY
+                if flags.intersects(
+                    vk::QueryResultFlags::WITH_AVAILABILITY | vk::QueryResultFlags::PARTIAL,
+                ) =>
+            {
+                Ok(Some(data))
+            }
+            vk::Result::NOT_READY => Ok(None),
+            error => self
+                .device
+                .check_lost(Err(error), "failed to get query results")
+                .map(|()| None),
+        }
+    }
+
+    /// Read the timestamps written to the queries `queries` of a `TIMESTAMP` pool by queues of `queue_family`,
+    /// waiting for them if `wait`, to be converted into durations with the `timestamp_period` of the device
+    /// Return `None` as well if the queues of `queue_family` don't support timestamps
+    pub fn get_timestamps(
+        &self,
+        queries: Range<u32>,
+        wait: bool,
+        queue_family: &QueueFamily,
+    ) -> Result<Option<Timestamps>, DeviceLost> {
+        assert_eq!(
+            self.query_type,
+            vk::QueryType::TIMESTAMP,
+            "the pool doesn't hold timestamps"
+        );
+
+        let timestamp_valid_bits = queue_family.timestamp_valid_bits();
+        if timestamp_valid_bits == 0 {
+            return Ok(None);
+        }
+
+        let flags = if wait {
+            vk::QueryResultFlags::WAIT
+        } else {
+            vk::QueryResultFlags::empty()
+        };
+
+        Ok(self.get_results::<u64>(queries, flags)?.and_then(|ticks| {
+            Timestamps::new(ticks, self.device.timestamp_period(), timestamp_valid_bits)
+        }))
+    }
+}
+
+impl Drop for QueryPool {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device.destroy_query_pool(self.handle, None);
+        }
+    }
+}
+
+/// Raw timestamps written by a query pool, converted into durations with the `timestamp_period` limit of the device
+/// Only the `timestamp_valid_bits` low bits of the ticks are meaningful, and they wrap around
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timestamps {
+    ticks: Vec<u64>,
+    timestamp_period: f32,
+    mask: u64,
+}
+
+impl Timestamps {
+    /// `timestamp_valid_bits` is the one of the queue family that wrote the timestamps,
+    /// return `None` if it's 0 since the family doesn't support timestamps
+    pub fn new(ticks: Vec<u64>, timestamp_period: f32, timestamp_valid_bits: u32) -> Option<Self> {
+        if timestamp_valid_bits == 0 {
+            return None;
+        }
+
+        Some(Self {
+            ticks,
+            timestamp_period,
+            mask: u64::MAX >> (64 - timestamp_valid_bits.min(64)),
+        })
+    }
+
+    pub fn ticks(&self) -> &[u64] {
+        &self.ticks
+    }
+
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    /// Elapsed time between the timestamps `from` and `to`
+    pub fn delta(&self, from: usize, to: usize) -> Duration {
+        let ticks = (self.ticks[to] & self.mask).wrapping_sub(self.ticks[from] & self.mask);
+        self.ticks_to_duration(ticks & self.mask)
+    }
+
+    /// Elapsed time between each timestamp and the next one
+    pub fn deltas(&self) -> Vec<Duration> {
+        (1..self.ticks.len())
+            .map(|index| self.delta(index - 1, index))
+            .collect()
+    }
+
+    /// Elapsed time between the first and the last timestamps
+    pub fn total(&self) -> Duration {
+        match self.ticks.len() {
+            0 => Duration::default(),
+            len => self.delta(0, len - 1),
+        }
+    }
+
+    fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        let nanos = ticks as f64 * self.timestamp_period as f64;
+        Duration::from_nanos(nanos.round() as u64)
+    }
+}
+
+impl fmt::Display for Timestamps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "total: {:?}", self.total())?;
+
+        for (index, delta) in self.deltas().into_iter().enumerate() {
+            write!(f, ", {} -> {}: {:?}", index, index + 1, delta)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Pipeline statistics named after the flags the query pool was created with,
+/// a field is `None` if its statistic wasn't queried
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PipelineStatistics {
+    pub input_assembly_vertices: Option<u64>,
+    pub input_assembly_primitives: Option<u64>,
+    pub vertex_shader_invocations: Option<u64>,
+    pub geometry_shader_invocations: Option<u64>,
+    pub geometry_shader_primitives: Option<u64>,
+    pub clipping_invocations: Option<u64>,
+    pub clipping_primitives: Option<u64>,
+    pub fragment_shader_invocations: Option<u64>,
+    pub tessellation_control_shader_patches: Option<u64>,
+    pub tessellation_evaluation_shader_invocations: Option<u64>,
+    pub compute_shader_invocations: Option<u64>,
+}
+
+impl PipelineStatistics {
+    /// Build the statistics from the results of one query, which are written in the order of the bits of `flags`
+    pub fn from_results(flags: vk::QueryPipelineStatisticFlags, results: &[u64]) -> Self {
+        let mut statistics = Self::default();
+        let mut results = results.iter().copied();
+
+        for (flag, field) in statistics.fields_mut() {
+            if flags.contains(flag) {
+                *field = results.next();
+            }
+        }
+
+        statistics
+    }
+
+    /// Number of results written by a query with these flags
+    pub fn result_count(flags: vk::QueryPipelineStatisticFlags) -> usize {
+        flags.as_raw().count_ones() as _
+    }
+
+    fn fields_mut(&mut self) -> [(vk::QueryPipelineStatisticFlags, &mut Option<u64>); 11] {
+        type Flags = vk::QueryPipelineStatisticFlags;
+
+        [
+            (
+                Flags::INPUT_ASSEMBLY_VERTICES,
+                &mut self.input_assembly_vertices,
+            ),
+            (
+                Flags::INPUT_ASSEMBLY_PRIMITIVES,
+                &mut self.input_assembly_primitives,
+            ),
+            (
+                Flags::VERTEX_SHADER_INVOCATIONS,
+                &mut self.vertex_shader_invocations,
+            ),
+            (
+                Flags::GEOMETRY_SHADER_INVOCATIONS,
+                &mut self.geometry_shader_invocations,
+            ),
+            (
+                Flags::GEOMETRY_SHADER_PRIMITIVES,
+                &mut self.geometry_shader_primitives,
+            ),
+            (Flags::CLIPPING_INVOCATIONS, &mut self.clipping_invocations),
+            (Flags::CLIPPING_PRIMITIVES, &mut self.clipping_primitives),
+            (
+                Flags::FRAGMENT_SHADER_INVOCATIONS,
+                &mut self.fragment_shader_invocations,
+            ),
+            (
+                Flags::TESSELLATION_CONTROL_SHADER_PATCHES,
+                &mut self.tessellation_control_shader_patches,
+            ),
+            (
+                Flags::TESSELLATION_EVALUATION_SHADER_INVOCATIONS,
+                &mut self.tessellation_evaluation_shader_invocations,
+            ),
+            (
+                Flags::COMPUTE_SHADER_INVOCATIONS,
+                &mut self.compute_shader_invocations,
+            ),
+        ]
+    }
+}
+
+impl fmt::Display for PipelineStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let statistics = [
+            ("input assembly vertices", self.input_assembly_vertices),
+            ("input assembly primitives", self.input_assembly_primitives),
+            ("vertex shader invocations", self.vertex_shader_invocations),
+            (
+                "geometry shader invocations",
+                self.geometry_shader_invocations,
+            ),
+            (
+                "geometry shader primitives",
+                self.geometry_shader_primitives,
+            ),
+            ("clipping invocations", self.clipping_invocations),
+            ("clipping primitives", self.clipping_primitives),
+            (
+                "fragment shader invocations",
+                self.fragment_shader_invocations,
+            ),
+            (
+                "tessellation control shader patches",
+                self.tessellation_control_shader_patches,
+            ),
+            (
+                "tessellation evaluation shader invocations",
+                self.tessellation_evaluation_shader_invocations,
+            ),
+            (
+                "compute shader invocations",
+                self.compute_shader_invocations,
+            ),
+        ];
+
+        let mut first = true;
+
+        for (name, value) in statistics.iter() {
+            if let Some(value) = value {
+                if !first {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}: {}", name, value)?;
+                first = false;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use ash::vk;
+
+    use super::*;
+    use crate::utils;
+    use crate::vulkan::CommandPool;
+
+    #[test]
+    fn timestamps_to_durations() {
+        let timestamps = Timestamps::new(vec![100, 150, 400], 2.5, 64).unwrap();
+
+        assert_eq!(timestamps.delta(0, 1), Duration::from_nanos(125));
+        assert_eq!(
+            timestamps.deltas(),
+            vec![Duration::from_nanos(125), Duration::from_nanos(625)]
+        );
+        assert_eq!(timestamps.total(), Duration::from_nanos(750));
+
+        assert_eq!(Timestamps::new(vec![100, 150], 2.5, 0), None);
+    }
+
+    #[test]
+    fn timestamps_wrap_around_valid_bits() {
+        // The bits above the 8 valid ones are undefined
+        let timestamps = Timestamps::new(vec![0xff00 | 250, 0x1200 | 4], 1.0, 8).unwrap();
+
+        assert_eq!(timestamps.delta(0, 1), Duration::from_nanos(10));
+        assert_eq!(timestamps.delta(1, 0), Duration::from_nanos(246));
+    }
+
+    #[test]
+    fn pipeline_statistics_follow_flag_order() {
+        let flags = vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS
+            | vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES;
+
+        assert_eq!(PipelineStatistics::result_count(flags), 2);
+
+        let statistics = PipelineStatistics::from_results(flags, &[3, 1024]);
+
+        assert_eq!(statistics.input_assembly_vertices, Some(3));
+        assert_eq!(statistics.compute_shader_invocations, Some(1024));
+        assert_eq!(statistics.vertex_shader_invocations, None);
+        assert_eq!(
+            statistics.to_string(),
+            "input assembly vertices: 3, compute shader invocations: 1024"
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn timestamps_are_written() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+
+        // Writing timestamps isn't allowed on such families
+        if queue.family().timestamp_valid_bits() == 0 {
+            return;
+        }
+
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+        let query_pool = QueryPool::new(
+            vk::QueryType::TIMESTAMP,
+            2,
+            vk::QueryPipelineStatisticFlags::empty(),
+            device,
+        );
+
+        command_pool
+            .single_time(&mut queue, |recorder| {
+                recorder
+                    .as_generic()
+                    .reset_query_pool(&query_pool, 0..2)
+                    .write_timestamp(vk::PipelineStageFlags::TOP_OF_PIPE, &query_pool, 0)
+                    .write_timestamp(vk::PipelineStageFlags::BOTTOM_OF_PIPE, &query_pool, 1);
+            })
+            .unwrap();
+
+        let timestamps = query_pool
+            .get_timestamps(0..2, true, queue.family())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(timestamps.len(), 2);
+    }
+}
@@ -1,9 +1,90 @@
+use std::error::Error;
+use std::fmt;
 use std::rc::Rc;
 
 use ash::version::DeviceV1_0;
 use ash::vk;
 
-use super::{Device, QueueSubmission};
+use super::{
+    CommandPool, CopyError, Device, DeviceLost, Fence, FencePool, Image, LayoutTransitionError,
+    QueueSubmission, Surface,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransitionError {
+    /// The transition isn't supported by `Image::transition_layout`
+    UnsupportedLayout(LayoutTransitionError),
+    /// The transition waits for or blocks these stages, which the queue family doesn't support
+    UnsupportedStages(vk::PipelineStageFlags),
+    DeviceLost,
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedLayout(error) => error.fmt(f),
+            Self::UnsupportedStages(stages) => write!(
+                f,
+                "The queue family doesn't support the stages {:?} of the transition!",
+                stages
+            ),
+            Self::DeviceLost => write!(f, "The device was lost during the transition!"),
+        }
+    }
+}
+
+impl Error for TransitionError {}
+
+impl From<LayoutTransitionError> for TransitionError {
+    fn from(error: LayoutTransitionError) -> Self {
+        Self::UnsupportedLayout(error)
+    }
+}
+
+impl From<DeviceLost> for TransitionError {
+    fn from(_: DeviceLost) -> Self {
+        Self::DeviceLost
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SubmitError {
+    /// A command buffer without `SIMULTANEOUS_USE` is submitted while a previous submission of it is still pending
+    Pending,
+    /// A command buffer is submitted after its pool was reset by `CommandPool::reset`, without being recorded again
+    NotExecutable,
+    /// `Queue::submit` can't free the owned command buffers once they're executed, use `submit_batch`
+    OwnedCommandBuffers,
+    DeviceLost,
+}
+
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pending => write!(
+                f,
+                "A command buffer without SIMULTANEOUS_USE is submitted while still pending!"
+            ),
+            Self::NotExecutable => write!(
+                f,
+                "A command buffer is submitted after its command pool was reset!"
+            ),
+            Self::OwnedCommandBuffers => write!(
+                f,
+                "Submissions owning command buffers must be submitted with a submit handle!"
+            ),
+            Self::DeviceLost => write!(f, "The device was lost!"),
+        }
+    }
+}
+
+impl Error for SubmitError {}
+
+impl From<DeviceLost> for SubmitError {
+    fn from(_: DeviceLost) -> Self {
+        Self::DeviceLost
+    }
+}
 
 pub struct QueueCreateInfo {
     priorities: Vec<f32>,
@@ -35,15 +116,40 @@ impl Queue {
         &self.device.get_queue_families(self.queue_family_index)
     }
 
-    pub fn wait_idle(&mut self) {
-        unsafe { self.device.device.queue_wait_idle(self.handle) }.unwrap()
+    pub fn wait_idle(&mut self) -> Result<(), DeviceLost> {
+        self.device.check_lost(
+            unsafe { self.device.device.queue_wait_idle(self.handle) },
+            "failed to wait queue idle",
+        )
+    }
+
+    /// Submit `submits`, signaling `fence` if any when they complete
+    /// The command buffers aren't seen pending afterwards, since the fence may be destroyed or reset without
+    /// the crate knowing, use `submit_batch` to track them
+    /// The submissions can't own command buffers, which would be freed while they're executed
+    pub fn submit(
+        &mut self,
+        submits: &[QueueSubmission<'_>],
+        fence: Option<vk::Fence>,
+    ) -> Result<(), SubmitError> {
+        if submits.iter().any(QueueSubmission::owns_command_buffers) {
+            return Err(SubmitError::OwnedCommandBuffers);
+        }
+
+        self.submit_unchecked(submits, fence)
     }
 
-    pub fn submit(&mut self, submits: &[QueueSubmission<'_>], fence: Option<vk::Fence>) {
+    fn submit_unchecked(
+        &mut self,
+        submits: &[QueueSubmission<'_>],
+        fence: Option<vk::Fence>,
+    ) -> Result<(), SubmitError> {
         let mut submit_info_builders = Vec::with_capacity(submits.len());
         let fence = fence.unwrap_or_default();
 
         for submit in submits {
+            submit.check_not_pending()?;
+
             let submit_info = vk::SubmitInfo::builder()
                 .wait_semaphores(&submit.wait_semaphores())
                 .wait_dst_stage_mask(&submit.wait_dst_stage_masks())
@@ -59,15 +165,405 @@ impl Queue {
             .map(vk::SubmitInfoBuilder::build)
             .collect::<Vec<_>>();
 
-        unsafe {
-            self.device
-                .device
-                .queue_submit(self.handle, &submit_infos, fence)
-                .expect("failed to submit queue")
+        self.device.check_lost(
+            unsafe {
+                self.device
+                    .device
+                    .queue_submit(self.handle, &submit_infos, fence)
+            },
+            "failed to submit queue",
+        )?;
+
+        Ok(())
+    }
+
+    /// Same as `submit`, marking the command buffers pending until `fence` is observed signaled,
+    /// `fence` must be owned by the crate so that it's neither destroyed nor reset before, and the owned
+    /// command buffers must be kept until then
+    pub(crate) fn submit_tracked(
+        &mut self,
+        submits: &[QueueSubmission<'_>],
+        fence: vk::Fence,
+    ) -> Result<(), SubmitError> {
+        self.submit_unchecked(submits, Some(fence))?;
+
+        for submit in submits {
+            submit.mark_pending(fence);
+        }
+
+        Ok(())
+    }
+
+    /// Same as `submit`, signaling `fence` when the submissions complete
+    pub fn submit_fenced(
+        &mut self,
+        submits: &[QueueSubmission<'_>],
+        fence: &Fence,
+    ) -> Result<(), SubmitError> {
+        self.submit(submits, Some(fence.handle))
+    }
+
+    /// Submit `submits` and keep them, as well as the command buffers they own, alive until
+    /// the returned handle has observed their completion
+    pub fn submit_batch<'a>(
+        &mut self,
+        submits: Vec<QueueSubmission<'a>>,
+    ) -> Result<SubmitHandle<'a>, SubmitError> {
+        let fence_info = vk::FenceCreateInfo::builder();
+
+        let fence = unsafe { self.device.device.create_fence(&fence_info, None) }
+            .expect("failed to create fence");
+
+        // The handle is only made once the fence is submitted, since dropping it waits for the fence
+        if let Err(error) = self.submit_tracked(&submits, fence) {
+            unsafe { self.device.device.destroy_fence(fence, None) };
+
+            return Err(error);
+        }
+
+        Ok(SubmitHandle {
+            fence,
+            fence_pool: None,
+            submits,
+            device: Rc::clone(&self.device),
+        })
+    }
+
+    /// Same as `submit_batch`, with a fence taken from `fence_pool` and given back to it when the handle is dropped
+    pub fn submit_batch_with_pool<'a>(
+        &mut self,
+        submits: Vec<QueueSubmission<'a>>,
+        fence_pool: &Rc<FencePool>,
+    ) -> Result<SubmitHandle<'a>, SubmitError> {
+        let fence = fence_pool.acquire();
+
+        // The handle is only made once the fence is submitted, since dropping it waits for the fence
+        if let Err(error) = self.submit_tracked(&submits, fence) {
+            // The fence is still unsignaled, the pool destroys it if the device is lost
+            let _ = fence_pool.release(fence);
+
+            return Err(error);
+        }
+
+        Ok(SubmitHandle {
+            fence,
+            fence_pool: Some(Rc::clone(fence_pool)),
+            submits,
+            device: Rc::clone(&self.device),
+        })
+    }
+
+    /// Transition `image` to `new_layout` with a command buffer of `command_pool` recording only the barrier,
+    /// submitted to this queue and waited for
+    /// On a compute queue, the shader stages of the transition are the compute one, and the transitions
+    /// involving attachments fail
+    pub fn transition_image(
+        &mut self,
+        image: &mut Image,
+        new_layout: vk::ImageLayout,
+        command_pool: &mut Rc<CommandPool>,
+    ) -> Result<(), TransitionError> {
+        let (src_stage_mask, dst_stage_mask, dependency_flags, barrier) =
+            image.layout_transition(new_layout)?;
+
+        // The image is already in this layout
+        if src_stage_mask.is_empty() {
+            return Ok(());
+        }
+
+        let image_memory_barriers = [barrier.build()];
+
+        let family = self.family();
+        let (src_stage_mask, dst_stage_mask) = match (
+            family_stages(family, src_stage_mask),
+            family_stages(family, dst_stage_mask),
+        ) {
+            (Some(src_stage_mask), Some(dst_stage_mask)) => (src_stage_mask, dst_stage_mask),
+            _ => {
+                return Err(TransitionError::UnsupportedStages(
+                    src_stage_mask | dst_stage_mask,
+                ))
+            }
+        };
+
+        command_pool.single_time(self, |recorder| {
+            recorder.as_generic().pipeline_barrier(
+                src_stage_mask,
+                dst_stage_mask,
+                dependency_flags,
+                &[],
+                &[],
+                &image_memory_barriers,
+            );
+        })?;
+
+        // The image keeps its layout if the device was lost before the barrier was executed
+        image.layout = new_layout;
+
+        Ok(())
+    }
+}
+
+/// `stages` with their graphics shader stages replaced by the shader stages of `family` if it doesn't support
+/// graphics operations, `None` if they still contain stages it doesn't support
+fn family_stages(
+    family: &QueueFamily,
+    stages: vk::PipelineStageFlags,
+) -> Option<vk::PipelineStageFlags> {
+    if family.support_graphics() {
+        return Some(stages);
+    }
+
+    let graphics_shaders = vk::PipelineStageFlags::VERTEX_SHADER
+        | vk::PipelineStageFlags::TESSELLATION_CONTROL_SHADER
+        | vk::PipelineStageFlags::TESSELLATION_EVALUATION_SHADER
+        | vk::PipelineStageFlags::GEOMETRY_SHADER
+        | vk::PipelineStageFlags::FRAGMENT_SHADER;
+    let graphics = graphics_shaders
+        | vk::PipelineStageFlags::VERTEX_INPUT
+        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+        | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+        | vk::PipelineStageFlags::ALL_GRAPHICS;
+
+    let mut adapted = stages & !graphics_shaders;
+    if stages.intersects(graphics_shaders) {
+        adapted |= family.shader_stages();
+    }
+
+    if adapted.is_empty() || adapted.intersects(graphics) {
+        None
+    } else {
+        Some(adapted)
+    }
+}
+
+/// Queues of a same family, handed out in turn to spread independent submissions over them
+pub struct QueueGroup {
+    queues: Vec<Queue>,
+    next: usize,
+}
+
+impl QueueGroup {
+    /// `queues` must be of the same family, as the ones `Device::new` returns for a family
+    pub fn new(queues: Vec<Queue>) -> Self {
+        assert!(!queues.is_empty(), "a queue group needs a queue");
+        assert!(
+            queues
+                .iter()
+                .all(|queue| queue.queue_family_index == queues[0].queue_family_index),
+            "the queues of a group must be of the same family"
+        );
+
+        Self { queues, next: 0 }
+    }
+
+    /// The queue following the one returned last, going back to the first after the last queue
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> &mut Queue {
+        let index = self.next;
+        self.next = (self.next + 1) % self.queues.len();
+
+        &mut self.queues[index]
+    }
+
+    /// Submit each batch of `batches` to the next queue, so that they may execute concurrently
+    /// If a submission fails, the handles of the previous batches are dropped and so waited for
+    pub fn submit_all<'a>(
+        &mut self,
+        batches: Vec<Vec<QueueSubmission<'a>>>,
+    ) -> Result<Vec<SubmitHandle<'a>>, SubmitError> {
+        batches
+            .into_iter()
+            .map(|submits| self.next().submit_batch(submits))
+            .collect()
+    }
+
+    /// Wait until every queue of the group is idle
+    pub fn wait_idle(&mut self) -> Result<(), DeviceLost> {
+        self.queues.iter_mut().try_for_each(Queue::wait_idle)
+    }
+
+    pub fn family(&self) -> &QueueFamily {
+        self.queues[0].family()
+    }
+
+    pub fn queues(&self) -> &[Queue] {
+        &self.queues
+    }
+
+    pub fn queues_mut(&mut self) -> &mut [Queue] {
+        &mut self.queues
+    }
+
+    pub fn into_queues(self) -> Vec<Queue> {
+        self.queues
+    }
+}
+
+impl From<Vec<Queue>> for QueueGroup {
+    fn from(queues: Vec<Queue>) -> Self {
+        Self::new(queues)
+    }
+}
+
+/// Handle to a batch of submissions, dropping it waits until the GPU is done with them
+pub struct SubmitHandle<'a> {
+    fence: vk::Fence,
+    fence_pool: Option<Rc<FencePool>>,
+    submits: Vec<QueueSubmission<'a>>,
+    device: Rc<Device>,
+}
+
+impl<'a> SubmitHandle<'a> {
+    pub fn is_complete(&self) -> Result<bool, DeviceLost> {
+        self.device.check_lost(
+            unsafe { self.device.device.get_fence_status(self.fence) },
+            "failed to get fence status",
+        )
+    }
+
+    /// Wait until the submissions are executed, then free the command buffers they own
+    pub fn wait(self) -> Result<(), DeviceLost> {
+        self.wait_for_fence()
+    }
+
+    fn wait_for_fence(&self) -> Result<(), DeviceLost> {
+        self.device.check_lost(
+            unsafe {
+                self.device
+                    .device
+                    .wait_for_fences(&[self.fence], true, u64::MAX)
+            },
+            "failed to wait for fence",
+        )
+    }
+}
+
+impl SubmitHandle<'static> {
+    /// Run `callback` once the submissions are executed and their resources freed, from the first
+    /// `Device::poll` observing it, so on the thread calling `poll`
+    /// The handle keeps the device alive until then, and so does `callback` if it captures an `Rc<Device>`,
+    /// `Device::shutdown` must run the callbacks left before the device can be dropped
+    pub fn on_complete<F: FnOnce() + 'static>(self, callback: F) {
+        let device = Rc::clone(&self.device);
+
+        device.register_completion_callback(self, Box::new(callback));
+    }
+}
+
+impl<'a> Drop for SubmitHandle<'a> {
+    fn drop(&mut self) {
+        // Nothing is executing anymore on a lost device, so the resources can be freed anyway
+        let _ = self.wait_for_fence();
+
+        for submit in &self.submits {
+            submit.mark_complete(self.fence);
+        }
+
+        match &self.fence_pool {
+            // The pool destroys the fence itself if it can't be reset
+            Some(fence_pool) => {
+                let _ = fence_pool.release(self.fence);
+            }
+            None => unsafe {
+                self.device.device.destroy_fence(self.fence, None);
+            },
+        }
+    }
+}
+
+/// Handle to batches of submissions made to several queues by `Device::submit_multi`,
+/// dropping it waits until the GPU is done with all of them
+pub struct MultiSubmitHandle<'a> {
+    fences: Vec<vk::Fence>,
+    submits: Vec<Vec<QueueSubmission<'a>>>,
+    device: Rc<Device>,
+}
+
+impl<'a> MultiSubmitHandle<'a> {
+    pub(crate) fn new(device: Rc<Device>) -> Self {
+        Self {
+            fences: vec![],
+            submits: vec![],
+            device,
+        }
+    }
+
+    pub(crate) fn push(&mut self, fence: vk::Fence, submits: Vec<QueueSubmission<'a>>) {
+        self.fences.push(fence);
+        self.submits.push(submits);
+    }
+
+    /// Whether every batch has been executed
+    pub fn is_complete(&self) -> Result<bool, DeviceLost> {
+        for fence in &self.fences {
+            let complete = self.device.check_lost(
+                unsafe { self.device.device.get_fence_status(*fence) },
+                "failed to get fence status",
+            )?;
+
+            if !complete {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Wait until all the batches are executed, then free the command buffers they own
+    pub fn wait(self) -> Result<(), DeviceLost> {
+        self.wait_for_fences()
+    }
+
+    fn wait_for_fences(&self) -> Result<(), DeviceLost> {
+        if self.fences.is_empty() {
+            return Ok(());
+        }
+
+        self.device.check_lost(
+            unsafe {
+                self.device
+                    .device
+                    .wait_for_fences(&self.fences, true, u64::MAX)
+            },
+            "failed to wait for fences",
+        )
+    }
+}
+
+impl<'a> Drop for MultiSubmitHandle<'a> {
+    fn drop(&mut self) {
+        // Nothing is executing anymore on a lost device, so the resources can be freed anyway
+        let _ = self.wait_for_fences();
+
+        for (fence, submits) in self.fences.iter().zip(&self.submits) {
+            for submit in submits {
+                submit.mark_complete(*fence);
+            }
+        }
+
+        for fence in &self.fences {
+            unsafe {
+                self.device.device.destroy_fence(*fence, None);
+            }
         }
     }
 }
 
+/// Summary of the operations supported by a queue family
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct QueueCapabilities {
+    pub graphics: bool,
+    pub compute: bool,
+    /// Also true for graphics and compute families, which implicitly support transfer operations
+    pub transfer: bool,
+    pub sparse_binding: bool,
+    pub protected: bool,
+    /// Present to the surface given to `QueueFamily::capabilities_for`, always false with `capabilities`
+    pub present: bool,
+}
+
 #[derive(Clone, Copy)]
 pub struct QueueFamily {
     pub(crate) property: vk::QueueFamilyProperties,
@@ -97,6 +593,28 @@ impl QueueFamily {
                 .intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
     }
 
+    /// Shader stages the queues of this family can wait for in their barriers, the graphics ones with
+    /// graphics operations and the compute one with compute operations
+    pub fn shader_stages(&self) -> vk::PipelineStageFlags {
+        let mut stages = vk::PipelineStageFlags::empty();
+
+        if self.support_graphics() {
+            stages |=
+                vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER;
+        }
+
+        if self.support_compute() {
+            stages |= vk::PipelineStageFlags::COMPUTE_SHADER;
+        }
+
+        stages
+    }
+
+    /// Whether queues of this family can present to `surface`, a swapchain needs such a queue
+    pub fn support_present(&self, surface: &Surface) -> bool {
+        surface.supports_present(self.physical_device, self.index)
+    }
+
     pub fn support_sparse_binding(&self) -> bool {
         self.property
             .queue_flags
@@ -109,6 +627,29 @@ impl QueueFamily {
             .contains(vk::QueueFlags::PROTECTED)
     }
 
+    pub fn capabilities(&self) -> QueueCapabilities {
+        let flags = self.property.queue_flags;
+
+        QueueCapabilities {
+            graphics: flags.contains(vk::QueueFlags::GRAPHICS),
+            compute: flags.contains(vk::QueueFlags::COMPUTE),
+            transfer: flags.intersects(
+                vk::QueueFlags::TRANSFER | vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+            ),
+            sparse_binding: flags.contains(vk::QueueFlags::SPARSE_BINDING),
+            protected: flags.contains(vk::QueueFlags::PROTECTED),
+            present: false,
+        }
+    }
+
+    /// `capabilities` including whether queues of this family can present to `surface`
+    pub fn capabilities_for(&self, surface: &Surface) -> QueueCapabilities {
+        QueueCapabilities {
+            present: self.support_present(surface),
+            ..self.capabilities()
+        }
+    }
+
     pub const fn queue_count(&self) -> usize {
         self.property.queue_count as _
     }
@@ -121,7 +662,348 @@ impl QueueFamily {
         self.property.min_image_transfer_granularity
     }
 
+    /// Whether an image region at `offset` of `extent` in a subresource of `image_extent` can be copied
+    /// by queues of this family, see `align_image_region`
+    pub fn is_image_region_aligned(
+        &self,
+        offset: vk::Offset3D,
+        extent: vk::Extent3D,
+        image_extent: vk::Extent3D,
+    ) -> Result<bool, CopyError> {
+        let (aligned_offset, aligned_extent) =
+            self.align_image_region(offset, extent, image_extent)?;
+
+        Ok((aligned_offset, aligned_extent) == (offset, extent))
+    }
+
+    /// Grow an image region at `offset` of `extent` in a subresource of `image_extent` so that it can be copied
+    /// by queues of this family: its offset must be a multiple of `min_image_transfer_granularity` and so must be
+    /// its extent, unless it reaches the end of the subresource, while a granularity of 0 only allows whole subresources
+    /// Graphics and compute families have a granularity of 1, so their regions are never grown
+    /// A region with a negative offset or going past the end of the subresource is `CopyError::OutOfBounds`
+    pub fn align_image_region(
+        &self,
+        offset: vk::Offset3D,
+        extent: vk::Extent3D,
+        image_extent: vk::Extent3D,
+    ) -> Result<(vk::Offset3D, vk::Extent3D), CopyError> {
+        let granularity = self.min_image_transfer_granularity();
+
+        let (x, width) = align_axis(
+            offset.x,
+            extent.width,
+            image_extent.width,
+            granularity.width,
+        )
+        .ok_or(CopyError::OutOfBounds)?;
+        let (y, height) = align_axis(
+            offset.y,
+            extent.height,
+            image_extent.height,
+            granularity.height,
+        )
+        .ok_or(CopyError::OutOfBounds)?;
+        let (z, depth) = align_axis(
+            offset.z,
+            extent.depth,
+            image_extent.depth,
+            granularity.depth,
+        )
+        .ok_or(CopyError::OutOfBounds)?;
+
+        Ok((
+            vk::Offset3D { x, y, z },
+            vk::Extent3D {
+                width,
+                height,
+                depth,
+            },
+        ))
+    }
+
     pub const fn index(&self) -> usize {
         self.index
     }
 }
+
+/// Align a range of texels along an axis of `image_len` texels to `granularity`,
+/// `None` if the range isn't in the axis
+fn align_axis(offset: i32, len: u32, image_len: u32, granularity: u32) -> Option<(i32, u32)> {
+    if offset < 0 {
+        return None;
+    }
+
+    let offset = offset as u32;
+    let end = offset.checked_add(len).filter(|&end| end <= image_len)?;
+
+    if granularity == 0 {
+        return Some((0, image_len));
+    }
+
+    let start = offset / granularity * granularity;
+    let end = match end % granularity {
+        0 => end,
+        remainder => end.saturating_add(granularity - remainder).min(image_len),
+    };
+
+    Some((start as i32, end - start))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils;
+    use crate::vulkan::{CommandPool, Instance};
+
+    fn family_with(queue_flags: vk::QueueFlags) -> QueueFamily {
+        QueueFamily {
+            property: vk::QueueFamilyProperties::builder()
+                .queue_flags(queue_flags)
+                .build(),
+            index: 0,
+            physical_device: vk::PhysicalDevice::null(),
+        }
+    }
+
+    #[test]
+    fn transition_stages_of_compute_families() {
+        let graphics = family_with(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE);
+        let compute = family_with(vk::QueueFlags::COMPUTE);
+        let transfer = family_with(vk::QueueFlags::TRANSFER);
+
+        assert_eq!(
+            family_stages(&graphics, vk::PipelineStageFlags::FRAGMENT_SHADER),
+            Some(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        );
+        assert_eq!(
+            family_stages(&compute, vk::PipelineStageFlags::FRAGMENT_SHADER),
+            Some(vk::PipelineStageFlags::COMPUTE_SHADER)
+        );
+        assert_eq!(
+            family_stages(
+                &compute,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER
+            ),
+            None
+        );
+        assert_eq!(
+            family_stages(&compute, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT),
+            None
+        );
+        assert_eq!(
+            family_stages(&transfer, vk::PipelineStageFlags::TRANSFER),
+            Some(vk::PipelineStageFlags::TRANSFER)
+        );
+        assert_eq!(
+            family_stages(&transfer, vk::PipelineStageFlags::FRAGMENT_SHADER),
+            None
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn queue_group_round_robin() {
+        let instance = Rc::new(Instance::new_or_panic());
+        let (_device, mut queues) = Device::new(
+            |queue_family, _| {
+                if queue_family.support_compute() {
+                    Some(QueueCreateInfo::new(vec![
+                        1.0;
+                        queue_family.queue_count().min(2)
+                    ]))
+                } else {
+                    None
+                }
+            },
+            instance,
+        )
+        .unwrap();
+
+        let mut group = QueueGroup::new(queues.swap_remove(0));
+        let count = group.queues().len();
+
+        let indices = (0..2 * count)
+            .map(|_| group.next().queue_index)
+            .collect::<Vec<_>>();
+        assert_eq!(indices, (0..2).flat_map(|_| 0..count).collect::<Vec<_>>());
+
+        let handles = group
+            .submit_all(
+                (0..3)
+                    .map(|_| vec![QueueSubmission::builder().build()])
+                    .collect(),
+            )
+            .unwrap();
+        assert_eq!(handles.len(), 3);
+        group.wait_idle().unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn submit_multi_waits_for_every_queue() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let command_buffers = command_pool
+            .allocate_command_buffers(vk::CommandBufferLevel::PRIMARY, 2)
+            .into_iter()
+            .map(|command_buffer| {
+                command_buffer
+                    .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+                    .end()
+            })
+            .collect::<Vec<_>>();
+
+        let handle = device
+            .submit_multi(vec![(
+                &mut queue,
+                command_buffers
+                    .iter()
+                    .map(|command_buffer| {
+                        QueueSubmission::builder()
+                            .with_command_buffer(command_buffer)
+                            .build()
+                    })
+                    .collect(),
+            )])
+            .unwrap();
+
+        handle.wait().unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn completion_callback_runs_on_poll() {
+        use std::cell::Cell;
+
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let completed = Rc::new(Cell::new(false));
+
+        {
+            let completed = Rc::clone(&completed);
+            queue
+                .submit_batch(vec![])
+                .unwrap()
+                .on_complete(move || completed.set(true));
+        }
+
+        while !completed.get() {
+            device.poll().unwrap();
+        }
+
+        assert_eq!(device.poll(), Ok(0));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn shutdown_releases_the_completion_callbacks() {
+        use std::cell::Cell;
+
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let completed = Rc::new(Cell::new(false));
+        let device_count = Rc::strong_count(&device);
+
+        {
+            let completed = Rc::clone(&completed);
+            let callback_device = Rc::clone(&device);
+            queue
+                .submit_batch(vec![])
+                .unwrap()
+                .on_complete(move || completed.set(!callback_device.is_lost()));
+        }
+        // The registered handle and the callback hold the device
+        assert_eq!(Rc::strong_count(&device), device_count + 2);
+
+        device.shutdown().unwrap();
+
+        assert!(completed.get());
+        assert_eq!(Rc::strong_count(&device), device_count);
+    }
+
+    #[test]
+    fn image_regions_grow_to_granularity() {
+        let family = |granularity| QueueFamily {
+            property: vk::QueueFamilyProperties::builder()
+                .queue_flags(vk::QueueFlags::TRANSFER)
+                .min_image_transfer_granularity(granularity)
+                .build(),
+            index: 0,
+            physical_device: vk::PhysicalDevice::null(),
+        };
+        let extent = |width, height| vk::Extent3D {
+            width,
+            height,
+            depth: 1,
+        };
+        let offset = |x, y| vk::Offset3D { x, y, z: 0 };
+        let image_extent = extent(100, 50);
+
+        let transfer_family = family(extent(16, 16));
+        assert_eq!(
+            transfer_family.align_image_region(offset(20, 0), extent(10, 50), image_extent),
+            Ok((offset(16, 0), extent(16, 50)))
+        );
+        // Regions reaching the end of the image don't need an aligned extent
+        assert_eq!(
+            transfer_family.is_image_region_aligned(offset(96, 48), extent(4, 2), image_extent),
+            Ok(true)
+        );
+        assert_eq!(
+            transfer_family.is_image_region_aligned(offset(0, 0), extent(10, 10), image_extent),
+            Ok(false)
+        );
+        // Regions out of the image aren't aligned, even to a granularity of 0
+        for &(region_offset, region_extent) in &[
+            (offset(-16, 0), extent(10, 10)),
+            (offset(110, 0), extent(10, 10)),
+            (offset(96, 0), extent(8, 10)),
+            (offset(i32::MAX, 0), extent(u32::MAX, 10)),
+        ] {
+            assert_eq!(
+                transfer_family.align_image_region(region_offset, region_extent, image_extent),
+                Err(CopyError::OutOfBounds)
+            );
+            assert_eq!(
+                family(extent(0, 0)).is_image_region_aligned(
+                    region_offset,
+                    region_extent,
+                    image_extent
+                ),
+                Err(CopyError::OutOfBounds)
+            );
+        }
+
+        let whole_family = family(extent(0, 0));
+        assert_eq!(
+            whole_family.align_image_region(offset(20, 10), extent(10, 10), image_extent),
+            Ok((offset(0, 0), image_extent))
+        );
+
+        assert_eq!(
+            family(extent(1, 1)).is_image_region_aligned(offset(3, 7), extent(5, 9), image_extent),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn capabilities_of_compute_family() {
+        let family = QueueFamily {
+            property: vk::QueueFamilyProperties::builder()
+                .queue_flags(vk::QueueFlags::COMPUTE | vk::QueueFlags::SPARSE_BINDING)
+                .build(),
+            index: 0,
+            physical_device: vk::PhysicalDevice::null(),
+        };
+
+        assert_eq!(
+            family.capabilities(),
+            QueueCapabilities {
+                compute: true,
+                transfer: true,
+                sparse_binding: true,
+                ..QueueCapabilities::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use std::rc::Rc;
+
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+use super::{Device, Image};
+
+/// A render pass built by `RenderPassBuilder`
+pub struct RenderPass {
+    pub handle: vk::RenderPass,
+    attachment_count: usize,
+    device: Rc<Device>,
+}
+
+impl RenderPass {
+    pub fn attachment_count(&self) -> usize {
+        self.attachment_count
+    }
+
+    /// Begin info of an instance of the render pass drawing into `framebuffer`, to give to
+    /// `GraphicsCommandBuffer::renderpass`
+    /// `clear_values` are indexed by attachment, only the attachments cleared on load need a meaningful value
+    pub fn begin_info<'a>(
+        &self,
+        framebuffer: &Framebuffer,
+        render_area: vk::Rect2D,
+        clear_values: &'a [vk::ClearValue],
+    ) -> vk::RenderPassBeginInfoBuilder<'a> {
+        assert_eq!(
+            framebuffer.render_pass, self.handle,
+            "the framebuffer wasn't created for this render pass"
+        );
+        assert!(
+            clear_values.is_empty() || clear_values.len() == self.attachment_count,
+            "there must be one clear value per attachment"
+        );
+
+        vk::RenderPassBeginInfo::builder()
+            .render_pass(self.handle)
+            .framebuffer(framebuffer.handle)
+            .render_area(render_area)
+            .clear_values(clear_values)
+    }
+}
+
+impl Drop for RenderPass {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device.destroy_render_pass(self.handle, None);
+        }
+    }
+}
+
+/// Attachments used by a subpass, referenced by index in the attachments of the render pass
+struct SubpassAttachments {
+    input: Vec<vk::AttachmentReference>,
+    color: Vec<vk::AttachmentReference>,
+    depth_stencil: Option<vk::AttachmentReference>,
+}
+
+/// Builder of a `RenderPass` for the graphics bind point, which needs at least one subpass
+pub struct RenderPassBuilder<'a> {
+    attachments: Vec<vk::AttachmentDescription>,
+    subpasses: Vec<SubpassAttachments>,
+    dependencies: Vec<vk::SubpassDependency>,
+    name: Option<&'a str>,
+}
+
+impl<'a> RenderPassBuilder<'a> {
+    pub fn new() -> Self {
+        Self {
+            attachments: vec![],
+            subpasses: vec![],
+            dependencies: vec![],
+            name: None,
+        }
+    }
+
+    /// Add an attachment, referenced by the subpasses with its index in the order of the calls
+    pub fn attachment(mut self, attachment: vk::AttachmentDescription) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    /// Single sampled color attachment cleared on load and stored, left in `final_layout`
+    pub fn color_attachment(self, format: vk::Format, final_layout: vk::ImageLayout) -> Self {
+        self.attachment(
+            vk::AttachmentDescription::builder()
+                .format(format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(final_layout)
+                .build(),
+        )
+    }
+
+    /// Add a subpass, the subpasses are executed in the order of the calls
+    pub fn subpass(
+        mut self,
+        input_attachments: &[vk::AttachmentReference],
+        color_attachments: &[vk::AttachmentReference],
+        depth_stencil_attachment: Option<vk::AttachmentReference>,
+    ) -> Self {
+        self.subpasses.push(SubpassAttachments {
+            input: input_attachments.to_vec(),
+            color: color_attachments.to_vec(),
+            depth_stencil: depth_stencil_attachment,
+        });
+        self
+    }
+
+    /// Dependency between two subpasses, or between a subpass and the commands around the render pass
+    /// with `vk::SUBPASS_EXTERNAL`
+    pub fn dependency(mut self, dependency: vk::SubpassDependency) -> Self {
+        self.dependencies.push(dependency);
+        self
+    }
+
+    /// Name given to the render pass for debugging when the `validation-layers` feature is enabled
+    pub fn name(mut self, name: &'a str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn build(&self, device: Rc<Device>) -> RenderPass {
+        assert!(!self.subpasses.is_empty(), "a render pass needs a subpass");
+
+        let attachment_count = self.attachments.len();
+        let is_valid = |reference: &vk::AttachmentReference| {
+            reference.attachment == vk::ATTACHMENT_UNUSED
+                || (reference.attachment as usize) < attachment_count
+        };
+        assert!(
+            self.subpasses.iter().all(|subpass| subpass
+                .input
+                .iter()
+                .chain(&subpass.color)
+                .chain(&subpass.depth_stencil)
+                .all(is_valid)),
+            "a subpass references an attachment the render pass doesn't have"
+        );
+
+        let subpasses = self
+            .subpasses
+            .iter()
+            .map(|subpass| {
+                let mut description = vk::SubpassDescription::builder()
+                    .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                    .input_attachments(&subpass.input)
+                    .color_attachments(&subpass.color);
+
+                if let Some(depth_stencil) = &subpass.depth_stencil {
+                    description = description.depth_stencil_attachment(depth_stencil);
+                }
+
+                description.build()
+            })
+            .collect::<Vec<_>>();
+
+        let create_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&self.attachments)
+            .subpasses(&subpasses)
+            .dependencies(&self.dependencies);
+
+        let handle = unsafe { device.device.create_render_pass(&create_info, None) }
+            .expect("failed to create render pass!");
+
+        if let Some(name) = self.name {
+            device.set_object_name(handle, name);
+        }
+
+        RenderPass {
+            handle,
+            attachment_count,
+            device,
+        }
+    }
+}
+
+impl<'a> Default for RenderPassBuilder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Attachments of a render pass instance, which must outlive the framebuffer
+pub struct Framebuffer {
+    pub handle: vk::Framebuffer,
+    pub extent: vk::Extent2D,
+    render_pass: vk::RenderPass,
+    device: Rc<Device>,
+}
+
+impl Framebuffer {
+    /// `attachments` are the images of the attachments of `render_pass` in order, each at least `extent` large
+    pub fn new(
+        render_pass: &RenderPass,
+        attachments: &[&Image],
+        extent: vk::Extent2D,
+        device: Rc<Device>,
+    ) -> Self {
+        assert!(
+            attachments
+                .iter()
+                .all(|image| image.extent.width >= extent.width
+                    && image.extent.height >= extent.height),
+            "an attachment is smaller than the framebuffer"
+        );
+
+        let views = attachments
+            .iter()
+            .map(|image| image.view)
+            .collect::<Vec<_>>();
+
+        Self::from_views(render_pass, &views, extent, device)
+    }
+
+    /// Same as `new` with the views of the attachments, such as the views of the images of a swapchain
+    pub fn from_views(
+        render_pass: &RenderPass,
+        views: &[vk::ImageView],
+        extent: vk::Extent2D,
+        device: Rc<Device>,
+    ) -> Self {
+        assert_eq!(
+            views.len(),
+            render_pass.attachment_count,
+            "a framebuffer needs a view per attachment of the render pass"
+        );
+
+        let create_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass.handle)
+            .attachments(views)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+
+        let handle = unsafe { device.device.create_framebuffer(&create_info, None) }
+            .expect("failed to create framebuffer!");
+
+        Self {
+            handle,
+            extent,
+            render_pass: render_pass.handle,
+            device,
+        }
+    }
+
+    /// Area covering the whole framebuffer
+    pub fn render_area(&self) -> vk::Rect2D {
+        vk::Rect2D::builder().extent(self.extent).build()
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device.destroy_framebuffer(self.handle, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils;
+    use crate::vulkan::ImageBuilder;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn framebuffer_begin_info() {
+        let (instance, device, _) = utils::create_test_device();
+
+        let format = vk::Format::R8G8B8A8_UNORM;
+        let render_pass = RenderPassBuilder::new()
+            .color_attachment(format, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .subpass(
+                &[],
+                &[vk::AttachmentReference::builder()
+                    .attachment(0)
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .build()],
+                None,
+            )
+            .build(Rc::clone(&device));
+
+        let image = ImageBuilder::new(16, 16, format, vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .build(Rc::clone(&device), &instance);
+        let framebuffer = Framebuffer::new(
+            &render_pass,
+            &[&image],
+            vk::Extent2D {
+                width: 16,
+                height: 16,
+            },
+            device,
+        );
+
+        let clear_values = [vk::ClearValue::default()];
+        let begin_info =
+            render_pass.begin_info(&framebuffer, framebuffer.render_area(), &clear_values);
+
+        assert_eq!(begin_info.framebuffer, framebuffer.handle);
+        assert_eq!(begin_info.clear_value_count, 1);
+        assert_eq!(begin_info.render_area.extent.width, 16);
+    }
+}
@@ -0,0 +1,124 @@
+use std::rc::Rc;
+
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+use super::Device;
+
+pub struct Sampler {
+    pub handle: vk::Sampler,
+    device: Rc<Device>,
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device.destroy_sampler(self.handle, None);
+        }
+    }
+}
+
+/// Builder of a `Sampler`, by default with linear filtering and mipmapping over all the levels,
+/// repeating the texture in every direction, without anisotropy nor depth comparison
+pub struct SamplerBuilder {
+    mag_filter: vk::Filter,
+    min_filter: vk::Filter,
+    mipmap_mode: vk::SamplerMipmapMode,
+    address_modes: [vk::SamplerAddressMode; 3],
+    max_anisotropy: Option<f32>,
+    compare_op: Option<vk::CompareOp>,
+}
+
+impl SamplerBuilder {
+    pub fn new() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_modes: [vk::SamplerAddressMode::REPEAT; 3],
+            max_anisotropy: None,
+            compare_op: None,
+        }
+    }
+
+    /// Filters used when the texture is magnified and minified
+    pub fn filters(mut self, mag_filter: vk::Filter, min_filter: vk::Filter) -> Self {
+        self.mag_filter = mag_filter;
+        self.min_filter = min_filter;
+        self
+    }
+
+    pub fn mipmap_mode(mut self, mipmap_mode: vk::SamplerMipmapMode) -> Self {
+        self.mipmap_mode = mipmap_mode;
+        self
+    }
+
+    /// Address mode of the U, V and W coordinates
+    pub fn address_modes(
+        mut self,
+        u: vk::SamplerAddressMode,
+        v: vk::SamplerAddressMode,
+        w: vk::SamplerAddressMode,
+    ) -> Self {
+        self.address_modes = [u, v, w];
+        self
+    }
+
+    /// Same address mode for all the coordinates
+    pub fn address_mode(self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_modes(address_mode, address_mode, address_mode)
+    }
+
+    /// Enable anisotropic filtering, the `sampler_anisotropy` feature must be enabled
+    /// `max_anisotropy` is clamped to the limit of the device
+    pub fn anisotropy(mut self, max_anisotropy: f32) -> Self {
+        self.max_anisotropy = Some(max_anisotropy);
+        self
+    }
+
+    /// Compare the fetched texels to a reference value with `compare_op`, as for shadow maps
+    pub fn compare_op(mut self, compare_op: vk::CompareOp) -> Self {
+        self.compare_op = Some(compare_op);
+        self
+    }
+
+    pub fn build(&self, device: Rc<Device>) -> Sampler {
+        let [address_mode_u, address_mode_v, address_mode_w] = self.address_modes;
+
+        let mut create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(self.mag_filter)
+            .min_filter(self.min_filter)
+            .mipmap_mode(self.mipmap_mode)
+            .address_mode_u(address_mode_u)
+            .address_mode_v(address_mode_v)
+            .address_mode_w(address_mode_w)
+            .min_lod(0.0)
+            .max_lod(vk::LOD_CLAMP_NONE);
+
+        if let Some(max_anisotropy) = self.max_anisotropy {
+            assert!(
+                device.is_feature_enabled(|features| features.sampler_anisotropy),
+                "anisotropic filtering requires the sampler_anisotropy feature"
+            );
+
+            create_info = create_info.anisotropy_enable(true).max_anisotropy(
+                max_anisotropy.min(device.properties().limits.max_sampler_anisotropy),
+            );
+        }
+
+        if let Some(compare_op) = self.compare_op {
+            create_info = create_info.compare_enable(true).compare_op(compare_op);
+        }
+
+        let handle = unsafe { device.device.create_sampler(&create_info, None) }
+            .expect("failed to create sampler!");
+
+        Sampler { handle, device }
+    }
+}
+
+impl Default for SamplerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
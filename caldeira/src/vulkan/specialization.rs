@@ -0,0 +1,112 @@
+use std::mem;
+use std::slice;
+
+use ash::vk;
+
+use super::ByteCopiable;
+
+/// Values given to the specialization constants of a shader when a pipeline is created,
+/// each constant is identified by its `constant_id`
+#[derive(Clone, Debug, Default)]
+pub struct SpecializationConstants {
+    map_entries: Vec<vk::SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+impl SpecializationConstants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the constant `constant_id` to `value`, which must be a scalar of the type of the constant,
+    /// boolean constants are given as `vk::Bool32`
+    pub fn with<T: ByteCopiable + Copy>(mut self, constant_id: u32, value: T) -> Self {
+        let size = mem::size_of::<T>();
+
+        assert!(
+            matches!(size, 1 | 2 | 4 | 8),
+            "a specialization constant must be a scalar"
+        );
+        assert!(
+            self.map_entries
+                .iter()
+                .all(|entry| entry.constant_id != constant_id),
+            "specialization constant {} is set twice",
+            constant_id
+        );
+
+        self.map_entries.push(
+            vk::SpecializationMapEntry::builder()
+                .constant_id(constant_id)
+                .offset(self.data.len() as _)
+                .size(size)
+                .build(),
+        );
+
+        let bytes = unsafe { slice::from_raw_parts(&value as *const T as *const u8, size) };
+        self.data.extend_from_slice(bytes);
+
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map_entries.is_empty()
+    }
+
+    /// Specialization info pointing to the constants, to give to a shader stage
+    pub fn info(&self) -> vk::SpecializationInfoBuilder<'_> {
+        vk::SpecializationInfo::builder()
+            .map_entries(&self.map_entries)
+            .data(&self.data)
+    }
+
+    /// Whether the constant `constant_id` is set, whatever its size
+    pub(crate) fn contains(&self, constant_id: u32) -> bool {
+        self.map_entries
+            .iter()
+            .any(|entry| entry.constant_id == constant_id)
+    }
+
+    /// Value of the constant `constant_id` if it's a 32 bits one
+    pub(crate) fn u32_value(&self, constant_id: u32) -> Option<u32> {
+        let entry = self
+            .map_entries
+            .iter()
+            .find(|entry| entry.constant_id == constant_id && entry.size == 4)?;
+        let offset = entry.offset as usize;
+
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&self.data[offset..offset + 4]);
+
+        Some(u32::from_ne_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn constants_are_packed() {
+        let constants = SpecializationConstants::new()
+            .with(0, 64u32)
+            .with(3, 0.5f64)
+            .with(1, vk::TRUE);
+
+        let info = constants.info();
+        let entries = constants
+            .map_entries
+            .iter()
+            .map(|entry| (entry.constant_id, entry.offset, entry.size))
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries, vec![(0, 0, 4), (3, 4, 8), (1, 12, 4)]);
+        assert_eq!(info.data_size, 16);
+        assert_eq!(constants.u32_value(0), Some(64));
+        assert_eq!(constants.u32_value(1), Some(vk::TRUE));
+        assert_eq!(constants.u32_value(3), None);
+        assert_eq!(constants.u32_value(2), None);
+        assert!(constants.contains(3));
+        assert!(!constants.contains(2));
+    }
+}
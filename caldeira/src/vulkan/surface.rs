@@ -0,0 +1,427 @@
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+use ash::extensions::khr::Surface as SurfaceLoader;
+use ash::prelude::VkResult;
+use ash::vk;
+use winit::window::Window as WinitWindow;
+
+use super::{Device, Instance};
+
+/// Surface of a window, which a `Swapchain` presents images to
+pub struct Surface {
+    pub handle: vk::SurfaceKHR,
+    loader: SurfaceLoader,
+    /// Kept alive until the surface is destroyed
+    _instance: Rc<Instance>,
+}
+
+impl Surface {
+    /// Create a surface for `window` with the platform surface extension enabled by the instance,
+    /// only Win32, Xlib and Wayland windows are supported, and a headless instance has no surface extension
+    pub fn new(window: &WinitWindow, instance: Rc<Instance>) -> VkResult<Self> {
+        if instance.is_headless() {
+            return Err(vk::Result::ERROR_EXTENSION_NOT_PRESENT);
+        }
+
+        let handle = unsafe { Self::create_platform_surface(window, &instance) }?;
+        let loader = SurfaceLoader::new(&instance.entry, &instance.instance);
+
+        Ok(Self {
+            handle,
+            loader,
+            _instance: instance,
+        })
+    }
+
+    #[cfg(windows)]
+    unsafe fn create_platform_surface(
+        window: &WinitWindow,
+        instance: &Instance,
+    ) -> VkResult<vk::SurfaceKHR> {
+        use ash::extensions::khr::Win32Surface;
+        use winit::platform::windows::WindowExtWindows;
+
+        let create_info = vk::Win32SurfaceCreateInfoKHR::builder()
+            .hinstance(window.hinstance() as _)
+            .hwnd(window.hwnd() as _);
+
+        Win32Surface::new(&instance.entry, &instance.instance)
+            .create_win32_surface(&create_info, None)
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))]
+    unsafe fn create_platform_surface(
+        window: &WinitWindow,
+        instance: &Instance,
+    ) -> VkResult<vk::SurfaceKHR> {
+        use ash::extensions::khr::{WaylandSurface, XlibSurface};
+        use winit::platform::unix::WindowExtUnix;
+
+        if let (Some(display), Some(surface)) = (window.wayland_display(), window.wayland_surface())
+        {
+            if !instance.is_extension_enabled("VK_KHR_wayland_surface") {
+                return Err(vk::Result::ERROR_EXTENSION_NOT_PRESENT);
+            }
+
+            let create_info = vk::WaylandSurfaceCreateInfoKHR::builder()
+                .display(display)
+                .surface(surface);
+
+            return WaylandSurface::new(&instance.entry, &instance.instance)
+                .create_wayland_surface(&create_info, None);
+        }
+
+        let (display, xlib_window) = match (window.xlib_display(), window.xlib_window()) {
+            (Some(display), Some(xlib_window)) => (display, xlib_window),
+            _ => return Err(vk::Result::ERROR_EXTENSION_NOT_PRESENT),
+        };
+
+        if !instance.is_extension_enabled("VK_KHR_xlib_surface") {
+            return Err(vk::Result::ERROR_EXTENSION_NOT_PRESENT);
+        }
+
+        let create_info = vk::XlibSurfaceCreateInfoKHR::builder()
+            .dpy(display as _)
+            .window(xlib_window as _);
+
+        XlibSurface::new(&instance.entry, &instance.instance)
+            .create_xlib_surface(&create_info, None)
+    }
+
+    #[cfg(not(any(
+        windows,
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    )))]
+    unsafe fn create_platform_surface(
+        _window: &WinitWindow,
+        _instance: &Instance,
+    ) -> VkResult<vk::SurfaceKHR> {
+        Err(vk::Result::ERROR_EXTENSION_NOT_PRESENT)
+    }
+
+    pub fn capabilities(&self, device: &Device) -> VkResult<vk::SurfaceCapabilitiesKHR> {
+        unsafe {
+            self.loader
+                .get_physical_device_surface_capabilities(device.physical_device, self.handle)
+        }
+    }
+
+    pub fn formats(&self, device: &Device) -> VkResult<Vec<vk::SurfaceFormatKHR>> {
+        unsafe {
+            self.loader
+                .get_physical_device_surface_formats(device.physical_device, self.handle)
+        }
+    }
+
+    pub fn present_modes(&self, device: &Device) -> VkResult<Vec<vk::PresentModeKHR>> {
+        unsafe {
+            self.loader
+                .get_physical_device_surface_present_modes(device.physical_device, self.handle)
+        }
+    }
+
+    /// Whether queues of the family `queue_family_index` of `physical_device` can present to the surface
+    pub(crate) fn supports_present(
+        &self,
+        physical_device: vk::PhysicalDevice,
+        queue_family_index: usize,
+    ) -> bool {
+        unsafe {
+            self.loader.get_physical_device_surface_support(
+                physical_device,
+                queue_family_index as _,
+                self.handle,
+            )
+        }
+        .expect("failed to query surface support")
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        unsafe {
+            self.loader.destroy_surface(self.handle, None);
+        }
+    }
+}
+
+/// Instance extensions to create surfaces on the target platform, `VK_KHR_surface` and the platform ones
+/// of `platform_surface_extensions`, enabled by non headless instances
+/// `None` if the platform has no surface support, only headless instances can be created there
+pub fn surface_extensions() -> Option<Vec<&'static str>> {
+    let mut extensions = vec!["VK_KHR_surface"];
+    extensions.extend(platform_surface_extensions()?);
+
+    Some(extensions)
+}
+
+/// Extensions creating surfaces for the windows of the target platform, those the driver lists are enabled
+/// and at least one is required
+/// On Linux and the BSDs, winit opens either Wayland or X11 windows, so both extensions are enabled if available
+/// `None` on the other platforms, since `Surface::new` doesn't support them
+pub(crate) fn platform_surface_extensions() -> Option<&'static [&'static str]> {
+    if cfg!(windows) {
+        Some(&["VK_KHR_win32_surface"])
+    } else if cfg!(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    )) {
+        Some(&["VK_KHR_wayland_surface", "VK_KHR_xlib_surface"])
+    } else {
+        None
+    }
+}
+
+/// Composite alpha modes tried in order when none is requested
+const COMPOSITE_ALPHA_PREFERENCES: [vk::CompositeAlphaFlagsKHR; 4] = [
+    vk::CompositeAlphaFlagsKHR::OPAQUE,
+    vk::CompositeAlphaFlagsKHR::INHERIT,
+    vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+    vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SurfaceCompositionError {
+    /// The surface doesn't support the requested pre-transform
+    UnsupportedTransform(vk::SurfaceTransformFlagsKHR),
+    /// The surface doesn't support the requested composite alpha mode
+    UnsupportedCompositeAlpha(vk::CompositeAlphaFlagsKHR),
+    /// The requested pre-transform isn't exactly one transform
+    NotSingleTransform(vk::SurfaceTransformFlagsKHR),
+    /// The requested composite alpha isn't exactly one mode
+    NotSingleCompositeAlpha(vk::CompositeAlphaFlagsKHR),
+}
+
+impl fmt::Display for SurfaceCompositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedTransform(transform) => {
+                write!(
+                    f,
+                    "The surface doesn't support the transform {:?}!",
+                    transform
+                )
+            }
+            Self::UnsupportedCompositeAlpha(composite_alpha) => write!(
+                f,
+                "The surface doesn't support the composite alpha {:?}!",
+                composite_alpha
+            ),
+            Self::NotSingleTransform(transform) => {
+                write!(f, "{:?} isn't a single transform!", transform)
+            }
+            Self::NotSingleCompositeAlpha(composite_alpha) => write!(
+                f,
+                "{:?} isn't a single composite alpha mode!",
+                composite_alpha
+            ),
+        }
+    }
+}
+
+impl Error for SurfaceCompositionError {}
+
+/// Pre-transform and composite alpha a swapchain is created with, chosen among the ones supported by its surface
+/// By default, the current transform of the surface and the first supported of `OPAQUE`, `INHERIT`,
+/// `PRE_MULTIPLIED` and `POST_MULTIPLIED`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SurfaceComposition {
+    pre_transform: vk::SurfaceTransformFlagsKHR,
+    composite_alpha: vk::CompositeAlphaFlagsKHR,
+    supported_transforms: vk::SurfaceTransformFlagsKHR,
+    supported_composite_alpha: vk::CompositeAlphaFlagsKHR,
+}
+
+impl SurfaceComposition {
+    /// Query the capabilities of `surface` on the physical device of `device` and choose the defaults
+    pub fn query(surface: vk::SurfaceKHR, device: &Device) -> VkResult<Self> {
+        let instance = device.instance();
+        let surface_loader = SurfaceLoader::new(&instance.entry, &instance.instance);
+
+        let capabilities = unsafe {
+            surface_loader.get_physical_device_surface_capabilities(device.physical_device, surface)
+        }?;
+
+        Ok(Self::from_capabilities(&capabilities))
+    }
+
+    pub fn from_capabilities(capabilities: &vk::SurfaceCapabilitiesKHR) -> Self {
+        let composite_alpha = COMPOSITE_ALPHA_PREFERENCES
+            .iter()
+            .copied()
+            .find(|&composite_alpha| {
+                capabilities
+                    .supported_composite_alpha
+                    .contains(composite_alpha)
+            })
+            // At least one mode is always supported
+            .unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE);
+
+        Self {
+            pre_transform: capabilities.current_transform,
+            composite_alpha,
+            supported_transforms: capabilities.supported_transforms,
+            supported_composite_alpha: capabilities.supported_composite_alpha,
+        }
+    }
+
+    /// Override the pre-transform, it must be a single transform supported by the surface
+    pub fn with_pre_transform(
+        mut self,
+        pre_transform: vk::SurfaceTransformFlagsKHR,
+    ) -> Result<Self, SurfaceCompositionError> {
+        if pre_transform.as_raw().count_ones() != 1 {
+            return Err(SurfaceCompositionError::NotSingleTransform(pre_transform));
+        }
+
+        if !self.supported_transforms.contains(pre_transform) {
+            return Err(SurfaceCompositionError::UnsupportedTransform(pre_transform));
+        }
+
+        self.pre_transform = pre_transform;
+        Ok(self)
+    }
+
+    /// Override the composite alpha mode, it must be a single mode supported by the surface
+    pub fn with_composite_alpha(
+        mut self,
+        composite_alpha: vk::CompositeAlphaFlagsKHR,
+    ) -> Result<Self, SurfaceCompositionError> {
+        if composite_alpha.as_raw().count_ones() != 1 {
+            return Err(SurfaceCompositionError::NotSingleCompositeAlpha(
+                composite_alpha,
+            ));
+        }
+
+        if !self.supported_composite_alpha.contains(composite_alpha) {
+            return Err(SurfaceCompositionError::UnsupportedCompositeAlpha(
+                composite_alpha,
+            ));
+        }
+
+        self.composite_alpha = composite_alpha;
+        Ok(self)
+    }
+
+    pub fn pre_transform(&self) -> vk::SurfaceTransformFlagsKHR {
+        self.pre_transform
+    }
+
+    pub fn composite_alpha(&self) -> vk::CompositeAlphaFlagsKHR {
+        self.composite_alpha
+    }
+
+    /// Set the pre-transform and composite alpha of a swapchain create info
+    pub fn apply<'a>(
+        &self,
+        create_info: vk::SwapchainCreateInfoKHRBuilder<'a>,
+    ) -> vk::SwapchainCreateInfoKHRBuilder<'a> {
+        create_info
+            .pre_transform(self.pre_transform)
+            .composite_alpha(self.composite_alpha)
+    }
+}
+
+/// Number of swapchain images to create for a surface with `capabilities`, `requested` or by default `min_image_count + 1`
+/// for triple buffering, clamped into the range supported by the surface, whose maximum is unbounded when it is 0
+pub fn clamp_image_count(capabilities: &vk::SurfaceCapabilitiesKHR, requested: Option<u32>) -> u32 {
+    let image_count = requested
+        .unwrap_or(capabilities.min_image_count + 1)
+        .max(capabilities.min_image_count);
+
+    match capabilities.max_image_count {
+        0 => image_count,
+        max_image_count => image_count.min(max_image_count),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn composition_defaults_and_overrides() {
+        let capabilities = vk::SurfaceCapabilitiesKHR::builder()
+            .current_transform(vk::SurfaceTransformFlagsKHR::ROTATE_90)
+            .supported_transforms(
+                vk::SurfaceTransformFlagsKHR::IDENTITY | vk::SurfaceTransformFlagsKHR::ROTATE_90,
+            )
+            .supported_composite_alpha(
+                vk::CompositeAlphaFlagsKHR::INHERIT | vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+            )
+            .build();
+
+        let composition = SurfaceComposition::from_capabilities(&capabilities);
+
+        assert_eq!(
+            composition.pre_transform(),
+            vk::SurfaceTransformFlagsKHR::ROTATE_90
+        );
+        assert_eq!(
+            composition.composite_alpha(),
+            vk::CompositeAlphaFlagsKHR::INHERIT
+        );
+
+        let composition = composition
+            .with_pre_transform(vk::SurfaceTransformFlagsKHR::IDENTITY)
+            .unwrap();
+        assert_eq!(
+            composition.pre_transform(),
+            vk::SurfaceTransformFlagsKHR::IDENTITY
+        );
+
+        assert_eq!(
+            composition.with_composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE),
+            Err(SurfaceCompositionError::UnsupportedCompositeAlpha(
+                vk::CompositeAlphaFlagsKHR::OPAQUE
+            ))
+        );
+
+        // Supported, but not a single flag
+        let both = vk::CompositeAlphaFlagsKHR::INHERIT | vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED;
+        assert_eq!(
+            composition.with_composite_alpha(both),
+            Err(SurfaceCompositionError::NotSingleCompositeAlpha(both))
+        );
+        assert_eq!(
+            composition.with_pre_transform(vk::SurfaceTransformFlagsKHR::empty()),
+            Err(SurfaceCompositionError::NotSingleTransform(
+                vk::SurfaceTransformFlagsKHR::empty()
+            ))
+        );
+    }
+
+    #[test]
+    fn image_count_clamping() {
+        let capabilities = vk::SurfaceCapabilitiesKHR::builder()
+            .min_image_count(2)
+            .max_image_count(3)
+            .build();
+
+        assert_eq!(clamp_image_count(&capabilities, None), 3);
+        assert_eq!(clamp_image_count(&capabilities, Some(1)), 2);
+        assert_eq!(clamp_image_count(&capabilities, Some(8)), 3);
+
+        let unbounded = vk::SurfaceCapabilitiesKHR::builder()
+            .min_image_count(2)
+            .max_image_count(0)
+            .build();
+
+        assert_eq!(clamp_image_count(&unbounded, Some(8)), 8);
+    }
+}
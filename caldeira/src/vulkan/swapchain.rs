@@ -0,0 +1,368 @@
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+use ash::extensions::khr::Swapchain as SwapchainLoader;
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+use super::{clamp_image_count, Device, DeviceLost, Queue, Semaphore, Surface, SurfaceComposition};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SwapchainError {
+    /// The surface changed, for instance because its window was resized or minimized,
+    /// and the swapchain must be recreated before presenting again
+    OutOfDate,
+    /// The surface can't be presented to anymore, for instance because its window was closed
+    SurfaceLost,
+    DeviceLost,
+}
+
+impl fmt::Display for SwapchainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfDate => write!(f, "The swapchain doesn't match its surface anymore!"),
+            Self::SurfaceLost => write!(f, "The surface of the swapchain was lost!"),
+            Self::DeviceLost => write!(f, "The device was lost while presenting!"),
+        }
+    }
+}
+
+impl Error for SwapchainError {}
+
+impl From<DeviceLost> for SwapchainError {
+    fn from(_: DeviceLost) -> Self {
+        Self::DeviceLost
+    }
+}
+
+/// Images presented to a `Surface`, which must outlive the swapchain
+/// The surface format, present mode and extent are chosen from what the surface supports,
+/// preferring an sRGB format and the mailbox present mode
+pub struct Swapchain {
+    pub handle: vk::SwapchainKHR,
+    loader: SwapchainLoader,
+    format: vk::SurfaceFormatKHR,
+    present_mode: vk::PresentModeKHR,
+    extent: vk::Extent2D,
+    images: Vec<vk::Image>,
+    image_views: Vec<vk::ImageView>,
+    device: Rc<Device>,
+}
+
+impl Swapchain {
+    /// Create a swapchain presenting to `surface`, whose images have the size of the window, `window_extent`,
+    /// unless the surface requires another one
+    /// It fails with `OutOfDate` while the window is minimized
+    pub fn new(
+        surface: &Surface,
+        window_extent: vk::Extent2D,
+        device: Rc<Device>,
+    ) -> Result<Self, SwapchainError> {
+        let loader = SwapchainLoader::new(&device.instance().instance, &device.device);
+
+        let mut swapchain = Self {
+            handle: vk::SwapchainKHR::null(),
+            loader,
+            format: vk::SurfaceFormatKHR::default(),
+            present_mode: vk::PresentModeKHR::FIFO,
+            extent: vk::Extent2D::default(),
+            images: vec![],
+            image_views: vec![],
+            device,
+        };
+
+        swapchain.recreate(surface, window_extent)?;
+
+        Ok(swapchain)
+    }
+
+    /// Recreate the swapchain after the surface changed, for instance when the window is resized
+    /// The old swapchain is given to the new one so that its presentations can complete, but the device is waited
+    /// for before destroying its images, which are replaced
+    pub fn recreate(
+        &mut self,
+        surface: &Surface,
+        window_extent: vk::Extent2D,
+    ) -> Result<(), SwapchainError> {
+        let capabilities = self.check(
+            surface.capabilities(&self.device),
+            "failed to query surface capabilities",
+        )?;
+        let formats = self.check(
+            surface.formats(&self.device),
+            "failed to query surface formats",
+        )?;
+        let present_modes = self.check(
+            surface.present_modes(&self.device),
+            "failed to query surface present modes",
+        )?;
+
+        let format = choose_surface_format(&formats);
+        let present_mode = choose_present_mode(&present_modes);
+        let extent = choose_extent(&capabilities, window_extent);
+
+        if extent.width == 0 || extent.height == 0 {
+            return Err(SwapchainError::OutOfDate);
+        }
+
+        // Transfers allow to blit the result of a compute shader into the images
+        let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_DST);
+
+        let create_info = vk::SwapchainCreateInfoKHR::builder()
+            .surface(surface.handle)
+            .min_image_count(clamp_image_count(&capabilities, None))
+            .image_format(format.format)
+            .image_color_space(format.color_space)
+            .image_extent(extent)
+            .image_array_layers(1)
+            .image_usage(image_usage)
+            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .present_mode(present_mode)
+            .clipped(true)
+            .old_swapchain(self.handle);
+        let create_info = SurfaceComposition::from_capabilities(&capabilities).apply(create_info);
+
+        let handle = self.check(
+            unsafe { self.loader.create_swapchain(&create_info, None) },
+            "failed to create swapchain",
+        )?;
+
+        // The old swapchain is retired, but its images may still be in use
+        let wait_result = self.device.check_lost(
+            unsafe { self.device.device.device_wait_idle() },
+            "failed to wait device idle",
+        );
+        self.destroy();
+        self.handle = handle;
+        wait_result?;
+
+        self.format = format;
+        self.present_mode = present_mode;
+        self.extent = extent;
+        self.images = self.check(
+            unsafe { self.loader.get_swapchain_images(handle) },
+            "failed to get swapchain images",
+        )?;
+        self.image_views = self
+            .images
+            .iter()
+            .map(|&image| self.create_image_view(image))
+            .collect();
+
+        Ok(())
+    }
+
+    /// Index of the next image to render to, and whether the swapchain is suboptimal for its surface
+    /// `semaphore` is signaled once the presentation engine stopped reading the image
+    pub fn acquire_next_image(&self, semaphore: &Semaphore) -> Result<(u32, bool), SwapchainError> {
+        self.check(
+            unsafe {
+                self.loader.acquire_next_image(
+                    self.handle,
+                    u64::MAX,
+                    semaphore.handle,
+                    vk::Fence::null(),
+                )
+            },
+            "failed to acquire swapchain image",
+        )
+    }
+
+    /// Present the image `image_index` once `wait_semaphore` is signaled, it must be in the `PRESENT_SRC_KHR` layout
+    /// Return whether the swapchain is suboptimal for its surface
+    pub fn present(
+        &self,
+        queue: &mut Queue,
+        image_index: u32,
+        wait_semaphore: &Semaphore,
+    ) -> Result<bool, SwapchainError> {
+        let wait_semaphores = [wait_semaphore.handle];
+        let swapchains = [self.handle];
+        let image_indices = [image_index];
+
+        let present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(&wait_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&image_indices);
+
+        self.check(
+            unsafe { self.loader.queue_present(queue.handle, &present_info) },
+            "failed to present",
+        )
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format.format
+    }
+
+    pub fn color_space(&self) -> vk::ColorSpaceKHR {
+        self.format.color_space
+    }
+
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.present_mode
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn images(&self) -> &[vk::Image] {
+        &self.images
+    }
+
+    /// Color views of the images, in the same order
+    pub fn image_views(&self) -> &[vk::ImageView] {
+        &self.image_views
+    }
+
+    fn create_image_view(&self, image: vk::Image) -> vk::ImageView {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build();
+
+        let create_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(self.format.format)
+            .subresource_range(subresource_range);
+
+        unsafe { self.device.device.create_image_view(&create_info, None) }
+            .expect("failed to create swapchain image view!")
+    }
+
+    /// Turn the errors meaning that the swapchain must be recreated or dropped into `SwapchainError`,
+    /// any other error than a device loss is unrecoverable and panics with `message`
+    fn check<T>(&self, result: VkResult<T>, message: &str) -> Result<T, SwapchainError> {
+        match result {
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Err(SwapchainError::OutOfDate),
+            Err(vk::Result::ERROR_SURFACE_LOST_KHR) => Err(SwapchainError::SurfaceLost),
+            result => Ok(self.device.check_lost(result, message)?),
+        }
+    }
+
+    fn destroy(&mut self) {
+        unsafe {
+            for image_view in self.image_views.drain(..) {
+                self.device.device.destroy_image_view(image_view, None);
+            }
+            self.loader.destroy_swapchain(self.handle, None);
+        }
+        self.images.clear();
+    }
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        self.destroy();
+    }
+}
+
+/// 8 bits BGRA sRGB if supported, otherwise the first supported format
+fn choose_surface_format(formats: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
+    formats
+        .iter()
+        .copied()
+        .find(|format| {
+            format.format == vk::Format::B8G8R8A8_SRGB
+                && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+        })
+        .unwrap_or(formats[0])
+}
+
+/// Mailbox if supported, otherwise FIFO which is always supported
+fn choose_present_mode(present_modes: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+    if present_modes.contains(&vk::PresentModeKHR::MAILBOX) {
+        vk::PresentModeKHR::MAILBOX
+    } else {
+        vk::PresentModeKHR::FIFO
+    }
+}
+
+/// The current extent of the surface, or `window_extent` clamped into the supported range
+/// when the surface lets the swapchain choose it
+fn choose_extent(
+    capabilities: &vk::SurfaceCapabilitiesKHR,
+    window_extent: vk::Extent2D,
+) -> vk::Extent2D {
+    if capabilities.current_extent.width != u32::MAX {
+        return capabilities.current_extent;
+    }
+
+    let (min, max) = (capabilities.min_image_extent, capabilities.max_image_extent);
+
+    vk::Extent2D {
+        width: window_extent.width.max(min.width).min(max.width),
+        height: window_extent.height.max(min.height).min(max.height),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn swapchain_choices() {
+        let unorm = vk::SurfaceFormatKHR {
+            format: vk::Format::B8G8R8A8_UNORM,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        };
+        let srgb = vk::SurfaceFormatKHR {
+            format: vk::Format::B8G8R8A8_SRGB,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        };
+
+        assert_eq!(choose_surface_format(&[unorm, srgb]), srgb);
+        assert_eq!(choose_surface_format(&[unorm]), unorm);
+
+        assert_eq!(
+            choose_present_mode(&[vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX]),
+            vk::PresentModeKHR::MAILBOX
+        );
+        assert_eq!(
+            choose_present_mode(&[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::FIFO]),
+            vk::PresentModeKHR::FIFO
+        );
+
+        let window_extent = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+        let free = vk::SurfaceCapabilitiesKHR::builder()
+            .current_extent(vk::Extent2D {
+                width: u32::MAX,
+                height: u32::MAX,
+            })
+            .min_image_extent(vk::Extent2D {
+                width: 1,
+                height: 1,
+            })
+            .max_image_extent(vk::Extent2D {
+                width: 640,
+                height: 4096,
+            })
+            .build();
+        assert_eq!(
+            choose_extent(&free, window_extent),
+            vk::Extent2D {
+                width: 640,
+                height: 600,
+            }
+        );
+
+        let fixed = vk::SurfaceCapabilitiesKHR::builder()
+            .current_extent(vk::Extent2D {
+                width: 1024,
+                height: 768,
+            })
+            .build();
+        assert_eq!(choose_extent(&fixed, window_extent), fixed.current_extent);
+    }
+}
@@ -0,0 +1,543 @@
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+use super::{Device, DeviceLost, Queue, QueueSubmission, SubmitError, SubmitHandle};
+
+pub const EXTERNAL_SEMAPHORE_FD: &str = "VK_KHR_external_semaphore_fd";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExternalSemaphoreError {
+    /// The device extension `EXTERNAL_SEMAPHORE_FD` isn't enabled
+    ExtensionNotEnabled,
+    /// The semaphore wasn't created by `Semaphore::new_exportable`
+    NotExportable,
+    Vulkan(vk::Result),
+}
+
+impl fmt::Display for ExternalSemaphoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ExtensionNotEnabled => write!(
+                f,
+                "The device extension {} isn't enabled!",
+                EXTERNAL_SEMAPHORE_FD
+            ),
+            Self::NotExportable => write!(f, "The semaphore wasn't created exportable!"),
+            Self::Vulkan(result) => write!(f, "Failed to share semaphore: {}!", result),
+        }
+    }
+}
+
+impl Error for ExternalSemaphoreError {}
+
+/// A binary semaphore
+pub struct Semaphore {
+    pub handle: vk::Semaphore,
+    exportable: bool,
+    device: Rc<Device>,
+}
+
+impl Semaphore {
+    pub fn new(device: Rc<Device>) -> Self {
+        let create_info = vk::SemaphoreCreateInfo::builder();
+
+        Self::create(&create_info, false, device)
+    }
+
+    /// Create a semaphore which can be exported as an opaque file descriptor to be waited on or signaled
+    /// by another API, the device extension `EXTERNAL_SEMAPHORE_FD` must be enabled
+    pub fn new_exportable(device: Rc<Device>) -> Result<Self, ExternalSemaphoreError> {
+        if !device.is_extension_enabled(EXTERNAL_SEMAPHORE_FD) {
+            return Err(ExternalSemaphoreError::ExtensionNotEnabled);
+        }
+
+        let mut export_info = vk::ExportSemaphoreCreateInfo::builder().handle_types(
+            vk::ExternalSemaphoreHandleTypeFlags::EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_FD,
+        );
+        let create_info = vk::SemaphoreCreateInfo::builder().push_next(&mut export_info);
+
+        Ok(Self::create(&create_info, true, device))
+    }
+
+    fn create(create_info: &vk::SemaphoreCreateInfo, exportable: bool, device: Rc<Device>) -> Self {
+        let handle = unsafe { device.device.create_semaphore(create_info, None) }
+            .expect("failed to create semaphore!");
+
+        Self {
+            handle,
+            exportable,
+            device,
+        }
+    }
+
+    /// Export the payload of the semaphore as a file descriptor, which is owned by the caller
+    #[cfg(unix)]
+    pub fn export_fd(&self) -> Result<i32, ExternalSemaphoreError> {
+        if !self.exportable {
+            return Err(ExternalSemaphoreError::NotExportable);
+        }
+
+        let get_fd_info = vk::SemaphoreGetFdInfoKHR::builder()
+            .semaphore(self.handle)
+            .handle_type(
+                vk::ExternalSemaphoreHandleTypeFlags::EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_FD,
+            );
+
+        let mut fd = -1;
+
+        match unsafe {
+            self.external_semaphore_fd()?.get_semaphore_fd_khr(
+                self.device.device.handle(),
+                &*get_fd_info,
+                &mut fd,
+            )
+        } {
+            vk::Result::SUCCESS => Ok(fd),
+            result => Err(ExternalSemaphoreError::Vulkan(result)),
+        }
+    }
+
+    /// Replace the payload of the semaphore by the one of `fd`, exported by another API
+    /// On success the file descriptor is owned by Vulkan and must not be used anymore,
+    /// if `temporary` the payload is only used until the next wait on the semaphore
+    #[cfg(unix)]
+    pub fn import_fd(&mut self, fd: i32, temporary: bool) -> Result<(), ExternalSemaphoreError> {
+        let flags = if temporary {
+            vk::SemaphoreImportFlags::TEMPORARY
+        } else {
+            vk::SemaphoreImportFlags::empty()
+        };
+
+        let import_info = vk::ImportSemaphoreFdInfoKHR::builder()
+            .semaphore(self.handle)
+            .flags(flags)
+            .handle_type(
+                vk::ExternalSemaphoreHandleTypeFlags::EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_FD,
+            )
+            .fd(fd);
+
+        match unsafe {
+            self.external_semaphore_fd()?
+                .import_semaphore_fd_khr(self.device.device.handle(), &*import_info)
+        } {
+            vk::Result::SUCCESS => Ok(()),
+            result => Err(ExternalSemaphoreError::Vulkan(result)),
+        }
+    }
+
+    #[cfg(unix)]
+    fn external_semaphore_fd(
+        &self,
+    ) -> Result<vk::KhrExternalSemaphoreFdFn, ExternalSemaphoreError> {
+        use std::mem;
+
+        use ash::version::InstanceV1_0;
+
+        if !self.device.is_extension_enabled(EXTERNAL_SEMAPHORE_FD) {
+            return Err(ExternalSemaphoreError::ExtensionNotEnabled);
+        }
+
+        let instance = &self.device.instance().instance;
+        let device = self.device.device.handle();
+
+        Ok(vk::KhrExternalSemaphoreFdFn::load(|name| unsafe {
+            mem::transmute(instance.get_device_proc_addr(device, name.as_ptr()))
+        }))
+    }
+}
+
+impl Drop for Semaphore {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device.destroy_semaphore(self.handle, None);
+        }
+    }
+}
+
+/// A fence, signaled by the GPU when a submission completes and waited for by the host
+pub struct Fence {
+    pub handle: vk::Fence,
+    device: Rc<Device>,
+}
+
+impl Fence {
+    pub fn new(signaled: bool, device: Rc<Device>) -> Self {
+        let flags = if signaled {
+            vk::FenceCreateFlags::SIGNALED
+        } else {
+            vk::FenceCreateFlags::empty()
+        };
+        let fence_info = vk::FenceCreateInfo::builder().flags(flags);
+
+        let handle = unsafe { device.device.create_fence(&fence_info, None) }
+            .expect("failed to create fence");
+
+        Self { handle, device }
+    }
+
+    /// Wait until the fence is signaled, or until `timeout` is elapsed if any, which fails with `TIMEOUT`
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<(), vk::Result> {
+        let timeout = timeout.map_or(u64::MAX, |timeout| timeout.as_nanos() as u64);
+
+        unsafe {
+            self.device
+                .device
+                .wait_for_fences(&[self.handle], true, timeout)
+        }
+    }
+
+    /// Unsignal the fence, it must not be used by a pending submission
+    pub fn reset(&mut self) {
+        unsafe { self.device.device.reset_fences(&[self.handle]) }.expect("failed to reset fence");
+    }
+
+    pub fn is_signaled(&self) -> bool {
+        unsafe { self.device.device.get_fence_status(self.handle) }
+            .expect("failed to get fence status")
+    }
+}
+
+impl Drop for Fence {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device.destroy_fence(self.handle, None);
+        }
+    }
+}
+
+/// An event, set and reset by the host or by command buffers and waited for by command buffers of the same queue,
+/// for dependencies finer than a pipeline barrier
+pub struct Event {
+    pub handle: vk::Event,
+    device: Rc<Device>,
+}
+
+impl Event {
+    /// The event is created unset
+    pub fn new(device: Rc<Device>) -> Self {
+        let event_info = vk::EventCreateInfo::builder();
+
+        let handle = unsafe { device.device.create_event(&event_info, None) }
+            .expect("failed to create event");
+
+        Self { handle, device }
+    }
+
+    /// Set the event from the host
+    pub fn set(&self) {
+        unsafe { self.device.device.set_event(self.handle) }.expect("failed to set event");
+    }
+
+    /// Unset the event from the host, no command waiting for it must be pending
+    pub fn reset(&self) {
+        unsafe { self.device.device.reset_event(self.handle) }.expect("failed to reset event");
+    }
+
+    /// Whether the event is set
+    pub fn get_status(&self) -> Result<bool, DeviceLost> {
+        self.device.check_lost(
+            unsafe { self.device.device.get_event_status(self.handle) },
+            "failed to get event status",
+        )
+    }
+}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device.destroy_event(self.handle, None);
+        }
+    }
+}
+
+/// Fences recycled across submissions instead of being created and destroyed for each of them
+pub struct FencePool {
+    free: RefCell<Vec<vk::Fence>>,
+    fence_count: Cell<usize>,
+    device: Rc<Device>,
+}
+
+impl FencePool {
+    pub fn new(device: Rc<Device>) -> Self {
+        Self {
+            free: RefCell::new(vec![]),
+            fence_count: Cell::new(0),
+            device,
+        }
+    }
+
+    /// Take an unsignaled fence, which is created if none is free
+    pub fn acquire(&self) -> vk::Fence {
+        if let Some(fence) = self.free.borrow_mut().pop() {
+            return fence;
+        }
+
+        let fence_info = vk::FenceCreateInfo::builder();
+
+        let fence = unsafe { self.device.device.create_fence(&fence_info, None) }
+            .expect("failed to create fence");
+        self.fence_count.set(self.fence_count.get() + 1);
+
+        fence
+    }
+
+    /// Give back a fence acquired from this pool, it must not be used by a pending submission anymore
+    pub fn release(&self, fence: vk::Fence) -> Result<(), DeviceLost> {
+        let result = self.device.check_lost(
+            unsafe { self.device.device.reset_fences(&[fence]) },
+            "failed to reset fence",
+        );
+
+        match result {
+            Ok(()) => self.free.borrow_mut().push(fence),
+            Err(DeviceLost) => {
+                unsafe {
+                    self.device.device.destroy_fence(fence, None);
+                }
+                self.fence_count.set(self.fence_count.get() - 1);
+            }
+        }
+
+        result
+    }
+
+    /// Number of fences created by the pool which aren't destroyed yet, free or acquired
+    pub fn fence_count(&self) -> usize {
+        self.fence_count.get()
+    }
+
+    /// Number of fences ready to be acquired without creating a new one
+    pub fn free_count(&self) -> usize {
+        self.free.borrow().len()
+    }
+}
+
+impl Drop for FencePool {
+    fn drop(&mut self) {
+        for fence in self.free.get_mut().drain(..) {
+            unsafe {
+                self.device.device.destroy_fence(fence, None);
+            }
+        }
+    }
+}
+
+/// Resources of the frames in flight, so that a frame loop never waits for the GPU within a frame
+///
+/// Instead of waiting for the compute work of a frame with `Queue::wait_idle` or `SubmitHandle::wait`
+/// before presenting it, the compute submission signals `compute_finished` and the present submission
+/// waits on it, both being submitted with `FrameSync::submit`. `begin_frame` then only waits for the
+/// submissions of the frame made `frames_in_flight` frames ago, which are usually complete already.
+pub struct FrameSync<'a> {
+    frames: Vec<FrameResources<'a>>,
+    current: usize,
+    fence_pool: Rc<FencePool>,
+}
+
+struct FrameResources<'a> {
+    compute_finished: Semaphore,
+    submissions: Vec<SubmitHandle<'a>>,
+}
+
+impl<'a> FrameSync<'a> {
+    pub fn new(frames_in_flight: usize, device: Rc<Device>) -> Self {
+        assert_ne!(frames_in_flight, 0, "at least one frame must be in flight");
+
+        let frames = (0..frames_in_flight)
+            .map(|_| FrameResources {
+                compute_finished: Semaphore::new(Rc::clone(&device)),
+                submissions: vec![],
+            })
+            .collect();
+
+        Self {
+            frames,
+            current: 0,
+            fence_pool: Rc::new(FencePool::new(device)),
+        }
+    }
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Index of the current frame among the frames in flight, to select other per-frame resources
+    pub fn frame_index(&self) -> usize {
+        self.current
+    }
+
+    /// Move to the next frame, waiting for its previous submissions to complete and freeing them
+    pub fn begin_frame(&mut self) -> Result<(), DeviceLost> {
+        self.current = (self.current + 1) % self.frames.len();
+
+        self.frames[self.current]
+            .submissions
+            .drain(..)
+            .try_for_each(SubmitHandle::wait)
+    }
+
+    /// Semaphore to be signaled by the compute submission of the current frame and waited on by its
+    /// present submission, which must be submitted with `submit` too so that it's not reused too early
+    pub fn compute_finished(&self) -> vk::Semaphore {
+        self.frames[self.current].compute_finished.handle
+    }
+
+    /// Submit `submits` to `queue` without waiting, they are kept alive until the frame comes back
+    pub fn submit(
+        &mut self,
+        queue: &mut Queue,
+        submits: Vec<QueueSubmission<'a>>,
+    ) -> Result<(), SubmitError> {
+        let handle = queue.submit_batch_with_pool(submits, &self.fence_pool)?;
+
+        self.frames[self.current].submissions.push(handle);
+
+        Ok(())
+    }
+
+    /// Wait for the submissions of every frame, before destroying resources they use
+    pub fn wait_all(&mut self) -> Result<(), DeviceLost> {
+        self.frames
+            .iter_mut()
+            .flat_map(|frame| frame.submissions.drain(..))
+            .try_for_each(SubmitHandle::wait)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils;
+    use crate::vulkan::CommandPool;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn fenced_submission() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut fence = Fence::new(true, device);
+
+        assert!(fence.is_signaled());
+        fence.reset();
+        assert!(!fence.is_signaled());
+
+        queue.submit_fenced(&[], &fence).unwrap();
+        fence.wait(Some(Duration::from_secs(1))).unwrap();
+        assert!(fence.is_signaled());
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn fence_pool_recycles_fences() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let fence_pool = Rc::new(FencePool::new(device));
+
+        for _ in 0..100 {
+            queue
+                .submit_batch_with_pool(vec![], &fence_pool)
+                .unwrap()
+                .wait()
+                .unwrap();
+        }
+
+        assert_eq!(fence_pool.fence_count(), 1);
+        assert_eq!(fence_pool.free_count(), 1);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn frame_sync_keeps_frames_in_flight() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut frame_sync = FrameSync::new(2, device);
+
+        for frame in 0..10 {
+            frame_sync.begin_frame().unwrap();
+            assert_eq!(frame_sync.frame_index(), (frame + 1) % 2);
+
+            frame_sync.submit(&mut queue, vec![]).unwrap();
+        }
+
+        frame_sync.wait_all().unwrap();
+        assert_eq!(frame_sync.fence_pool.fence_count(), 2);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn events_are_set_by_host_and_device() {
+        let (_instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+        let first = Event::new(Rc::clone(&device));
+        let second = Event::new(device);
+
+        assert_eq!(first.get_status(), Ok(false));
+        first.set();
+        assert_eq!(first.get_status(), Ok(true));
+
+        let mut recorder = command_pool
+            .allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)
+            .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        recorder
+            .as_generic()
+            .set_event(&second, vk::PipelineStageFlags::TOP_OF_PIPE)
+            .wait_events(
+                &[&first, &second],
+                vk::PipelineStageFlags::HOST | vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                &[],
+                &[],
+                &[],
+            )
+            .reset_event(&first, vk::PipelineStageFlags::BOTTOM_OF_PIPE);
+        let command_buffer = recorder.end();
+
+        queue
+            .submit_batch(vec![QueueSubmission::builder()
+                .with_command_buffer(&command_buffer)
+                .build()])
+            .unwrap()
+            .wait()
+            .unwrap();
+
+        assert_eq!(first.get_status(), Ok(false));
+        assert_eq!(second.get_status(), Ok(true));
+        second.reset();
+        assert_eq!(second.get_status(), Ok(false));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn exportable_semaphore_needs_extension() {
+        let (_instance, device, _queue) = utils::create_test_device();
+
+        assert_eq!(
+            Semaphore::new_exportable(device).err(),
+            Some(ExternalSemaphoreError::ExtensionNotEnabled)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn semaphore_payload_shared_as_fd() {
+        let (_instance, device, _queue) =
+            utils::create_test_device_with_extensions(&[EXTERNAL_SEMAPHORE_FD]);
+
+        let exportable = Semaphore::new_exportable(Rc::clone(&device)).unwrap();
+        let fd = exportable.export_fd().unwrap();
+        assert!(fd >= 0);
+
+        // The imported file descriptor is owned by Vulkan, so it isn't closed here
+        let mut imported = Semaphore::new(Rc::clone(&device));
+        imported.import_fd(fd, true).unwrap();
+
+        assert_eq!(
+            imported.export_fd(),
+            Err(ExternalSemaphoreError::NotExportable)
+        );
+    }
+}
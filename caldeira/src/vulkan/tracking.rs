@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+use super::{Buffer, CommandBufferRecorder, Image};
+
+/// Accesses which write memory, the other ones only read it
+const WRITE_ACCESSES: vk::AccessFlags = vk::AccessFlags::from_raw(
+    vk::AccessFlags::SHADER_WRITE.as_raw()
+        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags::HOST_WRITE.as_raw()
+        | vk::AccessFlags::MEMORY_WRITE.as_raw(),
+);
+
+/// Execution and memory dependency needed by an access on the previous ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Dependency {
+    src_stage_mask: vk::PipelineStageFlags,
+    dst_stage_mask: vk::PipelineStageFlags,
+    src_access_mask: vk::AccessFlags,
+    dst_access_mask: vk::AccessFlags,
+}
+
+/// Accesses to a resource since the start of the command buffer
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct ResourceState {
+    /// Stages and accesses of the last write, or of the last layout transition
+    last_write: Option<(vk::PipelineStageFlags, vk::AccessFlags)>,
+    /// Stages and accesses the last write was made visible to by a barrier
+    visible: Vec<(vk::PipelineStageFlags, vk::AccessFlags)>,
+    /// Stages which read the resource since the last write
+    read_stages: vk::PipelineStageFlags,
+}
+
+impl ResourceState {
+    /// Record an access by `stage` with `access`, changing the layout of the image if `transition`,
+    /// and return the dependency it needs on the previous accesses, if any
+    fn access(
+        &mut self,
+        stage: vk::PipelineStageFlags,
+        access: vk::AccessFlags,
+        transition: bool,
+    ) -> Option<Dependency> {
+        let writes = transition || access.intersects(WRITE_ACCESSES);
+
+        let dependency = if writes {
+            // Write-after-write and write-after-read hazards, reads only need an execution dependency
+            let (write_stage, write_access) = self
+                .last_write
+                .unwrap_or((vk::PipelineStageFlags::empty(), vk::AccessFlags::empty()));
+            let src_stage_mask = write_stage | self.read_stages;
+
+            if src_stage_mask.is_empty() && !transition {
+                None
+            } else if src_stage_mask.is_empty() {
+                // The first access, after the ones synchronized by the submission
+                Some(Dependency {
+                    src_stage_mask: vk::PipelineStageFlags::TOP_OF_PIPE,
+                    dst_stage_mask: stage,
+                    src_access_mask: vk::AccessFlags::empty(),
+                    dst_access_mask: access,
+                })
+            } else {
+                Some(Dependency {
+                    src_stage_mask,
+                    dst_stage_mask: stage,
+                    src_access_mask: write_access,
+                    dst_access_mask: access,
+                })
+            }
+        } else {
+            // Read-after-write hazard, unless a barrier already made the write visible to this access
+            match self.last_write {
+                Some((write_stage, write_access))
+                    if !self.visible.iter().any(|&(visible_stage, visible_access)| {
+                        visible_stage.contains(stage) && visible_access.contains(access)
+                    }) =>
+                {
+                    Some(Dependency {
+                        src_stage_mask: write_stage,
+                        dst_stage_mask: stage,
+                        src_access_mask: write_access,
+                        dst_access_mask: access,
+                    })
+                }
+                _ => None,
+            }
+        };
+
+        if writes {
+            // A layout transition is a write, conservatively made available as any memory write
+            let write_access = match access & WRITE_ACCESSES {
+                write_access if write_access.is_empty() => vk::AccessFlags::MEMORY_WRITE,
+                write_access => write_access,
+            };
+
+            self.last_write = Some((stage, write_access));
+            self.visible = vec![(stage, access)];
+            self.read_stages = vk::PipelineStageFlags::empty();
+        } else {
+            if dependency.is_some() {
+                self.visible.push((stage, access));
+            }
+            self.read_stages |= stage;
+        }
+
+        dependency
+    }
+}
+
+/// Command buffer recorder inserting the barriers between the accesses to buffers and images declared to it
+///
+/// Each access must be declared with `access_buffer` or `access_image` before recording the command doing it
+/// with `recorder`. The barriers are conservative: they cover whole resources, and every read since the
+/// last write is waited for before writing again. Accesses done before the command buffer must be
+/// synchronized by its submission, with a semaphore or by waiting for a fence.
+pub struct TrackedCommandBuffer<'a, 'b: 'a> {
+    recorder: &'a mut CommandBufferRecorder<'b>,
+    buffers: HashMap<vk::Buffer, ResourceState>,
+    images: HashMap<vk::Image, ResourceState>,
+    barrier_count: usize,
+}
+
+impl<'a, 'b: 'a> TrackedCommandBuffer<'a, 'b> {
+    pub fn new(recorder: &'a mut CommandBufferRecorder<'b>) -> Self {
+        Self {
+            recorder,
+            buffers: HashMap::new(),
+            images: HashMap::new(),
+            barrier_count: 0,
+        }
+    }
+
+    /// Declare that the next command accesses `buffer` in `stage` with `access`
+    pub fn access_buffer(
+        &mut self,
+        buffer: &Buffer,
+        stage: vk::PipelineStageFlags,
+        access: vk::AccessFlags,
+    ) -> &mut Self {
+        let dependency = self
+            .buffers
+            .entry(buffer.handle)
+            .or_default()
+            .access(stage, access, false);
+
+        if let Some(dependency) = dependency {
+            let barrier = vk::BufferMemoryBarrier::builder()
+                .src_access_mask(dependency.src_access_mask)
+                .dst_access_mask(dependency.dst_access_mask)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(buffer.handle)
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+                .build();
+
+            self.pipeline_barrier(dependency, &[barrier], &[]);
+        }
+
+        self
+    }
+
+    /// Declare that the next command accesses `image` in `layout`, in `stage` with `access`
+    /// The image is transitioned if it's in another layout
+    pub fn access_image(
+        &mut self,
+        image: &mut Image,
+        layout: vk::ImageLayout,
+        stage: vk::PipelineStageFlags,
+        access: vk::AccessFlags,
+    ) -> &mut Self {
+        let dependency = self.images.entry(image.handle).or_default().access(
+            stage,
+            access,
+            image.layout != layout,
+        );
+
+        if let Some(dependency) = dependency {
+            let barrier = image
+                .layout_barrier(
+                    layout,
+                    dependency.src_access_mask,
+                    dependency.dst_access_mask,
+                )
+                .build();
+
+            self.pipeline_barrier(dependency, &[], &[barrier]);
+        }
+
+        image.layout = layout;
+
+        self
+    }
+
+    /// Recorder of the commands, whose accesses must be declared before
+    pub fn recorder(&mut self) -> &mut CommandBufferRecorder<'b> {
+        self.recorder
+    }
+
+    /// Number of barriers recorded so far
+    pub fn barrier_count(&self) -> usize {
+        self.barrier_count
+    }
+
+    fn pipeline_barrier(
+        &mut self,
+        dependency: Dependency,
+        buffer_memory_barriers: &[vk::BufferMemoryBarrier],
+        image_memory_barriers: &[vk::ImageMemoryBarrier],
+    ) {
+        unsafe {
+            self.recorder.device().device.cmd_pipeline_barrier(
+                self.recorder.handle(),
+                dependency.src_stage_mask,
+                dependency.dst_stage_mask,
+                vk::DependencyFlags::empty(),
+                &[],
+                buffer_memory_barriers,
+                image_memory_barriers,
+            )
+        }
+
+        self.barrier_count += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::utils;
+    use crate::vulkan::CommandPool;
+
+    #[test]
+    fn read_after_write_dependencies() {
+        let mut state = ResourceState::default();
+        let compute = vk::PipelineStageFlags::COMPUTE_SHADER;
+
+        // The first write is synchronized by the submission
+        assert_eq!(
+            state.access(compute, vk::AccessFlags::SHADER_WRITE, false),
+            None
+        );
+
+        let read_after_write = Some(Dependency {
+            src_stage_mask: compute,
+            dst_stage_mask: compute,
+            src_access_mask: vk::AccessFlags::SHADER_WRITE,
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+        });
+        assert_eq!(
+            state.access(compute, vk::AccessFlags::SHADER_READ, false),
+            read_after_write
+        );
+        // The write is already visible to this read
+        assert_eq!(
+            state.access(compute, vk::AccessFlags::SHADER_READ, false),
+            None
+        );
+
+        // A write waits for the previous write and the reads since
+        assert_eq!(
+            state.access(
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                false
+            ),
+            Some(Dependency {
+                src_stage_mask: compute,
+                dst_stage_mask: vk::PipelineStageFlags::TRANSFER,
+                src_access_mask: vk::AccessFlags::SHADER_WRITE,
+                dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            })
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn storage_image_read_after_write() {
+        let (instance, device, mut queue) = utils::create_test_device();
+        let mut command_pool = Rc::new(CommandPool::new(queue.family(), Rc::clone(&device)));
+
+        let mut image = Image::new_storage(1, 1, device, &instance);
+        let mut barrier_count = 0;
+
+        command_pool
+            .single_time(&mut queue, |recorder| {
+                let mut tracked = TrackedCommandBuffer::new(recorder);
+                let compute = vk::PipelineStageFlags::COMPUTE_SHADER;
+
+                // Transition from the undefined layout
+                tracked.access_image(
+                    &mut image,
+                    vk::ImageLayout::GENERAL,
+                    compute,
+                    vk::AccessFlags::SHADER_WRITE,
+                );
+                assert_eq!(tracked.barrier_count(), 1);
+
+                tracked.access_image(
+                    &mut image,
+                    vk::ImageLayout::GENERAL,
+                    compute,
+                    vk::AccessFlags::SHADER_READ,
+                );
+                barrier_count = tracked.barrier_count();
+            })
+            .unwrap();
+
+        assert_eq!(barrier_count, 2);
+        assert_eq!(image.layout, vk::ImageLayout::GENERAL);
+    }
+}
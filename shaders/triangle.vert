@@ -0,0 +1,12 @@
+#version 450
+
+// A triangle in the middle of the viewport, drawn without vertex buffers
+const vec2[3] positions = {
+    {0.0, -0.5},
+    {0.5, 0.5},
+    {-0.5, 0.5},
+};
+
+void main() {
+    gl_Position = vec4(positions[gl_VertexIndex], 0.0, 1.0);
+}